@@ -23,6 +23,21 @@ fn bench_add(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_add_batch(c: &mut Criterion) {
+    let mut hll = HyperLogLog::new();
+    let mut group = c.benchmark_group("add_batch");
+    let items = generate_random_numbers(1_000_000)
+        .into_iter()
+        .map(u64::from)
+        .collect::<Vec<_>>();
+
+    group.bench_function("HyperLogLog", |b| {
+        b.iter(|| hll.add_batch(black_box(&items)))
+    });
+
+    group.finish();
+}
+
 fn bench_estimate(c: &mut Criterion) {
     let mut hll = HyperLogLog::new();
     let mut group = c.benchmark_group("estimate");
@@ -54,8 +69,29 @@ fn process_users(c: &mut Criterion) {
         })
     });
 
+    group.bench_function("HyperLogLog add_batch_strs", |b| {
+        b.iter(|| {
+            let mut hll = HyperLogLog::new();
+            let mut rng = nanorand::tls_rng();
+            let visits = rng.generate_range(1..3);
+            let users = (1..50_792)
+                .map(|user_id| format!("user-{}", user_id))
+                .collect::<Vec<_>>();
+
+            for _ in 0..visits {
+                hll.add_batch_strs(black_box(&users));
+            }
+        })
+    });
+
     group.finish();
 }
 
-criterion_group!(benches, bench_add, bench_estimate, process_users);
+criterion_group!(
+    benches,
+    bench_add,
+    bench_add_batch,
+    bench_estimate,
+    process_users
+);
 criterion_main!(benches);
@@ -0,0 +1,124 @@
+//! Measures the empirical bias of the raw HyperLogLog++ estimate at the precision the crate
+//! is built with, following Heule et al., "HyperLogLog in Practice" (2013).
+//!
+//! Sketches are filled with uniformly distributed 64-bit hashes. At 200 cardinalities
+//! evenly spaced from 0 to `5.2 * M`, the mean raw estimate and its mean excess over the
+//! cardinality are recorded. A second set of sketches then gives the linear counting
+//! threshold: the largest cardinality at which linear counting still has a smaller error
+//! than the bias corrected estimate.
+//!
+//! The table is printed in the format of `BiasTable::parse`, after the threshold as a
+//! comment, so it can be loaded with `HyperLogLogPlusPlus::with_bias_table` as it is:
+//!
+//! ```sh
+//! cargo run --release --example calibrate_bias -- 1000 > p20.txt
+//! cargo run --release --features p16 --example calibrate_bias -- 5000 > p16.txt
+//! ```
+//!
+//! The built-in tables of `src/bias/tables.rs` were produced this way, with 1,000 runs at
+//! precision 20 and 5,000 at the others.
+
+use hyperlog_simd::{bias::BiasTable, HyperLogLogPlusPlus, M, P};
+
+/// Number of cardinalities the bias is measured at.
+const POINTS: usize = 200;
+
+/// Number of sketches filled per cardinality unless given on the command line.
+const DEFAULT_RUNS: usize = 1_000;
+
+/// Generates uniformly distributed 64-bit hashes with SplitMix64, seeded so that tables
+/// can be reproduced.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// Fills `runs` sketches up to the last cardinality of `grid`.
+///
+/// # Arguments
+///
+/// * `grid`: The cardinalities to sample, in increasing order.
+/// * `runs`: The number of sketches.
+/// * `seed`: The seed of the hashes.
+///
+/// # Returns
+/// For every cardinality, the raw estimate and zero register count of every sketch.
+fn simulate(grid: &[u64], runs: usize, seed: u64) -> Vec<Vec<(f64, usize)>> {
+    let mut samples = vec![Vec::with_capacity(runs); grid.len()];
+    let mut hashes = SplitMix64(seed);
+    let mut hllpp = HyperLogLogPlusPlus::new();
+
+    for _ in 0..runs {
+        hllpp.clear();
+        let mut n = 0;
+        for (samples, &cardinality) in samples.iter_mut().zip(grid) {
+            while n < cardinality {
+                hllpp.add_hashed(hashes.next());
+                n += 1;
+            }
+            samples.push((hllpp.raw_estimate(), hllpp.zero_register_count()));
+        }
+    }
+
+    samples
+}
+
+fn main() {
+    let runs = std::env::args()
+        .nth(1)
+        .map(|runs| runs.parse().expect("the number of runs must be an integer"))
+        .unwrap_or(DEFAULT_RUNS);
+    let grid = (0..POINTS as u64)
+        .map(|i| i * (52 * M as u64 / 10) / (POINTS as u64 - 1))
+        .collect::<Vec<_>>();
+
+    let (raw_estimates, biases): (Vec<f64>, Vec<f64>) = simulate(&grid, runs, P as u64)
+        .iter()
+        .zip(&grid)
+        .map(|(samples, &cardinality)| {
+            let mean = samples.iter().map(|&(raw, _)| raw).sum::<f64>() / runs as f64;
+            (mean, mean - cardinality as f64)
+        })
+        .unzip();
+    let table = BiasTable::new(raw_estimates.clone(), biases.clone())
+        .expect("mean raw estimates grow with the cardinality");
+
+    let m = M as f64;
+    let root_mean_square = |errors: &mut dyn Iterator<Item = f64>| {
+        (errors.map(|error| error * error).sum::<f64>() / runs as f64).sqrt()
+    };
+    let threshold = simulate(&grid, runs, !(P as u64))
+        .iter()
+        .zip(&grid)
+        .skip(1)
+        .find(|(samples, &cardinality)| {
+            let n = cardinality as f64;
+            let linear_counting =
+                root_mean_square(&mut samples.iter().map(|&(_, zeros)| match zeros {
+                    0 => f64::INFINITY,
+                    zeros => m * (m / zeros as f64).ln() - n,
+                }));
+            let bias_corrected =
+                root_mean_square(&mut samples.iter().map(|&(raw, _)| match raw <= 5.0 * m {
+                    true => raw - table.bias(raw) - n,
+                    false => raw - n,
+                }));
+
+            linear_counting > bias_corrected
+        })
+        .map(|(_, &cardinality)| cardinality - grid[1])
+        .unwrap_or(*grid.last().unwrap());
+
+    println!("# precision {}, {} runs", P, runs);
+    println!("# linear counting threshold {}", threshold);
+    for (raw, bias) in raw_estimates.iter().zip(&biases) {
+        println!("{:.1} {:.1}", raw, bias);
+    }
+}
@@ -0,0 +1,116 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Exact number of items added to a sketch and when it was created and last updated,
+/// recorded by sketches that opted in with `track_activity`.
+///
+/// Reports typically show the total number of events next to the number of distinct
+/// ones; tracking the total inside the sketch keeps both in sync through merges and
+/// serialization. Times are Unix timestamps in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SketchActivity {
+    /// Total number of items added, including those added to merged sketches.
+    pub adds: u64,
+    /// When the sketch, or the oldest sketch merged into it, started tracking activity.
+    pub created: u64,
+    /// When an item was last added to the sketch or to any sketch merged into it.
+    pub updated: u64,
+}
+
+impl SketchActivity {
+    /// Starts tracking activity now, with no items added.
+    ///
+    /// # Returns
+    /// A new `SketchActivity` instance.
+    pub fn new() -> Self {
+        let now = now();
+
+        Self {
+            adds: 0,
+            created: now,
+            updated: now,
+        }
+    }
+
+    /// Records an added item. Reads the system clock, which is the main cost of tracking.
+    #[inline(always)]
+    pub(crate) fn record_add(&mut self) {
+        self.adds += 1;
+        self.updated = now();
+    }
+
+    /// Records a merge with a sketch carrying the `other` activity: adds are summed, the
+    /// creation time is the earliest and the update time the latest of both.
+    #[inline(always)]
+    pub(crate) fn record_merge(&mut self, other: &SketchActivity) {
+        self.adds += other.adds;
+        self.created = self.created.min(other.created);
+        self.updated = self.updated.max(other.updated);
+    }
+
+    /// Returns the fraction of added items that were distinct, e.g. for dedup-rate
+    /// dashboards.
+    ///
+    /// # Parameters
+    /// * `distinct`: The estimated number of distinct items, e.g. `estimate()`.
+    ///
+    /// # Returns
+    /// `distinct / adds` clamped to `[0, 1]`, as the estimate can slightly exceed the
+    /// exact count, or `None` when nothing was added yet.
+    pub fn distinct_ratio(&self, distinct: f64) -> Option<f64> {
+        if self.adds == 0 {
+            return None;
+        }
+
+        Some((distinct / self.adds as f64).clamp(0.0, 1.0))
+    }
+
+    /// Returns the average number of times each distinct item was added, the inverse of
+    /// `distinct_ratio`.
+    ///
+    /// # Parameters
+    /// * `distinct`: The estimated number of distinct items, e.g. `estimate()`.
+    ///
+    /// # Returns
+    /// `adds / distinct`, at least `1.0`, or `None` when nothing was added yet.
+    pub fn duplication_factor(&self, distinct: f64) -> Option<f64> {
+        if self.adds == 0 {
+            return None;
+        }
+
+        Some((self.adds as f64 / distinct.max(1.0)).max(1.0))
+    }
+}
+
+impl Default for SketchActivity {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the current time as a Unix timestamp in milliseconds, or zero if the system
+/// clock is set before 1970.
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ratios() {
+        let mut activity = SketchActivity::new();
+        assert_eq!(activity.distinct_ratio(0.0), None);
+        assert_eq!(activity.duplication_factor(0.0), None);
+
+        activity.adds = 400;
+        assert_eq!(activity.distinct_ratio(100.0), Some(0.25));
+        assert_eq!(activity.duplication_factor(100.0), Some(4.0));
+
+        // Estimates above the exact count are clamped
+        assert_eq!(activity.distinct_ratio(401.5), Some(1.0));
+        assert_eq!(activity.duplication_factor(401.5), Some(1.0));
+    }
+}
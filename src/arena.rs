@@ -0,0 +1,203 @@
+use crate::{HyperLogLog, M};
+
+/// Identifies a sketch allocated from an `HllArena`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct HllHandle(usize);
+
+impl HllHandle {
+    /// Returns the slot of the sketch within its arena.
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+/// Allocates the registers of many HyperLogLogs from one contiguous buffer, handing out
+/// handles instead of owned sketches.
+///
+/// Per-key aggregation servers holding thousands of sketches otherwise perform one large
+/// allocation per key, which stresses the allocator and fragments the heap as keys come
+/// and go. Freed slots are zeroed and reused by later allocations.
+///
+/// Sketches are accessed as `HyperLogLog`s borrowing their slot, so every sketch method is
+/// available. The `stats` and `validate` state of these borrowed sketches only lives as
+/// long as the borrow.
+#[derive(Debug, Clone, Default)]
+pub struct HllArena {
+    registers: Vec<u8>,
+    free: Vec<usize>,
+}
+
+impl HllArena {
+    /// Creates an empty arena.
+    ///
+    /// # Returns
+    /// A new `HllArena` instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an empty arena with room for `sketches` sketches before it reallocates.
+    ///
+    /// # Parameters
+    /// * `sketches`: The number of sketches to reserve room for.
+    ///
+    /// # Returns
+    /// A new `HllArena` instance.
+    pub fn with_capacity(sketches: usize) -> Self {
+        Self {
+            registers: Vec::with_capacity(sketches * M),
+            free: Vec::new(),
+        }
+    }
+
+    /// Allocates an empty sketch, reusing a freed slot when there is one.
+    ///
+    /// # Returns
+    /// The `HllHandle` of the new sketch.
+    pub fn alloc(&mut self) -> HllHandle {
+        match self.free.pop() {
+            Some(slot) => HllHandle(slot),
+            None => {
+                let slot = self.registers.len() / M;
+                self.registers.resize(self.registers.len() + M, 0);
+                HllHandle(slot)
+            }
+        }
+    }
+
+    /// Frees a sketch, clearing its registers so the slot can be reused. The handle must
+    /// not be used afterwards, since it would access whichever sketch reuses the slot.
+    ///
+    /// # Parameters
+    /// * `handle`: The handle of the sketch to free.
+    ///
+    /// # Panics
+    /// Panics if `handle` was not allocated from this arena.
+    pub fn free(&mut self, handle: HllHandle) {
+        self.slot_mut(handle).fill(0);
+        self.free.push(handle.0);
+    }
+
+    /// Borrows a sketch.
+    ///
+    /// # Parameters
+    /// * `handle`: The handle of the sketch.
+    ///
+    /// # Returns
+    /// A `HyperLogLog` reading the registers of the slot.
+    ///
+    /// # Panics
+    /// Panics if `handle` was not allocated from this arena.
+    pub fn get(&self, handle: HllHandle) -> HyperLogLog<&[u8; M]> {
+        let slot = &self.registers[handle.0 * M..(handle.0 + 1) * M];
+
+        HyperLogLog::with_registers(slot.try_into().expect("slots hold M registers"))
+    }
+
+    /// Borrows a sketch mutably, e.g. to add items to it or merge into it.
+    ///
+    /// # Parameters
+    /// * `handle`: The handle of the sketch.
+    ///
+    /// # Returns
+    /// A `HyperLogLog` updating the registers of the slot in place.
+    ///
+    /// # Panics
+    /// Panics if `handle` was not allocated from this arena.
+    pub fn get_mut(&mut self, handle: HllHandle) -> HyperLogLog<&mut [u8; M]> {
+        HyperLogLog::with_registers(self.slot_mut(handle))
+    }
+
+    fn slot_mut(&mut self, handle: HllHandle) -> &mut [u8; M] {
+        let slot = &mut self.registers[handle.0 * M..(handle.0 + 1) * M];
+
+        slot.try_into().expect("slots hold M registers")
+    }
+
+    /// Returns the number of sketches currently allocated.
+    pub fn len(&self) -> usize {
+        self.registers.len() / M - self.free.len()
+    }
+
+    /// Returns `true` if no sketch is currently allocated.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of sketches the arena can hold before it reallocates.
+    pub fn capacity(&self) -> usize {
+        self.registers.capacity() / M
+    }
+
+    /// Releases the memory of freed slots at the end of the arena and any capacity
+    /// reserved beyond the allocated sketches, e.g. after a burst of short-lived sketches.
+    /// Freed slots between allocated ones are kept for reuse, since moving sketches would
+    /// invalidate their handles.
+    pub fn compact(&mut self) {
+        let mut slots = self.registers.len() / M;
+
+        self.free.sort_unstable();
+        while slots > 0 && self.free.last() == Some(&(slots - 1)) {
+            self.free.pop();
+            slots -= 1;
+        }
+
+        self.registers.truncate(slots * M);
+        self.registers.shrink_to_fit();
+        self.free.shrink_to_fit();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alloc_and_reuse() {
+        let mut arena = HllArena::with_capacity(2);
+        let a = arena.alloc();
+        let b = arena.alloc();
+        assert_eq!(arena.len(), 2);
+        assert_eq!(arena.capacity(), 2);
+
+        let mut expected = HyperLogLog::new();
+        for i in 0..1_000 {
+            arena.get_mut(a).add(i);
+            expected.add(i);
+        }
+        assert_eq!(arena.get(a).estimate(), expected.estimate());
+        assert_eq!(arena.get(b).estimate(), 0.0);
+
+        arena.get_mut(b).merge(&expected);
+        assert_eq!(arena.get(b).estimate(), expected.estimate());
+
+        arena.free(b);
+        assert_eq!(arena.len(), 1);
+        let c = arena.alloc();
+        assert_eq!(c, b);
+        assert_eq!(arena.get(c).estimate(), 0.0);
+    }
+
+    #[test]
+    fn test_compact() {
+        let mut arena = HllArena::with_capacity(8);
+        let handles = (0..4).map(|_| arena.alloc()).collect::<Vec<_>>();
+        arena.get_mut(handles[0]).add("item");
+
+        arena.free(handles[1]);
+        arena.free(handles[3]);
+        arena.free(handles[2]);
+        arena.compact();
+        assert_eq!(arena.len(), 1);
+        assert_eq!(arena.capacity(), 1);
+        assert_eq!(arena.get(handles[0]).estimate().round(), 1.0);
+
+        // Freed slots in the middle are kept
+        let b = arena.alloc();
+        arena.alloc();
+        arena.free(b);
+        arena.compact();
+        assert_eq!(arena.capacity(), 3);
+        assert_eq!(arena.alloc(), b);
+    }
+}
@@ -0,0 +1,215 @@
+use std::sync::OnceLock;
+
+use crate::Error;
+
+mod tables;
+
+/// Number of table entries whose bias is averaged by `BiasTable::bias`, as in the
+/// HyperLogLog++ paper of Heule et al.
+pub const NEIGHBORS: usize = 6;
+
+/// Largest linear counting estimate HyperLogLog++ reports as its estimate at precision `P`.
+/// Above it, the bias corrected raw estimate is the more accurate one.
+pub const LINEAR_COUNTING_THRESHOLD: f64 = tables::LINEAR_COUNTING_THRESHOLD;
+
+/// Empirical bias of the raw HyperLogLog estimate, as a list of raw estimates and the
+/// average amount by which each overestimates the true cardinality.
+///
+/// HyperLogLog++ subtracts the bias from mid-range raw estimates, which are noticeably
+/// too large before the estimate converges, using the built-in table of `empirical`
+/// unless another one is given. Tables depend on the precision and the hash function, so
+/// deployments with unusual ones can calibrate their own (e.g. by filling sketches with
+/// known cardinalities and averaging `raw_estimate() - cardinality`, as
+/// `examples/calibrate_bias.rs` does) and load them with
+/// `HyperLogLogPlusPlus::with_bias_table`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BiasTable {
+    raw_estimates: Vec<f64>,
+    biases: Vec<f64>,
+}
+
+impl BiasTable {
+    /// Creates a table from matching lists of raw estimates and biases.
+    ///
+    /// # Parameters
+    /// * `raw_estimates`: The raw estimates, in increasing order.
+    /// * `biases`: The bias measured at each raw estimate.
+    ///
+    /// # Returns
+    /// The `BiasTable`, or an `Error::InvalidBiasTable` if the lists are empty, of
+    /// different lengths, not finite or not sorted.
+    pub fn new(raw_estimates: Vec<f64>, biases: Vec<f64>) -> Result<Self, Error> {
+        let invalid = |msg: &str| Err(Error::InvalidBiasTable(msg.to_string()));
+
+        if raw_estimates.is_empty() {
+            return invalid("no entries");
+        }
+        if raw_estimates.len() != biases.len() {
+            return invalid("as many raw estimates as biases are needed");
+        }
+        if raw_estimates.iter().chain(&biases).any(|v| !v.is_finite()) {
+            return invalid("entries must be finite");
+        }
+        if raw_estimates.windows(2).any(|w| w[0] > w[1]) {
+            return invalid("raw estimates must be in increasing order");
+        }
+
+        Ok(Self {
+            raw_estimates,
+            biases,
+        })
+    }
+
+    /// Returns the built-in table for precision `P`, measured by `examples/calibrate_bias.rs`
+    /// with uniformly distributed 64-bit hashes at 200 cardinalities up to `5.2 * M`, as
+    /// described by Heule et al.
+    ///
+    /// These are not the bias tables published by Heule et al., which are not bundled, so
+    /// mid-range estimates can differ slightly from those of implementations using them.
+    /// To match such an implementation, load its table with `parse` and
+    /// `HyperLogLogPlusPlus::with_bias_table`.
+    ///
+    /// # Returns
+    /// The table, built on first use and shared by all sketches.
+    pub fn empirical() -> &'static BiasTable {
+        static TABLE: OnceLock<BiasTable> = OnceLock::new();
+
+        TABLE.get_or_init(|| {
+            Self::new(tables::RAW_ESTIMATES.to_vec(), tables::BIASES.to_vec())
+                .expect("the built-in bias table is valid")
+        })
+    }
+
+    /// Parses a table written as one `raw_estimate bias` pair per line, separated by
+    /// whitespace or a comma. Blank lines and lines starting with `#` are skipped.
+    ///
+    /// # Parameters
+    /// * `text`: The table.
+    ///
+    /// # Returns
+    /// The `BiasTable`, or an `Error::InvalidBiasTable` naming the first bad line.
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut raw_estimates = Vec::new();
+        let mut biases = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|field| !field.is_empty())
+                .map(str::parse::<f64>);
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some(Ok(raw)), Some(Ok(bias)), None) => {
+                    raw_estimates.push(raw);
+                    biases.push(bias);
+                }
+                _ => {
+                    return Err(Error::InvalidBiasTable(format!(
+                        "line {} is not a raw estimate and bias pair",
+                        number + 1
+                    )))
+                }
+            }
+        }
+
+        Self::new(raw_estimates, biases)
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.raw_estimates.len()
+    }
+
+    /// Returns `true` if the table has no entries, which `new` never allows.
+    pub fn is_empty(&self) -> bool {
+        self.raw_estimates.is_empty()
+    }
+
+    /// Returns the largest raw estimate of the table.
+    pub fn max_raw_estimate(&self) -> f64 {
+        self.raw_estimates[self.len() - 1]
+    }
+
+    /// Interpolates the bias at a raw estimate by averaging the biases of the `NEIGHBORS`
+    /// entries whose raw estimates are closest to it.
+    ///
+    /// # Parameters
+    /// * `raw`: The raw estimate.
+    ///
+    /// # Returns
+    /// The estimated bias, to be subtracted from `raw`.
+    pub fn bias(&self, raw: f64) -> f64 {
+        let k = NEIGHBORS.min(self.len());
+        let (mut lo, mut hi) = {
+            let i = self.raw_estimates.partition_point(|&e| e < raw);
+            (i, i)
+        };
+
+        while hi - lo < k {
+            let take_lo = match (lo.checked_sub(1), self.raw_estimates.get(hi)) {
+                (Some(below), Some(&above)) => raw - self.raw_estimates[below] <= above - raw,
+                (Some(_), None) => true,
+                (None, _) => false,
+            };
+            match take_lo {
+                true => lo -= 1,
+                false => hi += 1,
+            }
+        }
+
+        self.biases[lo..hi].iter().sum::<f64>() / k as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bias() {
+        let table = BiasTable::new(
+            (0..10).map(|i| i as f64 * 10.0).collect(),
+            (0..10).map(|i| i as f64).collect(),
+        )
+        .unwrap();
+
+        // Neighbors 20, 30, 40, 50, 60 and 70
+        assert_eq!(table.bias(44.0), 4.5);
+        assert_eq!(table.bias(-5.0), 2.5);
+        assert_eq!(table.bias(1e9), 6.5);
+        assert_eq!(table.max_raw_estimate(), 90.0);
+    }
+
+    #[test]
+    fn test_empirical() {
+        let table = BiasTable::empirical();
+        assert_eq!(table.len(), 200);
+        assert!(table.max_raw_estimate() > 5.0 * crate::M as f64);
+
+        // An empty sketch has a raw estimate of ALPHA * M, all of it bias
+        let raw = crate::ALPHA * crate::M as f64;
+        assert!((table.raw_estimates[0] - raw).abs() < 1.0);
+        assert_eq!(table.raw_estimates[0], table.biases[0]);
+        // and from five times the number of registers on, almost none of it is
+        let raw = 5.0 * crate::M as f64;
+        assert!(table.bias(raw).abs() / raw < 0.001);
+    }
+
+    #[test]
+    fn test_parse() {
+        let table = BiasTable::parse("# raw, bias\n10, 5\n\n20 4.5\n30,4\n").unwrap();
+        assert_eq!(
+            table,
+            BiasTable::new(vec![10.0, 20.0, 30.0], vec![5.0, 4.5, 4.0]).unwrap()
+        );
+        assert_eq!(table.bias(12.0), 4.5);
+
+        assert!(BiasTable::parse("10 5\n20\n").is_err());
+        assert!(BiasTable::parse("20 5\n10 4\n").is_err());
+        assert!(BiasTable::parse("").is_err());
+    }
+}
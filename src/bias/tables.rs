@@ -0,0 +1,221 @@
+//! Built-in bias tables of HyperLogLog++, one per precision the crate can be built with.
+//!
+//! The tables were produced by `examples/calibrate_bias.rs`, which follows the procedure
+//! of Heule et al., with 5,000 runs at precisions 12, 14 and 16 and 1,000 at precision 20:
+//!
+//! ```sh
+//! cargo run --release --features p12 --example calibrate_bias -- 5000
+//! cargo run --release --example calibrate_bias -- 1000
+//! ```
+//!
+//! Only the linear counting thresholds of precisions 12, 14 and 16 are the ones published
+//! by Heule et al. Their published bias tables are not included, and they publish neither
+//! a table nor a threshold for precision 20, whose threshold is the one measured by the
+//! calibration.
+
+/// Largest linear counting estimate used as the estimate at precision 12, as published
+/// by Heule et al.
+#[cfg(feature = "p12")]
+pub(super) const LINEAR_COUNTING_THRESHOLD: f64 = 3_100.0;
+/// Mean raw estimates at precision 12, in increasing order.
+#[cfg(feature = "p12")]
+pub(super) const RAW_ESTIMATES: [f64; 200] = [
+    2953.7, 3005.4, 3057.9, 3110.9, 3164.6, 3218.9, 3273.8, 3329.5, 3385.7, 3442.6, 3500.1, 3558.4,
+    3617.3, 3676.7, 3737.0, 3797.7, 3859.1, 3921.2, 3983.9, 4047.3, 4111.3, 4176.0, 4241.3, 4307.1,
+    4373.5, 4440.7, 4508.3, 4576.5, 4645.6, 4715.3, 4785.4, 4856.2, 4927.7, 4999.7, 5073.0, 5146.1,
+    5219.8, 5294.3, 5369.1, 5444.7, 5520.7, 5597.4, 5674.7, 5752.6, 5830.8, 5909.5, 5989.1, 6068.9,
+    6149.3, 6230.1, 6311.5, 6393.4, 6476.3, 6559.0, 6642.7, 6726.7, 6811.4, 6896.5, 6981.6, 7067.5,
+    7154.0, 7241.0, 7328.1, 7415.7, 7503.9, 7592.5, 7681.3, 7772.0, 7861.6, 7951.8, 8042.5, 8133.5,
+    8224.6, 8316.3, 8408.2, 8501.1, 8594.0, 8687.0, 8780.9, 8874.8, 8968.8, 9063.9, 9158.7, 9253.9,
+    9349.0, 9444.9, 9540.8, 9637.3, 9734.1, 9831.1, 9928.4, 10025.8, 10123.5, 10221.1, 10319.4,
+    10418.2, 10517.1, 10616.0, 10715.6, 10815.3, 10915.6, 11015.4, 11115.6, 11215.9, 11316.0,
+    11416.6, 11517.4, 11619.1, 11719.9, 11821.7, 11923.2, 12025.1, 12127.2, 12229.6, 12331.8,
+    12434.3, 12537.7, 12640.0, 12742.8, 12845.8, 12949.0, 13052.0, 13155.6, 13258.9, 13362.5,
+    13465.8, 13568.6, 13671.9, 13776.0, 13880.0, 13984.4, 14088.6, 14193.2, 14298.5, 14402.9,
+    14507.4, 14612.1, 14716.6, 14820.7, 14925.3, 15030.3, 15135.9, 15241.2, 15346.7, 15451.9,
+    15557.6, 15663.2, 15768.7, 15874.6, 15980.8, 16086.2, 16192.3, 16298.4, 16404.2, 16509.9,
+    16615.2, 16721.3, 16827.1, 16933.1, 17038.8, 17144.9, 17250.6, 17356.4, 17461.5, 17567.8,
+    17673.8, 17781.3, 17887.8, 17993.8, 18100.2, 18206.8, 18312.8, 18419.1, 18525.5, 18632.2,
+    18738.3, 18845.8, 18952.8, 19058.9, 19165.8, 19271.7, 19377.5, 19483.9, 19590.5, 19696.2,
+    19803.1, 19910.5, 20017.3, 20124.0, 20230.7, 20337.2, 20443.4, 20550.2, 20656.6, 20762.4,
+    20869.3, 20976.1, 21082.8, 21189.6, 21296.6,
+];
+/// Mean bias of the raw estimates of `RAW_ESTIMATES` at precision 12.
+#[cfg(feature = "p12")]
+pub(super) const BIASES: [f64; 200] = [
+    2953.7, 2898.4, 2843.9, 2789.9, 2736.6, 2683.9, 2631.8, 2580.5, 2529.7, 2479.6, 2430.1, 2381.4,
+    2333.3, 2285.7, 2239.0, 2192.7, 2147.1, 2102.2, 2057.9, 2014.3, 1971.3, 1929.0, 1887.3, 1846.1,
+    1805.5, 1765.7, 1726.3, 1687.5, 1649.6, 1612.3, 1575.4, 1539.2, 1503.7, 1468.7, 1434.0, 1400.1,
+    1366.8, 1334.3, 1302.1, 1270.7, 1239.7, 1209.4, 1179.7, 1150.6, 1121.8, 1093.5, 1066.1, 1038.9,
+    1012.3, 986.1, 960.5, 935.4, 911.3, 887.0, 863.7, 840.7, 818.4, 796.5, 774.6, 753.5, 733.0,
+    713.0, 693.1, 673.7, 654.9, 636.5, 618.3, 601.0, 583.6, 566.8, 550.5, 534.5, 518.6, 503.3,
+    488.2, 474.1, 460.0, 446.0, 432.9, 419.8, 406.8, 394.9, 382.7, 370.9, 359.0, 347.9, 336.8,
+    326.3, 316.1, 306.1, 296.4, 286.8, 277.5, 268.1, 259.4, 251.2, 243.1, 235.0, 227.6, 220.3,
+    212.6, 205.4, 198.6, 191.9, 185.0, 178.6, 172.4, 167.1, 160.9, 155.7, 150.2, 145.1, 140.2,
+    135.6, 130.8, 126.3, 122.7, 118.0, 113.8, 109.8, 106.0, 102.0, 98.6, 94.9, 91.5, 87.8, 83.6,
+    79.9, 77.0, 74.0, 71.4, 68.6, 66.2, 63.5, 60.9, 58.4, 56.1, 53.6, 50.7, 48.3, 46.3, 44.9, 43.2,
+    41.7, 39.9, 38.6, 37.2, 35.7, 34.6, 33.8, 32.2, 31.3, 30.4, 29.2, 27.9, 26.2, 25.3, 24.1, 23.1,
+    21.8, 20.9, 19.6, 18.4, 16.5, 15.8, 14.8, 14.3, 13.8, 12.8, 12.2, 11.8, 10.8, 10.1, 9.5, 9.2,
+    8.3, 8.8, 8.8, 7.9, 7.8, 6.7, 5.5, 4.9, 4.5, 3.2, 3.1, 3.5, 3.3, 3.0, 2.7, 2.2, 1.4, 1.2, 0.6,
+    -0.6, -0.7, -0.9, -1.2, -1.4, -2.4,
+];
+
+/// Largest linear counting estimate used as the estimate at precision 14, as published
+/// by Heule et al.
+#[cfg(feature = "p14")]
+pub(super) const LINEAR_COUNTING_THRESHOLD: f64 = 11_500.0;
+/// Mean raw estimates at precision 14, in increasing order.
+#[cfg(feature = "p14")]
+pub(super) const RAW_ESTIMATES: [f64; 200] = [
+    11817.0, 12024.1, 12233.7, 12445.9, 12660.6, 12878.1, 13097.9, 13320.4, 13545.6, 13773.9,
+    14004.1, 14237.0, 14472.5, 14710.6, 14951.0, 15194.1, 15439.9, 15688.8, 15939.7, 16193.2,
+    16449.2, 16707.7, 16969.0, 17232.6, 17498.6, 17767.8, 18038.6, 18312.2, 18588.1, 18866.7,
+    19147.5, 19431.2, 19717.2, 20005.0, 20296.2, 20589.5, 20884.6, 21182.2, 21481.6, 21784.2,
+    22088.7, 22395.6, 22705.4, 23016.3, 23329.9, 23645.2, 23963.0, 24282.5, 24605.1, 24929.2,
+    25255.5, 25584.1, 25914.2, 26246.7, 26580.7, 26916.8, 27254.6, 27594.5, 27936.7, 28280.7,
+    28626.9, 28974.5, 29323.5, 29674.3, 30026.9, 30381.3, 30737.5, 31096.9, 31456.0, 31816.8,
+    32179.0, 32543.1, 32908.7, 33276.6, 33644.3, 34016.3, 34387.4, 34760.3, 35134.5, 35510.0,
+    35886.6, 36264.8, 36644.7, 37026.9, 37408.2, 37791.3, 38175.7, 38560.6, 38947.5, 39334.8,
+    39724.1, 40113.9, 40506.0, 40897.8, 41290.9, 41685.0, 42079.5, 42475.4, 42872.2, 43270.7,
+    43669.9, 44069.5, 44470.8, 44872.6, 45273.2, 45676.7, 46080.4, 46484.7, 46890.7, 47296.7,
+    47704.3, 48110.7, 48519.1, 48926.9, 49335.3, 49744.9, 50155.7, 50566.7, 50978.4, 51390.3,
+    51803.2, 52215.6, 52627.6, 53041.3, 53456.0, 53870.4, 54286.6, 54702.8, 55119.0, 55534.6,
+    55950.8, 56367.9, 56785.5, 57204.0, 57623.0, 58040.6, 58459.7, 58878.2, 59296.5, 59716.0,
+    60137.6, 60558.2, 60978.2, 61398.9, 61821.1, 62243.1, 62665.2, 63088.0, 63511.2, 63933.0,
+    64356.6, 64779.6, 65201.3, 65623.5, 66047.2, 66472.7, 66895.6, 67318.3, 67742.6, 68165.6,
+    68588.4, 69012.4, 69435.8, 69859.5, 70283.7, 70708.1, 71133.5, 71558.7, 71982.6, 72407.4,
+    72832.3, 73257.4, 73681.1, 74107.6, 74533.8, 74961.8, 75388.3, 75812.8, 76236.2, 76660.8,
+    77087.9, 77514.8, 77940.7, 78366.8, 78792.3, 79218.3, 79644.0, 80068.6, 80494.3, 80921.1,
+    81347.4, 81775.3, 82200.8, 82628.1, 83055.0, 83481.5, 83907.9, 84335.7, 84762.3, 85190.8,
+];
+/// Mean bias of the raw estimates of `RAW_ESTIMATES` at precision 14.
+#[cfg(feature = "p14")]
+pub(super) const BIASES: [f64; 200] = [
+    11817.0, 11596.1, 11377.7, 11161.9, 10948.6, 10738.1, 10529.9, 10324.4, 10121.6, 9920.9,
+    9723.1, 9528.0, 9335.5, 9145.6, 8958.0, 8773.1, 8590.9, 8410.8, 8233.7, 8059.2, 7887.2, 7717.7,
+    7551.0, 7386.6, 7224.6, 7064.8, 6907.6, 6753.2, 6601.1, 6451.7, 6304.5, 6160.2, 6018.2, 5878.0,
+    5740.2, 5605.5, 5472.6, 5342.2, 5213.6, 5088.2, 4964.7, 4843.6, 4724.4, 4607.3, 4492.9, 4380.2,
+    4270.0, 4161.5, 4056.1, 3952.2, 3849.5, 3750.1, 3652.2, 3556.7, 3462.7, 3370.8, 3280.6, 3192.5,
+    3106.7, 3021.7, 2939.9, 2859.5, 2780.5, 2703.3, 2627.9, 2554.3, 2482.5, 2412.9, 2344.0, 2276.8,
+    2211.0, 2147.1, 2084.7, 2024.6, 1964.3, 1907.3, 1850.4, 1795.3, 1741.5, 1689.0, 1637.6, 1587.8,
+    1539.7, 1492.9, 1446.2, 1401.3, 1357.7, 1314.6, 1273.5, 1232.8, 1194.1, 1155.9, 1119.0, 1082.8,
+    1047.9, 1014.0, 980.5, 948.4, 917.2, 887.7, 857.9, 829.5, 802.8, 776.6, 749.2, 724.7, 700.4,
+    676.7, 653.7, 631.7, 611.3, 589.7, 570.1, 549.9, 530.3, 511.9, 494.7, 476.7, 460.4, 444.3,
+    429.2, 413.6, 397.6, 383.3, 370.0, 355.4, 343.6, 331.8, 320.0, 307.6, 295.8, 284.9, 274.5,
+    264.0, 255.0, 244.6, 235.7, 226.2, 216.5, 208.0, 201.6, 193.2, 185.2, 177.9, 172.1, 166.1,
+    160.2, 155.0, 150.2, 144.0, 138.6, 133.6, 127.3, 121.5, 117.2, 114.7, 109.6, 104.3, 99.6, 94.6,
+    89.4, 85.4, 80.8, 76.5, 72.7, 69.1, 65.5, 62.7, 58.6, 55.4, 52.3, 49.4, 45.1, 43.6, 41.8, 40.8,
+    39.3, 35.8, 31.2, 27.8, 26.9, 25.8, 23.7, 20.8, 18.3, 16.3, 14.0, 10.6, 8.3, 7.1, 5.4, 4.3,
+    1.8, 1.1, -0.0, -1.5, -3.1, -3.3, -4.7, -5.2,
+];
+
+/// Largest linear counting estimate used as the estimate at precision 16, as published
+/// by Heule et al.
+#[cfg(feature = "p16")]
+pub(super) const LINEAR_COUNTING_THRESHOLD: f64 = 50_000.0;
+/// Mean raw estimates at precision 16, in increasing order.
+#[cfg(feature = "p16")]
+pub(super) const RAW_ESTIMATES: [f64; 200] = [
+    47270.3, 48098.7, 48937.2, 49786.5, 50645.9, 51515.9, 52395.8, 53286.6, 54187.0, 55098.7,
+    56020.0, 56952.0, 57894.0, 58846.8, 59809.4, 60782.9, 61765.8, 62759.8, 63763.1, 64777.6,
+    65801.7, 66836.5, 67880.1, 68935.1, 69999.3, 71073.9, 72157.6, 73251.7, 74355.6, 75469.6,
+    76593.0, 77727.0, 78870.5, 80024.3, 81186.0, 82358.4, 83539.4, 84730.2, 85929.3, 87138.9,
+    88357.1, 89584.7, 90820.4, 92065.5, 93318.5, 94580.9, 95851.6, 97132.3, 98419.6, 99717.4,
+    101021.3, 102334.7, 103654.8, 104985.1, 106322.4, 107667.5, 109018.7, 110379.5, 111746.5,
+    113122.3, 114504.9, 115895.6, 117292.5, 118696.6, 120107.7, 121526.6, 122949.9, 124381.3,
+    125818.4, 127263.6, 128713.6, 130170.9, 131633.5, 133103.6, 134578.6, 136059.2, 137545.8,
+    139038.1, 140534.4, 142035.6, 143542.5, 145057.1, 146576.0, 148101.2, 149629.7, 151164.0,
+    152701.5, 154245.5, 155792.1, 157343.8, 158899.1, 160459.0, 162022.9, 163591.4, 165165.4,
+    166743.1, 168324.6, 169909.2, 171495.8, 173088.0, 174683.1, 176283.5, 177884.9, 179488.6,
+    181096.8, 182709.0, 184322.9, 185941.6, 187560.1, 189183.9, 190809.1, 192437.9, 194069.0,
+    195701.3, 197334.5, 198973.6, 200615.4, 202256.7, 203900.5, 205549.7, 207200.5, 208850.2,
+    210504.4, 212161.1, 213816.3, 215476.5, 217136.2, 218798.0, 220460.5, 222126.5, 223793.4,
+    225463.6, 227131.8, 228801.4, 230472.9, 232148.3, 233823.7, 235499.3, 237177.7, 238858.0,
+    240541.2, 242225.0, 243906.9, 245589.0, 247274.0, 248960.2, 250646.4, 252333.1, 254023.8,
+    255714.1, 257406.9, 259099.2, 260793.1, 262488.4, 264181.3, 265874.9, 267567.7, 269266.8,
+    270966.2, 272664.7, 274363.4, 276061.2, 277759.1, 279457.5, 281158.2, 282858.4, 284556.2,
+    286260.2, 287963.4, 289666.4, 291369.1, 293070.5, 294772.4, 296475.3, 298182.9, 299887.1,
+    301594.3, 303298.6, 305002.5, 306707.4, 308413.0, 310121.3, 311825.8, 313532.3, 315237.0,
+    316939.9, 318645.1, 320352.3, 322060.7, 323769.9, 325475.4, 327186.4, 328892.7, 330602.3,
+    332311.2, 334019.4, 335725.3, 337436.5, 339147.8, 340854.9,
+];
+/// Mean bias of the raw estimates of `RAW_ESTIMATES` at precision 16.
+#[cfg(feature = "p16")]
+pub(super) const BIASES: [f64; 200] = [
+    47270.3, 46386.7, 45513.2, 44649.5, 43796.9, 42953.9, 42121.8, 41299.6, 40488.0, 39686.7,
+    38896.0, 38115.0, 37345.0, 36584.8, 35835.4, 35095.9, 34366.8, 33647.8, 32939.1, 32240.6,
+    31552.7, 30874.5, 30206.1, 29548.1, 28900.3, 28261.9, 27633.6, 27014.7, 26406.6, 25807.6,
+    25219.0, 24640.0, 24071.5, 23512.3, 22962.0, 22421.4, 21890.4, 21368.2, 20855.3, 20351.9,
+    19858.1, 19372.7, 18896.4, 18428.5, 17969.5, 17518.9, 17077.6, 16645.3, 16220.6, 15805.4,
+    15397.3, 14997.7, 14605.8, 14223.1, 13848.4, 13480.5, 13119.7, 12767.5, 12422.5, 12085.3,
+    11755.9, 11433.6, 11118.5, 10809.6, 10508.7, 10214.6, 9925.9, 9644.3, 9369.4, 9101.6, 8839.6,
+    8583.9, 8334.5, 8091.6, 7854.6, 7622.2, 7396.8, 7176.1, 6960.4, 6748.6, 6543.5, 6345.1, 6152.0,
+    5964.2, 5780.7, 5602.0, 5427.5, 5258.5, 5093.1, 4931.8, 4775.1, 4622.0, 4473.9, 4329.4, 4191.4,
+    4056.1, 3925.6, 3797.2, 3671.8, 3551.0, 3434.1, 3321.5, 3210.9, 3101.6, 2997.8, 2897.0, 2798.9,
+    2704.6, 2611.1, 2521.9, 2435.1, 2350.9, 2270.0, 2189.3, 2110.5, 2036.6, 1966.4, 1894.7, 1826.5,
+    1762.7, 1701.5, 1638.2, 1580.4, 1524.1, 1467.3, 1414.5, 1362.2, 1311.0, 1261.5, 1214.5, 1169.4,
+    1126.6, 1082.8, 1039.4, 998.9, 961.3, 924.7, 887.3, 853.7, 821.0, 792.2, 763.0, 732.9, 702.0,
+    675.0, 648.2, 622.4, 596.1, 574.8, 552.1, 532.9, 512.2, 494.1, 476.4, 457.3, 437.9, 418.7,
+    404.8, 392.2, 377.7, 364.4, 349.2, 335.1, 320.5, 309.2, 296.4, 282.2, 273.2, 264.4, 254.4,
+    245.1, 233.5, 223.4, 213.3, 208.9, 200.1, 195.3, 186.6, 178.5, 170.4, 164.0, 159.3, 151.8,
+    145.3, 138.0, 127.9, 121.1, 115.3, 111.7, 107.9, 101.4, 99.4, 93.7, 90.3, 87.2, 82.4, 76.3,
+    74.5, 73.8, 67.9,
+];
+
+/// Largest linear counting estimate used as the estimate at precision 20, as measured by
+/// `examples/calibrate_bias.rs`.
+#[cfg(not(any(feature = "p12", feature = "p14", feature = "p16")))]
+pub(super) const LINEAR_COUNTING_THRESHOLD: f64 = 2_602_998.0;
+/// Mean raw estimates at precision 20, in increasing order.
+#[cfg(not(any(feature = "p12", feature = "p14", feature = "p16")))]
+pub(super) const RAW_ESTIMATES: [f64; 200] = [
+    756337.1, 769594.9, 783017.8, 796603.2, 810354.2, 824270.9, 838351.9, 852599.3, 867012.9,
+    881587.9, 896335.0, 911243.7, 926317.9, 941557.3, 956957.7, 972527.0, 988261.0, 1004155.6,
+    1020216.2, 1036440.0, 1052823.3, 1069373.9, 1086082.3, 1102957.3, 1119988.4, 1137187.3,
+    1154540.5, 1172053.4, 1189722.2, 1207548.5, 1225525.3, 1243667.3, 1261957.8, 1280404.4,
+    1299000.6, 1317746.5, 1336644.7, 1355697.8, 1374899.8, 1394243.9, 1413731.2, 1433368.6,
+    1453152.1, 1473073.8, 1493140.4, 1513334.7, 1533675.6, 1554151.5, 1574763.2, 1595502.0,
+    1616386.3, 1637396.3, 1658521.5, 1679789.5, 1701188.5, 1722702.2, 1744336.5, 1766099.2,
+    1787977.7, 1809976.7, 1832100.3, 1854332.0, 1876699.3, 1899169.3, 1921747.5, 1944422.9,
+    1967229.5, 1990132.1, 2013141.4, 2036236.0, 2059453.2, 2082758.2, 2106152.6, 2129655.2,
+    2153256.1, 2176944.8, 2200724.9, 2224601.3, 2248565.8, 2272608.5, 2296741.6, 2320976.8,
+    2345272.9, 2369657.4, 2394124.0, 2418654.1, 2443260.4, 2467947.1, 2492704.4, 2517526.3,
+    2542425.0, 2567374.5, 2592413.6, 2617531.8, 2642708.4, 2667934.1, 2693221.6, 2718555.2,
+    2743952.4, 2769421.2, 2794942.8, 2820516.3, 2846158.6, 2871838.1, 2897570.9, 2923325.3,
+    2949159.4, 2975037.3, 3000951.9, 3026917.9, 3052938.5, 3078996.3, 3105094.0, 3131227.5,
+    3157387.2, 3183600.4, 3209863.1, 3236148.0, 3262487.3, 3288846.1, 3315229.3, 3341642.8,
+    3368099.7, 3394602.5, 3421113.9, 3447640.6, 3474217.8, 3500829.6, 3527460.6, 3554105.2,
+    3580790.2, 3607501.7, 3634233.2, 3660978.0, 3687756.4, 3714531.5, 3741366.8, 3768199.5,
+    3795051.2, 3821916.2, 3848818.9, 3875723.4, 3902657.8, 3929622.9, 3956598.1, 3983626.9,
+    4010642.9, 4037668.3, 4064697.6, 4091720.3, 4118784.0, 4145831.4, 4172876.1, 4199975.6,
+    4227060.9, 4254161.3, 4281290.7, 4308434.1, 4335563.3, 4362705.5, 4389871.3, 4417028.5,
+    4444207.4, 4471387.9, 4498592.8, 4525777.2, 4553003.4, 4580224.3, 4607442.3, 4634682.2,
+    4661926.0, 4689167.7, 4716410.2, 4743675.5, 4770920.5, 4798196.4, 4825474.6, 4852748.6,
+    4880035.1, 4907298.0, 4934590.1, 4961857.6, 4989159.0, 5016461.4, 5043775.6, 5071080.1,
+    5098367.9, 5125694.6, 5153043.5, 5180369.0, 5207691.3, 5235020.6, 5262370.5, 5289721.1,
+    5317031.7, 5344376.1, 5371714.7, 5399047.1, 5426383.0, 5453737.4,
+];
+/// Mean bias of the raw estimates of `RAW_ESTIMATES` at precision 20.
+#[cfg(not(any(feature = "p12", feature = "p14", feature = "p16")))]
+pub(super) const BIASES: [f64; 200] = [
+    756337.1, 742195.9, 728218.8, 714404.2, 700755.2, 687271.9, 673952.9, 660800.3, 647813.9,
+    634988.9, 622336.0, 609844.7, 597518.9, 585358.3, 573358.7, 561528.0, 549862.0, 538356.6,
+    527017.2, 515841.0, 504824.3, 493974.9, 483283.3, 472758.3, 462389.4, 452188.3, 442141.5,
+    432254.4, 422523.2, 412949.5, 403526.3, 394268.3, 385158.8, 376205.4, 367401.6, 358747.5,
+    350245.7, 341898.8, 333700.8, 325644.9, 317733.2, 309970.6, 302354.1, 294875.8, 287542.4,
+    280336.7, 273277.6, 266353.5, 259565.2, 252904.0, 246388.3, 239998.3, 233723.5, 227591.5,
+    221590.5, 215704.2, 209938.5, 204301.2, 198779.7, 193378.7, 188102.3, 182934.0, 177901.3,
+    172971.3, 168149.5, 163424.9, 158831.5, 154334.1, 149943.4, 145638.0, 141455.2, 137360.2,
+    133354.6, 129457.2, 125658.1, 121946.8, 118326.9, 114803.3, 111367.8, 108010.5, 104744.6,
+    101579.8, 98475.9, 95460.4, 92527.0, 89657.1, 86863.4, 84150.1, 81507.4, 78929.3, 76428.0,
+    73977.5, 71616.6, 69334.8, 67111.4, 64937.1, 62824.6, 60758.2, 58755.4, 56824.2, 54945.8,
+    53119.3, 51361.6, 49641.1, 47973.9, 46328.3, 44762.4, 43240.3, 41754.9, 40320.9, 38941.5,
+    37599.3, 36297.0, 35030.5, 33790.2, 32603.4, 31466.1, 30351.0, 29290.3, 28249.1, 27233.3,
+    26246.8, 25303.7, 24406.5, 23517.9, 22644.6, 21821.8, 21033.6, 20264.6, 19509.2, 18794.2,
+    18105.7, 17437.2, 16782.0, 16160.4, 15535.5, 14970.8, 14403.5, 13855.2, 13320.2, 12822.9,
+    12327.4, 11861.8, 11426.9, 11002.1, 10630.9, 10246.9, 9872.3, 9501.6, 9124.3, 8788.0, 8435.4,
+    8080.1, 7779.6, 7464.9, 7165.3, 6894.7, 6638.1, 6367.3, 6109.5, 5876.3, 5633.5, 5412.4, 5192.9,
+    4997.8, 4782.2, 4608.4, 4429.3, 4247.3, 4087.2, 3931.0, 3772.7, 3615.2, 3480.5, 3325.5, 3201.4,
+    3079.6, 2953.6, 2840.1, 2703.0, 2595.1, 2462.6, 2364.0, 2266.4, 2180.6, 2085.1, 1972.9, 1899.6,
+    1848.5, 1774.0, 1696.3, 1625.6, 1575.5, 1526.1, 1436.7, 1381.1, 1319.7, 1252.1, 1188.0, 1142.4,
+];
@@ -0,0 +1,276 @@
+use crate::{
+    hash::DEFAULT_SEAHASH_KEY,
+    metadata::{Algorithm, HasherId, SketchMetadata},
+    registers::Registers,
+    DecodeError, Error,
+};
+
+/// Leading bytes of every sketch written by `serialize_into`.
+const MAGIC: &[u8; 4] = b"HLLB";
+
+/// Latest version of the layout written by `serialize_into`. Version 2 appends the SeaHash
+/// key to the seed, and is only written for sketches whose key was set with `with_seed`,
+/// so that readers only knowing version 1 keep accepting all other sketches.
+pub const FORMAT_VERSION: u8 = 2;
+
+/// Returns the version of the layout `serialize_into` writes a sketch in.
+fn version(metadata: &SketchMetadata) -> u8 {
+    match metadata.key == DEFAULT_SEAHASH_KEY {
+        true => 1,
+        false => 2,
+    }
+}
+
+/// Returns the exact number of bytes `serialize_into` appends for a sketch.
+///
+/// # Parameters
+/// * `registers`: The registers of the sketch.
+/// * `metadata`: The metadata of the sketch.
+pub(crate) fn serialized_size<R: Registers + ?Sized>(
+    registers: &R,
+    metadata: &SketchMetadata,
+) -> usize {
+    header_len(metadata) + registers.len()
+}
+
+/// Appends a sketch to `buf` in the binary layout:
+///
+/// ```text
+/// "HLLB" | version: u8 | precision: u8
+///        | algorithm name length: u8 | algorithm name
+///        | hasher name length: u8 | hasher name
+///        | seed: u64 little-endian | key: 4 x u64 little-endian (version 2 only)
+///        | registers, one byte each
+/// ```
+///
+/// Nothing is allocated besides growing `buf`, which is reserved to the final size up
+/// front, so a buffer reused across calls is only grown once.
+///
+/// Labels are not part of the layout, only serde preserves them.
+///
+/// # Parameters
+/// * `registers`: The registers of the sketch.
+/// * `metadata`: The metadata of the sketch.
+/// * `buf`: The buffer the sketch is appended to.
+pub(crate) fn serialize_into<R: Registers + ?Sized>(
+    registers: &R,
+    metadata: &SketchMetadata,
+    buf: &mut Vec<u8>,
+) {
+    buf.reserve(serialized_size(registers, metadata));
+
+    buf.extend_from_slice(MAGIC);
+    buf.push(version(metadata));
+    buf.push(metadata.precision);
+    for name in [metadata.algorithm.name(), metadata.hasher.name()] {
+        buf.push(name.len() as u8);
+        buf.extend_from_slice(name.as_bytes());
+    }
+    buf.extend_from_slice(&metadata.seed.to_le_bytes());
+    if version(metadata) > 1 {
+        for word in metadata.key {
+            buf.extend_from_slice(&word.to_le_bytes());
+        }
+    }
+    registers.for_each_chunk(|chunk| buf.extend_from_slice(chunk));
+}
+
+/// Splits a sketch written by `serialize_into` into its metadata and registers.
+///
+/// The metadata must match `expected`, except for the hasher, seed and key, which are
+/// taken from `bytes`. The registers themselves are not validated.
+///
+/// # Parameters
+/// * `bytes`: The serialized sketch.
+/// * `expected`: The metadata of the sketch being deserialized.
+///
+/// # Returns
+/// The registers and metadata, or an `Error` if `bytes` is not a sketch of the expected
+/// kind.
+pub(crate) fn deserialize(
+    bytes: &[u8],
+    expected: SketchMetadata,
+) -> Result<(&[u8], SketchMetadata), Error> {
+    let mut reader = Reader(bytes);
+
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(DecodeError::Malformed("not a binary sketch".to_string()).into());
+    }
+    let version = reader.byte()?;
+    if !(1..=FORMAT_VERSION).contains(&version) {
+        return Err(DecodeError::Version {
+            found: version.into(),
+            supported: FORMAT_VERSION.into(),
+        }
+        .into());
+    }
+
+    let precision = reader.byte()?;
+    let algorithm = reader.name()?;
+    let algorithm = Algorithm::from_name(algorithm)
+        .ok_or_else(|| DecodeError::Malformed(format!("unknown algorithm {}", algorithm)))?;
+    let hasher = reader.name()?;
+    let hasher = HasherId::from_name(hasher)
+        .ok_or_else(|| DecodeError::Malformed(format!("unknown hasher {}", hasher)))?;
+    let seed = reader.u64()?;
+    let mut key = DEFAULT_SEAHASH_KEY;
+    if version > 1 {
+        for word in &mut key {
+            *word = reader.u64()?;
+        }
+    }
+
+    let found = SketchMetadata {
+        precision,
+        algorithm,
+        hasher,
+        seed,
+        key,
+    };
+    SketchMetadata {
+        hasher,
+        seed,
+        key,
+        ..expected
+    }
+    .check_compatible(&found)?;
+
+    Ok((reader.0, found))
+}
+
+/// Number of bytes preceding the registers.
+fn header_len(metadata: &SketchMetadata) -> usize {
+    let key_len = match version(metadata) {
+        1 => 0,
+        _ => 32,
+    };
+
+    MAGIC.len()
+        + 2
+        + 1
+        + metadata.algorithm.name().len()
+        + 1
+        + metadata.hasher.name().len()
+        + 8
+        + key_len
+}
+
+/// Reads the header fields off the front of a serialized sketch.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.0.len() < len {
+            return Err(DecodeError::Truncated {
+                expected: len,
+                found: self.0.len(),
+            }
+            .into());
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+
+        Ok(head)
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(
+            self.take(8)?.try_into().expect("8-byte word"),
+        ))
+    }
+
+    fn name(&mut self) -> Result<&'a str, Error> {
+        let len = self.byte()? as usize;
+
+        std::str::from_utf8(self.take(len)?)
+            .map_err(|e| DecodeError::Malformed(e.to_string()).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FORMAT_VERSION;
+    use crate::{DecodeError, Error, HyperLogLog, HyperLogLogPlusPlus, M};
+
+    #[test]
+    fn test_round_trip() {
+        let mut hll = HyperLogLog::new();
+        let mut plusplus = HyperLogLogPlusPlus::new();
+        for i in 0..10_000 {
+            hll.add(i);
+            plusplus.add(i);
+        }
+
+        let mut buf = Vec::new();
+        hll.serialize_into(&mut buf);
+        assert_eq!(buf.len(), hll.serialized_size_hint());
+        let restored = HyperLogLog::deserialize_from(&buf).unwrap();
+        assert!(restored.diff(&hll).is_identical());
+        assert_eq!(restored.metadata(), hll.metadata());
+
+        // The buffer is reused without growing
+        let capacity = buf.capacity();
+        buf.clear();
+        hll.serialize_into(&mut buf);
+        assert_eq!(buf.capacity(), capacity);
+
+        buf.clear();
+        plusplus.serialize_into(&mut buf);
+        assert_eq!(buf.len(), plusplus.serialized_size_hint());
+        let restored = HyperLogLogPlusPlus::deserialize_from(&buf).unwrap();
+        assert_eq!(&restored.registers[..], &plusplus.registers[..]);
+
+        assert!(matches!(
+            HyperLogLog::deserialize_from(&buf),
+            Err(Error::Incompatible(_))
+        ));
+        assert!(HyperLogLog::deserialize_from(&buf[..10]).is_err());
+        assert!(matches!(
+            HyperLogLogPlusPlus::deserialize_from(&buf[..buf.len() - M / 2]),
+            Err(Error::Decode(DecodeError::Truncated { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let mut buf = Vec::new();
+        HyperLogLog::new().serialize_into(&mut buf);
+
+        let mut corrupt = buf.clone();
+        *corrupt.last_mut().unwrap() = 0xff;
+        assert!(matches!(
+            HyperLogLog::deserialize_from(&corrupt),
+            Err(Error::Decode(DecodeError::InvalidRegister { index, value: 0xff })) if index == M - 1
+        ));
+
+        corrupt = buf.clone();
+        corrupt.push(0);
+        assert!(matches!(
+            HyperLogLog::deserialize_from(&corrupt),
+            Err(Error::Decode(DecodeError::Length { found, .. })) if found == M + 1
+        ));
+
+        corrupt = buf.clone();
+        corrupt[4] = FORMAT_VERSION + 1;
+        assert!(matches!(
+            HyperLogLog::deserialize_from(&corrupt),
+            Err(Error::Decode(DecodeError::Version { .. }))
+        ));
+
+        assert!(matches!(
+            HyperLogLog::deserialize_from(&buf[..6]),
+            Err(Error::Decode(DecodeError::Truncated {
+                expected: 1,
+                found: 0
+            }))
+        ));
+        assert!(matches!(
+            HyperLogLog::deserialize_from(b"JSON"),
+            Err(Error::Decode(DecodeError::Malformed(_)))
+        ));
+    }
+}
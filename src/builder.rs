@@ -0,0 +1,456 @@
+use crate::{
+    dynamic::{DynHyperLogLog, MAX_PRECISION, MIN_PRECISION},
+    hash::{self, siphash},
+    hll::Estimator,
+    metadata::{Algorithm, HasherId, SketchMetadata},
+    registers::Hll4Registers,
+    tiered::{TieredHyperLogLog, DEFAULT_EXACT_LIMIT, DEFAULT_SPARSE_LIMIT},
+    Error, HyperLogLog, HyperLogLogPlusPlus, M, P,
+};
+
+/// How a built sketch holds its registers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Mode {
+    /// All `2^precision` registers, one byte each, from the start.
+    #[default]
+    Dense,
+    /// An exact list of hashes, then a sparse list of registers, promoted to dense
+    /// registers once it holds more than the sparse limit, see `TieredHyperLogLog`.
+    Sparse,
+    /// All `2^precision` registers, 4 bits each as an offset from a shared base, see
+    /// `Hll4Registers`.
+    Hll4,
+}
+
+/// Collects the configuration of a sketch and checks it once, in `build`, instead of each
+/// constructor panicking on its own subset of the settings.
+///
+/// Every setting defaults to what the zero-argument `new` of the sketch types uses: a
+/// precision of `P`, SeaHash with its default seed, dense registers and the classic
+/// estimator. The type of sketch built is chosen by the caller, see `FromBuilder`.
+///
+/// # Examples
+///
+/// ```ignore
+/// let sketch: DynHyperLogLog = HllBuilder::new()
+///     .precision(14)
+///     .hasher(HasherId::Xxh3)
+///     .seed(42)
+///     .estimator(Estimator::Ertl)
+///     .build()?;
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HllBuilder {
+    precision: u8,
+    hasher: HasherId,
+    seed: Option<u64>,
+    mode: Mode,
+    exact_limit: usize,
+    sparse_limit: usize,
+    estimator: Option<Estimator>,
+}
+
+impl HllBuilder {
+    /// Creates a builder holding the default settings.
+    ///
+    /// # Returns
+    /// A new `HllBuilder` instance.
+    pub fn new() -> Self {
+        Self {
+            precision: P as u8,
+            hasher: HasherId::SeaHash,
+            seed: None,
+            mode: Mode::Dense,
+            exact_limit: DEFAULT_EXACT_LIMIT,
+            sparse_limit: DEFAULT_SPARSE_LIMIT,
+            estimator: None,
+        }
+    }
+
+    /// Sets the precision, the number of index bits.
+    ///
+    /// # Parameters
+    /// * `precision`: Between `MIN_PRECISION` and `MAX_PRECISION`, and exactly `P` for
+    ///   the sketch types whose precision is fixed at compile time.
+    pub fn precision(mut self, precision: u8) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Sets the hash function applied to added items, e.g. `HasherId::Xxh3` with the `xxh3`
+    /// feature, which is noticeably faster than SeaHash on short keys.
+    ///
+    /// # Parameters
+    /// * `hasher`: The hash function. `HasherId::SipHash` needs the deployment key to be
+    ///   set with `hash::siphash::set_key` before building.
+    pub fn hasher(mut self, hasher: HasherId) -> Self {
+        self.hasher = hasher;
+        self
+    }
+
+    /// Sets the seed of the hash function, instead of the default seed of the hasher.
+    ///
+    /// # Parameters
+    /// * `seed`: The seed. Only seeded hashers accept one, and `HasherId::Murmur3` only
+    ///   takes 32-bit seeds.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Sets how the registers are held.
+    ///
+    /// # Parameters
+    /// * `mode`: The `Mode` of the sketch.
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets the number of distinct items a `Mode::Sparse` sketch lists exactly before
+    /// moving to sparse registers, `DEFAULT_EXACT_LIMIT` by default.
+    ///
+    /// # Parameters
+    /// * `limit`: The number of items, `0` to start with sparse registers right away.
+    pub fn exact_limit(mut self, limit: usize) -> Self {
+        self.exact_limit = limit;
+        self
+    }
+
+    /// Sets the number of non-zero registers a `Mode::Sparse` sketch holds sparsely before
+    /// it is promoted to dense registers, `DEFAULT_SPARSE_LIMIT` by default.
+    ///
+    /// # Parameters
+    /// * `limit`: The number of registers, at most `M / 4`, beyond which sparse
+    ///   registers take more memory than dense ones.
+    pub fn sparse_limit(mut self, limit: usize) -> Self {
+        self.sparse_limit = limit;
+        self
+    }
+
+    /// Sets the estimator the sketch applies in `estimate`, instead of the default of the
+    /// sketch type: `Estimator::Ertl` for a `HyperLogLogPlusPlus`, `Estimator::Classic`
+    /// otherwise.
+    ///
+    /// # Parameters
+    /// * `estimator`: The `Estimator`. `Mode::Sparse` only supports
+    ///   `Estimator::Classic`.
+    pub fn estimator(mut self, estimator: Estimator) -> Self {
+        self.estimator = Some(estimator);
+        self
+    }
+
+    /// Checks the settings and builds an empty sketch.
+    ///
+    /// # Returns
+    /// The sketch, or an `Error::InvalidConfig` describing the first invalid setting.
+    pub fn build<S: FromBuilder>(&self) -> Result<S, Error> {
+        S::from_builder(self)
+    }
+
+    /// Checks the settings every sketch type depends on.
+    ///
+    /// # Returns
+    /// The metadata of the sketch to build, or an `Error::InvalidConfig`.
+    fn metadata(&self) -> Result<SketchMetadata, Error> {
+        if !(MIN_PRECISION..=MAX_PRECISION).contains(&self.precision) {
+            return invalid(format!(
+                "the precision must be between {} and {}, found {}",
+                MIN_PRECISION, MAX_PRECISION, self.precision
+            ));
+        }
+        if self.mode == Mode::Sparse && self.estimator.unwrap_or_default() != Estimator::Classic {
+            return invalid("the sparse mode only supports the classic estimator");
+        }
+        if self.mode != Mode::Sparse
+            && (self.exact_limit, self.sparse_limit) != (DEFAULT_EXACT_LIMIT, DEFAULT_SPARSE_LIMIT)
+        {
+            return invalid("the exact and sparse limits only apply to the sparse mode");
+        }
+        if self.sparse_limit > M / 4 {
+            return invalid(format!(
+                "the sparse limit must be at most {}, found {}",
+                M / 4,
+                self.sparse_limit
+            ));
+        }
+        if self.hasher == HasherId::SipHash && siphash::key_fingerprint().is_none() {
+            return invalid("no SipHash key set, see hash::siphash::set_key");
+        }
+
+        let seed = match self.seed {
+            None => hash::default_seed(self.hasher),
+            Some(seed) => {
+                check_seed(self.hasher, seed)?;
+                seed
+            }
+        };
+
+        let mut metadata = SketchMetadata::new(Algorithm::HyperLogLog, self.precision as usize);
+        metadata.hasher = self.hasher;
+        metadata.seed = seed;
+
+        Ok(metadata)
+    }
+
+    /// Checks the settings of a sketch type whose precision is fixed to `P`.
+    fn fixed_precision_metadata(&self, name: &str) -> Result<SketchMetadata, Error> {
+        let metadata = self.metadata()?;
+        if self.precision as usize != P {
+            return invalid(format!(
+                "a {} has a precision of {}, found {}, see DynHyperLogLog",
+                name, P, self.precision
+            ));
+        }
+
+        Ok(metadata)
+    }
+}
+
+impl Default for HllBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A sketch type `HllBuilder::build` can produce.
+pub trait FromBuilder: Sized {
+    /// Builds an empty sketch from the settings of `builder`.
+    ///
+    /// # Parameters
+    /// * `builder`: The settings.
+    ///
+    /// # Returns
+    /// The sketch, or an `Error::InvalidConfig` if the settings are invalid or not
+    /// supported by this sketch type.
+    fn from_builder(builder: &HllBuilder) -> Result<Self, Error>;
+}
+
+impl FromBuilder for HyperLogLog {
+    fn from_builder(builder: &HllBuilder) -> Result<Self, Error> {
+        let metadata = builder.fixed_precision_metadata("HyperLogLog")?;
+        if builder.mode != Mode::Dense {
+            return invalid("a HyperLogLog of byte registers needs Mode::Dense");
+        }
+
+        let mut sketch = HyperLogLog::with_metadata(metadata);
+        sketch.set_estimator(builder.estimator.unwrap_or_default());
+
+        Ok(sketch)
+    }
+}
+
+impl FromBuilder for HyperLogLogPlusPlus {
+    fn from_builder(builder: &HllBuilder) -> Result<Self, Error> {
+        let mut metadata = builder.fixed_precision_metadata("HyperLogLogPlusPlus")?;
+        if builder.mode != Mode::Dense {
+            return invalid("a HyperLogLogPlusPlus of byte registers needs Mode::Dense");
+        }
+        metadata.algorithm = Algorithm::HyperLogLogPlusPlus;
+
+        let mut sketch = HyperLogLogPlusPlus::with_metadata(metadata);
+        sketch.set_estimator(builder.estimator.unwrap_or(Estimator::Ertl));
+
+        Ok(sketch)
+    }
+}
+
+impl FromBuilder for HyperLogLog<Hll4Registers> {
+    fn from_builder(builder: &HllBuilder) -> Result<Self, Error> {
+        let metadata = builder.fixed_precision_metadata("HyperLogLog")?;
+        if builder.mode != Mode::Hll4 {
+            return invalid("4-bit registers need Mode::Hll4");
+        }
+
+        let mut sketch = HyperLogLog::with_registers_and_metadata(Hll4Registers::new(M), metadata);
+        sketch.set_estimator(builder.estimator.unwrap_or_default());
+
+        Ok(sketch)
+    }
+}
+
+impl FromBuilder for DynHyperLogLog {
+    fn from_builder(builder: &HllBuilder) -> Result<Self, Error> {
+        let metadata = builder.metadata()?;
+        if builder.mode != Mode::Dense {
+            return invalid("a DynHyperLogLog is dense");
+        }
+
+        let mut sketch = DynHyperLogLog::with_metadata(metadata);
+        sketch.set_estimator(builder.estimator.unwrap_or_default());
+
+        Ok(sketch)
+    }
+}
+
+impl FromBuilder for TieredHyperLogLog {
+    /// Builds a sketch with the exact and sparse limits of the builder, which needs
+    /// `Mode::Sparse`.
+    fn from_builder(builder: &HllBuilder) -> Result<Self, Error> {
+        let metadata = builder.fixed_precision_metadata("TieredHyperLogLog")?;
+        if builder.mode != Mode::Sparse {
+            return invalid("a TieredHyperLogLog starts sparse, see HyperLogLog");
+        }
+
+        Ok(TieredHyperLogLog::with_metadata(
+            builder.exact_limit,
+            builder.sparse_limit,
+            metadata,
+        ))
+    }
+}
+
+/// Checks that `hasher` is keyed by the seed of the sketch and can take `seed`.
+#[cfg_attr(not(feature = "murmur3"), allow(unused_variables))]
+fn check_seed(hasher: HasherId, seed: u64) -> Result<(), Error> {
+    match hasher {
+        HasherId::SeaHash => invalid("SeaHash is not seeded"),
+        HasherId::SipHash => invalid("SipHash is keyed by the deployment key"),
+        #[cfg(feature = "murmur3")]
+        HasherId::Murmur3 if seed > u64::from(u32::MAX) => {
+            invalid(format!("MurmurHash3 takes a 32-bit seed, found {}", seed))
+        }
+        #[allow(unreachable_patterns)]
+        _ => Ok(()),
+    }
+}
+
+/// Reports an invalid setting.
+fn invalid<T>(msg: impl Into<String>) -> Result<T, Error> {
+    Err(Error::InvalidConfig(msg.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::tiered::Tier;
+
+    #[test]
+    fn test_build_defaults() {
+        let hll: HyperLogLog = HllBuilder::new().build().unwrap();
+        assert_eq!(hll.metadata(), HyperLogLog::new().metadata());
+        assert_eq!(hll.estimator(), Estimator::Classic);
+
+        let tiered: TieredHyperLogLog = HllBuilder::new().mode(Mode::Sparse).build().unwrap();
+        assert_eq!(tiered.metadata(), hll.metadata());
+
+        let sketch: DynHyperLogLog = HllBuilder::new()
+            .precision(12)
+            .estimator(Estimator::Ertl)
+            .build()
+            .unwrap();
+        assert_eq!(sketch.precision(), 12);
+        assert_eq!(sketch.estimator(), Estimator::Ertl);
+    }
+
+    #[test]
+    fn test_build_plusplus() {
+        let sketch: HyperLogLogPlusPlus = HllBuilder::new().build().unwrap();
+        assert_eq!(sketch.metadata(), HyperLogLogPlusPlus::new().metadata());
+        assert_eq!(sketch.estimator(), Estimator::Ertl);
+
+        let sketch: HyperLogLogPlusPlus = HllBuilder::new()
+            .estimator(Estimator::Classic)
+            .build()
+            .unwrap();
+        assert_eq!(sketch.estimator(), Estimator::Classic);
+    }
+
+    #[cfg(feature = "xxh3")]
+    #[test]
+    fn test_build_xxh3() {
+        use std::hash::Hasher;
+
+        let builder = HllBuilder::new().hasher(HasherId::Xxh3).seed(42);
+        let mut hll: HyperLogLog = builder.build().unwrap();
+        let mut plusplus: HyperLogLogPlusPlus = builder.build().unwrap();
+        let mut expected = HyperLogLog::new();
+        for i in 0..10_000u64 {
+            hll.add(i);
+            plusplus.add(i);
+            let mut hasher = xxhash_rust::xxh3::Xxh3::with_seed(42);
+            hasher.write(&i.to_le_bytes());
+            expected.add_hashed(hasher.finish());
+        }
+
+        assert_eq!(hll.metadata().hasher, HasherId::Xxh3);
+        assert_eq!(plusplus.metadata().seed, 42);
+        assert!(hll.diff(&expected).is_identical());
+        assert_eq!(plusplus.as_bytes(), expected.as_bytes());
+    }
+
+    #[test]
+    fn test_sparse_promotion() {
+        let mut sketch: TieredHyperLogLog = HllBuilder::new()
+            .mode(Mode::Sparse)
+            .exact_limit(0)
+            .sparse_limit(100)
+            .build()
+            .unwrap();
+        let mut dense = HyperLogLog::new();
+
+        for i in 0..1_000 {
+            sketch.add(i);
+            dense.add(i);
+            if i == 0 {
+                assert_eq!(sketch.tier(), Tier::Sparse);
+            }
+        }
+        assert_eq!(sketch.tier(), Tier::Dense);
+        assert_eq!(sketch.estimate(), dense.estimate());
+
+        assert!(matches!(
+            HllBuilder::new()
+                .mode(Mode::Sparse)
+                .sparse_limit(M)
+                .build::<TieredHyperLogLog>(),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_build_hll4() {
+        let mut hll4: HyperLogLog<Hll4Registers> =
+            HllBuilder::new().mode(Mode::Hll4).build().unwrap();
+        let mut dense = HyperLogLog::new();
+        for i in 0..100_000 {
+            hll4.add(i);
+            dense.add(i);
+        }
+
+        assert_eq!(hll4.estimate(), dense.estimate());
+        assert!(hll4.memory_usage() >= M / 2 && hll4.memory_usage() < M / 2 + M / 16);
+        assert!(HllBuilder::new()
+            .build::<HyperLogLog<Hll4Registers>>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_build_rejects_invalid_settings() {
+        let builder = HllBuilder::new();
+        let invalid =
+            |result: Result<DynHyperLogLog, Error>| matches!(result, Err(Error::InvalidConfig(_)));
+
+        assert!(invalid(builder.precision(MIN_PRECISION - 1).build()));
+        assert!(invalid(builder.precision(MAX_PRECISION + 1).build()));
+        assert!(invalid(builder.mode(Mode::Sparse).build()));
+        assert!(invalid(builder.seed(42).build()));
+        assert!(invalid(builder.sparse_limit(10).build()));
+        assert!(matches!(
+            builder.precision(P as u8 - 1).build::<HyperLogLog>(),
+            Err(Error::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            builder.build::<TieredHyperLogLog>(),
+            Err(Error::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            builder
+                .mode(Mode::Sparse)
+                .estimator(Estimator::Ertl)
+                .build::<TieredHyperLogLog>(),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+}
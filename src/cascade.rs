@@ -0,0 +1,354 @@
+use std::hash::{Hash, Hasher};
+
+use seahash::SeaHasher;
+
+#[cfg(feature = "serde_support")]
+use crate::serde::{serialize_registers, CompressedRegistersVisitor};
+#[cfg(feature = "serde_support")]
+use serde::{
+    de::{Deserializer, Error},
+    Deserialize, Serialize, Serializer,
+};
+
+use crate::{
+    hll,
+    metadata::{Algorithm, SketchMetadata},
+    simd,
+    sketch::Sketch,
+    HyperLogLog,
+};
+
+/// Number of bits used to select a register in the coarse sketch
+pub const COARSE_P: usize = 10;
+/// Number of registers in the coarse sketch, computed as 2^COARSE_P
+pub const COARSE_M: usize = 1 << COARSE_P;
+/// Metadata shared by all coarse sketches
+const COARSE_METADATA: SketchMetadata = SketchMetadata::new(Algorithm::HyperLogLog, COARSE_P);
+
+/// A small, low-precision HyperLogLog using `COARSE_M` registers (1 KiB).
+///
+/// Its estimates have a relative standard error of about 3.3%, compared to about 0.1% for
+/// the full `HyperLogLog`, but it is a thousand times smaller and correspondingly cheaper
+/// to estimate, merge and serialize.
+#[derive(Debug, Clone)]
+pub struct CoarseHyperLogLog {
+    /// An array of `COARSE_M` registers.
+    pub registers: Box<[u8; COARSE_M]>,
+}
+
+impl CoarseHyperLogLog {
+    /// Creates a new coarse HyperLogLog with all registers initialized to zero.
+    ///
+    /// # Returns
+    /// A new `CoarseHyperLogLog` instance.
+    pub fn new() -> Self {
+        Self::from(Box::new([0; COARSE_M]))
+    }
+
+    /// Adds an item to the coarse HyperLogLog.
+    ///
+    /// # Parameters
+    /// * `item`: An item that implements the `Hash` trait to be added.
+    #[inline(always)]
+    pub fn add<T: Hash>(&mut self, item: T) {
+        let mut hasher = SeaHasher::new();
+        item.hash(&mut hasher);
+        self.add_hash(hasher.finish());
+    }
+
+    /// Updates the registers for an already hashed item.
+    ///
+    /// # Parameters
+    /// * `hash`: The 64-bit SeaHash of the item.
+    #[inline(always)]
+    pub(crate) fn add_hash(&mut self, hash: u64) {
+        let index = hash as usize & (COARSE_M - 1);
+        let rank = hll::rank(hash, COARSE_P);
+
+        self.registers[index] = self.registers[index].max(rank);
+    }
+
+    /// Provides an estimate of the number of unique items added.
+    ///
+    /// # Returns
+    /// A `f64` approximate count of unique items.
+    #[inline(always)]
+    pub fn estimate(&self) -> f64 {
+        hll::estimate_registers(self.registers.as_slice())
+    }
+
+    /// Merges another coarse HyperLogLog into this one.
+    ///
+    /// # Parameters
+    /// * `other`: A reference to another `CoarseHyperLogLog` instance to be merged.
+    #[inline(always)]
+    pub fn merge(&mut self, other: &CoarseHyperLogLog) {
+        simd::merge_max(self.registers.as_mut_slice(), other.registers.as_slice());
+    }
+
+    /// Resets every register to zero, keeping the allocation.
+    pub fn clear(&mut self) {
+        self.registers.fill(0);
+    }
+
+    /// Returns the precision, algorithm and hasher the registers were built with.
+    pub fn metadata(&self) -> SketchMetadata {
+        COARSE_METADATA
+    }
+}
+
+impl Default for CoarseHyperLogLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<Box<[u8; COARSE_M]>> for CoarseHyperLogLog {
+    /// Creates a `CoarseHyperLogLog` instance from a boxed array of registers.
+    ///
+    /// # Arguments
+    ///
+    /// * `registers`: A boxed array of `u8` representing the internal state.
+    fn from(registers: Box<[u8; COARSE_M]>) -> Self {
+        Self { registers }
+    }
+}
+
+impl Sketch for CoarseHyperLogLog {
+    #[inline(always)]
+    fn add<T: Hash>(&mut self, item: T) {
+        CoarseHyperLogLog::add(self, item)
+    }
+
+    #[inline(always)]
+    fn estimate(&self) -> f64 {
+        CoarseHyperLogLog::estimate(self)
+    }
+
+    #[inline(always)]
+    fn merge(&mut self, other: &Self) {
+        CoarseHyperLogLog::merge(self, other)
+    }
+
+    #[inline(always)]
+    fn clear(&mut self) {
+        CoarseHyperLogLog::clear(self)
+    }
+}
+
+#[cfg(feature = "serde_support")]
+impl Serialize for CoarseHyperLogLog {
+    /// Serializes the `CoarseHyperLogLog` instance in the same format as `HyperLogLog`.
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize_registers(
+            self.registers.as_slice(),
+            &COARSE_METADATA,
+            None,
+            None,
+            serializer,
+        )
+    }
+}
+
+#[cfg(feature = "serde_support")]
+impl<'de> Deserialize<'de> for CoarseHyperLogLog {
+    /// Deserializes data to construct a `CoarseHyperLogLog` instance.
+    fn deserialize<D>(deserializer: D) -> Result<CoarseHyperLogLog, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let (registers, metadata, ..) = deserializer
+            .deserialize_map(CompressedRegistersVisitor::<COARSE_M>::new(COARSE_METADATA))?;
+        COARSE_METADATA
+            .check_compatible(&metadata)
+            .map_err(D::Error::custom)?;
+
+        Ok(Self::from(registers))
+    }
+}
+
+/// A pair of sketches fed by the same items: a coarse `CoarseHyperLogLog` for cheap triage
+/// queries and a full-precision `HyperLogLog` for precise ones.
+///
+/// Every item is hashed once and added to both. When millions of keys have to be ranked
+/// or filtered, `estimate_coarse` answers from 1 KiB of registers instead of 1 MiB, and
+/// only the keys that matter need a precise `estimate`. Either sketch can be accessed (and
+/// serialized) on its own through `coarse` and `fine`.
+#[derive(Debug, Clone, Default)]
+pub struct CascadeHyperLogLog {
+    coarse: CoarseHyperLogLog,
+    fine: HyperLogLog,
+}
+
+impl CascadeHyperLogLog {
+    /// Creates a new cascade with both sketches empty.
+    ///
+    /// # Returns
+    /// A new `CascadeHyperLogLog` instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an item to both sketches, hashing it only once.
+    ///
+    /// # Parameters
+    /// * `item`: An item that implements the `Hash` trait to be added.
+    #[inline(always)]
+    pub fn add<T: Hash>(&mut self, item: T) {
+        let mut hasher = SeaHasher::new();
+        item.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        self.coarse.add_hash(hash);
+        self.fine.add_hash(hash);
+    }
+
+    /// Provides a cheap approximate estimate from the coarse sketch.
+    ///
+    /// # Returns
+    /// A `f64` approximate count of unique items, with roughly 3.3% standard error.
+    #[inline(always)]
+    pub fn estimate_coarse(&self) -> f64 {
+        self.coarse.estimate()
+    }
+
+    /// Provides a precise estimate from the full-precision sketch.
+    ///
+    /// # Returns
+    /// A `f64` approximate count of unique items.
+    #[inline(always)]
+    pub fn estimate(&self) -> f64 {
+        self.fine.estimate()
+    }
+
+    /// Merges another cascade into this one, level by level.
+    ///
+    /// # Parameters
+    /// * `other`: A reference to another `CascadeHyperLogLog` instance to be merged.
+    #[inline(always)]
+    pub fn merge(&mut self, other: &CascadeHyperLogLog) {
+        self.coarse.merge(&other.coarse);
+        self.fine.merge(&other.fine);
+    }
+
+    /// Resets both sketches to their empty state, keeping their allocations.
+    pub fn clear(&mut self) {
+        self.coarse.clear();
+        self.fine.clear();
+    }
+
+    /// Returns the coarse sketch.
+    pub fn coarse(&self) -> &CoarseHyperLogLog {
+        &self.coarse
+    }
+
+    /// Returns the full-precision sketch.
+    pub fn fine(&self) -> &HyperLogLog {
+        &self.fine
+    }
+
+    /// Splits the cascade into its coarse and full-precision sketches.
+    pub fn into_parts(self) -> (CoarseHyperLogLog, HyperLogLog) {
+        (self.coarse, self.fine)
+    }
+}
+
+impl Sketch for CascadeHyperLogLog {
+    #[inline(always)]
+    fn add<T: Hash>(&mut self, item: T) {
+        CascadeHyperLogLog::add(self, item)
+    }
+
+    #[inline(always)]
+    fn estimate(&self) -> f64 {
+        CascadeHyperLogLog::estimate(self)
+    }
+
+    #[inline(always)]
+    fn merge(&mut self, other: &Self) {
+        CascadeHyperLogLog::merge(self, other)
+    }
+
+    #[inline(always)]
+    fn clear(&mut self) {
+        CascadeHyperLogLog::clear(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::M;
+
+    #[test]
+    fn test_coarse_and_fine_estimates() {
+        let mut cascade = CascadeHyperLogLog::new();
+        for i in 0..100_000 {
+            cascade.add(i);
+        }
+
+        let coarse = cascade.estimate_coarse();
+        let fine = cascade.estimate();
+        assert!(
+            (coarse - 100_000.0).abs() < 100_000.0 * 0.15,
+            "Coarse estimate {} out of expected range",
+            coarse
+        );
+        assert!(
+            (fine - 100_000.0).abs() / 100_000.0 < 4.0 * 1.04 / (M as f64).sqrt(),
+            "Fine estimate {} out of expected range",
+            fine
+        );
+
+        let mut hll = HyperLogLog::new();
+        for i in 0..100_000 {
+            hll.add(i);
+        }
+        assert!(cascade.fine().diff(&hll).is_identical());
+    }
+
+    #[test]
+    fn test_coarse_rank_saturates() {
+        let mut coarse = CoarseHyperLogLog::new();
+        coarse.add_hash(0);
+        assert_eq!(coarse.registers[0], (64 - COARSE_P + 1) as u8);
+
+        coarse.add_hash(1 << 63);
+        assert_eq!(coarse.registers[0], (64 - COARSE_P + 1) as u8);
+
+        coarse.add_hash((1 << 63) | 1);
+        assert_eq!(coarse.registers[1], 1);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut a = CascadeHyperLogLog::new();
+        let mut b = CascadeHyperLogLog::new();
+        a.add(1);
+        b.add(2);
+        a.merge(&b);
+
+        assert_eq!(a.estimate().round() as u32, 2);
+        assert_eq!(a.estimate_coarse().round() as u32, 2);
+    }
+
+    #[cfg(feature = "serde_support")]
+    #[test]
+    fn test_serialize_levels() {
+        let mut cascade = CascadeHyperLogLog::new();
+        for i in 0..1_000 {
+            cascade.add(i);
+        }
+
+        let coarse = serde_json::to_string(cascade.coarse()).unwrap();
+        let coarse: CoarseHyperLogLog = serde_json::from_str(&coarse).unwrap();
+        assert_eq!(coarse.registers, cascade.coarse().registers);
+
+        let fine = serde_json::to_string(cascade.fine()).unwrap();
+        let fine: HyperLogLog = serde_json::from_str(&fine).unwrap();
+        assert!(fine.diff(cascade.fine()).is_identical());
+    }
+}
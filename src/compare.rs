@@ -0,0 +1,213 @@
+#[cfg(feature = "simd")]
+use packed_simd::u8x16;
+
+use crate::{HyperLogLog, M};
+
+/// Register-level comparison between two sketches, as returned by `diff`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegisterDiff {
+    /// Number of registers holding a different value in the two sketches.
+    pub differing: usize,
+    /// Number of registers where this sketch holds the larger value.
+    pub self_ahead: usize,
+    /// Number of registers where the other sketch holds the larger value.
+    pub other_ahead: usize,
+    /// Estimate of this sketch minus the estimate of the other sketch.
+    pub estimate_gap: f64,
+}
+
+impl RegisterDiff {
+    /// Returns `true` when both sketches hold exactly the same registers.
+    pub fn is_identical(&self) -> bool {
+        self.differing == 0
+    }
+}
+
+/// How the registers of two replicas of the same counter relate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplicaRelation {
+    /// Both replicas hold the same registers.
+    Identical,
+    /// `a` holds at least the value of `b` in every register and more in some: `a` has
+    /// seen everything `b` has, plus more.
+    AAhead,
+    /// `b` holds at least the value of `a` in every register and more in some.
+    BAhead,
+    /// Each replica is ahead of the other in some registers: both have seen items the
+    /// other has not.
+    Diverged,
+}
+
+/// The merge that brings two replicas back in sync.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeAction {
+    /// The replicas are identical, nothing to do.
+    None,
+    /// Merge `a` into `b`, `a` is left as is.
+    MergeAIntoB,
+    /// Merge `b` into `a`, `b` is left as is.
+    MergeBIntoA,
+    /// Merge each replica into the other, or both into a fresh union.
+    MergeBoth,
+}
+
+/// Summary of the drift between two replicas of a counter, as returned by
+/// `compare_replicas`, for operators debugging counters that diverged across
+/// datacenters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DriftReport {
+    /// Fraction of the registers holding the same value in both replicas, between 0 and 1.
+    pub agreement: f64,
+    /// Number of registers where `a` holds the larger value.
+    pub a_ahead: usize,
+    /// Number of registers where `b` holds the larger value.
+    pub b_ahead: usize,
+    /// Estimate of `a` minus the estimate of `b`.
+    pub estimate_delta: f64,
+    /// Which replica, if any, has seen everything the other has.
+    pub relation: ReplicaRelation,
+    /// The merge that brings the replicas back in sync.
+    pub action: MergeAction,
+}
+
+impl From<RegisterDiff> for DriftReport {
+    /// Builds the report from the `RegisterDiff` of `a` against `b`, e.g. to compare
+    /// `HyperLogLogPlusPlus` replicas with `DriftReport::from(a.diff(&b))`.
+    fn from(diff: RegisterDiff) -> Self {
+        let (relation, action) = match (diff.self_ahead, diff.other_ahead) {
+            (0, 0) => (ReplicaRelation::Identical, MergeAction::None),
+            (_, 0) => (ReplicaRelation::AAhead, MergeAction::MergeAIntoB),
+            (0, _) => (ReplicaRelation::BAhead, MergeAction::MergeBIntoA),
+            _ => (ReplicaRelation::Diverged, MergeAction::MergeBoth),
+        };
+
+        DriftReport {
+            agreement: 1.0 - diff.differing as f64 / M as f64,
+            a_ahead: diff.self_ahead,
+            b_ahead: diff.other_ahead,
+            estimate_delta: diff.estimate_gap,
+            relation,
+            action,
+        }
+    }
+}
+
+/// Compares two replicas of a counter that should hold identical state, reporting how
+/// far they drifted apart and how to reconcile them. A replica ahead in some registers
+/// and behind in none has seen a superset of the items of the other, so merging it into
+/// the other is enough; replicas that diverged need merging both ways.
+///
+/// # Parameters
+/// * `a`: The first replica.
+/// * `b`: The second replica.
+///
+/// # Returns
+/// A `DriftReport` of `a` against `b`.
+pub fn compare_replicas(a: &HyperLogLog, b: &HyperLogLog) -> DriftReport {
+    a.diff(b).into()
+}
+
+/// Counts the registers where `a` is larger than `b` and where `b` is larger than `a`.
+///
+/// # Arguments
+///
+/// * `a`: The first register array.
+/// * `b`: The second register array, of the same length as `a`.
+#[cfg(feature = "simd")]
+pub(crate) fn count_ahead(a: &[u8], b: &[u8]) -> (usize, usize) {
+    debug_assert_eq!(a.len(), b.len());
+
+    let mut a_ahead = 0;
+    let mut b_ahead = 0;
+
+    let a_chunks = a.chunks_exact(16);
+    let b_chunks = b.chunks_exact(16);
+    let (a_rem, b_rem) = (a_chunks.remainder(), b_chunks.remainder());
+
+    for (a, b) in a_chunks.zip(b_chunks) {
+        let a = u8x16::from_slice_unaligned(a);
+        let b = u8x16::from_slice_unaligned(b);
+
+        a_ahead += a.gt(b).bitmask().count_ones() as usize;
+        b_ahead += b.gt(a).bitmask().count_ones() as usize;
+    }
+
+    for (a, b) in a_rem.iter().zip(b_rem) {
+        a_ahead += (a > b) as usize;
+        b_ahead += (b > a) as usize;
+    }
+
+    (a_ahead, b_ahead)
+}
+
+/// Scalar counterpart of `count_ahead`, compiled without the `simd` feature.
+#[cfg(not(feature = "simd"))]
+pub(crate) fn count_ahead(a: &[u8], b: &[u8]) -> (usize, usize) {
+    debug_assert_eq!(a.len(), b.len());
+
+    a.iter().zip(b).fold((0, 0), |(a_ahead, b_ahead), (a, b)| {
+        (a_ahead + (a > b) as usize, b_ahead + (b > a) as usize)
+    })
+}
+
+/// Builds the `RegisterDiff` between two register arrays and their estimates.
+pub(crate) fn diff(a: &[u8], b: &[u8], a_estimate: f64, b_estimate: f64) -> RegisterDiff {
+    let (self_ahead, other_ahead) = count_ahead(a, b);
+
+    RegisterDiff {
+        differing: self_ahead + other_ahead,
+        self_ahead,
+        other_ahead,
+        estimate_gap: a_estimate - b_estimate,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_ahead() {
+        let mut a = vec![0u8; 35];
+        let mut b = vec![0u8; 35];
+        a[0] = 3;
+        a[20] = 1;
+        b[20] = 2;
+        b[34] = 5;
+
+        assert_eq!(count_ahead(&a, &b), (1, 2));
+        assert_eq!(count_ahead(&a, &a), (0, 0));
+    }
+
+    #[test]
+    fn test_compare_replicas() {
+        let mut a = HyperLogLog::new();
+        for i in 0..1_000 {
+            a.add(i);
+        }
+        let mut b = a.clone();
+
+        let report = compare_replicas(&a, &b);
+        assert_eq!(report.relation, ReplicaRelation::Identical);
+        assert_eq!((report.agreement, report.action), (1.0, MergeAction::None));
+
+        for i in 1_000..1_100 {
+            b.add(i);
+        }
+        let report = compare_replicas(&a, &b);
+        assert_eq!(report.relation, ReplicaRelation::BAhead);
+        assert_eq!(report.action, MergeAction::MergeBIntoA);
+        assert!(report.b_ahead > 0 && report.a_ahead == 0);
+        assert!(report.estimate_delta < 0.0 && report.agreement < 1.0);
+
+        a.add("only in a");
+        let report = compare_replicas(&a, &b);
+        assert_eq!(report.relation, ReplicaRelation::Diverged);
+        assert_eq!(report.action, MergeAction::MergeBoth);
+        assert_eq!(
+            compare_replicas(&b, &a).b_ahead,
+            report.a_ahead,
+            "swapping the replicas swaps the sides"
+        );
+    }
+}
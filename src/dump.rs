@@ -0,0 +1,231 @@
+use std::{error, fmt, fmt::Write};
+
+use crate::{M, MAX_RANK};
+
+/// Number of registers covered by a single line of a hex dump. Lines always start on
+/// a multiple of this value, so dumps of two sketches line up when diffed.
+const LINE_WIDTH: usize = 64;
+
+/// Error returned when a hex dump cannot be parsed back into registers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HexDumpError {
+    /// A line is not of the form `offset: token token ...`.
+    MalformedLine(usize),
+    /// A line starts at a different offset than the registers parsed so far.
+    OffsetMismatch {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    /// A token is not a hex register value of at most the maximum rank, with an optional
+    /// `*count` suffix.
+    InvalidToken(usize),
+    /// The dump does not describe exactly `M` registers.
+    Length(usize),
+}
+
+impl fmt::Display for HexDumpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HexDumpError::MalformedLine(line) => write!(f, "malformed hex dump line {}", line),
+            HexDumpError::OffsetMismatch {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "hex dump line {} starts at offset {:x}, expected {:x}",
+                line, found, expected
+            ),
+            HexDumpError::InvalidToken(line) => {
+                write!(f, "invalid token on hex dump line {}", line)
+            }
+            HexDumpError::Length(found) => {
+                write!(f, "hex dump describes {} registers, expected {}", found, M)
+            }
+        }
+    }
+}
+
+impl error::Error for HexDumpError {}
+
+/// Renders registers as a compact, line-oriented hex dump.
+///
+/// Each line has the form `offset: token token ...` where the offset is the hex index of
+/// the first register on the line and every token is a hex register value, optionally
+/// followed by `*count` (in decimal) when the value repeats. Lines are broken at every
+/// `LINE_WIDTH` registers, except that runs covering whole lines are collapsed into one.
+///
+/// # Arguments
+///
+/// * `registers`: The registers to render.
+pub(crate) fn to_hex_dump(registers: &[u8]) -> String {
+    let mut out = String::new();
+    let mut offset = 0;
+
+    while offset < registers.len() {
+        let value = registers[offset];
+        let run = registers[offset..]
+            .iter()
+            .take_while(|&&v| v == value)
+            .count();
+
+        // A run that spans at least one full line is written as a single token,
+        // trimmed back to a line boundary so subsequent lines stay aligned.
+        let end = if offset % LINE_WIDTH == 0 && run >= LINE_WIDTH {
+            offset + run - run % LINE_WIDTH
+        } else {
+            ((offset / LINE_WIDTH) + 1) * LINE_WIDTH
+        }
+        .min(registers.len());
+
+        let _ = write!(out, "{:06x}:", offset);
+
+        let mut i = offset;
+        while i < end {
+            let value = registers[i];
+            let run = registers[i..end]
+                .iter()
+                .take_while(|&&v| v == value)
+                .count();
+
+            if run > 1 {
+                let _ = write!(out, " {:02x}*{}", value, run);
+            } else {
+                let _ = write!(out, " {:02x}", value);
+            }
+
+            i += run;
+        }
+
+        out.push('\n');
+        offset = end;
+    }
+
+    out
+}
+
+/// Parses a hex dump produced by `to_hex_dump` back into registers.
+///
+/// Line breaks only need to be consistent with the offsets written at the start of each
+/// line, so hand-edited dumps are accepted as long as they still describe `M` registers
+/// of valid ranks.
+///
+/// # Arguments
+///
+/// * `dump`: The textual hex dump.
+pub(crate) fn from_hex_dump(dump: &str) -> Result<Box<[u8; M]>, HexDumpError> {
+    let mut registers = Vec::with_capacity(M);
+
+    for (index, line) in dump.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let (offset, tokens) = line
+            .split_once(':')
+            .ok_or(HexDumpError::MalformedLine(line_number))?;
+        let offset = usize::from_str_radix(offset.trim(), 16)
+            .map_err(|_| HexDumpError::MalformedLine(line_number))?;
+
+        if offset != registers.len() {
+            return Err(HexDumpError::OffsetMismatch {
+                line: line_number,
+                expected: registers.len(),
+                found: offset,
+            });
+        }
+
+        for token in tokens.split_whitespace() {
+            let (value, count) = match token.split_once('*') {
+                Some((value, count)) => (value, count.parse::<usize>().ok()),
+                None => (token, Some(1)),
+            };
+            let value = u8::from_str_radix(value, 16).ok();
+
+            match (value, count) {
+                (Some(value), Some(count)) if value <= MAX_RANK => {
+                    let len = registers
+                        .len()
+                        .checked_add(count)
+                        .filter(|&len| len <= M)
+                        .ok_or(HexDumpError::Length(registers.len().saturating_add(count)))?;
+                    registers.resize(len, value)
+                }
+                _ => return Err(HexDumpError::InvalidToken(line_number)),
+            }
+        }
+    }
+
+    registers
+        .into_boxed_slice()
+        .try_into()
+        .map_err(|r: Box<[u8]>| HexDumpError::Length(r.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut registers = vec![0u8; M];
+        registers[3] = 7;
+        registers[4] = 7;
+        registers[M / 2] = 1;
+        registers[M - 1] = 42;
+
+        let dump = to_hex_dump(&registers);
+        let parsed = from_hex_dump(&dump).unwrap();
+
+        assert_eq!(&parsed[..], &registers[..]);
+    }
+
+    #[test]
+    fn test_format() {
+        let mut registers = vec![0u8; M];
+        registers[1] = 3;
+
+        let dump = to_hex_dump(&registers);
+        let mut lines = dump.lines();
+
+        assert_eq!(lines.next(), Some("000000: 00 03 00*62"));
+        assert_eq!(
+            lines.next(),
+            Some(format!("000040: 00*{}", M - 64).as_str())
+        );
+        assert_eq!(lines.next(), None);
+    }
+
+    #[test]
+    fn test_rejects_bad_dumps() {
+        assert_eq!(
+            from_hex_dump("000000 00*4"),
+            Err(HexDumpError::MalformedLine(1))
+        );
+        assert_eq!(
+            from_hex_dump("000000: 00*4\n000008: 00"),
+            Err(HexDumpError::OffsetMismatch {
+                line: 2,
+                expected: 4,
+                found: 8
+            })
+        );
+        assert_eq!(
+            from_hex_dump("000000: zz"),
+            Err(HexDumpError::InvalidToken(1))
+        );
+        assert_eq!(from_hex_dump("000000: 00*4"), Err(HexDumpError::Length(4)));
+        assert_eq!(
+            from_hex_dump(&format!("000000: 00*2 00*{}", usize::MAX)),
+            Err(HexDumpError::Length(usize::MAX))
+        );
+        assert_eq!(
+            from_hex_dump(&format!("000000: {:02x}*{}", MAX_RANK + 1, M)),
+            Err(HexDumpError::InvalidToken(1))
+        );
+    }
+}
@@ -0,0 +1,416 @@
+use std::hash::Hash;
+
+use crate::{
+    ertl, hash,
+    hll::{self, Estimator, HyperLogLog},
+    metadata::{Algorithm, HasherId, SketchMetadata},
+    registers::{self, Registers, RegistersMut},
+    sketch::Sketch,
+    Error, P,
+};
+
+/// Smallest precision a `DynHyperLogLog` can be built with.
+pub const MIN_PRECISION: u8 = 4;
+
+/// Largest precision a `DynHyperLogLog` can be built with, 2^26 registers taking 64 MiB.
+pub const MAX_PRECISION: u8 = 26;
+
+/// Returns the relative standard error of the estimates of a sketch of the given
+/// precision, `1.04 / sqrt(2^precision)`.
+///
+/// # Parameters
+/// * `precision`: The number of index bits.
+///
+/// # Returns
+/// The relative standard error, e.g. `0.008125` for a precision of 14.
+pub fn error_for_precision(precision: usize) -> f64 {
+    1.04 / ((1u64 << precision) as f64).sqrt()
+}
+
+/// Returns the smallest precision whose relative standard error is at most `rel_err`, so
+/// capacity planning can derive the precision of a sketch from an accuracy target.
+/// Precisions below `MIN_PRECISION` are rounded up to it.
+///
+/// Estimates fall within one standard error of the true count about 65% of the time, and
+/// within two about 95% of the time, so an SLA on most estimates calls for half the
+/// error it states.
+///
+/// # Parameters
+/// * `rel_err`: The target relative standard error, e.g. `0.01` for 1%.
+///
+/// # Returns
+/// The precision, e.g. `14` for 1%.
+///
+/// # Panics
+/// Panics if `rel_err` is not positive, or so small that it needs a precision above
+/// `MAX_PRECISION`.
+pub fn precision_for_error(rel_err: f64) -> usize {
+    assert!(rel_err > 0.0, "the relative error must be positive");
+
+    (MIN_PRECISION as usize..=MAX_PRECISION as usize)
+        .find(|&precision| error_for_precision(precision) <= rel_err)
+        .unwrap_or_else(|| {
+            panic!(
+                "a relative error of {} needs a precision above {}",
+                rel_err, MAX_PRECISION
+            )
+        })
+}
+
+/// A HyperLogLog whose precision is chosen at runtime, e.g. read from a config file,
+/// rather than fixed at compile time by `P`.
+///
+/// The registers are a boxed slice of `2^precision` bytes. Items map to registers
+/// exactly as in a `HyperLogLog` of the same precision, so a `DynHyperLogLog` of
+/// precision `P` holds the same registers as a `HyperLogLog` fed the same items, and
+/// converts to and from it without loss.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DynHyperLogLog {
+    registers: Box<[u8]>,
+    metadata: SketchMetadata,
+    estimator: Estimator,
+}
+
+impl DynHyperLogLog {
+    /// Creates a new HyperLogLog of the given precision, with all registers initialized
+    /// to zero.
+    ///
+    /// # Parameters
+    /// * `precision`: The number of index bits, between `MIN_PRECISION` and
+    ///   `MAX_PRECISION`. The sketch holds `2^precision` one-byte registers, with a
+    ///   standard error of `1.04 / sqrt(2^precision)`.
+    ///
+    /// # Returns
+    /// A new `DynHyperLogLog` instance.
+    ///
+    /// # Panics
+    /// Panics if `precision` is out of range.
+    pub fn new(precision: u8) -> Self {
+        Self::with_hasher(precision, HasherId::SeaHash)
+    }
+
+    /// Creates a new HyperLogLog of the given precision, hashing items with the given
+    /// hash function instead of SeaHash.
+    ///
+    /// # Parameters
+    /// * `precision`: The number of index bits, between `MIN_PRECISION` and
+    ///   `MAX_PRECISION`.
+    /// * `hasher`: The hash function applied to added items.
+    ///
+    /// # Returns
+    /// A new `DynHyperLogLog` instance.
+    ///
+    /// # Panics
+    /// Panics if `precision` is out of range.
+    pub fn with_hasher(precision: u8, hasher: HasherId) -> Self {
+        assert!(
+            (MIN_PRECISION..=MAX_PRECISION).contains(&precision),
+            "the precision must be between {} and {}",
+            MIN_PRECISION,
+            MAX_PRECISION
+        );
+
+        let mut metadata = SketchMetadata::new(Algorithm::HyperLogLog, precision as usize);
+        metadata.hasher = hasher;
+        metadata.seed = hash::default_seed(hasher);
+
+        Self::with_metadata(metadata)
+    }
+
+    /// Creates a new HyperLogLog of the precision, hasher and seed of `metadata`, which
+    /// the caller has checked, e.g. `HllBuilder::build`.
+    pub(crate) fn with_metadata(metadata: SketchMetadata) -> Self {
+        Self {
+            registers: vec![0; 1 << metadata.precision].into_boxed_slice(),
+            metadata,
+            estimator: Estimator::default(),
+        }
+    }
+
+    /// Creates a HyperLogLog from a copy of raw registers, e.g. read from storage. The
+    /// precision is derived from their number.
+    ///
+    /// # Parameters
+    /// * `bytes`: The registers, one byte each, a power of two of them.
+    ///
+    /// # Returns
+    /// The `DynHyperLogLog`, or an `Error` if `bytes` does not hold a supported number of
+    /// valid registers.
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let precision = bytes.len().trailing_zeros() as u8;
+        if !bytes.len().is_power_of_two() || !(MIN_PRECISION..=MAX_PRECISION).contains(&precision) {
+            return Err(Error::Length {
+                expected: 1 << precision.clamp(MIN_PRECISION, MAX_PRECISION),
+                found: bytes.len(),
+            });
+        }
+        registers::check_ranks(bytes, max_rank(precision))?;
+
+        let mut sketch = Self::new(precision);
+        sketch.registers.copy_from_slice(bytes);
+
+        Ok(sketch)
+    }
+
+    /// Converts a HyperLogLog, folding its registers down when `precision` is below `P`.
+    ///
+    /// # Parameters
+    /// * `hll`: The sketch to convert.
+    /// * `precision`: The precision of the new sketch, at most `P`.
+    ///
+    /// # Returns
+    /// A new `DynHyperLogLog` holding the registers of `hll`.
+    ///
+    /// # Panics
+    /// Panics if `precision` is out of range or larger than `P`.
+    pub fn from_hyperloglog<R: Registers>(hll: &HyperLogLog<R>, precision: u8) -> Self {
+        Self::folded(&hll.registers, hll.metadata(), precision)
+    }
+
+    /// Folds the `P`-bit registers of a sketch down to the given precision, keeping its
+    /// algorithm, hasher and seed.
+    ///
+    /// # Panics
+    /// Panics if `precision` is out of range or larger than `P`.
+    pub(crate) fn folded<R: Registers + ?Sized>(
+        registers: &R,
+        metadata: SketchMetadata,
+        precision: u8,
+    ) -> Self {
+        assert!(
+            (MIN_PRECISION..=MAX_PRECISION).contains(&precision),
+            "the precision must be between {} and {}",
+            MIN_PRECISION,
+            MAX_PRECISION
+        );
+        assert!(
+            precision as usize <= P,
+            "cannot convert to a precision above {}",
+            P
+        );
+
+        let mut sketch = Self::with_metadata(SketchMetadata {
+            precision,
+            ..metadata
+        });
+        registers::fold_max(registers, &mut sketch.registers);
+
+        sketch
+    }
+
+    /// Converts the sketch into a `HyperLogLog`, which needs a precision of at least `P`.
+    /// Sketches of a higher precision are folded down.
+    ///
+    /// # Returns
+    /// The `HyperLogLog`, or an `Error::Incompatible` if the precision is below `P` or
+    /// the seed is not the default one of the hasher.
+    pub fn to_hyperloglog(&self) -> Result<HyperLogLog, Error> {
+        let mut hll = HyperLogLog::with_hasher(self.metadata.hasher);
+        match hll.metadata().check_compatible(&self.metadata) {
+            Ok(()) => hll.registers.merge_max(&self.registers),
+            Err(e) if e.is_foldable() => registers::fold_max(&self.registers, &mut hll.registers),
+            Err(e) => return Err(e.into()),
+        }
+
+        Ok(hll)
+    }
+
+    /// Adds an item to the HyperLogLog.
+    ///
+    /// # Parameters
+    /// * `item`: An item that implements the `Hash` trait to be added.
+    #[inline(always)]
+    pub fn add<T: Hash>(&mut self, item: T) {
+        self.add_hash(hash::hash_item(&self.metadata, &item));
+    }
+
+    /// Updates the registers for an already hashed item, as `HyperLogLog::add_hash` does
+    /// at the precision of this sketch.
+    #[inline(always)]
+    pub(crate) fn add_hash(&mut self, hash: u64) {
+        let precision = self.metadata.precision as usize;
+        let j = hash as usize & (self.registers.len() - 1);
+
+        self.registers.update_max(j, hll::rank(hash, precision));
+    }
+
+    /// Estimates the number of unique items added, with the `Estimator` set with
+    /// `set_estimator`, `Estimator::Classic` by default.
+    ///
+    /// # Returns
+    /// A `f64` approximate count of unique items.
+    pub fn estimate(&self) -> f64 {
+        match self.estimator {
+            Estimator::Classic => hll::estimate_registers(&self.registers),
+            Estimator::Ertl => ertl::estimate_registers(&self.registers),
+        }
+    }
+
+    /// Merges another HyperLogLog into this one.
+    ///
+    /// # Parameters
+    /// * `other`: A reference to another `DynHyperLogLog` instance to be merged.
+    ///
+    /// # Panics
+    /// Panics if the sketches cannot be merged, see `try_merge`.
+    pub fn merge(&mut self, other: &DynHyperLogLog) {
+        if let Err(e) = self.try_merge(other) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Merges another HyperLogLog into this one after checking that both use the same
+    /// hasher and seed. A sketch of higher precision is folded down to the precision of
+    /// this one.
+    ///
+    /// # Parameters
+    /// * `other`: A reference to another `DynHyperLogLog` instance to be merged.
+    ///
+    /// # Returns
+    /// `Ok(())` once merged, or an `Error::Incompatible` leaving this sketch untouched if
+    /// `other` has a lower precision or another hasher or seed.
+    pub fn try_merge(&mut self, other: &DynHyperLogLog) -> Result<(), Error> {
+        match self.metadata.check_compatible(&other.metadata) {
+            Ok(()) => self.registers.merge_max(&other.registers),
+            Err(e) if e.is_foldable() => registers::fold_max(&other.registers, &mut self.registers),
+            Err(e) => return Err(e.into()),
+        }
+
+        Ok(())
+    }
+
+    /// Resets all registers to zero, keeping the precision.
+    pub fn clear(&mut self) {
+        self.registers.clear();
+    }
+
+    /// Returns the precision, the number of index bits.
+    pub fn precision(&self) -> u8 {
+        self.metadata.precision
+    }
+
+    /// Returns the precision, algorithm, hasher and seed of the sketch.
+    pub fn metadata(&self) -> SketchMetadata {
+        self.metadata
+    }
+
+    /// Returns the estimator `estimate` applies.
+    pub fn estimator(&self) -> Estimator {
+        self.estimator
+    }
+
+    /// Sets the estimator `estimate` applies.
+    ///
+    /// # Parameters
+    /// * `estimator`: The `Estimator` to apply on subsequent estimates.
+    pub fn set_estimator(&mut self, estimator: Estimator) {
+        self.estimator = estimator;
+    }
+
+    /// Returns the registers, `2^precision` of them.
+    pub fn registers(&self) -> &[u8] {
+        &self.registers
+    }
+}
+
+impl Sketch for DynHyperLogLog {
+    #[inline(always)]
+    fn add<T: Hash>(&mut self, item: T) {
+        DynHyperLogLog::add(self, item)
+    }
+
+    #[inline(always)]
+    fn estimate(&self) -> f64 {
+        DynHyperLogLog::estimate(self)
+    }
+
+    #[inline(always)]
+    fn merge(&mut self, other: &Self) {
+        DynHyperLogLog::merge(self, other)
+    }
+
+    #[inline(always)]
+    fn clear(&mut self) {
+        DynHyperLogLog::clear(self)
+    }
+}
+
+/// The largest rank a register of a sketch of the given precision can hold.
+fn max_rank(precision: u8) -> u8 {
+    64 - precision + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_runtime_precision() {
+        for precision in [MIN_PRECISION, 10, 14] {
+            let mut sketch = DynHyperLogLog::new(precision);
+            for i in 0..20_000 {
+                sketch.add(i);
+            }
+            assert_eq!(sketch.registers().len(), 1 << precision);
+
+            let error = error_for_precision(precision as usize);
+            let estimate = sketch.estimate();
+            assert!(
+                (estimate - 20_000.0).abs() / 20_000.0 < 4.0 * error,
+                "precision {}: estimate {}",
+                precision,
+                estimate
+            );
+        }
+    }
+
+    #[test]
+    fn test_precision_for_error() {
+        assert_eq!(precision_for_error(0.01), 14);
+        assert_eq!(precision_for_error(0.5), MIN_PRECISION as usize);
+        for precision in MIN_PRECISION as usize..=MAX_PRECISION as usize {
+            assert_eq!(
+                precision_for_error(error_for_precision(precision)),
+                precision
+            );
+        }
+        assert_eq!(error_for_precision(14), 1.04 / 128.0);
+    }
+
+    #[test]
+    fn test_merge_folds_higher_precision() {
+        let mut coarse = DynHyperLogLog::new(10);
+        let mut fine = DynHyperLogLog::new(12);
+        for i in 0..5_000 {
+            coarse.add(i);
+            fine.add(i + 2_500);
+        }
+
+        coarse.merge(&fine);
+        let mut expected = DynHyperLogLog::new(10);
+        for i in 0..7_500 {
+            expected.add(i);
+        }
+        assert_eq!(coarse, expected);
+        assert!(fine.try_merge(&coarse).is_err());
+    }
+
+    #[test]
+    fn test_hyperloglog_round_trip() {
+        let mut hll = HyperLogLog::new();
+        let mut sketch = DynHyperLogLog::new(P as u8);
+        for i in 0..10_000 {
+            hll.add(i);
+            sketch.add(i);
+        }
+
+        assert_eq!(DynHyperLogLog::from_hyperloglog(&hll, P as u8), sketch);
+        assert!(sketch.to_hyperloglog().unwrap().diff(&hll).is_identical());
+        assert_eq!(
+            DynHyperLogLog::try_from_bytes(sketch.registers()).unwrap(),
+            sketch
+        );
+        assert!(DynHyperLogLog::try_from_bytes(&[0; 100]).is_err());
+        assert!(DynHyperLogLog::new(8).to_hyperloglog().is_err());
+    }
+}
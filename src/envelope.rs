@@ -0,0 +1,224 @@
+use std::fmt;
+
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Key, XChaCha20Poly1305, XNonce,
+};
+
+use crate::{DecodeError, Error};
+
+/// Leading bytes of every envelope.
+const MAGIC: &[u8; 4] = b"HLLE";
+/// Version of the envelope layout.
+const VERSION: u8 = 1;
+/// Length of an XChaCha20-Poly1305 nonce.
+const NONCE_LEN: usize = 24;
+/// Length of a Poly1305 tag.
+const TAG_LEN: usize = 16;
+
+/// A 256-bit key sealing and opening envelopes, with the id recorded in every envelope it
+/// seals so that the right key can be picked when keys are rotated.
+///
+/// The key itself is never printed by `Debug`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EnvelopeKey {
+    id: String,
+    key: [u8; 32],
+}
+
+impl EnvelopeKey {
+    /// Creates a key.
+    ///
+    /// # Parameters
+    /// * `id`: The id of the key, at most 255 bytes long.
+    /// * `key`: The raw XChaCha20-Poly1305 key.
+    ///
+    /// # Returns
+    /// A new `EnvelopeKey` instance.
+    ///
+    /// # Panics
+    /// Panics if `id` is longer than 255 bytes.
+    pub fn new(id: impl Into<String>, key: [u8; 32]) -> Self {
+        let id = id.into();
+        assert!(
+            id.len() <= u8::MAX as usize,
+            "key ids are at most 255 bytes"
+        );
+
+        Self { id, key }
+    }
+
+    /// Returns the id of the key.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(Key::from_slice(&self.key))
+    }
+}
+
+impl fmt::Debug for EnvelopeKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EnvelopeKey")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Encrypts a serialized sketch with XChaCha20-Poly1305, for sketches of sensitive
+/// aggregates that are persisted to untrusted storage.
+///
+/// The envelope is the magic bytes `HLLE`, a version byte, the length and bytes of the key
+/// id, a random 24-byte nonce and the ciphertext followed by its tag. The header is
+/// authenticated along with the payload, so neither can be altered without `open` failing.
+///
+/// # Parameters
+/// * `key`: The key to seal the payload with.
+/// * `payload`: The serialized sketch, in any format.
+///
+/// # Returns
+/// The sealed envelope.
+pub fn seal(key: &EnvelopeKey, payload: &[u8]) -> Vec<u8> {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let mut envelope =
+        Vec::with_capacity(MAGIC.len() + 2 + key.id.len() + NONCE_LEN + payload.len() + TAG_LEN);
+    envelope.extend_from_slice(MAGIC);
+    envelope.extend([VERSION, key.id.len() as u8]);
+    envelope.extend_from_slice(key.id.as_bytes());
+    envelope.extend_from_slice(&nonce);
+
+    let ciphertext = key
+        .cipher()
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: payload,
+                aad: &envelope,
+            },
+        )
+        .expect("payload is too large to encrypt");
+    envelope.extend_from_slice(&ciphertext);
+
+    envelope
+}
+
+/// Reads the id of the key an envelope was sealed with, without opening it.
+///
+/// # Parameters
+/// * `envelope`: The sealed envelope.
+///
+/// # Returns
+/// The key id, or an `Error::Decode` if the bytes are not an envelope.
+pub fn key_id(envelope: &[u8]) -> Result<&str, Error> {
+    let (id, _, _) = split(envelope)?;
+
+    Ok(id)
+}
+
+/// Decrypts an envelope sealed by `seal` with whichever of `keys` has its key id.
+///
+/// # Parameters
+/// * `envelope`: The sealed envelope.
+/// * `keys`: The keys the envelope may have been sealed with.
+///
+/// # Returns
+/// The serialized sketch, an `Error::UnknownKey` if none of `keys` has the key id of the
+/// envelope, an `Error::Authentication` if the envelope was altered or sealed with a
+/// different key of that id, or an `Error::Decode` if the bytes are not an envelope.
+pub fn open(envelope: &[u8], keys: &[EnvelopeKey]) -> Result<Vec<u8>, Error> {
+    let (id, header_len, ciphertext) = split(envelope)?;
+    let key = keys
+        .iter()
+        .find(|key| key.id == id)
+        .ok_or_else(|| Error::UnknownKey(id.to_string()))?;
+
+    let nonce = XNonce::from_slice(&envelope[header_len - NONCE_LEN..header_len]);
+    key.cipher()
+        .decrypt(
+            nonce,
+            Payload {
+                msg: ciphertext,
+                aad: &envelope[..header_len],
+            },
+        )
+        .map_err(|_| Error::Authentication)
+}
+
+/// Splits an envelope into its key id, the length of its header and its ciphertext.
+fn split(envelope: &[u8]) -> Result<(&str, usize, &[u8]), Error> {
+    let (version, id_len, rest) = match envelope.strip_prefix(MAGIC) {
+        Some([version, id_len, rest @ ..]) => (*version, *id_len as usize, rest),
+        _ => return Err(DecodeError::Malformed("not an envelope".to_string()).into()),
+    };
+
+    if version != VERSION {
+        return Err(DecodeError::Version {
+            found: version.into(),
+            supported: VERSION.into(),
+        }
+        .into());
+    }
+    if rest.len() < id_len + NONCE_LEN + TAG_LEN {
+        return Err(DecodeError::Truncated {
+            expected: id_len + NONCE_LEN + TAG_LEN,
+            found: rest.len(),
+        }
+        .into());
+    }
+
+    let id = std::str::from_utf8(&rest[..id_len])
+        .map_err(|_| DecodeError::Malformed("key id is not UTF-8".to_string()))?;
+    let header_len = MAGIC.len() + 2 + id_len + NONCE_LEN;
+
+    Ok((id, header_len, &envelope[header_len..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HyperLogLog;
+
+    #[test]
+    fn test_seal_and_open() {
+        let mut hll = HyperLogLog::new();
+        for i in 0..1_000 {
+            hll.add(i);
+        }
+        let payload = hll.registers.to_vec();
+
+        let old = EnvelopeKey::new("2023-q3", [1; 32]);
+        let new = EnvelopeKey::new("2023-q4", [2; 32]);
+        let envelope = seal(&new, &payload);
+
+        assert_eq!(key_id(&envelope), Ok("2023-q4"));
+        assert_ne!(&envelope[envelope.len() - payload.len()..], &payload[..]);
+        assert_eq!(open(&envelope, &[old.clone(), new]).unwrap(), payload);
+
+        assert_eq!(
+            open(&envelope, &[old]),
+            Err(Error::UnknownKey("2023-q4".to_string()))
+        );
+        assert!(!format!("{:?}", EnvelopeKey::new("k", [7; 32])).contains('7'));
+    }
+
+    #[test]
+    fn test_tampering_is_detected() {
+        let key = EnvelopeKey::new("k", [3; 32]);
+        let envelope = seal(&key, b"registers");
+
+        let mut altered = envelope.clone();
+        *altered.last_mut().unwrap() ^= 1;
+        assert_eq!(
+            open(&altered, std::slice::from_ref(&key)),
+            Err(Error::Authentication)
+        );
+
+        let impostor = EnvelopeKey::new("k", [4; 32]);
+        assert_eq!(open(&envelope, &[impostor]), Err(Error::Authentication));
+
+        assert!(open(&envelope[..20], &[key]).is_err());
+        assert!(key_id(b"HLLX").is_err());
+    }
+}
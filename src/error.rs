@@ -0,0 +1,172 @@
+use std::{error, fmt};
+
+use crate::{dump::HexDumpError, metadata::MetadataMismatch};
+
+/// Errors returned by the fallible (`try_`) operations of this crate.
+///
+/// Each of these operations has a panicking counterpart for callers that consider the
+/// failure a bug. Services handling sketches from storage or the network can use the
+/// `try_` variants instead and treat a bad sketch as a recoverable error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The sketches were built with a different precision, algorithm, hasher or seed.
+    Incompatible(MetadataMismatch),
+    /// A register array does not hold the expected number of registers.
+    Length { expected: usize, found: usize },
+    /// A register holds a value that no added item can produce.
+    InvalidRegister { index: usize, value: u8 },
+    /// A hex dump could not be parsed.
+    HexDump(HexDumpError),
+    /// A serialized sketch could not be decoded.
+    Decode(DecodeError),
+    /// An encrypted envelope failed authentication: it was altered, or sealed with a
+    /// different key.
+    Authentication,
+    /// No key with the id recorded in an encrypted envelope was provided.
+    UnknownKey(String),
+    /// A storage backend failed.
+    Storage(String),
+    /// A bias table is malformed.
+    InvalidBiasTable(String),
+    /// An `HllBuilder` holds settings that no sketch can be built with.
+    InvalidConfig(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Incompatible(mismatch) => mismatch.fmt(f),
+            Error::Length { expected, found } => {
+                write!(f, "expected {} registers, found {}", expected, found)
+            }
+            Error::InvalidRegister { index, value } => {
+                write!(f, "register {} holds invalid rank {}", index, value)
+            }
+            Error::HexDump(e) => e.fmt(f),
+            Error::Decode(e) => write!(f, "failed to decode sketch: {}", e),
+            Error::Authentication => write!(f, "envelope failed authentication"),
+            Error::UnknownKey(id) => write!(f, "no key with id {:?}", id),
+            Error::Storage(msg) => write!(f, "storage failed: {}", msg),
+            Error::InvalidBiasTable(msg) => write!(f, "invalid bias table: {}", msg),
+            Error::InvalidConfig(msg) => write!(f, "invalid configuration: {}", msg),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Incompatible(mismatch) => Some(mismatch),
+            Error::HexDump(e) => Some(e),
+            Error::Decode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<MetadataMismatch> for Error {
+    fn from(mismatch: MetadataMismatch) -> Self {
+        Error::Incompatible(mismatch)
+    }
+}
+
+impl From<HexDumpError> for Error {
+    fn from(e: HexDumpError) -> Self {
+        Error::HexDump(e)
+    }
+}
+
+impl From<DecodeError> for Error {
+    fn from(e: DecodeError) -> Self {
+        Error::Decode(e)
+    }
+}
+
+impl Error {
+    /// Reports a register array read from a serialized sketch as corrupt: a `Length` error
+    /// becomes `DecodeError::Truncated` or `DecodeError::Length`, and an `InvalidRegister`
+    /// error `DecodeError::InvalidRegister`. Other errors are returned unchanged.
+    pub(crate) fn into_decode(self) -> Self {
+        match self {
+            Error::Length { expected, found } if found < expected => {
+                DecodeError::Truncated { expected, found }.into()
+            }
+            Error::Length { expected, found } => DecodeError::Length { expected, found }.into(),
+            Error::InvalidRegister { index, value } => {
+                DecodeError::InvalidRegister { index, value }.into()
+            }
+            e => e,
+        }
+    }
+}
+
+/// The reason a serialized sketch could not be decoded, returned within `Error::Decode`
+/// by `deserialize_from` and the decoders of foreign formats, and reported in the
+/// message of the serde error when deserializing.
+///
+/// Services reading sketches from storage or the network can tell corruption in transit
+/// (`Base64`, `Lz4`, `Truncated`) from sketches written by another configuration or
+/// release (`Length`, `Version`) and from tampered registers (`InvalidRegister`), and log
+/// or alert on each accordingly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DecodeError {
+    /// The registers are not valid base64.
+    Base64(String),
+    /// The lz4 frame holding the registers is corrupt.
+    Lz4(String),
+    /// The payload ends early: `expected` bytes were needed, only `found` are left.
+    Truncated { expected: usize, found: usize },
+    /// The payload holds `found` registers where the sketch has `expected`, or `found`
+    /// bytes of packed registers where `expected` are needed.
+    Length { expected: usize, found: usize },
+    /// The payload was written in format `found`, newer than the `supported` one.
+    Version { found: u32, supported: u32 },
+    /// A register holds a value that no added item can produce.
+    InvalidRegister { index: usize, value: u8 },
+    /// The payload is not a sketch, or a field of it does not hold a valid value.
+    Malformed(String),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Base64(msg) => write!(f, "invalid base64: {}", msg),
+            DecodeError::Lz4(msg) => write!(f, "corrupt lz4 frame: {}", msg),
+            DecodeError::Truncated { expected, found } => write!(
+                f,
+                "truncated payload: {} bytes needed, {} left",
+                expected, found
+            ),
+            DecodeError::Length { expected, found } => {
+                write!(f, "expected {} registers, found {}", expected, found)
+            }
+            DecodeError::Version { found, supported } => write!(
+                f,
+                "unsupported format version {}, expected at most {}",
+                found, supported
+            ),
+            DecodeError::InvalidRegister { index, value } => {
+                write!(f, "register {} holds invalid rank {}", index, value)
+            }
+            DecodeError::Malformed(msg) => msg.fmt(f),
+        }
+    }
+}
+
+impl error::Error for DecodeError {}
+
+#[cfg(feature = "mmap")]
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Storage(e.to_string())
+    }
+}
+
+#[cfg(feature = "sled")]
+impl From<sled::Error> for Error {
+    fn from(e: sled::Error) -> Self {
+        Error::Storage(e.to_string())
+    }
+}
@@ -0,0 +1,237 @@
+use crate::{ertl, metadata::SketchMetadata, simd, Error, HyperLogLog, M};
+
+/// Storage of the registers of a `FrozenHyperLogLog`.
+#[derive(Debug, Clone)]
+enum FrozenRegisters {
+    /// The registers as a plain array.
+    Plain(Box<[u8; M]>),
+    /// The registers as a sequence of `(value, run length)` pairs, with the run length
+    /// encoded as a LEB128 varint.
+    RunLength(Box<[u8]>),
+}
+
+/// An immutable, read-optimized snapshot of a `HyperLogLog`, created with
+/// `HyperLogLog::freeze` or `HyperLogLog::freeze_compressed`.
+///
+/// The estimate and the register histogram are computed once when freezing, so reading
+/// them is free. A frozen sketch cannot be updated anymore, but it can still be merged
+/// into other sketches, which makes it a good fit for sketches that enter a query-only
+/// phase after ingestion.
+#[derive(Debug, Clone)]
+pub struct FrozenHyperLogLog {
+    registers: FrozenRegisters,
+    histogram: Box<[u32; 256]>,
+    estimate: f64,
+    metadata: SketchMetadata,
+}
+
+impl FrozenHyperLogLog {
+    /// Freezes a HyperLogLog, optionally run-length encoding its registers. The encoded
+    /// form is only kept when it is smaller than the plain registers, which is the case
+    /// for sketches holding few items.
+    pub(crate) fn new(hll: HyperLogLog, compress: bool) -> Self {
+        let estimate = hll.estimate();
+        let metadata = hll.metadata();
+        let histogram = ertl::histogram(&hll.registers);
+
+        let registers = match compress {
+            true => match run_length_encode(hll.registers.as_slice()) {
+                encoded if encoded.len() < M => FrozenRegisters::RunLength(encoded),
+                _ => FrozenRegisters::Plain(hll.registers),
+            },
+            false => FrozenRegisters::Plain(hll.registers),
+        };
+
+        Self {
+            registers,
+            histogram,
+            estimate,
+            metadata,
+        }
+    }
+
+    /// Returns the estimate computed when the sketch was frozen.
+    ///
+    /// # Returns
+    /// A `f64` approximate count of unique items.
+    #[inline(always)]
+    pub fn estimate(&self) -> f64 {
+        self.estimate
+    }
+
+    /// Returns the register histogram, where entry `i` is the number of registers
+    /// holding the value `i`.
+    pub fn histogram(&self) -> &[u32; 256] {
+        &self.histogram
+    }
+
+    /// Returns the metadata of the sketch that was frozen.
+    pub fn metadata(&self) -> SketchMetadata {
+        self.metadata
+    }
+
+    /// Returns `true` when the registers are held in run-length encoded form.
+    pub fn is_compressed(&self) -> bool {
+        matches!(self.registers, FrozenRegisters::RunLength(_))
+    }
+
+    /// Returns the number of bytes used to hold the registers.
+    pub fn registers_size(&self) -> usize {
+        match &self.registers {
+            FrozenRegisters::Plain(registers) => registers.len(),
+            FrozenRegisters::RunLength(encoded) => encoded.len(),
+        }
+    }
+
+    /// Merges the frozen registers into another HyperLogLog.
+    ///
+    /// # Parameters
+    /// * `target`: The `HyperLogLog` the registers are merged into.
+    ///
+    /// # Panics
+    /// Panics if `target` was built with different metadata, see `try_merge_into`.
+    pub fn merge_into(&self, target: &mut HyperLogLog) {
+        if let Err(e) = self.try_merge_into(target) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Merges the frozen registers into another HyperLogLog after checking that both were
+    /// built with the same precision, algorithm, hasher and seed.
+    ///
+    /// # Parameters
+    /// * `target`: The `HyperLogLog` the registers are merged into.
+    ///
+    /// # Returns
+    /// `Ok(())` once merged, or an `Error::Incompatible` leaving `target` untouched.
+    pub fn try_merge_into(&self, target: &mut HyperLogLog) -> Result<(), Error> {
+        target.metadata().check_compatible(&self.metadata)?;
+
+        match &self.registers {
+            FrozenRegisters::Plain(registers) => {
+                simd::merge_max(target.registers.as_mut_slice(), registers.as_slice())
+            }
+            FrozenRegisters::RunLength(encoded) => {
+                let mut offset = 0;
+
+                for (value, run) in RunLengthIter::new(encoded) {
+                    if value > 0 {
+                        for register in &mut target.registers[offset..offset + run] {
+                            *register = (*register).max(value);
+                        }
+                    }
+
+                    offset += run;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl HyperLogLog {
+    /// Freezes the HyperLogLog into an immutable sketch with a precomputed estimate.
+    ///
+    /// # Returns
+    /// A `FrozenHyperLogLog` holding the registers as they are.
+    pub fn freeze(self) -> FrozenHyperLogLog {
+        FrozenHyperLogLog::new(self, false)
+    }
+
+    /// Freezes the HyperLogLog into an immutable sketch with a precomputed estimate,
+    /// run-length encoding the registers in memory when that makes them smaller.
+    ///
+    /// # Returns
+    /// A `FrozenHyperLogLog`, possibly holding compressed registers.
+    pub fn freeze_compressed(self) -> FrozenHyperLogLog {
+        FrozenHyperLogLog::new(self, true)
+    }
+}
+
+/// Encodes registers as `(value, run length)` pairs with LEB128 run lengths.
+fn run_length_encode(registers: &[u8]) -> Box<[u8]> {
+    let mut encoded = Vec::new();
+    let mut offset = 0;
+
+    while offset < registers.len() && encoded.len() < registers.len() {
+        let value = registers[offset];
+        let mut run = registers[offset..]
+            .iter()
+            .take_while(|&&v| v == value)
+            .count();
+        offset += run;
+
+        encoded.push(value);
+        while run >= 0x80 {
+            encoded.push((run as u8 & 0x7f) | 0x80);
+            run >>= 7;
+        }
+        encoded.push(run as u8);
+    }
+
+    encoded.into_boxed_slice()
+}
+
+/// Iterator over the `(value, run length)` pairs of run-length encoded registers.
+struct RunLengthIter<'a> {
+    encoded: &'a [u8],
+}
+
+impl<'a> RunLengthIter<'a> {
+    fn new(encoded: &'a [u8]) -> Self {
+        Self { encoded }
+    }
+}
+
+impl Iterator for RunLengthIter<'_> {
+    type Item = (u8, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (&value, mut rest) = self.encoded.split_first()?;
+        let mut run = 0;
+        let mut shift = 0;
+
+        while let Some((&byte, tail)) = rest.split_first() {
+            rest = tail;
+            run |= ((byte & 0x7f) as usize) << shift;
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+
+        self.encoded = rest;
+        Some((value, run))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_freeze() {
+        let mut hll = HyperLogLog::new();
+        for i in 0..1_000 {
+            hll.add(i);
+        }
+        let estimate = hll.estimate();
+
+        let frozen = hll.clone().freeze();
+        assert!(!frozen.is_compressed());
+        assert_eq!(frozen.estimate(), estimate);
+        assert_eq!(frozen.histogram().iter().sum::<u32>(), M as u32);
+
+        let compressed = hll.clone().freeze_compressed();
+        assert!(compressed.is_compressed());
+        assert!(compressed.registers_size() < M);
+        assert_eq!(compressed.estimate(), estimate);
+        assert_eq!(compressed.histogram(), frozen.histogram());
+
+        let mut target = HyperLogLog::new();
+        compressed.merge_into(&mut target);
+        assert!(target.diff(&hll).is_identical());
+    }
+}
@@ -0,0 +1,223 @@
+#[cfg(feature = "simd")]
+use packed_simd::u64x4;
+
+use super::long::diffuse;
+#[cfg(feature = "simd")]
+use super::long::{self, read_u64, DIFFUSE_K};
+use crate::metadata::{HasherId, SketchMetadata};
+
+/// Strings shorter than this are hashed four at a time by `hash_strs`, longer ones one by
+/// one. A string is followed by a `0xff` terminator, so the longest short string is hashed
+/// from four words.
+#[cfg(feature = "simd")]
+const SHORT_STR_LEN: usize = 32;
+
+/// Hashes integers exactly as `add(item)` does, writing the hash of `items[i]` to
+/// `hashes[i]`.
+///
+/// With SeaHash, an integer is a single word: its hash is the key lanes XOR-ed with the
+/// diffused word and the length, diffused once more. Four integers are hashed at a time,
+/// one per lane of a `u64x4`. Other hashers hash the integers one by one.
+///
+/// # Arguments
+///
+/// * `metadata`: The metadata of the sketch, selecting the hash function.
+/// * `items`: The integers to hash.
+/// * `hashes`: The hashes, as many as `items`.
+#[inline(always)]
+pub(crate) fn hash_u64s(metadata: &SketchMetadata, items: &[u64], hashes: &mut [u64]) {
+    debug_assert_eq!(items.len(), hashes.len());
+
+    match metadata.hasher {
+        HasherId::SeaHash => seahash_u64s(metadata.key, items, hashes),
+        _ => {
+            for (hash, item) in hashes.iter_mut().zip(items) {
+                *hash = super::hash_item(metadata, item);
+            }
+        }
+    }
+}
+
+/// Hashes integers with SeaHash keyed by `key`, four at a time, see `hash_u64s`.
+#[cfg(feature = "simd")]
+#[inline(always)]
+fn seahash_u64s(key: [u64; 4], items: &[u64], hashes: &mut [u64]) {
+    let [k0, k1, k2, k3] = key;
+    let k = u64x4::splat(DIFFUSE_K);
+    let first = u64x4::splat(k0);
+    let rest = u64x4::splat(k1 ^ k2 ^ k3 ^ 8);
+
+    let mut words = items.chunks_exact(4);
+    let mut out = hashes.chunks_exact_mut(4);
+    for (words, out) in (&mut words).zip(&mut out) {
+        let x = diffuse_x4(first ^ u64x4::from_slice_unaligned(words), k);
+        diffuse_x4(x ^ rest, k).write_to_slice_unaligned(out);
+    }
+
+    seahash_u64s_scalar(key, words.remainder(), out.into_remainder());
+}
+
+/// Scalar counterpart of the vectorized `seahash_u64s`, compiled without the `simd`
+/// feature.
+#[cfg(not(feature = "simd"))]
+#[inline(always)]
+fn seahash_u64s(key: [u64; 4], items: &[u64], hashes: &mut [u64]) {
+    seahash_u64s_scalar(key, items, hashes)
+}
+
+/// Hashes strings exactly as `add(s)` does, writing the hash of `items[i]` to `hashes[i]`.
+///
+/// With SeaHash, a string is fed to the hasher as its bytes followed by a `0xff`
+/// terminator, pushed word by word into the four lanes of the hasher state. Four short
+/// strings are hashed at a time, each lane of four `u64x4` holding the state of one
+/// string; a string running out of words keeps its state while the others are pushed.
+/// Other hashers, and groups holding a string of at least `SHORT_STR_LEN` bytes, hash the
+/// strings one by one.
+///
+/// # Arguments
+///
+/// * `metadata`: The metadata of the sketch, selecting the hash function.
+/// * `items`: The strings to hash.
+/// * `hashes`: The hashes, as many as `items`.
+#[inline(always)]
+pub(crate) fn hash_strs<S: AsRef<str>>(metadata: &SketchMetadata, items: &[S], hashes: &mut [u64]) {
+    debug_assert_eq!(items.len(), hashes.len());
+
+    #[cfg(feature = "simd")]
+    if metadata.hasher == HasherId::SeaHash {
+        seahash_strs(metadata.key, items, hashes);
+        return;
+    }
+
+    for (hash, item) in hashes.iter_mut().zip(items) {
+        *hash = super::hash_str(metadata, item.as_ref());
+    }
+}
+
+/// Hashes strings with SeaHash keyed by `key`, four short strings at a time, see
+/// `hash_strs`.
+#[cfg(feature = "simd")]
+#[inline(always)]
+fn seahash_strs<S: AsRef<str>>(key: [u64; 4], items: &[S], hashes: &mut [u64]) {
+    let mut groups = items.chunks_exact(4);
+    let mut out = hashes.chunks_exact_mut(4);
+
+    for (group, out) in (&mut groups).zip(&mut out) {
+        let group = [0, 1, 2, 3].map(|i| group[i].as_ref());
+
+        match group.iter().all(|s| s.len() < SHORT_STR_LEN) {
+            true => seahash_short_strs(key, group.map(str::as_bytes)).write_to_slice_unaligned(out),
+            false => {
+                for (hash, s) in out.iter_mut().zip(group) {
+                    *hash = long::hash_str_with_key(s, key);
+                }
+            }
+        }
+    }
+
+    for (hash, s) in out.into_remainder().iter_mut().zip(groups.remainder()) {
+        *hash = long::hash_str_with_key(s.as_ref(), key);
+    }
+}
+
+/// Hashes four strings shorter than `SHORT_STR_LEN` with SeaHash keyed by `key`, one per
+/// lane.
+#[cfg(feature = "simd")]
+#[inline(always)]
+fn seahash_short_strs(key: [u64; 4], strs: [&[u8]; 4]) -> u64x4 {
+    let k = u64x4::splat(DIFFUSE_K);
+    // Every string followed by its terminator and zero padding up to whole words
+    let mut bufs = [[0u8; SHORT_STR_LEN + 8]; 4];
+    for (buf, s) in bufs.iter_mut().zip(strs) {
+        buf[..s.len()].copy_from_slice(s);
+        buf[s.len()] = 0xff;
+    }
+    let lens = strs.map(|s| s.len() as u64 + 1);
+    let len = u64x4::from(lens);
+    let words = u64x4::from(lens.map(|len| len / 8));
+
+    let [mut a, mut b, mut c, mut d] = key.map(u64x4::splat);
+    let word_at = |offset: usize| u64x4::from(bufs.map(|buf| read_u64(&buf[offset..offset + 8])));
+
+    for w in 0..(lens.iter().max().copied().unwrap_or(0) / 8) {
+        let pushed = words.gt(u64x4::splat(w));
+        let lane = diffuse_x4(a ^ word_at(w as usize * 8), k);
+
+        a = pushed.select(b, a);
+        b = pushed.select(c, b);
+        c = pushed.select(d, c);
+        d = pushed.select(lane, d);
+    }
+
+    let tail = u64x4::from([0, 1, 2, 3].map(|i| {
+        let offset = (lens[i] / 8 * 8) as usize;
+        read_u64(&bufs[i][offset..offset + 8])
+    }));
+    let has_tail = u64x4::from(lens.map(|len| len % 8)).ne(u64x4::splat(0));
+    let first = has_tail.select(diffuse_x4(a ^ tail, k), a);
+
+    diffuse_x4(first ^ b ^ c ^ d ^ len, k)
+}
+
+/// Hashes integers with SeaHash keyed by `key`, one at a time.
+#[inline(always)]
+fn seahash_u64s_scalar(key: [u64; 4], items: &[u64], hashes: &mut [u64]) {
+    let [k0, k1, k2, k3] = key;
+
+    for (hash, &item) in hashes.iter_mut().zip(items) {
+        *hash = diffuse(diffuse(k0 ^ item) ^ k1 ^ k2 ^ k3 ^ 8);
+    }
+}
+
+/// The SeaHash diffusion function applied to four words at once.
+#[cfg(feature = "simd")]
+#[inline(always)]
+fn diffuse_x4(x: u64x4, k: u64x4) -> u64x4 {
+    let x = x * k;
+    let x = x ^ ((x >> 32) >> (x >> 60));
+
+    x * k
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::{hash_item, DEFAULT_SEAHASH_KEY};
+
+    #[test]
+    fn test_matches_hash_item() {
+        let items = (0..103u64)
+            .map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+            .collect::<Vec<_>>();
+        let mut metadata = SketchMetadata::default();
+
+        for key in [DEFAULT_SEAHASH_KEY, [1, 2, 3, 4]] {
+            metadata.key = key;
+            let mut hashes = vec![0; items.len()];
+            hash_u64s(&metadata, &items, &mut hashes);
+
+            for (hash, item) in hashes.iter().zip(&items) {
+                assert_eq!(*hash, hash_item(&metadata, item));
+            }
+        }
+    }
+
+    #[test]
+    fn test_strs_match_hash_item() {
+        let text = "https://example.com/a/rather/long/path?with=query&and=more#fragment";
+        // Groups of short strings of every length, then a group with a long one
+        let mut items = (0..=40).map(|len| &text[..len]).collect::<Vec<_>>();
+        items.extend([&text[..3], &text[..5], text, &text[..7], &text[..1]]);
+        let mut metadata = SketchMetadata::default();
+
+        for key in [DEFAULT_SEAHASH_KEY, [1, 2, 3, 4]] {
+            metadata.key = key;
+            let mut hashes = vec![0; items.len()];
+            hash_strs(&metadata, &items, &mut hashes);
+
+            for (hash, item) in hashes.iter().zip(&items) {
+                assert_eq!(*hash, hash_item(&metadata, item), "{:?}", item);
+            }
+        }
+    }
+}
@@ -0,0 +1,252 @@
+#[cfg(feature = "simd")]
+use packed_simd::u64x4;
+
+/// Keys at least this long are hashed by `hash_slice` and `hash_str` rather than through
+/// the streaming `SeaHasher`, when added with `add_bytes`, `add_str` or `add_iter_bytes`.
+pub const LONG_KEY_LEN: usize = 64;
+
+/// Multiplier of the SeaHash diffusion function.
+pub(super) const DIFFUSE_K: u64 = 0x6eed_0e9d_a4d9_4a4f;
+
+/// Hashes a byte slice exactly as `add(bytes)` does with the default SeaHash hasher,
+/// i.e. as `seahash::SeaHasher` fed by the `Hash` implementation of `[u8]`, which writes
+/// the length before the bytes.
+///
+/// SeaHash consumes its input 32 bytes at a time, one 64-bit word for each of its four
+/// independent lanes. This implementation diffuses the four lanes in a single `u64x4`,
+/// which keeps long keys such as URLs or user agents from being bound by scalar hashing.
+///
+/// # Parameters
+/// * `bytes`: The bytes of the item.
+///
+/// # Returns
+/// The 64-bit hash.
+pub fn hash_slice(bytes: &[u8]) -> u64 {
+    hash_slice_with_key(bytes, super::DEFAULT_SEAHASH_KEY)
+}
+
+/// Hashes a byte slice like `hash_slice`, with SeaHash keyed by `key` instead of the
+/// default key, i.e. as `seahash::SeaHasher::with_seeds` would.
+///
+/// # Parameters
+/// * `bytes`: The bytes of the item.
+/// * `key`: The 256-bit SeaHash key.
+///
+/// # Returns
+/// The 64-bit hash.
+pub fn hash_slice_with_key(bytes: &[u8], key: [u64; 4]) -> u64 {
+    let mut lanes = Lanes::new(key);
+    lanes.write(&bytes.len().to_le_bytes());
+    lanes.write(bytes);
+
+    lanes.finish()
+}
+
+/// Hashes a string exactly as `add(s)` does with the default SeaHash hasher, i.e. as
+/// `seahash::SeaHasher` fed by the `Hash` implementation of `str`, which writes a `0xff`
+/// terminator after the bytes. See `hash_slice`.
+///
+/// # Parameters
+/// * `s`: The item.
+///
+/// # Returns
+/// The 64-bit hash.
+pub fn hash_str(s: &str) -> u64 {
+    hash_str_with_key(s, super::DEFAULT_SEAHASH_KEY)
+}
+
+/// Hashes a string like `hash_str`, with SeaHash keyed by `key` instead of the default
+/// key, i.e. as `seahash::SeaHasher::with_seeds` would.
+///
+/// # Parameters
+/// * `s`: The item.
+/// * `key`: The 256-bit SeaHash key.
+///
+/// # Returns
+/// The 64-bit hash.
+pub fn hash_str_with_key(s: &str, key: [u64; 4]) -> u64 {
+    let mut lanes = Lanes::new(key);
+    lanes.write(s.as_bytes());
+    lanes.write(&[0xff]);
+
+    lanes.finish()
+}
+
+/// The streaming SeaHash state: four lanes, the number of bytes pushed into them, and up
+/// to seven trailing bytes not yet forming a full word.
+struct Lanes {
+    state: [u64; 4],
+    written: u64,
+    tail: u64,
+    ntail: usize,
+}
+
+impl Lanes {
+    fn new(key: [u64; 4]) -> Self {
+        Self {
+            state: key,
+            written: 0,
+            tail: 0,
+            ntail: 0,
+        }
+    }
+
+    /// Pushes a word into the first lane and rotates the lanes.
+    #[inline(always)]
+    fn push(&mut self, word: u64) {
+        let lane = diffuse(self.state[0] ^ word);
+        self.state.rotate_left(1);
+        self.state[3] = lane;
+        self.written += 8;
+    }
+
+    /// Feeds bytes like `SeaHasher::write`: the pending tail is completed first, then
+    /// whole 32-byte blocks are diffused lane-wise, and the remaining words are pushed.
+    fn write(&mut self, bytes: &[u8]) {
+        let copied = (8 - self.ntail).min(bytes.len());
+        let mut tail = self.tail.to_le_bytes();
+        tail[self.ntail..self.ntail + copied].copy_from_slice(&bytes[..copied]);
+
+        if self.ntail + copied != 8 {
+            self.ntail += copied;
+            self.tail = u64::from_le_bytes(tail);
+            return;
+        }
+        self.push(u64::from_le_bytes(tail));
+        self.ntail = 0;
+        self.tail = 0;
+
+        let rest = &bytes[copied..];
+        let (blocks, excess) = rest.split_at(rest.len() & !31);
+
+        if !blocks.is_empty() {
+            self.diffuse_blocks(blocks);
+            self.written += blocks.len() as u64;
+        }
+
+        let mut words = excess.chunks_exact(8);
+        for word in &mut words {
+            self.push(read_u64(word));
+        }
+        let remainder = words.remainder();
+        self.tail = read_int(remainder);
+        self.ntail = remainder.len();
+    }
+
+    /// Diffuses whole 32-byte blocks, one word per lane, the four lanes in a `u64x4`.
+    #[cfg(feature = "simd")]
+    #[inline(always)]
+    fn diffuse_blocks(&mut self, blocks: &[u8]) {
+        let k = u64x4::splat(DIFFUSE_K);
+        let mut lanes = u64x4::from(self.state);
+
+        for block in blocks.chunks_exact(32) {
+            let words = u64x4::new(
+                read_u64(&block[..8]),
+                read_u64(&block[8..16]),
+                read_u64(&block[16..24]),
+                read_u64(&block[24..]),
+            );
+            let x = (lanes ^ words) * k;
+            let x = x ^ ((x >> 32) >> (x >> 60));
+            lanes = x * k;
+        }
+
+        self.state = lanes.into();
+    }
+
+    /// Scalar counterpart of the vectorized `diffuse_blocks`, compiled without the `simd`
+    /// feature.
+    #[cfg(not(feature = "simd"))]
+    #[inline(always)]
+    fn diffuse_blocks(&mut self, blocks: &[u8]) {
+        for block in blocks.chunks_exact(32) {
+            for (lane, word) in self.state.iter_mut().zip(block.chunks_exact(8)) {
+                *lane = diffuse(*lane ^ read_u64(word));
+            }
+        }
+    }
+
+    /// Finalizes the hash like `SeaHasher::finish`.
+    fn finish(&self) -> u64 {
+        let first = match self.ntail {
+            0 => self.state[0],
+            _ => diffuse(self.state[0] ^ self.tail),
+        };
+
+        diffuse(
+            first
+                ^ self.state[1]
+                ^ self.state[2]
+                ^ self.state[3]
+                ^ (self.written + self.ntail as u64),
+        )
+    }
+}
+
+/// The SeaHash diffusion function, a bijective mixing of a 64-bit word.
+#[inline(always)]
+pub(super) fn diffuse(mut x: u64) -> u64 {
+    x = x.wrapping_mul(DIFFUSE_K);
+    x ^= (x >> 32) >> (x >> 60);
+
+    x.wrapping_mul(DIFFUSE_K)
+}
+
+/// Reads a little-endian word from exactly eight bytes.
+#[inline(always)]
+pub(super) fn read_u64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes.try_into().unwrap())
+}
+
+/// Reads up to seven bytes as a little-endian integer.
+#[inline(always)]
+fn read_int(bytes: &[u8]) -> u64 {
+    let mut word = [0; 8];
+    word[..bytes.len()].copy_from_slice(bytes);
+
+    u64::from_le_bytes(word)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::{Hash, Hasher};
+
+    use seahash::SeaHasher;
+
+    use super::*;
+
+    fn seahash<T: Hash + ?Sized>(item: &T) -> u64 {
+        let mut hasher = SeaHasher::new();
+        item.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_matches_seahasher() {
+        let bytes = (0..300u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8)
+            .collect::<Vec<_>>();
+        let text = "https://example.com/a/rather/long/path?with=query&and=more#fragment".repeat(5);
+
+        for len in 0..bytes.len() {
+            assert_eq!(hash_slice(&bytes[..len]), seahash(&bytes[..len]), "{}", len);
+            assert_eq!(hash_str(&text[..len]), seahash(&text[..len]), "{}", len);
+        }
+    }
+
+    #[test]
+    fn test_matches_keyed_seahasher() {
+        let key = [1, 2, 3, 4];
+        let text = "https://example.com/a/rather/long/path?with=query".repeat(3);
+
+        let mut hasher = SeaHasher::with_seeds(1, 2, 3, 4);
+        text.as_bytes().hash(&mut hasher);
+        assert_eq!(hash_slice_with_key(text.as_bytes(), key), hasher.finish());
+
+        let mut hasher = SeaHasher::with_seeds(1, 2, 3, 4);
+        text.hash(&mut hasher);
+        assert_eq!(hash_str_with_key(&text, key), hasher.finish());
+        assert_ne!(hash_str_with_key(&text, key), hash_str(&text));
+    }
+}
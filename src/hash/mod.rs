@@ -0,0 +1,273 @@
+#[cfg(feature = "ahash")]
+use std::hash::BuildHasher;
+use std::hash::{Hash, Hasher};
+
+use seahash::SeaHasher;
+
+use crate::metadata::{HasherId, SketchMetadata};
+
+pub(crate) mod batch;
+pub mod long;
+#[cfg(feature = "murmur3")]
+pub mod murmur3;
+pub mod siphash;
+pub mod stable;
+pub mod wide;
+
+/// The key of `seahash::SeaHasher::new`, used by sketches hashing items with SeaHash unless
+/// another 256-bit key is set with `with_seed`.
+pub const DEFAULT_SEAHASH_KEY: [u64; 4] = [
+    0x16f1_1fe8_9b0d_677c,
+    0xb480_a793_d8e6_c86c,
+    0x6fe2_e5aa_f078_ebc9,
+    0x14f9_94a4_c525_9381,
+];
+
+/// Hashes an item with the hash function and seed recorded in the metadata of a sketch.
+///
+/// # Arguments
+///
+/// * `metadata`: The metadata of the sketch the item is added to.
+/// * `item`: The item to hash.
+///
+/// # Returns
+/// The 64-bit hash the registers are updated from.
+#[inline(always)]
+pub(crate) fn hash_item<T: Hash + ?Sized>(metadata: &SketchMetadata, item: &T) -> u64 {
+    match metadata.hasher {
+        HasherId::SeaHash => {
+            let [k1, k2, k3, k4] = metadata.key;
+            finish(SeaHasher::with_seeds(k1, k2, k3, k4), item)
+        }
+        HasherId::SipHash => finish(siphash::SipHasher24::deployment(metadata.seed), item),
+        #[cfg(feature = "murmur3")]
+        HasherId::Murmur3 => finish(
+            murmur3::Murmur3Hasher::with_seed(metadata.seed as u32),
+            item,
+        ),
+        #[cfg(feature = "xxh3")]
+        HasherId::Xxh3 => finish(xxhash_rust::xxh3::Xxh3::with_seed(metadata.seed), item),
+        #[cfg(feature = "wyhash")]
+        HasherId::WyHash => finish(wyhash::WyHash::with_seed(metadata.seed), item),
+        #[cfg(feature = "ahash")]
+        HasherId::AHash => finish(
+            ahash::RandomState::with_seeds(metadata.seed, 0, 0, 0).build_hasher(),
+            item,
+        ),
+    }
+}
+
+/// Hashes a byte slice like `hash_item`, taking the vectorized SeaHash path of
+/// `long::hash_slice_with_key` for keys of at least `long::LONG_KEY_LEN` bytes.
+///
+/// # Arguments
+///
+/// * `metadata`: The metadata of the sketch the item is added to.
+/// * `bytes`: The item.
+#[inline(always)]
+pub(crate) fn hash_bytes(metadata: &SketchMetadata, bytes: &[u8]) -> u64 {
+    match metadata.hasher {
+        HasherId::SeaHash if bytes.len() >= long::LONG_KEY_LEN => {
+            long::hash_slice_with_key(bytes, metadata.key)
+        }
+        _ => hash_item(metadata, bytes),
+    }
+}
+
+/// Hashes a string like `hash_item`, taking the vectorized SeaHash path of
+/// `long::hash_str_with_key` for keys of at least `long::LONG_KEY_LEN` bytes.
+///
+/// # Arguments
+///
+/// * `metadata`: The metadata of the sketch the item is added to.
+/// * `s`: The item.
+#[inline(always)]
+pub(crate) fn hash_str(metadata: &SketchMetadata, s: &str) -> u64 {
+    match metadata.hasher {
+        HasherId::SeaHash if s.len() >= long::LONG_KEY_LEN => {
+            long::hash_str_with_key(s, metadata.key)
+        }
+        _ => hash_item(metadata, s),
+    }
+}
+
+/// Hashes raw bytes with the hash function and seed recorded in the metadata of a sketch,
+/// without the framing of their `Hash` implementation, as libraries in other languages
+/// hash them: with `HasherId::Murmur3`, the hash is the first 64-bit half of
+/// `murmur3::murmur3_x64_128`, i.e. Guava's `Hashing.murmur3_128(seed).hashBytes(bytes)
+/// .asLong()`.
+///
+/// # Arguments
+///
+/// * `metadata`: The metadata of the sketch the item is added to.
+/// * `bytes`: The bytes of the item.
+#[inline(always)]
+pub(crate) fn hash_raw(metadata: &SketchMetadata, bytes: &[u8]) -> u64 {
+    hash_item(metadata, &Raw(bytes))
+}
+
+/// Returns the seed recorded by a sketch newly built with `hasher`: the fingerprint of the
+/// deployment key for `HasherId::SipHash`, and `0` otherwise.
+///
+/// # Panics
+/// Panics if `hasher` is `HasherId::SipHash` and no key was set.
+pub(crate) fn default_seed(hasher: HasherId) -> u64 {
+    match hasher {
+        HasherId::SipHash => {
+            siphash::key_fingerprint().expect("no SipHash key set, see hash::siphash::set_key")
+        }
+        _ => 0,
+    }
+}
+
+/// Feeds an item to a hasher and returns its hash.
+#[inline(always)]
+fn finish<H: Hasher, T: Hash + ?Sized>(hasher: H, item: &T) -> u64 {
+    let mut hasher = LittleEndian(hasher);
+    item.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Bytes whose `Hash` implementation writes them as they are, without a length prefix.
+struct Raw<'a>(&'a [u8]);
+
+impl Hash for Raw<'_> {
+    #[inline(always)]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write(self.0)
+    }
+}
+
+/// Feeds integers to the wrapped hasher as little-endian bytes.
+///
+/// `Hash` implementations of integers call `write_u64` and friends, which the standard
+/// library implements with native-endian bytes unless a hasher overrides them, as SeaHash
+/// does. Wrapping every hasher makes the hash of an item, and so the registers of a
+/// sketch, identical on little- and big-endian targets.
+struct LittleEndian<H>(H);
+
+impl<H: Hasher> Hasher for LittleEndian<H> {
+    #[inline(always)]
+    fn finish(&self) -> u64 {
+        self.0.finish()
+    }
+
+    #[inline(always)]
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.write(bytes)
+    }
+
+    #[inline(always)]
+    fn write_u8(&mut self, i: u8) {
+        self.0.write_u8(i)
+    }
+
+    #[inline(always)]
+    fn write_u16(&mut self, i: u16) {
+        self.0.write(&i.to_le_bytes())
+    }
+
+    #[inline(always)]
+    fn write_u32(&mut self, i: u32) {
+        self.0.write(&i.to_le_bytes())
+    }
+
+    #[inline(always)]
+    fn write_u64(&mut self, i: u64) {
+        self.0.write(&i.to_le_bytes())
+    }
+
+    #[inline(always)]
+    fn write_u128(&mut self, i: u128) {
+        self.0.write(&i.to_le_bytes())
+    }
+
+    #[inline(always)]
+    fn write_usize(&mut self, i: usize) {
+        self.0.write(&i.to_le_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HyperLogLog, M};
+
+    #[test]
+    fn test_every_hasher_estimates() {
+        siphash::set_key([7; 16]);
+        let hashers = [
+            HasherId::SeaHash,
+            HasherId::SipHash,
+            #[cfg(feature = "murmur3")]
+            HasherId::Murmur3,
+            #[cfg(feature = "xxh3")]
+            HasherId::Xxh3,
+            #[cfg(feature = "wyhash")]
+            HasherId::WyHash,
+            #[cfg(feature = "ahash")]
+            HasherId::AHash,
+        ];
+
+        for hasher in hashers {
+            let mut hll = HyperLogLog::with_hasher(hasher);
+            for i in 0..10_000 {
+                hll.add(i);
+            }
+
+            let error = (hll.estimate() - 10_000.0).abs() / 10_000.0;
+            assert!(
+                error < 4.0 * 1.04 / (M as f64).sqrt(),
+                "{} relative error {}",
+                hasher.name(),
+                error
+            );
+            assert_eq!(HasherId::from_name(hasher.name()), Some(hasher));
+        }
+    }
+
+    /// An integer whose `Hash` implementation writes its little-endian bytes, which is
+    /// what every hasher must see for an integer on any target.
+    struct LeBytes(u64);
+
+    impl Hash for LeBytes {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            state.write(&self.0.to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn test_integers_hash_as_little_endian() {
+        siphash::set_key([7; 16]);
+        let hashers = [
+            HasherId::SeaHash,
+            HasherId::SipHash,
+            #[cfg(feature = "murmur3")]
+            HasherId::Murmur3,
+            #[cfg(feature = "xxh3")]
+            HasherId::Xxh3,
+            #[cfg(feature = "wyhash")]
+            HasherId::WyHash,
+            #[cfg(feature = "ahash")]
+            HasherId::AHash,
+        ];
+
+        for hasher in hashers {
+            let metadata = HyperLogLog::with_hasher(hasher).metadata();
+            for n in [0, 1, 0x0102_0304_0506_0708, u64::MAX] {
+                assert_eq!(
+                    hash_item(&metadata, &n),
+                    hash_item(&metadata, &LeBytes(n)),
+                    "{}",
+                    hasher.name()
+                );
+            }
+        }
+
+        // The bytes hashed for an integer are those of the stable scheme
+        assert_eq!(
+            hash_item(&HyperLogLog::new().metadata(), &42u64),
+            seahash::hash(&42u64.to_le_bytes())
+        );
+    }
+}
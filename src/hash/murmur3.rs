@@ -0,0 +1,199 @@
+use std::hash::Hasher;
+
+const C1: u64 = 0x87c3_7b91_1142_53d5;
+const C2: u64 = 0x4cf5_ad43_2745_937f;
+
+/// Length of the blocks MurmurHash3 x64_128 consumes.
+const BLOCK_LEN: usize = 16;
+
+/// Computes the MurmurHash3 x64_128 hash of a byte string, as ZetaSketch, stream-lib,
+/// Guava and most other JVM libraries do.
+///
+/// # Parameters
+/// * `bytes`: The bytes to hash.
+/// * `seed`: The seed of the hash.
+///
+/// # Returns
+/// The 128-bit hash, with the first 64-bit half of the reference implementation in the low
+/// bits, so that `hash as u64` is the value Guava's `HashCode.asLong()` returns.
+pub fn murmur3_x64_128(bytes: &[u8], seed: u32) -> u128 {
+    let mut hasher = Murmur3Hasher::with_seed(seed);
+    hasher.write(bytes);
+
+    hasher.finish128()
+}
+
+/// A streaming MurmurHash3 x64_128 hasher.
+///
+/// Bytes can be written in any number of calls, and hash exactly as if they were passed
+/// to `murmur3_x64_128` at once. Items passed through the `Hash` trait are framed by
+/// their `Hash` implementation first (e.g. a `str` is followed by a `0xff` byte), so only
+/// raw bytes written with `write` hash as they do in other languages.
+#[derive(Debug, Clone)]
+pub struct Murmur3Hasher {
+    h1: u64,
+    h2: u64,
+    tail: [u8; BLOCK_LEN],
+    tail_len: usize,
+    len: u64,
+}
+
+impl Murmur3Hasher {
+    /// Creates a hasher with the given seed.
+    ///
+    /// # Parameters
+    /// * `seed`: The seed of the hash.
+    ///
+    /// # Returns
+    /// A new `Murmur3Hasher` instance.
+    pub fn with_seed(seed: u32) -> Self {
+        Self {
+            h1: u64::from(seed),
+            h2: u64::from(seed),
+            tail: [0; BLOCK_LEN],
+            tail_len: 0,
+            len: 0,
+        }
+    }
+
+    /// Returns the 128-bit hash of the bytes written so far, laid out as by
+    /// `murmur3_x64_128`.
+    pub fn finish128(&self) -> u128 {
+        let (mut h1, mut h2) = (self.h1, self.h2);
+        let tail = &self.tail[..self.tail_len];
+
+        if tail.len() > 8 {
+            let mut k2 = read_partial(&tail[8..]);
+            k2 = k2.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1);
+            h2 ^= k2;
+        }
+        if !tail.is_empty() {
+            let mut k1 = read_partial(&tail[..tail.len().min(8)]);
+            k1 = k1.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2);
+            h1 ^= k1;
+        }
+
+        h1 ^= self.len;
+        h2 ^= self.len;
+        h1 = h1.wrapping_add(h2);
+        h2 = h2.wrapping_add(h1);
+        h1 = fmix(h1);
+        h2 = fmix(h2);
+        h1 = h1.wrapping_add(h2);
+        h2 = h2.wrapping_add(h1);
+
+        u128::from(h2) << 64 | u128::from(h1)
+    }
+
+    /// Mixes a full block into the state.
+    #[inline(always)]
+    fn block(&mut self, block: &[u8]) {
+        let k1 = u64::from_le_bytes(block[..8].try_into().expect("16-byte block"));
+        let k2 = u64::from_le_bytes(block[8..].try_into().expect("16-byte block"));
+
+        self.h1 ^= k1.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2);
+        self.h1 = self
+            .h1
+            .rotate_left(27)
+            .wrapping_add(self.h2)
+            .wrapping_mul(5)
+            .wrapping_add(0x52dc_e729);
+
+        self.h2 ^= k2.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1);
+        self.h2 = self
+            .h2
+            .rotate_left(31)
+            .wrapping_add(self.h1)
+            .wrapping_mul(5)
+            .wrapping_add(0x3849_5ab5);
+    }
+}
+
+impl Default for Murmur3Hasher {
+    fn default() -> Self {
+        Self::with_seed(0)
+    }
+}
+
+impl Hasher for Murmur3Hasher {
+    fn write(&mut self, mut bytes: &[u8]) {
+        self.len += bytes.len() as u64;
+
+        if self.tail_len > 0 {
+            let take = bytes.len().min(BLOCK_LEN - self.tail_len);
+            self.tail[self.tail_len..self.tail_len + take].copy_from_slice(&bytes[..take]);
+            self.tail_len += take;
+            bytes = &bytes[take..];
+
+            if self.tail_len < BLOCK_LEN {
+                return;
+            }
+            let tail = self.tail;
+            self.block(&tail);
+            self.tail_len = 0;
+        }
+
+        let mut blocks = bytes.chunks_exact(BLOCK_LEN);
+        for block in &mut blocks {
+            self.block(block);
+        }
+
+        let rest = blocks.remainder();
+        self.tail[..rest.len()].copy_from_slice(rest);
+        self.tail_len = rest.len();
+    }
+
+    /// Returns the low 64 bits of `finish128`.
+    fn finish(&self) -> u64 {
+        self.finish128() as u64
+    }
+}
+
+/// Reads up to 8 bytes as a little-endian integer.
+#[inline(always)]
+fn read_partial(bytes: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    buf[..bytes.len()].copy_from_slice(bytes);
+
+    u64::from_le_bytes(buf)
+}
+
+/// The MurmurHash3 64-bit finalizer.
+#[inline(always)]
+fn fmix(mut k: u64) -> u64 {
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    k ^ k >> 33
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reference_vectors() {
+        assert_eq!(murmur3_x64_128(b"", 0), 0);
+        assert_eq!(
+            murmur3_x64_128(b"hello", 0),
+            0x5b1e_906a_48ae_1d19_cbd8_a7b3_41bd_9b02
+        );
+        assert_eq!(
+            murmur3_x64_128(b"The quick brown fox jumps over the lazy dog", 0),
+            0x7a43_3ca9_c49a_9347_e34b_bc7b_bc07_1b6c
+        );
+    }
+
+    #[test]
+    fn test_streaming() {
+        let bytes = (0..100u8).collect::<Vec<_>>();
+
+        for split in [0, 1, 15, 16, 17, 40, 100] {
+            let mut hasher = Murmur3Hasher::with_seed(7);
+            hasher.write(&bytes[..split]);
+            hasher.write(&bytes[split..]);
+            assert_eq!(hasher.finish128(), murmur3_x64_128(&bytes, 7));
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use std::{fmt, hash::Hasher, sync::OnceLock};
+
+/// The SipHash key of this deployment, set once by `set_key`.
+static KEY: OnceLock<(u64, u64)> = OnceLock::new();
+
+/// Message whose hash under the deployment key identifies the key in sketch metadata.
+const FINGERPRINT_MESSAGE: &[u8] = b"hyperlog_simd siphash key";
+
+/// Sets the secret SipHash key of this deployment, used by every sketch built with
+/// `HasherId::SipHash`.
+///
+/// With an unpredictable key, adversaries cannot craft items that collide into a few
+/// registers, so counters fed with user-controlled input (e.g. unique voters shown to the
+/// public) cannot be skewed. The key must be shared by every process whose sketches are
+/// merged, and kept secret: anyone holding it can craft such items again.
+///
+/// The key can only be set once per process.
+///
+/// # Parameters
+/// * `key`: The 128-bit secret key.
+///
+/// # Returns
+/// `true` if the key was set or already equal to `key`, `false` if a different key was
+/// set before.
+pub fn set_key(key: [u8; 16]) -> bool {
+    let key = (
+        u64::from_le_bytes(key[..8].try_into().expect("16-byte key")),
+        u64::from_le_bytes(key[8..].try_into().expect("16-byte key")),
+    );
+
+    *KEY.get_or_init(|| key) == key
+}
+
+/// Returns a fingerprint identifying the deployment key without revealing it: the hash of
+/// a fixed message under the key. Sketches built with `HasherId::SipHash` record it as
+/// their seed, so sketches hashed under different keys are refused on merge.
+///
+/// # Returns
+/// The `u64` fingerprint, or `None` if no key was set.
+pub fn key_fingerprint() -> Option<u64> {
+    KEY.get().map(|&(k0, k1)| {
+        let mut hasher = SipHasher24::new_with_keys(k0, k1);
+        hasher.write(FINGERPRINT_MESSAGE);
+        hasher.finish()
+    })
+}
+
+/// A streaming SipHash-2-4 hasher. Its state is derived from the key, so `Debug` does
+/// not print it.
+#[derive(Clone)]
+pub struct SipHasher24 {
+    v: [u64; 4],
+    tail: u64,
+    tail_len: usize,
+    len: usize,
+}
+
+impl SipHasher24 {
+    /// Creates a hasher keyed with `k0` and `k1`.
+    ///
+    /// # Parameters
+    /// * `k0`, `k1`: The two halves of the 128-bit key, read little-endian.
+    ///
+    /// # Returns
+    /// A new `SipHasher24` instance.
+    pub fn new_with_keys(k0: u64, k1: u64) -> Self {
+        Self {
+            v: [
+                k0 ^ 0x736f_6d65_7073_6575,
+                k1 ^ 0x646f_7261_6e64_6f6d,
+                k0 ^ 0x6c79_6765_6e65_7261,
+                k1 ^ 0x7465_6462_7974_6573,
+            ],
+            tail: 0,
+            tail_len: 0,
+            len: 0,
+        }
+    }
+
+    /// Creates a hasher keyed with the deployment key.
+    ///
+    /// # Parameters
+    /// * `fingerprint`: The key fingerprint recorded by the sketch being added to.
+    ///
+    /// # Panics
+    /// Panics if no key was set, or if the sketch was built under a different key.
+    #[inline(always)]
+    pub(crate) fn deployment(fingerprint: u64) -> Self {
+        let &(k0, k1) = KEY
+            .get()
+            .expect("no SipHash key set, see hash::siphash::set_key");
+        assert!(
+            fingerprint == deployment_fingerprint(),
+            "sketch was built under a different SipHash key"
+        );
+
+        Self::new_with_keys(k0, k1)
+    }
+
+    /// Appends a byte to the pending word, compressing it once full.
+    #[inline(always)]
+    fn push(&mut self, byte: u8) {
+        self.tail |= u64::from(byte) << (8 * self.tail_len);
+        self.tail_len += 1;
+
+        if self.tail_len == 8 {
+            let m = self.tail;
+            self.compress(m);
+            self.tail = 0;
+            self.tail_len = 0;
+        }
+    }
+
+    #[inline(always)]
+    fn round(&mut self) {
+        let [v0, v1, v2, v3] = &mut self.v;
+
+        *v0 = v0.wrapping_add(*v1);
+        *v1 = v1.rotate_left(13) ^ *v0;
+        *v0 = v0.rotate_left(32);
+        *v2 = v2.wrapping_add(*v3);
+        *v3 = v3.rotate_left(16) ^ *v2;
+        *v0 = v0.wrapping_add(*v3);
+        *v3 = v3.rotate_left(21) ^ *v0;
+        *v2 = v2.wrapping_add(*v1);
+        *v1 = v1.rotate_left(17) ^ *v2;
+        *v2 = v2.rotate_left(32);
+    }
+
+    #[inline(always)]
+    fn compress(&mut self, m: u64) {
+        self.v[3] ^= m;
+        self.round();
+        self.round();
+        self.v[0] ^= m;
+    }
+}
+
+impl fmt::Debug for SipHasher24 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SipHasher24").finish_non_exhaustive()
+    }
+}
+
+/// Fingerprint of the deployment key, computed once.
+fn deployment_fingerprint() -> u64 {
+    static FINGERPRINT: OnceLock<u64> = OnceLock::new();
+
+    *FINGERPRINT.get_or_init(|| key_fingerprint().expect("no SipHash key set"))
+}
+
+impl Hasher for SipHasher24 {
+    fn write(&mut self, mut bytes: &[u8]) {
+        self.len += bytes.len();
+
+        while self.tail_len > 0 && !bytes.is_empty() {
+            self.push(bytes[0]);
+            bytes = &bytes[1..];
+        }
+
+        let mut words = bytes.chunks_exact(8);
+        for word in &mut words {
+            self.compress(u64::from_le_bytes(word.try_into().expect("8-byte word")));
+        }
+        for &byte in words.remainder() {
+            self.push(byte);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        let mut state = self.clone();
+        let b = (self.len as u64 & 0xff) << 56 | self.tail;
+
+        state.compress(b);
+        state.v[2] ^= 0xff;
+        for _ in 0..4 {
+            state.round();
+        }
+
+        state.v.iter().fold(0, |hash, v| hash ^ v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_matches_reference() {
+        let (k0, k1) = (0x0706_0504_0302_0100, 0x0f0e_0d0c_0b0a_0908);
+
+        let hash = |bytes: &[u8]| {
+            let mut hasher = SipHasher24::new_with_keys(k0, k1);
+            hasher.write(bytes);
+            hasher.finish()
+        };
+        assert_eq!(hash(b""), 0x726f_db47_dd0e_0e31);
+
+        let bytes = (0..64u8).collect::<Vec<_>>();
+        for len in 0..bytes.len() {
+            let mut reference = std::hash::SipHasher::new_with_keys(k0, k1);
+            reference.write(&bytes[..len]);
+            assert_eq!(hash(&bytes[..len]), reference.finish(), "length {}", len);
+        }
+    }
+}
@@ -0,0 +1,95 @@
+/// Version of the stable hashing scheme described by this module. It only changes if the
+/// scheme does, which would make sketches of different versions unmergeable.
+pub const STABLE_VERSION: u32 = 1;
+
+/// Hashes raw bytes with the stable scheme: SeaHash with its default keys, i.e.
+/// `seahash::hash`, over exactly the given bytes.
+///
+/// Items added with `add` are framed by their `Hash` implementation first, which the
+/// standard library does not guarantee to keep across releases; bytes hashed here are
+/// not framed at all, so the result only depends on the bytes.
+///
+/// # Parameters
+/// * `bytes`: The bytes of the item.
+///
+/// # Returns
+/// The 64-bit hash.
+#[inline(always)]
+pub fn hash(bytes: &[u8]) -> u64 {
+    seahash::hash(bytes)
+}
+
+/// Extracts the register index and rank of a hash with the stable scheme: the index is
+/// the low `p` bits of the hash, and the rank is one plus the number of leading zeros of
+/// the remaining `64 - p` high bits, between 1 and `64 - p + 1`.
+///
+/// # Parameters
+/// * `hash`: The hash of the item.
+/// * `p`: The number of index bits.
+///
+/// # Returns
+/// The register index and rank.
+#[inline(always)]
+pub fn index_rank(hash: u64, p: usize) -> (usize, u8) {
+    let index = (hash & ((1 << p) - 1)) as usize;
+    let rank = match hash >> p {
+        0 => (64 - p + 1) as u8,
+        high => (high.leading_zeros() as usize - p + 1) as u8,
+    };
+
+    (index, rank)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HyperLogLog, P};
+
+    /// Byte strings with their stable hash, and the register index and rank at `P = 20`.
+    /// These must never change.
+    const VECTORS: [(&[u8], u64, usize, u8); 5] = [
+        (b"", 0xc920_ca43_256f_dcb9, 0xfdcb9, 1),
+        (b"a", 0x29c4_01b2_6a16_e94d, 0x6e94d, 3),
+        (b"hello", 0x0220_7565_1d74_6789, 0x46789, 7),
+        (b"hyperlog-simd", 0x1595_c439_ad78_c31b, 0x8c31b, 4),
+        (
+            b"The quick brown fox jumps over the lazy dog",
+            0xb5c9_3a0f_41f7_166c,
+            0x7166c,
+            1,
+        ),
+    ];
+
+    #[test]
+    fn test_vectors() {
+        for (bytes, expected_hash, expected_index, expected_rank) in VECTORS {
+            assert_eq!(hash(bytes), expected_hash);
+            assert_eq!(
+                index_rank(expected_hash, 20),
+                (expected_index, expected_rank)
+            );
+
+            let (index, rank) = index_rank(expected_hash, P);
+            let mut hll = HyperLogLog::new();
+            hll.add_stable(bytes);
+            assert_eq!(hll.registers[index], rank);
+        }
+
+        assert_eq!(index_rank(0, 20), (0, 45));
+        assert_eq!(index_rank(u64::MAX, 20), ((1 << 20) - 1, 1));
+    }
+
+    #[test]
+    fn test_sketches_follow_the_scheme() {
+        let mut hll = HyperLogLog::new();
+        let mut expected = vec![0u8; 1 << P];
+
+        for i in 0..100_000u64 {
+            let (index, rank) = index_rank(hash(&i.to_le_bytes()), P);
+            expected[index] = expected[index].max(rank);
+            hll.add_stable(i.to_le_bytes());
+        }
+
+        assert_eq!(&hll.registers[..], &expected[..]);
+    }
+}
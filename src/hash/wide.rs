@@ -0,0 +1,56 @@
+/// Folds a 128-bit hash into 64 bits by XOR-ing its halves, e.g. to feed it to
+/// `add_hashed` or to a sketch's exact shadow set.
+///
+/// # Parameters
+/// * `hash`: The 128-bit hash.
+///
+/// # Returns
+/// The low half XOR-ed with the high half.
+#[inline(always)]
+pub fn fold(hash: u128) -> u64 {
+    hash as u64 ^ (hash >> 64) as u64
+}
+
+/// Splits a 128-bit hash into a register index and rank, without reusing any bit: the
+/// index is the low `p` bits of the low 64-bit half, and the rank is one plus the number
+/// of leading zeros of the high 64-bit half.
+///
+/// The rank is capped at `64 - p + 1`, the largest rank a 64-bit hash can produce, so
+/// registers stay comparable with those built from 64-bit hashes. The cap is only reached
+/// with a probability of `2^-(64 - p)` per item.
+///
+/// Drawing index and rank from separate halves spends `p + 64` hash bits per item instead
+/// of 64, which keeps collisions between distinct items negligible even at cardinalities
+/// where 64-bit hashes start to collide.
+///
+/// # Parameters
+/// * `hash`: The 128-bit hash, e.g. from `murmur3::murmur3_x64_128`.
+/// * `p`: The number of index bits.
+///
+/// # Returns
+/// The register index and rank.
+#[inline(always)]
+pub fn split(hash: u128, p: usize) -> (usize, u8) {
+    let index = (hash as u64 & ((1 << p) - 1)) as usize;
+    let rank = ((hash >> 64) as u64).leading_zeros().min(64 - p as u32) + 1;
+
+    (index, rank as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        let hash = 0x0000_ffff_0000_0000_0000_0000_000a_bcde_u128;
+        assert_eq!(split(hash, 20), (0xabcde, 17));
+        assert_eq!(split(hash, 12), (0xcde, 17));
+
+        // Ranks are capped at the range of 64-bit hashes
+        assert_eq!(split(0x0fff, 20), (0x0fff, 45));
+        assert_eq!(split(u128::MAX, 20), ((1 << 20) - 1, 1));
+
+        assert_eq!(fold(0x1_0000_0000_0000_0003), 2);
+    }
+}
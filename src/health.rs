@@ -0,0 +1,122 @@
+use crate::registers::Registers;
+
+/// Registers within this many ranks of the largest representable rank count as high.
+pub const HIGH_RANK_MARGIN: u8 = 4;
+
+/// Fraction of high-rank registers above which the hash bits left after the register
+/// index are considered exhausted.
+const HIGH_RANK_LIMIT: f64 = 0.001;
+
+/// Load factor (estimate per register) above which linear counting, used while some
+/// registers are still zero, becomes more than twice as noisy as the HyperLogLog
+/// estimate it stands in for.
+pub const LINEAR_COUNTING_LOAD_LIMIT: f64 = 5.0;
+
+/// Cardinality above which collisions in the 64-bit hash space bias estimates downwards.
+pub const HASH_SPACE_LIMIT: f64 = 18_446_744_073_709_551_616.0 / 30.0;
+
+/// Largest precision a suggestion can point to.
+const MAX_SUGGESTED_PRECISION: u32 = 30;
+
+/// Indicators describing whether the estimate of a sketch can be trusted, returned by
+/// `health()` so operators are warned before estimates quietly degrade.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SketchHealth {
+    /// The estimate the indicators refer to.
+    pub estimate: f64,
+    /// Number of bits used to select a register.
+    pub precision: u8,
+    /// Expected relative standard error of the estimate, `1.04 / sqrt(m)`.
+    pub standard_error: f64,
+    /// Fraction of registers that are still zero.
+    pub zero_fraction: f64,
+    /// Fraction of registers within `HIGH_RANK_MARGIN` of the largest representable
+    /// rank. Anything but a tiny fraction means the hash bits left after the register
+    /// index are running out.
+    pub high_rank_fraction: f64,
+    /// Whether the estimate lies in the range where the estimator is reliable for this
+    /// precision.
+    pub in_reliable_range: bool,
+    /// A higher precision that would bring a sketch holding as many items back into its
+    /// reliable range, when increasing the precision helps.
+    pub suggested_precision: Option<u8>,
+}
+
+/// Computes the health indicators of a sketch.
+///
+/// # Parameters
+/// * `registers`: The registers of the sketch, a power of two of them.
+/// * `max_rank`: The largest rank a register of the sketch can hold.
+/// * `estimate`: The estimate of the sketch.
+///
+/// # Returns
+/// The `SketchHealth` of the sketch.
+pub(crate) fn assess<R: Registers + ?Sized>(
+    registers: &R,
+    max_rank: u8,
+    estimate: f64,
+) -> SketchHealth {
+    let high_rank = max_rank.saturating_sub(HIGH_RANK_MARGIN);
+    let mut zeros = 0;
+    let mut high = 0;
+
+    registers.for_each_chunk(|chunk| {
+        for &rank in chunk {
+            zeros += (rank == 0) as usize;
+            high += (rank > high_rank) as usize;
+        }
+    });
+
+    let m = registers.len() as f64;
+    let precision = registers.len().trailing_zeros();
+    let high_rank_fraction = high as f64 / m;
+
+    // Linear counting far beyond its useful load is the only problem more registers fix
+    let overloaded = zeros > 0 && estimate / m > LINEAR_COUNTING_LOAD_LIMIT;
+    let suggested_precision = match overloaded {
+        true => {
+            let needed = (estimate / LINEAR_COUNTING_LOAD_LIMIT).log2().ceil() as u32;
+            Some(needed.clamp(precision + 1, MAX_SUGGESTED_PRECISION) as u8)
+        }
+        false => None,
+    };
+
+    SketchHealth {
+        estimate,
+        precision: precision as u8,
+        standard_error: 1.04 / m.sqrt(),
+        zero_fraction: zeros as f64 / m,
+        high_rank_fraction,
+        in_reliable_range: !overloaded
+            && high_rank_fraction <= HIGH_RANK_LIMIT
+            && estimate <= HASH_SPACE_LIMIT,
+        suggested_precision,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assess() {
+        let mut registers = vec![0u8; 1 << 10];
+        let health = assess(registers.as_slice(), 55, 0.0);
+        assert!(health.in_reliable_range);
+        assert_eq!(health.zero_fraction, 1.0);
+        assert_eq!(health.precision, 10);
+
+        // Linear counting at a load of 8 items per register
+        registers[..1000].fill(3);
+        let health = assess(registers.as_slice(), 55, 8.0 * 1024.0);
+        assert!(!health.in_reliable_range);
+        assert_eq!(health.suggested_precision, Some(11));
+
+        // Registers close to the largest rank
+        registers.fill(54);
+        let health = assess(registers.as_slice(), 55, 1e6);
+        assert_eq!(health.high_rank_fraction, 1.0);
+        assert!(!health.in_reliable_range);
+        assert_eq!(health.suggested_precision, None);
+    }
+}
@@ -1,48 +1,731 @@
-use std::hash::{Hash, Hasher};
-
-use packed_simd::{f64x8, u8x16};
-use seahash::SeaHasher;
+use std::hash::Hash;
 
 #[cfg(feature = "serde_support")]
 use serde::{de::Deserializer, Deserialize, Serialize, Serializer};
 
 #[cfg(feature = "serde_support")]
 use crate::serde::{serialize_registers, CompressedRegistersVisitor};
+#[cfg(feature = "stats")]
+use crate::stats::SketchStats;
+#[cfg(feature = "validate")]
+use crate::validate::{ShadowSet, DEFAULT_VALIDATION_LIMIT};
 
-use crate::{M, P};
+use crate::{
+    activity::SketchActivity,
+    binary,
+    compare::{self, RegisterDiff},
+    dump::{self, HexDumpError},
+    dynamic::DynHyperLogLog,
+    ertl, hash,
+    health::{self, SketchHealth},
+    ingest,
+    labels::SketchLabels,
+    metadata::{Algorithm, HasherId, MergePolicy, SketchMetadata},
+    observer::{ObservedRegisters, RegisterObserver},
+    registers::{self, AlignedRegisters, CowRegisters, RegisterStore, Registers, RegistersMut},
+    simd, Error, M, MAX_RANK, P,
+};
 
 /// A HyperLogLog data structure for approximating the cardinality (number of unique elements)
 /// of a dataset.
+///
+/// The registers are held in a boxed array by default, but any `Registers` store holding
+/// `M` registers can be used instead, e.g. a `Vec<u8>`, a `PackedRegisters` or a mutably
+/// borrowed slice such as a memory mapped file.
 #[derive(Debug, Clone)]
-pub struct HyperLogLog {
+pub struct HyperLogLog<R = Box<[u8; M]>> {
     /// An array of registers. The number of registers is specified by the constant `M`
-    /// and determines the precision and memory usage of the HLL.
-    pub registers: Box<[u8; M]>,
+    /// and determines the precision and memory usage of the HLL. Only readable from the
+    /// outside through `registers` and `register`, so that every register holds a rank
+    /// `add` can produce.
+    pub(crate) registers: R,
+    /// Precision, algorithm and hasher the registers were built with.
+    metadata: SketchMetadata,
+    /// How sketches of a different precision are merged.
+    merge_policy: MergePolicy,
+    /// The estimator `estimate` applies to the registers.
+    estimator: Estimator,
+    /// User metadata describing the provenance of the sketch, if any.
+    labels: Option<Box<SketchLabels>>,
+    /// Exact add count and update times, only recorded once `track_activity` was called.
+    activity: Option<SketchActivity>,
+    /// Operational counters, only maintained with the `stats` feature.
+    #[cfg(feature = "stats")]
+    stats: SketchStats,
+    /// Exact set of added hashes, only maintained with the `validate` feature.
+    #[cfg(feature = "validate")]
+    shadow: ShadowSet,
 }
 
 impl HyperLogLog {
-    /// Computes the alpha constant for bias correction based on the size of the register list.
+    /// Creates a new HyperLogLog instance with all registers initialized to zero.
+    ///
+    /// # Returns
+    /// A new `HyperLogLog` instance.
+    pub fn new() -> Self {
+        Self::from(registers::zeroed_box())
+    }
+
+    /// Creates a new HyperLogLog hashing items with the given hash function instead of
+    /// SeaHash, e.g. to build registers compatible with another implementation. Only
+    /// sketches using the same hasher can be merged.
+    ///
+    /// # Parameters
+    /// * `hasher`: The hash function applied to added items.
+    ///
+    /// # Returns
+    /// A new `HyperLogLog` instance.
+    ///
+    /// # Panics
+    /// Panics if `hasher` is `HasherId::SipHash` and no deployment key was set with
+    /// `hash::siphash::set_key`.
+    pub fn with_hasher(hasher: HasherId) -> Self {
+        let mut metadata = SketchMetadata::new(Algorithm::HyperLogLog, P);
+        metadata.hasher = hasher;
+        metadata.seed = hash::default_seed(hasher);
+
+        Self::with_metadata(metadata)
+    }
+
+    /// Creates a new HyperLogLog hashing items with SeaHash keyed by `key` instead of the
+    /// default key. Producers that agree on a key build mergeable sketches, while sketches
+    /// built with different keys refuse to be merged, see `try_merge`.
+    ///
+    /// # Parameters
+    /// * `key`: The 256-bit SeaHash key, e.g. drawn at random once and shared by all
+    ///   producers.
+    ///
+    /// # Returns
+    /// A new `HyperLogLog` instance.
+    pub fn with_seed(key: [u64; 4]) -> Self {
+        let mut metadata = SketchMetadata::new(Algorithm::HyperLogLog, P);
+        metadata.key = key;
+
+        Self::with_metadata(metadata)
+    }
+
+    /// Creates a new HyperLogLog hashing items with the hasher and seed of `metadata`,
+    /// which the caller has checked, e.g. `HllBuilder::build`.
+    pub(crate) fn with_metadata(metadata: SketchMetadata) -> Self {
+        Self::with_registers_and_metadata(registers::zeroed_box(), metadata)
+    }
+
+    /// Creates a new HyperLogLog notifying `observer` of every register raised by an add or
+    /// a merge, see `ObservedRegisters`.
+    ///
+    /// # Parameters
+    /// * `observer`: The observer, e.g. a closure taking the index, previous value and
+    ///   new value of the raised register.
+    ///
+    /// # Returns
+    /// A new `HyperLogLog` instance whose registers report to `observer`.
+    pub fn with_observer<O: RegisterObserver>(
+        observer: O,
+    ) -> HyperLogLog<ObservedRegisters<Box<[u8; M]>, O>> {
+        HyperLogLog::with_registers(ObservedRegisters::new(registers::zeroed_box(), observer))
+    }
+
+    /// Compares the registers of this HyperLogLog with another one, reporting how many
+    /// registers differ, which side holds the larger value, and the resulting gap between
+    /// the two estimates. Useful for tracking down drift between replicas that should
+    /// hold identical state.
+    ///
+    /// # Parameters
+    /// * `other`: The `HyperLogLog` to compare against.
+    ///
+    /// # Returns
+    /// A `RegisterDiff` summarizing the differences.
+    pub fn diff(&self, other: &HyperLogLog) -> RegisterDiff {
+        compare::diff(
+            self.registers.as_slice(),
+            other.registers.as_slice(),
+            self.estimate(),
+            other.estimate(),
+        )
+    }
+
+    /// Approximates how many distinct items were added after `earlier` was taken,
+    /// where `earlier` is a snapshot (clone) of this HyperLogLog from a previous point in time.
+    ///
+    /// The result is the estimate of the union of both sketches minus the estimate of the
+    /// snapshot, clamped at zero. Taking the union makes the result robust against a
+    /// snapshot holding registers this sketch has not seen. Note that the absolute error
+    /// is that of the two estimates combined, i.e. it scales with the total cardinality
+    /// rather than with the number of new items, so small changes on top of a large
+    /// snapshot are indistinguishable from noise.
+    ///
+    /// # Parameters
+    /// * `earlier`: A snapshot of this sketch taken at an earlier point in time.
+    ///
+    /// # Returns
+    /// A non-negative `f64` approximate count of items that appeared since `earlier`.
+    pub fn estimate_new_since(&self, earlier: &HyperLogLog) -> f64 {
+        let mut union = self.clone();
+        union.merge(earlier);
+
+        (union.estimate() - earlier.estimate()).max(0.0)
+    }
+
+    /// Estimates how many distinct items were added to this sketch but not to `other`,
+    /// the size of the set difference `A \ B`.
+    ///
+    /// The result is computed as `|A ∪ B| - |B|`, which equals `|A| - |A ∩ B|` by
+    /// inclusion-exclusion but needs one estimate less, and is clamped to `[0, |A|]`.
+    /// Its absolute error is that of the union and `other` estimates combined, roughly
+    /// `1.04 / sqrt(M) * (|A ∪ B| + |B|)`, so it is independent of the size of the
+    /// difference itself: differences much smaller than that bound, e.g. a few new items
+    /// between two large, nearly identical sets, cannot be told apart from zero.
+    ///
+    /// # Parameters
+    /// * `other`: The `HyperLogLog` whose items are removed.
+    ///
+    /// # Returns
+    /// A non-negative `f64` approximate count of items in this sketch but not in `other`.
+    pub fn difference_estimate(&self, other: &HyperLogLog) -> f64 {
+        let mut union = self.clone();
+        union.merge(other);
+
+        (union.estimate() - other.estimate()).clamp(0.0, self.estimate())
+    }
+
+    /// Renders the registers as a compact hex dump, one line per 64 registers with
+    /// repeated values run-length encoded (e.g. `000040: 00*12 03 01 00*50`).
+    /// Dumps of two sketches can be diffed line by line when comparing their states.
+    ///
+    /// # Returns
+    /// A `String` containing the hex dump.
+    pub fn to_hex_dump(&self) -> String {
+        dump::to_hex_dump(self.registers.as_slice())
+    }
+
+    /// Reconstructs a `HyperLogLog` from a dump produced by `to_hex_dump`.
+    ///
+    /// # Parameters
+    /// * `dump`: The textual hex dump.
+    ///
+    /// # Returns
+    /// The reconstructed `HyperLogLog`, or a `HexDumpError` describing the first problem found.
+    pub fn from_hex_dump(dump: &str) -> Result<Self, HexDumpError> {
+        dump::from_hex_dump(dump).map(Self::from)
+    }
+
+    /// Creates a `HyperLogLog` from a copy of raw registers, e.g. read from storage.
+    ///
+    /// # Parameters
+    /// * `bytes`: The registers, one byte each.
+    ///
+    /// # Returns
+    /// The `HyperLogLog`, or an `Error` if `bytes` does not hold `M` valid registers.
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let registers: Box<[u8; M]> =
+            bytes
+                .to_vec()
+                .into_boxed_slice()
+                .try_into()
+                .map_err(|_| Error::Length {
+                    expected: M,
+                    found: bytes.len(),
+                })?;
+
+        Self::try_with_registers(registers)
+    }
+
+    /// Reads a sketch written by `serialize_into`.
+    ///
+    /// # Parameters
+    /// * `bytes`: The serialized sketch.
+    ///
+    /// # Returns
+    /// The `HyperLogLog`, or an `Error` if `bytes` does not hold a valid HyperLogLog of precision
+    /// `P`.
+    pub fn deserialize_from(bytes: &[u8]) -> Result<Self, Error> {
+        let (registers, metadata) =
+            binary::deserialize(bytes, SketchMetadata::new(Algorithm::HyperLogLog, P))?;
+        let mut sketch = Self::try_from_bytes(registers).map_err(Error::into_decode)?;
+        sketch.metadata = metadata;
+
+        Ok(sketch)
+    }
+
+    /// Adds every record of a file of delimited records, e.g. a log file with one record
+    /// per line, or NUL separated records. The file is memory mapped rather than read, and
+    /// records are hashed in batches straight from the map, without copying; with the
+    /// `rayon` feature, the file is split into chunks that are counted in parallel into
+    /// per-thread copies of this sketch, merged back at the end. Meant for backfills of
+    /// very large files, where throughput is bound by the storage rather than by hashing.
+    ///
+    /// Records are added as byte slices, exactly as `add(record)` would, and empty
+    /// records are skipped. For newline separated files, a `\r` preceding the newline is
+    /// part of the record.
+    ///
+    /// The file must not be truncated or modified while it is ingested: memory mapped
+    /// reads of a truncated file raise `SIGBUS`, and concurrent writes may be partially
+    /// counted.
+    ///
+    /// # Parameters
+    /// * `path`: The path of the file.
+    /// * `delimiter`: The byte separating records, e.g. `b'\n'` or `b'\0'`.
+    ///
+    /// # Returns
+    /// The number of records added, or the error raised opening or mapping the file.
+    #[cfg(feature = "mmap")]
+    pub fn ingest_mmap(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+        delimiter: u8,
+    ) -> std::io::Result<u64> {
+        ingest::ingest_mmap(self, path.as_ref(), delimiter)
+    }
+
+    /// Merges many sketches at once, with the same result as merging them one after the
+    /// other. The registers are merged block by block, each block of this sketch being
+    /// raised by all sketches while it is in cache, which is noticeably faster than
+    /// sweeping the full registers once per sketch when folding large batches.
+    ///
+    /// # Parameters
+    /// * `others`: The sketches to merge in.
+    ///
+    /// # Panics
+    /// Panics if a sketch was built with different metadata and cannot be folded, see
+    /// `try_merge`.
+    pub fn merge_many<'a, I: IntoIterator<Item = &'a HyperLogLog>>(&mut self, others: I) {
+        let mut batch = Vec::new();
+        for other in others {
+            match self.metadata.check_compatible(&other.metadata) {
+                Ok(()) => batch.push(other),
+                Err(_) => self.merge(other),
+            }
+        }
+
+        for start in (0..M).step_by(MERGE_BLOCK_LEN) {
+            let end = (start + MERGE_BLOCK_LEN).min(M);
+            for other in &batch {
+                simd::merge_max(
+                    &mut self.registers[start..end],
+                    &other.registers[start..end],
+                );
+            }
+        }
+        for other in batch {
+            self.record_merge(other);
+        }
+    }
+}
+
+impl<R: Registers> HyperLogLog<R> {
+    /// Creates a HyperLogLog on top of an existing register store.
+    ///
+    /// # Parameters
+    /// * `registers`: The store, holding `M` registers.
+    ///
+    /// # Returns
+    /// A new `HyperLogLog` instance using `registers` as its state.
+    ///
+    /// # Panics
+    /// Panics if `registers` does not hold exactly `M` registers.
+    pub fn with_registers(registers: R) -> Self {
+        assert_eq!(registers.len(), M, "a HyperLogLog needs {} registers", M);
+
+        Self::with_registers_unchecked(registers)
+    }
+
+    /// Creates a HyperLogLog on top of a register store the caller has checked to hold `M`
+    /// registers, in constant expressions too.
+    const fn with_registers_unchecked(registers: R) -> Self {
+        HyperLogLog {
+            registers,
+            metadata: SketchMetadata::new(Algorithm::HyperLogLog, P),
+            merge_policy: MergePolicy::Fold,
+            estimator: Estimator::Classic,
+            labels: None,
+            activity: None,
+            #[cfg(feature = "stats")]
+            stats: SketchStats::new(),
+            #[cfg(feature = "validate")]
+            shadow: ShadowSet::new(DEFAULT_VALIDATION_LIMIT),
+        }
+    }
+
+    /// Creates a HyperLogLog on top of an existing register store, hashing items with the
+    /// hasher and seed of `metadata`, which the caller has checked.
+    ///
+    /// # Panics
+    /// Panics if `registers` does not hold exactly `M` registers.
+    pub(crate) fn with_registers_and_metadata(registers: R, metadata: SketchMetadata) -> Self {
+        let mut sketch = Self::with_registers(registers);
+        sketch.metadata = metadata;
+
+        sketch
+    }
+
+    /// Creates a HyperLogLog on top of an existing register store, checking that it holds
+    /// `M` registers and that every register holds a value `add` can produce.
+    ///
+    /// # Parameters
+    /// * `registers`: The store, holding `M` registers.
     ///
     /// # Returns
-    /// A `f64` alpha constant value for the given `M`.
+    /// A new `HyperLogLog` instance, or an `Error` describing the problem with `registers`.
+    pub fn try_with_registers(registers: R) -> Result<Self, Error> {
+        if registers.len() != M {
+            return Err(Error::Length {
+                expected: M,
+                found: registers.len(),
+            });
+        }
+        registers::check_ranks(&registers, MAX_RANK)?;
+
+        Ok(Self::with_registers(registers))
+    }
+
+    /// Provides an estimate of the number of unique items added to the HLL, computed by
+    /// the `Estimator` set with `set_estimator`, `Estimator::Classic` by default.
+    ///
+    /// # Returns
+    /// A `f64` approximate count of unique items added to the HLL.
     #[inline(always)]
-    fn get_alpha() -> f64 {
-        match M {
-            16 => 0.673,
-            32 => 0.697,
-            64 => 0.709,
-            _ => 0.7213 / (1.0 + 1.079 / M as f64),
+    pub fn estimate(&self) -> f64 {
+        match self.estimator {
+            Estimator::Classic => estimate_registers(&self.registers),
+            Estimator::Ertl => ertl::estimate_registers(&self.registers),
         }
     }
 
-    /// Creates a new HyperLogLog instance with all registers initialized to zero.
+    /// Folds the registers down to a lower precision, e.g. to move sketches of ingest
+    /// nodes into compact long-term storage. The folded registers are exactly those of a
+    /// sketch of `precision` fed the same items, so its estimates have the standard error
+    /// of that precision, see `dynamic::error_for_precision`.
+    ///
+    /// # Parameters
+    /// * `precision`: The precision of the folded sketch, between
+    ///   `dynamic::MIN_PRECISION` and `P`.
     ///
     /// # Returns
-    /// A new `HyperLogLog` instance.
-    pub fn new() -> Self {
-        Self {
-            registers: Box::new([0; M]),
+    /// A `DynHyperLogLog` of the given precision, with the hasher and seed of this sketch.
+    ///
+    /// # Panics
+    /// Panics if `precision` is out of range.
+    pub fn fold(&self, precision: u8) -> DynHyperLogLog {
+        DynHyperLogLog::from_hyperloglog(self, precision)
+    }
+
+    /// Provides an estimate computed in single precision, summing twice as many registers
+    /// per SIMD vector as `estimate`. Meant for latency critical callers that can accept a
+    /// slightly coarser estimate.
+    ///
+    /// # Returns
+    /// A `f32` approximate count of unique items.
+    #[inline(always)]
+    pub fn estimate_f32(&self) -> f32 {
+        estimate_registers_f32(&self.registers)
+    }
+
+    /// Provides a rough estimate from every `stride`-th register only, for callers that
+    /// need a number in microseconds. The sampled registers form a sketch of
+    /// `M / stride` registers, whose estimate is scaled back up by `stride`, so the
+    /// standard error grows by a factor `sqrt(stride)`. Use `estimate` for final reporting.
+    ///
+    /// # Parameters
+    /// * `stride`: Distance between sampled registers, a power of two. `1` scans all of
+    ///   them and matches `estimate`.
+    ///
+    /// # Returns
+    /// A `f64` approximate count of unique items.
+    ///
+    /// # Panics
+    /// Panics if `stride` is not a power of two, or larger than the number of registers.
+    pub fn estimate_with_budget(&self, stride: usize) -> f64 {
+        if stride == 1 {
+            return self.estimate();
         }
+
+        let (sum, num_zeros) = strided_sums(&self.registers, stride);
+        let m = (self.registers.len() / stride) as f64;
+
+        let estimate = match num_zeros {
+            0 => alpha(self.registers.len() / stride) * m * m / sum,
+            _ => m * (m / num_zeros as f64).ln(),
+        };
+
+        estimate * stride as f64
+    }
+
+    /// Provides an estimate together with the branch of the estimator that produced it,
+    /// which helps explain accuracy anomalies.
+    ///
+    /// # Returns
+    /// The same value as `estimate`, and the `EstimateRegime` it was computed in.
+    pub fn estimate_with_regime(&self) -> (f64, EstimateRegime) {
+        estimate_registers_with_regime(&self.registers)
+    }
+
+    /// Provides an estimate computed with the improved estimator of Ertl, which works on
+    /// the register histogram in integer counts instead of summing `2^-register` per
+    /// register, and needs no switch to linear counting for small cardinalities. See
+    /// `ertl::estimate_histogram`.
+    ///
+    /// # Returns
+    /// A `f64` approximate count of unique items.
+    pub fn estimate_ertl(&self) -> f64 {
+        ertl::estimate_registers(&self.registers)
+    }
+
+    /// Provides the raw HyperLogLog estimate, `alpha * m^2 / sum(2^-register)`, whatever
+    /// regime `estimate` would use.
+    ///
+    /// # Returns
+    /// A `f64` raw estimate.
+    pub fn raw_estimate(&self) -> f64 {
+        raw_estimate_registers(&self.registers)
+    }
+
+    /// Computes a fast, non-cryptographic fingerprint of the registers, so replicas can
+    /// cheaply check whether their sketches already agree before shipping deltas or full
+    /// states. Sketches with equal registers have equal fingerprints; see
+    /// `registers::fingerprint`.
+    ///
+    /// # Returns
+    /// The `u64` fingerprint of this HyperLogLog.
+    pub fn fingerprint(&self) -> u64 {
+        registers::fingerprint(&self.registers)
+    }
+
+    /// Returns the number of bytes `serialize_into` appends for this HyperLogLog, so
+    /// buffers can be sized up front.
+    ///
+    /// # Returns
+    /// The exact serialized size in bytes.
+    pub fn serialized_size_hint(&self) -> usize {
+        binary::serialized_size(&self.registers, &self.metadata)
+    }
+
+    /// Appends this HyperLogLog to `buf` in a compact binary layout (a short metadata
+    /// header followed by the raw registers, without labels) that `deserialize_from`
+    /// reads back. Unlike
+    /// the serde implementation, no intermediate buffers or strings are allocated, so a
+    /// buffer reused across snapshots makes serialization allocation free.
+    ///
+    /// # Parameters
+    /// * `buf`: The buffer the sketch is appended to.
+    pub fn serialize_into(&self, buf: &mut Vec<u8>) {
+        binary::serialize_into(&self.registers, &self.metadata, buf)
+    }
+
+    /// Reports indicators of whether the estimate can be trusted: the fraction of empty
+    /// and of nearly saturated registers, whether the estimate is within the reliable
+    /// range for the precision, and a higher precision to use if it is not.
+    ///
+    /// # Returns
+    /// The `SketchHealth` of this HyperLogLog.
+    pub fn health(&self) -> SketchHealth {
+        health::assess(&self.registers, MAX_RANK, self.estimate())
+    }
+
+    /// Returns the precision, algorithm and hasher the registers were built with.
+    pub fn metadata(&self) -> SketchMetadata {
+        self.metadata
+    }
+
+    /// Returns the 256-bit SeaHash key items are hashed with, to be handed to other
+    /// producers building mergeable sketches with `with_seed`. Sketches using another
+    /// hasher report its seed in `metadata().seed`.
+    pub fn seed(&self) -> [u64; 4] {
+        self.metadata.key
+    }
+
+    /// Returns the precision, the number of bits used to select a register.
+    pub fn precision(&self) -> u8 {
+        self.metadata.precision
+    }
+
+    /// Returns the number of registers, `2^precision`.
+    pub fn register_count(&self) -> usize {
+        self.registers.len()
+    }
+
+    /// Returns the register store. Registers can only be changed through the methods of
+    /// the HyperLogLog; sketches holding registers from elsewhere are built with
+    /// `try_with_registers` or `try_from_bytes`, which check them.
+    pub fn registers(&self) -> &R {
+        &self.registers
+    }
+
+    /// Returns the value of one register, the largest rank of the items mapped to it.
+    ///
+    /// # Parameters
+    /// * `index`: The index of the register, below `register_count()`.
+    ///
+    /// # Panics
+    /// Panics if `index` is out of bounds.
+    pub fn register(&self, index: usize) -> u8 {
+        self.registers.get(index)
+    }
+
+    /// Counts the registers that are not zero, i.e. that at least one added item was
+    /// mapped to.
+    ///
+    /// # Returns
+    /// The number of non-zero registers, at most `register_count()`.
+    pub fn nonzero_register_count(&self) -> usize {
+        registers::count_nonzero(&self.registers)
+    }
+
+    /// Returns the number of heap bytes used by the HyperLogLog, i.e. by its registers
+    /// and its labels, so dashboards can report the footprint of a fleet of sketches.
+    /// Registers held inline or borrowed take no heap bytes.
+    pub fn memory_usage(&self) -> usize {
+        self.registers.heap_size() + self.labels.as_ref().map_or(0, |labels| labels.heap_size())
+    }
+
+    /// Returns how sketches of a different precision are merged into this one.
+    pub fn merge_policy(&self) -> MergePolicy {
+        self.merge_policy
+    }
+
+    /// Sets how sketches of a different precision are merged into this one.
+    ///
+    /// # Parameters
+    /// * `policy`: The `MergePolicy` to apply on subsequent merges.
+    pub fn set_merge_policy(&mut self, policy: MergePolicy) {
+        self.merge_policy = policy;
+    }
+
+    /// Returns the estimator `estimate` applies.
+    pub fn estimator(&self) -> Estimator {
+        self.estimator
+    }
+
+    /// Sets the estimator `estimate` applies. The other estimation methods, such as
+    /// `estimate_with_regime` or `estimate_ertl`, are not affected.
+    ///
+    /// # Parameters
+    /// * `estimator`: The `Estimator` to apply on subsequent estimates.
+    pub fn set_estimator(&mut self, estimator: Estimator) {
+        self.estimator = estimator;
+    }
+
+    /// Returns the labels describing the provenance of this HyperLogLog, if any.
+    pub fn labels(&self) -> Option<&SketchLabels> {
+        self.labels.as_deref()
+    }
+
+    /// Attaches labels to this HyperLogLog. They are serialized with it, and combined with
+    /// the labels of sketches merged into it, see `SketchLabels::combine`.
+    ///
+    /// # Parameters
+    /// * `labels`: The labels to attach, replacing any previous ones.
+    pub fn set_labels(&mut self, labels: SketchLabels) {
+        self.labels = Some(Box::new(labels));
+    }
+
+    /// Removes the labels of this HyperLogLog.
+    ///
+    /// # Returns
+    /// The removed labels, if any.
+    pub fn take_labels(&mut self) -> Option<SketchLabels> {
+        self.labels.take().map(|labels| *labels)
+    }
+
+    /// Starts recording the exact number of items added to this HyperLogLog and when it was
+    /// last updated, from now on. The activity is serialized with the sketch, and combined
+    /// with the activity of sketches merged into it. Every add then reads the system clock.
+    pub fn track_activity(&mut self) {
+        if self.activity.is_none() {
+            self.activity = Some(SketchActivity::new());
+        }
+    }
+
+    /// Returns the activity recorded by this HyperLogLog, or `None` unless `track_activity`
+    /// was called.
+    pub fn activity(&self) -> Option<SketchActivity> {
+        self.activity
+    }
+
+    /// Returns the fraction of the items added to this HyperLogLog that were distinct, see
+    /// `SketchActivity::distinct_ratio`.
+    ///
+    /// # Returns
+    /// The estimate divided by the exact add count, or `None` unless `track_activity` was
+    /// called and items were added since.
+    pub fn distinct_ratio(&self) -> Option<f64> {
+        self.activity?.distinct_ratio(self.estimate())
+    }
+
+    /// Returns the average number of times each distinct item was added to this HyperLogLog,
+    /// see `SketchActivity::duplication_factor`.
+    ///
+    /// # Returns
+    /// The exact add count divided by the estimate, or `None` unless `track_activity` was
+    /// called and items were added since.
+    pub fn duplication_factor(&self) -> Option<f64> {
+        self.activity?.duplication_factor(self.estimate())
+    }
+
+    /// Returns the operational counters recorded by this HyperLogLog.
+    ///
+    /// # Returns
+    /// A copy of the `SketchStats` accumulated so far.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> SketchStats {
+        self.stats
+    }
+
+    /// Computes the relative error of the current estimate against the exact number of
+    /// distinct items added, tracked alongside the registers.
+    ///
+    /// # Returns
+    /// `(estimate - exact) / exact`, or `None` when nothing was added yet or more distinct
+    /// items than the validation limit were added.
+    #[cfg(feature = "validate")]
+    pub fn observed_error(&self) -> Option<f64> {
+        self.shadow.relative_error(self.estimate())
+    }
+
+    /// Returns the exact number of distinct items added, or `None` once more than the
+    /// validation limit were added.
+    #[cfg(feature = "validate")]
+    pub fn exact_count(&self) -> Option<usize> {
+        self.shadow.exact_count()
+    }
+
+    /// Sets the number of distinct items tracked exactly, `DEFAULT_VALIDATION_LIMIT` by
+    /// default. Lowering it below the number already tracked stops tracking.
+    ///
+    /// # Parameters
+    /// * `limit`: The maximum number of distinct items to track.
+    #[cfg(feature = "validate")]
+    pub fn set_validation_limit(&mut self, limit: usize) {
+        self.shadow.set_limit(limit);
+    }
+}
+
+impl<R: RegisterStore> HyperLogLog<R> {
+    /// Returns the registers of the HyperLogLog as bytes, one per register, for stores
+    /// holding them contiguously.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.registers.as_bytes()
+    }
+
+    /// Counts the registers that are still zero, see `RegisterStore::zero_count`.
+    pub fn zero_register_count(&self) -> usize {
+        self.registers.zero_count()
+    }
+}
+
+impl<R: RegistersMut> HyperLogLog<R> {
+    /// Resets the HyperLogLog to its empty state, keeping its register allocation so it can
+    /// be reused instead of allocating a new one.
+    pub fn clear(&mut self) {
+        self.registers.clear();
+        if let Some(activity) = &mut self.activity {
+            *activity = SketchActivity::new();
+        }
+        #[cfg(feature = "stats")]
+        {
+            self.stats = SketchStats::default();
+        }
+        #[cfg(feature = "validate")]
+        self.shadow.clear();
     }
 
     /// Adds an item to the HyperLogLog. This does not increase the memory footprint
@@ -52,58 +735,201 @@ impl HyperLogLog {
     /// * `item`: An item that implements the `Hash` trait to be added to the HLL.
     #[inline(always)]
     pub fn add<T: Hash>(&mut self, item: T) {
-        let mut hasher = SeaHasher::new();
-        item.hash(&mut hasher);
-        let hashed_value = hasher.finish() as usize;
-        let j = hashed_value & (M - 1);
-        let w = hashed_value >> P;
-        let rho = w.leading_zeros() as u8 + 1;
-        self.registers[j] = std::cmp::max(self.registers[j], rho);
+        self.add_hash(hash::hash_item(&self.metadata, &item));
     }
 
-    /// Provides an estimate of the number of unique items added to the HLL.
+    /// Adds an item given as raw bytes with the stable hashing scheme of `hash::stable`,
+    /// which pins the hash function, its seed and the index and rank extraction, so the
+    /// registers only depend on the bytes and stay mergeable with sketches built by any
+    /// later version of this crate. Unlike `add`, the bytes are hashed without the framing
+    /// of their `Hash` implementation.
     ///
-    /// # Returns
-    /// A `f64` approximate count of unique items added to the HLL.
+    /// # Parameters
+    /// * `bytes`: The bytes of the item.
+    ///
+    /// # Panics
+    /// Panics if the sketch was not built with the default hasher and seed.
     #[inline(always)]
-    pub fn estimate(&self) -> f64 {
-        let len = self.registers.len();
-        let simd_iteration_count = len / 8;
-        let mut z = f64x8::splat(0.0);
-
-        for i in 0..simd_iteration_count {
-            z += f64x8::new(
-                2f64.powi(-i32::from(self.registers[i * 8])),
-                2f64.powi(-i32::from(self.registers[i * 8 + 1])),
-                2f64.powi(-i32::from(self.registers[i * 8 + 2])),
-                2f64.powi(-i32::from(self.registers[i * 8 + 3])),
-                2f64.powi(-i32::from(self.registers[i * 8 + 4])),
-                2f64.powi(-i32::from(self.registers[i * 8 + 5])),
-                2f64.powi(-i32::from(self.registers[i * 8 + 6])),
-                2f64.powi(-i32::from(self.registers[i * 8 + 7])),
-            );
-        }
+    pub fn add_stable<B: AsRef<[u8]>>(&mut self, bytes: B) {
+        assert!(
+            self.metadata.hasher == HasherId::SeaHash
+                && self.metadata.seed == 0
+                && self.metadata.key == hash::DEFAULT_SEAHASH_KEY,
+            "stable hashing needs the default hasher and seed"
+        );
 
-        // Processing the remainder
-        let rem = len % 8;
-        if rem != 0 {
-            let mut remainder = f64x8::splat(0.0);
-            for i in 0..rem {
-                remainder += f64x8::splat(
-                    2f64.powi(-i32::from(self.registers[simd_iteration_count * 8 + i])),
-                );
+        self.add_hash(hash::stable::hash(bytes.as_ref()));
+    }
+
+    /// Adds an item given as raw bytes, hashed with the hasher and seed of the sketch but
+    /// without the framing of their `Hash` implementation, as libraries in other languages
+    /// hash them. With `HasherId::Murmur3`, a sketch fed the same bytes with the same seed
+    /// and precision as a sketch using the first 64-bit half of MurmurHash3 x64_128, the
+    /// low bits as register index and the remaining ones for the rank, holds the same
+    /// registers.
+    ///
+    /// # Parameters
+    /// * `bytes`: The bytes of the item, e.g. the UTF-8 encoding of a string.
+    #[inline(always)]
+    pub fn add_raw<B: AsRef<[u8]>>(&mut self, bytes: B) {
+        self.add_hash(hash::hash_raw(&self.metadata, bytes.as_ref()));
+    }
+
+    /// Adds an item given as a byte slice, with the same result as `add(bytes)`. Keys of
+    /// at least `hash::long::LONG_KEY_LEN` bytes are hashed with the vectorized SeaHash of
+    /// `hash::long`, which is faster for long keys such as URLs.
+    ///
+    /// # Parameters
+    /// * `bytes`: The item.
+    #[inline(always)]
+    pub fn add_bytes(&mut self, bytes: &[u8]) {
+        self.add_hash(hash::hash_bytes(&self.metadata, bytes));
+    }
+
+    /// Adds an item given as a string, with the same result as `add(s)`, hashing long keys
+    /// like `add_bytes`.
+    ///
+    /// # Parameters
+    /// * `s`: The item.
+    #[inline(always)]
+    pub fn add_str(&mut self, s: &str) {
+        self.add_hash(hash::hash_str(&self.metadata, s));
+    }
+
+    /// Adds every byte slice yielded by an iterator to the HyperLogLog. The slices are
+    /// hashed in place, without copying them into owned values, and the register updates
+    /// are applied in batches. Each slice is added exactly as `add(slice)` would.
+    ///
+    /// # Parameters
+    /// * `it`: An iterator over borrowed byte slices, e.g. fields yielded by a parser.
+    pub fn add_iter_bytes<'a>(&mut self, it: impl Iterator<Item = &'a [u8]>) {
+        let metadata = self.metadata;
+        ingest::for_each_hash_batch(it, &metadata, |hashes| {
+            for &hash in hashes {
+                self.add_hash(hash);
+            }
+        });
+    }
+
+    /// Adds every item yielded by an iterator to the HyperLogLog, with the same registers as
+    /// calling `add` for every one of them. Items are hashed a chunk at a time and the
+    /// register updates of a chunk are applied in register order, which keeps bulk loads
+    /// from missing the cache on nearly every update. Since updates are reordered, the
+    /// number of register-raising adds counted by `stats` may differ from item-at-a-time
+    /// adds.
+    ///
+    /// # Parameters
+    /// * `items`: The items to add, e.g. a collection or an iterator over one.
+    pub fn add_all<T: Hash, I: IntoIterator<Item = T>>(&mut self, items: I) {
+        let metadata = self.metadata;
+        ingest::for_each_sorted_hash_chunk(items, &metadata, |hashes| {
+            for &hash in hashes {
+                self.add_hash(hash);
             }
-            z += remainder;
+        });
+    }
+
+    /// Adds a slice of integers to the HyperLogLog, with the same result as calling `add` for
+    /// every one of them. The integers are hashed a batch at a time, four per iteration
+    /// with the default SeaHash hasher, before the register updates of the batch are
+    /// applied in one pass.
+    ///
+    /// # Parameters
+    /// * `items`: The integers to add.
+    pub fn add_batch(&mut self, items: &[u64]) {
+        let metadata = self.metadata;
+        ingest::for_each_slice_hash_batch(
+            items,
+            |items, hashes| hash::batch::hash_u64s(&metadata, items, hashes),
+            |hashes| {
+                for &hash in hashes {
+                    self.add_hash(hash);
+                }
+            },
+        );
+    }
+
+    /// Adds a slice of strings to the HyperLogLog, with the same result as calling `add` for
+    /// every one of them. The strings are hashed a batch at a time, four short strings per
+    /// iteration with the default SeaHash hasher, before the register updates of the batch
+    /// are applied in one pass.
+    ///
+    /// # Parameters
+    /// * `items`: The strings to add, e.g. `&[&str]` or `&[String]`.
+    pub fn add_batch_strs<S: AsRef<str>>(&mut self, items: &[S]) {
+        let metadata = self.metadata;
+        ingest::for_each_slice_hash_batch(
+            items,
+            |items, hashes| hash::batch::hash_strs(&metadata, items, hashes),
+            |hashes| {
+                for &hash in hashes {
+                    self.add_hash(hash);
+                }
+            },
+        );
+    }
+
+    /// Adds a slice of byte slices to the HyperLogLog, with the same result as calling
+    /// `add_bytes` for every one of them. The slices are hashed a batch at a time before
+    /// the register updates of the batch are applied in one pass, see `add_iter_bytes`.
+    ///
+    /// # Parameters
+    /// * `items`: The byte slices to add, e.g. `&[&[u8]]` or `&[Vec<u8>]`.
+    pub fn add_batch_bytes<B: AsRef<[u8]>>(&mut self, items: &[B]) {
+        self.add_iter_bytes(items.iter().map(AsRef::as_ref));
+    }
+
+    /// Adds an item already hashed to 64 bits upstream, e.g. by a columnar engine, skipping
+    /// the hasher of the HyperLogLog. The low bits of the hash select the register and the
+    /// rank is taken from the remaining ones, so the hash should be uniformly distributed
+    /// over all 64 bits. Sketches fed this way can only be merged with sketches fed hashes
+    /// of the same function, which their metadata cannot tell.
+    ///
+    /// # Parameters
+    /// * `hash`: The 64-bit hash of the item.
+    #[inline(always)]
+    pub fn add_hashed(&mut self, hash: u64) {
+        self.add_hash(hash);
+    }
+
+    /// Adds an item already hashed to 128 bits, e.g. with MurmurHash3 x64_128, splitting
+    /// the hash into the register index and rank as described by `hash::wide::split`.
+    ///
+    /// # Parameters
+    /// * `hash`: The 128-bit hash of the item.
+    #[inline(always)]
+    pub fn add_hashed128(&mut self, hash: u128) {
+        let (j, rho) = hash::wide::split(hash, P);
+
+        #[cfg(feature = "stats")]
+        self.stats.record_add(rho > self.registers.get(j));
+        #[cfg(feature = "validate")]
+        self.shadow.record(hash::wide::fold(hash));
+        if let Some(activity) = &mut self.activity {
+            activity.record_add();
         }
 
-        let raw_estimate = Self::get_alpha() * (M * M) as f64 / z.sum();
-        let num_zeros = self.registers.iter().filter(|&&val| val == 0).count();
+        self.registers.update_max(j, rho);
+    }
 
-        if num_zeros > 0 {
-            return M as f64 * (M as f64 / num_zeros as f64).ln();
+    /// Updates the registers for an already hashed item.
+    ///
+    /// # Parameters
+    /// * `hash`: The 64-bit SeaHash of the item.
+    #[inline(always)]
+    pub(crate) fn add_hash(&mut self, hash: u64) {
+        let j = hash as usize & (M - 1);
+        let rho = rank(hash, P);
+
+        #[cfg(feature = "stats")]
+        self.stats.record_add(rho > self.registers.get(j));
+        #[cfg(feature = "validate")]
+        self.shadow.record(hash);
+        if let Some(activity) = &mut self.activity {
+            activity.record_add();
         }
 
-        raw_estimate
+        self.registers.update_max(j, rho);
     }
 
     /// Merges another HyperLogLog into the current HLL. This is useful when you want
@@ -111,26 +937,260 @@ impl HyperLogLog {
     ///
     /// # Parameters
     /// * `other`: A reference to another `HyperLogLog` instance to be merged.
+    ///
+    /// # Panics
+    /// Panics if the sketches were built with different metadata, see `try_merge`.
     #[inline(always)]
-    pub fn merge(&mut self, other: &HyperLogLog) {
-        const CHUNKS: usize = M / 16; // This needs to be a const
-
-        unsafe {
-            let self_regs =
-                std::slice::from_raw_parts_mut(self.registers.as_mut_ptr() as *mut u8x16, CHUNKS);
-            let other_regs =
-                std::slice::from_raw_parts(other.registers.as_ptr() as *const u8x16, CHUNKS);
+    pub fn merge<O: Registers>(&mut self, other: &HyperLogLog<O>) {
+        if let Err(e) = self.try_merge(other) {
+            panic!("{}", e);
+        }
+    }
 
-            for i in 0..CHUNKS {
-                self_regs[i] = self_regs[i].max(other_regs[i]);
+    /// Merges another HyperLogLog into this one after checking that both were built with
+    /// the same precision, algorithm, hasher and seed. Under `MergePolicy::Fold`, a sketch
+    /// of higher precision is folded down to the precision of this one instead.
+    ///
+    /// # Parameters
+    /// * `other`: A reference to another `HyperLogLog` instance to be merged.
+    ///
+    /// # Returns
+    /// `Ok(())` once merged, or an `Error::Incompatible` leaving this sketch untouched.
+    #[inline(always)]
+    pub fn try_merge<O: Registers>(&mut self, other: &HyperLogLog<O>) -> Result<(), Error> {
+        match self.metadata.check_compatible(&other.metadata) {
+            Ok(()) => self.registers.merge_max(&other.registers),
+            Err(e) if self.merge_policy == MergePolicy::Fold && e.is_foldable() => {
+                registers::fold_max(&other.registers, &mut self.registers)
             }
+            Err(e) => return Err(e.into()),
         }
 
-        // If M is not a multiple of 16, process remaining elements
-        for i in (CHUNKS * 16)..M {
-            self.registers[i] = std::cmp::max(self.registers[i], other.registers[i]);
+        self.record_merge(other);
+
+        Ok(())
+    }
+
+    /// Combines the labels, activity, counters and shadow set of a merged sketch into this one.
+    #[inline(always)]
+    fn record_merge<O: Registers>(&mut self, other: &HyperLogLog<O>) {
+        if let (Some(labels), Some(other)) = (&mut self.labels, &other.labels) {
+            labels.combine(other);
+        }
+        if let (Some(activity), Some(other)) = (&mut self.activity, &other.activity) {
+            activity.record_merge(other);
         }
+        #[cfg(feature = "stats")]
+        self.stats.record_merge(&other.stats);
+        #[cfg(feature = "validate")]
+        self.shadow.record_merge(&other.shadow);
+    }
+}
+
+/// Number of registers merged from every sketch in turn by `merge_many`, small enough for
+/// a block of the accumulator to stay in the L1 cache.
+const MERGE_BLOCK_LEN: usize = 16 * 1024;
+
+/// Computes the rank of a hash for a sketch of precision `p`: the position of the first
+/// set bit among the `64 - p` bits left after the register index, counted from the top,
+/// or `64 - p + 1` if all of them are zero.
+///
+/// # Parameters
+/// * `hash`: The 64-bit hash of the item.
+/// * `p`: The number of low bits used as the register index.
+///
+/// # Returns
+/// The rank, between 1 and `64 - p + 1`.
+#[inline(always)]
+pub(crate) fn rank(hash: u64, p: usize) -> u8 {
+    // `hash >> p` has at least `p` leading zeros, which are not part of the rank
+    ((hash >> p).leading_zeros() - p as u32 + 1) as u8
+}
+
+/// Computes the alpha constant for bias correction based on the number of registers.
+///
+/// # Parameters
+/// * `m`: The number of registers.
+///
+/// # Returns
+/// A `f64` alpha constant value for the given `m`.
+#[inline(always)]
+pub(crate) fn alpha(m: usize) -> f64 {
+    match m {
+        16 => 0.673,
+        32 => 0.697,
+        64 => 0.709,
+        _ => 0.7213 / (1.0 + 1.079 / m as f64),
+    }
+}
+
+/// The estimator a sketch applies to its registers in `estimate`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Estimator {
+    /// The raw HyperLogLog estimate, switching to linear counting for small
+    /// cardinalities, see `estimate_with_regime`. For a `HyperLogLogPlusPlus`, the legacy
+    /// estimator correcting mid-range raw estimates with a `BiasTable`.
+    #[default]
+    Classic,
+    /// The histogram based estimator of Ertl, see `estimate_ertl`. The default of a
+    /// `HyperLogLogPlusPlus`.
+    Ertl,
+}
+
+/// The branch of an estimator that produced an estimate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum EstimateRegime {
+    /// Linear counting over the registers that are still zero, used for small
+    /// cardinalities.
+    LinearCounting,
+    /// The raw HyperLogLog estimate, `alpha * m^2 / sum(2^-register)`.
+    Raw,
+    /// The raw estimate minus the empirical bias interpolated from a `BiasTable`.
+    BiasCorrected,
+}
+
+/// Sums `2^-register` over all registers and counts the registers that are still zero.
+///
+/// # Parameters
+/// * `registers`: The register array.
+///
+/// # Returns
+/// The harmonic sum and the number of zero registers.
+#[inline(always)]
+pub(crate) fn register_sums<R: Registers + ?Sized>(registers: &R) -> (f64, usize) {
+    let mut sum = 0.0;
+    let mut num_zeros = 0;
+    registers.for_each_chunk(|chunk| {
+        let (chunk_sum, chunk_zeros) = simd::harmonic_sum_and_zeros(chunk);
+        sum += chunk_sum;
+        num_zeros += chunk_zeros;
+    });
+
+    (sum, num_zeros)
+}
+
+/// Sums `2^-register` and counts the zero registers over every `stride`-th register only,
+/// which is itself a HyperLogLog register array of `len / stride` registers holding
+/// about `1 / stride` of the items.
+///
+/// # Parameters
+/// * `registers`: The register array.
+/// * `stride`: Distance between sampled registers.
+///
+/// # Returns
+/// The harmonic sum and the number of zero registers of the sample.
+///
+/// # Panics
+/// Panics if `stride` is not a power of two, or larger than the number of registers.
+pub(crate) fn strided_sums<R: Registers + ?Sized>(registers: &R, stride: usize) -> (f64, usize) {
+    assert!(
+        stride.is_power_of_two() && stride <= registers.len(),
+        "stride must be a power of two no larger than the number of registers"
+    );
+
+    (0..registers.len())
+        .step_by(stride)
+        .map(|index| registers.get(index))
+        .fold((0.0, 0), |(sum, zeros), rank| {
+            (sum + simd::pow2_neg(rank), zeros + (rank == 0) as usize)
+        })
+}
+
+/// Computes the raw HyperLogLog estimate of a register array of any power-of-two length.
+///
+/// # Parameters
+/// * `registers`: The register array.
+///
+/// # Returns
+/// The raw estimate, without any small-range correction.
+#[inline(always)]
+pub(crate) fn raw_estimate_registers<R: Registers + ?Sized>(registers: &R) -> f64 {
+    let (sum, _) = register_sums(registers);
+    let m = registers.len() as f64;
+
+    alpha(registers.len()) * m * m / sum
+}
+
+/// Estimates the cardinality represented by a HyperLogLog register array of any
+/// power-of-two length, using linear counting while some registers are still zero.
+///
+/// # Parameters
+/// * `registers`: The register array.
+///
+/// # Returns
+/// A `f64` approximate count of unique items and the regime that produced it.
+#[inline(always)]
+pub(crate) fn estimate_registers_with_regime<R: Registers + ?Sized>(
+    registers: &R,
+) -> (f64, EstimateRegime) {
+    let (sum, num_zeros) = register_sums(registers);
+
+    estimate_sums(sum, num_zeros, registers.len())
+}
+
+/// Turns the harmonic sum and zero register count of a register array into an estimate,
+/// see `estimate_registers_with_regime`.
+///
+/// # Parameters
+/// * `sum`: The sum of `2^-register` over all registers.
+/// * `num_zeros`: The number of registers that are zero.
+/// * `len`: The number of registers, a power of two.
+///
+/// # Returns
+/// A `f64` approximate count of unique items and the regime that produced it.
+#[inline(always)]
+pub(crate) fn estimate_sums(sum: f64, num_zeros: usize, len: usize) -> (f64, EstimateRegime) {
+    let m = len as f64;
+
+    if num_zeros > 0 {
+        return (
+            m * (m / num_zeros as f64).ln(),
+            EstimateRegime::LinearCounting,
+        );
     }
+
+    (alpha(len) * m * m / sum, EstimateRegime::Raw)
+}
+
+/// Single precision counterpart of `estimate_registers`, summing twice as many registers
+/// per SIMD vector at the cost of a slightly coarser estimate.
+///
+/// # Parameters
+/// * `registers`: The register array.
+///
+/// # Returns
+/// A `f32` approximate count of unique items.
+#[inline(always)]
+pub(crate) fn estimate_registers_f32<R: Registers + ?Sized>(registers: &R) -> f32 {
+    let mut sum = 0.0;
+    let mut num_zeros = 0;
+    registers.for_each_chunk(|chunk| {
+        let (chunk_sum, chunk_zeros) = simd::harmonic_sum_and_zeros_f32(chunk);
+        sum += chunk_sum;
+        num_zeros += chunk_zeros;
+    });
+
+    let m = registers.len() as f32;
+    if num_zeros > 0 {
+        return m * (m / num_zeros as f32).ln();
+    }
+
+    alpha(registers.len()) as f32 * m * m / sum
+}
+
+/// Estimates the cardinality represented by a HyperLogLog register array of any
+/// power-of-two length, see `estimate_registers_with_regime`.
+///
+/// # Parameters
+/// * `registers`: The register array.
+///
+/// # Returns
+/// A `f64` approximate count of unique items.
+#[inline(always)]
+pub(crate) fn estimate_registers<R: Registers + ?Sized>(registers: &R) -> f64 {
+    estimate_registers_with_regime(registers).0
 }
 
 impl Default for HyperLogLog {
@@ -145,20 +1205,142 @@ impl Default for HyperLogLog {
     /// // ... use the `hll` instance ...
     /// ```
     fn default() -> Self {
-        Self::new()
+        Self::new()
+    }
+}
+
+impl From<[u8; M]> for HyperLogLog {
+    /// Creates a `HyperLogLogPlusPlus` instance from a given array of registers.
+    ///
+    /// # Arguments
+    ///
+    /// * `registers`: An array of `u8` representing the internal state
+    ///   of the HyperLogLogPlusPlus.
+    fn from(registers: [u8; M]) -> Self {
+        Self::from(Box::new(registers))
+    }
+}
+
+impl From<Box<[u8; M]>> for HyperLogLog {
+    /// Creates a `HyperLogLog` instance from an already boxed array of registers,
+    /// avoiding a copy of the register array.
+    ///
+    /// # Arguments
+    ///
+    /// * `registers`: A boxed array of `u8` representing the internal state
+    ///   of the HyperLogLog.
+    fn from(registers: Box<[u8; M]>) -> Self {
+        Self::with_registers(registers)
+    }
+}
+
+impl<T: Hash, R: RegistersMut> Extend<T> for HyperLogLog<R> {
+    /// Adds every item of an iterator to the HyperLogLog through the bulk path of `add_all`,
+    /// e.g. `hll.extend(lines)`.
+    ///
+    /// # Arguments
+    ///
+    /// * `iter`: The items to add.
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.add_all(iter)
+    }
+}
+
+/// A `HyperLogLog` holding its registers inline instead of boxed, so a sketch is a plain
+/// value without an allocation of its own and arrays of sketches are laid out
+/// contiguously. Only available with the `p12` and `p14` features, where the registers
+/// take at most 16 KiB; larger values would risk overflowing the stack.
+#[cfg(any(feature = "p12", feature = "p14"))]
+pub type InlineHyperLogLog = HyperLogLog<[u8; M]>;
+
+#[cfg(any(feature = "p12", feature = "p14"))]
+impl InlineHyperLogLog {
+    /// Creates a new HyperLogLog with inline registers, all initialized to zero.
+    ///
+    /// # Returns
+    /// A new `InlineHyperLogLog` instance.
+    pub const fn new_inline() -> Self {
+        Self::new_const()
+    }
+}
+
+impl HyperLogLog<[u8; M]> {
+    /// Creates a new HyperLogLog with inline registers, all initialized to zero, in a
+    /// constant expression, e.g. the initializer of a `static` holding a process-wide
+    /// sketch behind a `Mutex`. Outside of statics, prefer `new`: with the default
+    /// precision, the registers take 1 MiB, which would risk overflowing the stack.
+    ///
+    /// # Returns
+    /// A new `HyperLogLog<[u8; M]>` instance.
+    pub const fn new_const() -> Self {
+        Self::with_registers_unchecked([0; M])
+    }
+}
+
+#[cfg(any(feature = "p12", feature = "p14"))]
+impl Default for InlineHyperLogLog {
+    fn default() -> Self {
+        Self::new_inline()
+    }
+}
+
+/// A `HyperLogLog` whose registers are allocated on a 64-byte boundary, so the SIMD merge
+/// and estimate kernels use aligned loads and stores on them. See `AlignedRegisters`.
+pub type AlignedHyperLogLog = HyperLogLog<Box<AlignedRegisters<M>>>;
+
+impl AlignedHyperLogLog {
+    /// Creates a new HyperLogLog with aligned registers, all initialized to zero.
+    ///
+    /// # Returns
+    /// A new `AlignedHyperLogLog` instance.
+    pub fn new_aligned() -> Self {
+        Self::with_registers(AlignedRegisters::new_boxed())
+    }
+}
+
+impl Default for AlignedHyperLogLog {
+    fn default() -> Self {
+        Self::new_aligned()
+    }
+}
+
+/// A `HyperLogLog` whose clones share their registers until one of them raises a register, so
+/// snapshots for concurrent readers are cheap. See `CowRegisters`.
+pub type CowHyperLogLog = HyperLogLog<CowRegisters>;
+
+impl CowHyperLogLog {
+    /// Creates a new copy-on-write HyperLogLog with all registers initialized to zero.
+    ///
+    /// # Returns
+    /// A new `CowHyperLogLog` instance.
+    pub fn new_cow() -> Self {
+        Self::with_registers(CowRegisters::new(M))
+    }
+}
+
+impl Default for CowHyperLogLog {
+    fn default() -> Self {
+        Self::new_cow()
     }
 }
 
-impl From<[u8; M]> for HyperLogLog {
-    /// Creates a `HyperLogLogPlusPlus` instance from a given array of registers.
+#[cfg(feature = "allocator_api")]
+impl<A: std::alloc::Allocator> HyperLogLog<Box<[u8; M], A>> {
+    /// Creates a new HyperLogLog whose registers are allocated by `alloc`, e.g. an arena or
+    /// bump allocator, for services creating and dropping sketches at a rate where the
+    /// global allocator becomes the bottleneck. Only available with the `allocator_api`
+    /// feature, which needs a nightly toolchain.
     ///
-    /// # Arguments
+    /// # Parameters
+    /// * `alloc`: The allocator of the register block.
     ///
-    /// * `registers`: An array of `u8` representing the internal state
-    ///   of the HyperLogLogPlusPlus.
-    fn from(registers: [u8; M]) -> Self {
-        let r = Box::new(registers);
-        HyperLogLog { registers: r }
+    /// # Returns
+    /// A new `HyperLogLog` instance with all registers initialized to zero.
+    pub fn new_in(alloc: A) -> Self {
+        // SAFETY: zeroed bytes are valid registers
+        let registers = unsafe { Box::<[u8; M], A>::new_zeroed_in(alloc).assume_init() };
+
+        Self::with_registers(registers)
     }
 }
 
@@ -172,7 +1354,13 @@ impl Serialize for HyperLogLog {
     where
         S: Serializer,
     {
-        serialize_registers(&self.registers, serializer)
+        serialize_registers(
+            self.registers.as_slice(),
+            &self.metadata,
+            self.labels.as_deref(),
+            self.activity.as_ref(),
+            serializer,
+        )
     }
 }
 
@@ -182,18 +1370,29 @@ impl<'de> Deserialize<'de> for HyperLogLog {
     ///
     /// The data is expected to contain a `registers` field in a specific
     /// serialized format. The `CompressedRegistersVisitor` is used to assist
-    /// in this deserialization process.
+    /// in this deserialization process. Payloads recording a different precision,
+    /// algorithm or hasher are rejected.
     fn deserialize<D>(deserializer: D) -> Result<HyperLogLog, D::Error>
     where
         D: Deserializer<'de>,
     {
-        deserializer.deserialize_map(CompressedRegistersVisitor::new())
+        let (registers, metadata, labels, activity) = deserializer.deserialize_map(
+            CompressedRegistersVisitor::new(SketchMetadata::new(Algorithm::HyperLogLog, P)),
+        )?;
+
+        Ok(HyperLogLog {
+            metadata,
+            labels: labels.map(Box::new),
+            activity,
+            ..Self::from(registers)
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::HyperLogLog;
+    use super::EstimateRegime;
+    use crate::{registers::PackedRegisters, Error, HyperLogLog, M, MAX_RANK, P};
     use std::collections::HashSet;
 
     #[test]
@@ -250,4 +1449,688 @@ mod tests {
 
         assert_eq!(hll1.estimate().round() as u32, 4);
     }
+
+    #[test]
+    fn test_merge_many() {
+        let sketches = (0..5)
+            .map(|s| {
+                let mut hll = HyperLogLog::new();
+                for i in 0..1_000 {
+                    hll.add(s * 700 + i);
+                }
+                hll
+            })
+            .collect::<Vec<_>>();
+
+        let mut sequential = HyperLogLog::new();
+        for hll in &sketches {
+            sequential.merge(hll);
+        }
+        let mut batched = HyperLogLog::new();
+        batched.merge_many(&sketches);
+
+        assert!(batched.diff(&sequential).is_identical());
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut hll1 = HyperLogLog::new();
+        let mut hll2 = HyperLogLog::new();
+
+        for i in 0..100 {
+            hll1.add(i);
+            hll2.add(i);
+        }
+
+        assert!(hll1.diff(&hll2).is_identical());
+
+        for i in 100..110 {
+            hll1.add(i);
+        }
+
+        let diff = hll1.diff(&hll2);
+        assert!(diff.differing > 0);
+        assert_eq!(diff.other_ahead, 0);
+        assert_eq!(diff.self_ahead, diff.differing);
+        assert!(diff.estimate_gap > 0.0);
+    }
+
+    #[test]
+    fn test_register_stores() {
+        let mut boxed = HyperLogLog::new();
+        let mut vec = HyperLogLog::with_registers(vec![0u8; M]);
+        let mut packed = HyperLogLog::with_registers(PackedRegisters::new(M));
+
+        for i in 0..10_000 {
+            boxed.add(i);
+            vec.add(i);
+            packed.add(i);
+        }
+
+        assert_eq!(vec.estimate(), boxed.estimate());
+        assert_eq!(packed.estimate(), boxed.estimate());
+
+        let mut merged = HyperLogLog::new();
+        merged.merge(&packed);
+        assert!(merged.diff(&boxed).is_identical());
+    }
+
+    #[test]
+    fn test_rank_boundaries() {
+        let mut hll = HyperLogLog::new();
+
+        // No bit set after the index saturates the register at 64 - P + 1
+        hll.add_hash(0);
+        assert_eq!(hll.registers[0], MAX_RANK);
+        assert_eq!(MAX_RANK as usize, 64 - P + 1);
+
+        // Only the lowest bit after the index set
+        hll.add_hash(1 << P | 1);
+        assert_eq!(hll.registers[1], MAX_RANK - 1);
+
+        // The top bit set gives the lowest rank
+        hll.add_hash(1 << 63 | 2);
+        assert_eq!(hll.registers[2], 1);
+
+        hll.add_hash(u64::MAX);
+        assert_eq!(hll.registers[M - 1], 1);
+
+        assert!(HyperLogLog::try_from_bytes(hll.registers.as_slice()).is_ok());
+    }
+
+    #[test]
+    fn test_estimate_regime() {
+        let mut hll = HyperLogLog::new();
+        for i in 0..1_000 {
+            hll.add(i);
+        }
+
+        let (estimate, regime) = hll.estimate_with_regime();
+        assert_eq!(estimate, hll.estimate());
+        assert_eq!(regime, EstimateRegime::LinearCounting);
+        assert!(hll.raw_estimate() > estimate);
+
+        let full = HyperLogLog::from(Box::new([10; M]));
+        let (estimate, regime) = full.estimate_with_regime();
+        assert_eq!(regime, EstimateRegime::Raw);
+        assert_eq!(estimate, full.raw_estimate());
+    }
+
+    #[test]
+    fn test_estimate_f32() {
+        let mut hll = HyperLogLog::new();
+        for i in 0..100_000 {
+            hll.add(i);
+        }
+        let gap = (f64::from(hll.estimate_f32()) - hll.estimate()).abs();
+        assert!(gap < 1e-4 * hll.estimate());
+
+        let full = HyperLogLog::from(Box::new([10; M]));
+        let gap = (f64::from(full.estimate_f32()) - full.estimate()).abs();
+        assert!(gap < 1e-4 * full.estimate());
+    }
+
+    #[test]
+    fn test_estimate_with_budget() {
+        let mut hll = HyperLogLog::new();
+        for i in 0..1_000_000 {
+            hll.add(i);
+        }
+
+        assert_eq!(hll.estimate_with_budget(1), hll.estimate());
+        for stride in [4, 16] {
+            let error = (hll.estimate_with_budget(stride) - 1_000_000.0).abs() / 1_000_000.0;
+            assert!(
+                error < 4.0 * 1.04 / ((M / stride) as f64).sqrt(),
+                "relative error {} at stride {}",
+                error,
+                stride
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_estimate_with_budget_stride() {
+        HyperLogLog::new().estimate_with_budget(3);
+    }
+
+    #[test]
+    fn test_difference_estimate() {
+        // Cardinalities scale with the precision to stay below the range where the last
+        // zero registers make linear counting saturate
+        let k = M / 200;
+        let mut a = HyperLogLog::new();
+        let mut b = HyperLogLog::new();
+        for i in 0..4 * k {
+            a.add(i);
+        }
+        for i in 3 * k..8 * k {
+            b.add(i);
+        }
+
+        let difference = a.difference_estimate(&b);
+        let bound = 4.0 * 1.04 / (M as f64).sqrt() * (8 * k + 5 * k) as f64;
+        assert!(
+            (difference - (3 * k) as f64).abs() < bound,
+            "{}",
+            difference
+        );
+        assert_eq!(a.difference_estimate(&a), 0.0);
+        assert_eq!(HyperLogLog::new().difference_estimate(&b), 0.0);
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let mut a = HyperLogLog::new();
+        let mut b = HyperLogLog::new();
+        assert_eq!(a.fingerprint(), 0);
+
+        for i in 0..1_000 {
+            a.add(i);
+            b.add(999 - i);
+        }
+        assert_eq!(a.fingerprint(), b.fingerprint());
+
+        b.add(1_000);
+        assert_ne!(a.fingerprint(), b.fingerprint());
+    }
+
+    #[cfg(feature = "murmur3")]
+    #[test]
+    fn test_with_hasher() {
+        use crate::metadata::HasherId;
+
+        let mut murmur = HyperLogLog::with_hasher(HasherId::Murmur3);
+        let mut sea = HyperLogLog::new();
+        for i in 0..10_000 {
+            murmur.add(i);
+            sea.add(i);
+        }
+
+        assert_eq!(murmur.metadata().hasher, HasherId::Murmur3);
+        assert!(!murmur.diff(&sea).is_identical());
+        assert!((murmur.estimate() - 10_000.0).abs() < 100.0);
+        assert!(murmur.try_merge(&sea).is_err());
+    }
+
+    #[cfg(feature = "murmur3")]
+    #[test]
+    fn test_add_raw_murmur3() {
+        use crate::{hash::murmur3::murmur3_x64_128, metadata::HasherId};
+
+        let mut hll = HyperLogLog::with_hasher(HasherId::Murmur3);
+        hll.add_raw("hello");
+        // The first half of MurmurHash3 x64_128("hello") is 0xcbd8_a7b3_41bd_9b02
+        assert_eq!(hll.register(0xcbd8_a7b3_41bd_9b02 & (M - 1)), 1);
+        assert_eq!(hll.zero_register_count(), M - 1);
+
+        let mut hashed = HyperLogLog::with_hasher(HasherId::Murmur3);
+        for i in 0..10_000 {
+            let key = format!("user-{}", i);
+            hll.add_raw(&key);
+            hashed.add_hashed(murmur3_x64_128(key.as_bytes(), 0) as u64);
+        }
+        hashed.add_hashed(0xcbd8_a7b3_41bd_9b02);
+        assert!(hll.diff(&hashed).is_identical());
+    }
+
+    #[test]
+    fn test_add_bytes_and_str() {
+        let mut generic = HyperLogLog::new();
+        let mut specialized = HyperLogLog::new();
+        for i in 0..2_000 {
+            let key = format!("https://example.com/{}", "segment/".repeat(i % 20)) + &i.to_string();
+            generic.add(key.as_str());
+            generic.add(key.as_bytes());
+            specialized.add_str(&key);
+            specialized.add_bytes(key.as_bytes());
+        }
+
+        assert!(specialized.diff(&generic).is_identical());
+    }
+
+    #[test]
+    fn test_add_hashed128() {
+        let mut hll = HyperLogLog::new();
+        for i in 0..50_000u64 {
+            let hash = u128::from(seahash::hash(&i.to_le_bytes())) << 64
+                | u128::from(seahash::hash(&i.to_be_bytes()));
+            hll.add_hashed128(hash);
+        }
+
+        let error = (hll.estimate() - 50_000.0).abs() / 50_000.0;
+        assert!(
+            error < 4.0 * 1.04 / (M as f64).sqrt(),
+            "relative error {}",
+            error
+        );
+        assert!(hll.registers.iter().all(|&rank| rank <= MAX_RANK));
+    }
+
+    #[test]
+    fn test_add_all() {
+        let keys = (0..10_000)
+            .map(|i| format!("user-{}", i))
+            .collect::<Vec<_>>();
+        let (mut bulk, mut hll) = (HyperLogLog::new(), HyperLogLog::new());
+        bulk.add_all(&keys);
+        bulk.add_all(0..5_000u32);
+        for key in &keys {
+            hll.add(key);
+        }
+        for i in 0..5_000u32 {
+            hll.add(i);
+        }
+
+        assert!(bulk.diff(&hll).is_identical());
+    }
+
+    #[test]
+    fn test_extend() {
+        let text = "the quick brown fox jumps over the lazy dog";
+        let mut hll = HyperLogLog::new();
+        hll.extend(text.split(' '));
+
+        let mut expected = HyperLogLog::new();
+        for word in text.split(' ') {
+            expected.add(word);
+        }
+        assert!(hll.diff(&expected).is_identical());
+        assert_eq!(hll.estimate().round(), 8.0);
+    }
+
+    #[test]
+    fn test_add_batch() {
+        let items = (0..10_000u64).map(|i| i * 7_919).collect::<Vec<_>>();
+        let keys = items.iter().map(|i| i.to_string()).collect::<Vec<_>>();
+        let (mut batched, mut hll) = (HyperLogLog::new(), HyperLogLog::new());
+        batched.add_batch(&items[..9_999]);
+        batched.add_batch(&items[9_999..]);
+        batched.add_batch_bytes(&keys);
+        for (item, key) in items.iter().zip(&keys) {
+            hll.add(item);
+            hll.add(key.as_bytes());
+        }
+
+        assert!(batched.diff(&hll).is_identical());
+    }
+
+    #[test]
+    fn test_add_batch_strs() {
+        let users = (1..50_000)
+            .map(|i| format!("user-{}", i))
+            .collect::<Vec<_>>();
+        let (mut batched, mut hll) = (HyperLogLog::new(), HyperLogLog::new());
+        batched.add_batch_strs(&users);
+        batched.add_batch_strs(&["a", "https://example.com/a/rather/long/path"]);
+        for user in &users {
+            hll.add(user);
+        }
+        hll.add("a");
+        hll.add("https://example.com/a/rather/long/path");
+
+        assert!(batched.diff(&hll).is_identical());
+    }
+
+    #[test]
+    fn test_add_hashed() {
+        let (mut hashed, mut hll) = (HyperLogLog::new(), HyperLogLog::new());
+        for i in 0..10_000u64 {
+            hashed.add_hashed(crate::hash::hash_item(&hll.metadata(), &i));
+            hll.add(i);
+        }
+
+        assert!(hashed.diff(&hll).is_identical());
+    }
+
+    #[test]
+    fn test_estimate_ertl() {
+        let mut hll = HyperLogLog::new();
+        assert_eq!(hll.estimate_ertl(), 0.0);
+
+        for i in 0..100_000 {
+            hll.add(i);
+        }
+        let error = (hll.estimate_ertl() - 100_000.0).abs() / 100_000.0;
+        assert!(
+            error < 4.0 * 1.04 / (M as f64).sqrt(),
+            "relative error {}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_health() {
+        let mut hll = HyperLogLog::new();
+        for i in 0..M / 20 {
+            hll.add(i);
+        }
+
+        let health = hll.health();
+        assert!(health.in_reliable_range);
+        assert_eq!(health.precision as usize, P);
+        assert_eq!(health.high_rank_fraction, 0.0);
+        assert!(health.zero_fraction > 0.9);
+    }
+
+    #[test]
+    fn test_try_from_bytes() {
+        let mut hll = HyperLogLog::new();
+        hll.add(1);
+
+        let decoded = HyperLogLog::try_from_bytes(hll.registers.as_slice()).unwrap();
+        assert!(decoded.diff(&hll).is_identical());
+
+        assert_eq!(
+            HyperLogLog::try_from_bytes(&[0; 16]).unwrap_err(),
+            Error::Length {
+                expected: M,
+                found: 16
+            }
+        );
+
+        let mut corrupt = vec![0u8; M];
+        corrupt[3] = u8::MAX;
+        assert!(matches!(
+            HyperLogLog::try_with_registers(corrupt),
+            Err(Error::InvalidRegister { index: 3, .. })
+        ));
+    }
+
+    #[cfg(feature = "validate")]
+    #[test]
+    fn test_observed_error() {
+        let mut hll = HyperLogLog::new();
+        assert_eq!(hll.observed_error(), None);
+
+        for i in 0..10_000 {
+            hll.add(i % 5_000);
+        }
+        assert_eq!(hll.exact_count(), Some(5_000));
+        assert!(hll.observed_error().unwrap().abs() < 0.05);
+
+        hll.set_validation_limit(1_000);
+        assert_eq!(hll.observed_error(), None);
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn test_stats() {
+        let mut hll1 = HyperLogLog::new();
+        for _ in 0..10 {
+            hll1.add("a");
+        }
+
+        let stats = hll1.stats();
+        assert_eq!(stats.adds, 10);
+        assert_eq!(stats.raising_adds, 1);
+
+        let mut hll2 = HyperLogLog::new();
+        hll2.add("b");
+        hll2.merge(&hll1);
+
+        let stats = hll2.stats();
+        assert_eq!(stats.adds, 11);
+        assert_eq!(stats.merges, 1);
+    }
+
+    #[cfg(feature = "serde_support")]
+    #[test]
+    fn test_labels() {
+        use crate::labels::SketchLabels;
+
+        let mut hll = HyperLogLog::new();
+        hll.set_labels(SketchLabels::named("users").with_time_range(100, 200));
+        hll.add(1);
+
+        let json = serde_json::to_string(&hll).unwrap();
+        let mut decoded: HyperLogLog = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.labels(), hll.labels());
+
+        let mut other = HyperLogLog::new();
+        other.set_labels(
+            SketchLabels::default()
+                .with_source("eu-1")
+                .with_time_range(50, 150),
+        );
+        decoded.merge(&other);
+        assert_eq!(
+            decoded.labels(),
+            Some(&SketchLabels {
+                name: Some("users".to_string()),
+                source: Some("eu-1".to_string()),
+                start: Some(50),
+                end: Some(200),
+            })
+        );
+
+        // Unlabeled sketches stay unlabeled
+        other.merge(&HyperLogLog::new());
+        let mut unlabeled = HyperLogLog::new();
+        unlabeled.merge(&other);
+        assert_eq!(unlabeled.labels(), None);
+        assert_eq!(unlabeled.take_labels(), None);
+        assert!(!serde_json::to_string(&unlabeled)
+            .unwrap()
+            .contains("\"name\""));
+    }
+
+    #[cfg(feature = "serde_support")]
+    #[test]
+    fn test_activity() {
+        let mut hll = HyperLogLog::new();
+        hll.add(0);
+        assert_eq!(hll.activity(), None);
+
+        hll.track_activity();
+        for i in 0..10 {
+            hll.add(i % 4);
+        }
+        let activity = hll.activity().unwrap();
+        assert_eq!(activity.adds, 10);
+        assert!(activity.created <= activity.updated);
+        assert!((hll.duplication_factor().unwrap() - 2.5).abs() < 0.01);
+        assert!((hll.distinct_ratio().unwrap() - 0.4).abs() < 0.01);
+
+        let json = serde_json::to_string(&hll).unwrap();
+        let mut decoded: HyperLogLog = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.activity(), Some(activity));
+
+        let mut other = HyperLogLog::new();
+        other.track_activity();
+        other.add(100);
+        decoded.merge(&other);
+        let merged = decoded.activity().unwrap();
+        assert_eq!(merged.adds, 11);
+        assert_eq!(merged.created, activity.created);
+        assert_eq!(merged.updated, other.activity().unwrap().updated);
+
+        decoded.clear();
+        assert_eq!(decoded.activity().unwrap().adds, 0);
+    }
+
+    #[test]
+    fn test_introspection() {
+        let mut hll = HyperLogLog::new();
+        assert_eq!(hll.precision() as usize, P);
+        assert_eq!(hll.register_count(), M);
+        assert_eq!(hll.nonzero_register_count(), 0);
+        assert_eq!(hll.memory_usage(), M);
+
+        hll.add("a");
+        hll.add("b");
+        assert_eq!(hll.nonzero_register_count(), 2);
+        hll.set_labels(crate::labels::SketchLabels::named("users"));
+        assert!(hll.memory_usage() > M);
+
+        let packed = HyperLogLog::with_registers(PackedRegisters::new(M));
+        assert_eq!(packed.memory_usage(), M / 4 * 3);
+        let borrowed = HyperLogLog::with_registers(&hll.registers[..]);
+        assert_eq!(borrowed.memory_usage(), 0);
+    }
+
+    #[test]
+    fn test_with_seed() {
+        let key = [1, 2, 3, 4];
+        let url = "https://example.com/a/rather/long/path?with=query&and=more#fragment";
+        let (mut a, mut b) = (HyperLogLog::with_seed(key), HyperLogLog::with_seed(key));
+        let mut unkeyed = HyperLogLog::new();
+        for i in 0..1_000 {
+            a.add(i);
+            b.add(i + 500);
+            unkeyed.add(i);
+        }
+        a.add_bytes(url.as_bytes());
+        b.add(url.as_bytes());
+
+        assert_eq!(a.seed(), key);
+        assert_eq!(unkeyed.seed(), crate::hash::DEFAULT_SEAHASH_KEY);
+        assert!(!a.diff(&unkeyed).is_identical());
+        assert!(matches!(a.try_merge(&unkeyed), Err(Error::Incompatible(_))));
+        a.merge(&b);
+        assert!((a.estimate() - 1_501.0).abs() < 20.0);
+
+        let mut buf = Vec::new();
+        a.serialize_into(&mut buf);
+        assert_eq!(buf.len(), a.serialized_size_hint());
+        assert_eq!(HyperLogLog::deserialize_from(&buf).unwrap().seed(), key);
+
+        #[cfg(feature = "serde_support")]
+        {
+            let json = serde_json::to_string(&a).unwrap();
+            let decoded: HyperLogLog = serde_json::from_str(&json).unwrap();
+            assert_eq!(decoded.seed(), key);
+            assert!(decoded.diff(&a).is_identical());
+        }
+    }
+
+    #[test]
+    fn test_register_accessors() {
+        let mut hll = HyperLogLog::new();
+        hll.add("a");
+
+        let hash = crate::hash::hash_item(&hll.metadata(), &"a");
+        let index = hash as usize & (M - 1);
+        assert_eq!(hll.register(index), super::rank(hash, P));
+        assert_eq!(hll.registers()[index], hll.register(index));
+        assert_eq!(hll.as_bytes(), &hll.registers()[..]);
+
+        let copy = HyperLogLog::try_from_bytes(hll.as_bytes()).unwrap();
+        assert!(copy.diff(&hll).is_identical());
+    }
+
+    #[cfg(feature = "allocator_api")]
+    #[test]
+    fn test_new_in() {
+        use std::{
+            alloc::{AllocError, Allocator, Global, Layout},
+            ptr::NonNull,
+            sync::atomic::{AtomicUsize, Ordering},
+        };
+
+        static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Clone, Copy)]
+        struct Counting;
+
+        unsafe impl Allocator for Counting {
+            fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+                ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+                Global.allocate(layout)
+            }
+
+            unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+                ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+                unsafe { Global.deallocate(ptr, layout) }
+            }
+        }
+
+        let mut hll = HyperLogLog::new_in(Counting);
+        let mut global = HyperLogLog::new();
+        assert_eq!(ALLOCATED.load(Ordering::Relaxed), M);
+        for i in 0..10_000 {
+            hll.add(i);
+            global.add(i);
+        }
+
+        assert_eq!(hll.estimate(), global.estimate());
+        global.merge(&hll);
+        assert_eq!(hll.memory_usage(), M);
+        drop(hll);
+        assert_eq!(ALLOCATED.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_aligned_registers() {
+        let mut aligned = crate::AlignedHyperLogLog::new_aligned();
+        let mut boxed = HyperLogLog::new();
+        for i in 0..10_000 {
+            aligned.add(i);
+            boxed.add(i);
+        }
+
+        assert!((aligned.registers.as_ptr() as usize).is_multiple_of(64));
+        assert_eq!(aligned.estimate(), boxed.estimate());
+        aligned.merge(&boxed);
+        boxed.merge(&aligned);
+        assert_eq!(boxed.as_bytes(), aligned.as_bytes());
+    }
+
+    #[test]
+    fn test_cow_clone() {
+        let mut hll = crate::CowHyperLogLog::new_cow();
+        for i in 0..10_000 {
+            hll.add(i);
+        }
+
+        let snapshot = hll.clone();
+        assert!(hll.registers.is_shared());
+        hll.add(0);
+        assert!(hll.registers.is_shared());
+
+        for i in 10_000..20_000 {
+            hll.add(i);
+        }
+        assert!(!snapshot.registers.is_shared());
+        let bound = 4.0 * 1.04 / (M as f64).sqrt();
+        assert!((snapshot.estimate() - 10_000.0).abs() / 10_000.0 < bound);
+        assert!((hll.estimate() - 20_000.0).abs() / 20_000.0 < bound);
+    }
+
+    #[test]
+    fn test_fold() {
+        let mut hll = HyperLogLog::new();
+        let mut expected = crate::DynHyperLogLog::new(10);
+        for i in 0..50_000 {
+            hll.add(i);
+            expected.add(i);
+        }
+
+        let folded = hll.fold(10);
+        assert_eq!(folded, expected);
+        assert_eq!(folded.metadata().hasher, hll.metadata().hasher);
+        assert_eq!(hll.fold(P as u8).registers(), &hll.registers[..]);
+    }
+
+    #[cfg(any(feature = "p12", feature = "p14"))]
+    #[test]
+    fn test_inline_registers() {
+        use crate::InlineHyperLogLog;
+
+        let mut sketches = vec![InlineHyperLogLog::new_inline(); 4];
+        let mut boxed = HyperLogLog::new();
+        for i in 0..10_000 {
+            sketches[i % 4].add(i);
+            boxed.add(i);
+        }
+
+        let mut union = InlineHyperLogLog::default();
+        for sketch in &sketches {
+            union.merge(sketch);
+        }
+        assert_eq!(&union.registers[..], &boxed.registers[..]);
+        assert_eq!(union.estimate(), boxed.estimate());
+        assert!(std::mem::size_of::<InlineHyperLogLog>() > M);
+    }
 }
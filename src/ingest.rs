@@ -0,0 +1,614 @@
+use std::{
+    hash::Hash,
+    io::{self, BufRead, BufReader, Read},
+};
+
+use crate::{hash, metadata::SketchMetadata, sketch::Sketch, HyperLogLog, M};
+
+/// Size of the read buffer used when streaming delimited files.
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Number of items hashed before their register updates are applied.
+pub(crate) const HASH_BATCH_SIZE: usize = 64;
+
+/// Number of items hashed and sorted by register index before their register updates are
+/// applied by `for_each_sorted_hash_chunk`.
+pub(crate) const SORTED_CHUNK_SIZE: usize = 4096;
+
+/// Hashes byte slices in batches of `HASH_BATCH_SIZE` and hands every batch of hashes
+/// to `apply`. Hashing a whole batch before touching the registers keeps the hashing loop
+/// tight and lets the register updates of a batch overlap their cache misses.
+///
+/// # Arguments
+///
+/// * `it`: An iterator over the byte slices to hash.
+/// * `metadata`: The metadata of the sketch, selecting the hash function.
+/// * `apply`: Called with each batch of hashes, in iteration order.
+pub(crate) fn for_each_hash_batch<'a>(
+    mut it: impl Iterator<Item = &'a [u8]>,
+    metadata: &SketchMetadata,
+    mut apply: impl FnMut(&[u64]),
+) {
+    let mut hashes = [0u64; HASH_BATCH_SIZE];
+
+    loop {
+        let mut len = 0;
+
+        for (slot, bytes) in hashes.iter_mut().zip(&mut it) {
+            *slot = hash::hash_bytes(metadata, bytes);
+            len += 1;
+        }
+
+        if len == 0 {
+            break;
+        }
+
+        apply(&hashes[..len]);
+
+        if len < HASH_BATCH_SIZE {
+            break;
+        }
+    }
+}
+
+/// Hashes a slice of items in batches of `HASH_BATCH_SIZE` like `for_each_hash_batch`,
+/// with a function hashing a whole batch at once, e.g. the vectorized
+/// `hash::batch::hash_u64s`.
+///
+/// # Arguments
+///
+/// * `items`: The items to hash.
+/// * `hash`: Writes the hash of every item of a batch to the slot of the same index.
+/// * `apply`: Called with each batch of hashes, in the order of `items`.
+pub(crate) fn for_each_slice_hash_batch<T>(
+    items: &[T],
+    mut hash: impl FnMut(&[T], &mut [u64]),
+    mut apply: impl FnMut(&[u64]),
+) {
+    let mut hashes = [0u64; HASH_BATCH_SIZE];
+
+    for chunk in items.chunks(HASH_BATCH_SIZE) {
+        let hashes = &mut hashes[..chunk.len()];
+        hash(chunk, hashes);
+
+        apply(hashes);
+    }
+}
+
+/// Hashes items in chunks of `SORTED_CHUNK_SIZE` and hands every chunk of hashes to
+/// `apply`, sorted by the register index in their low `P` bits. The updates of a chunk then
+/// walk the registers in one direction, hitting neighbouring registers in the same cache
+/// lines and pages instead of jumping across the whole register array for every item.
+///
+/// # Arguments
+///
+/// * `items`: The items to hash.
+/// * `metadata`: The metadata of the sketch, selecting the hash function.
+/// * `apply`: Called with each chunk of hashes, sorted by register index.
+pub(crate) fn for_each_sorted_hash_chunk<T: Hash>(
+    items: impl IntoIterator<Item = T>,
+    metadata: &SketchMetadata,
+    mut apply: impl FnMut(&[u64]),
+) {
+    let mut it = items.into_iter();
+    let mut hashes = [0u64; SORTED_CHUNK_SIZE];
+
+    loop {
+        let mut len = 0;
+
+        for (slot, item) in hashes.iter_mut().zip(&mut it) {
+            *slot = hash::hash_item(metadata, &item);
+            len += 1;
+        }
+
+        if len == 0 {
+            break;
+        }
+
+        let chunk = &mut hashes[..len];
+        chunk.sort_unstable_by_key(|hash| hash & (M as u64 - 1));
+        apply(chunk);
+
+        if len < SORTED_CHUNK_SIZE {
+            break;
+        }
+    }
+}
+
+/// Parser state while scanning a delimited record.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FieldState {
+    /// Inside an unquoted field, or between fields.
+    Unquoted,
+    /// Inside a quoted field.
+    Quoted,
+    /// Just saw a quote inside a quoted field; it either closes the field or is the
+    /// first half of an escaped `""` quote.
+    QuoteInQuoted,
+}
+
+/// Streams a comma separated file and adds every value of one column to a sketch.
+///
+/// This is a shorthand for `ingest_delimited_column` with `,` as the delimiter.
+///
+/// # Arguments
+///
+/// * `reader`: The source of the CSV data.
+/// * `column_index`: Zero-based index of the column to ingest.
+/// * `sketch`: The sketch the column values are added to.
+///
+/// # Returns
+/// The number of values added to the sketch.
+pub fn ingest_csv_column<R: Read, S: Sketch>(
+    reader: R,
+    column_index: usize,
+    sketch: &mut S,
+) -> io::Result<u64> {
+    ingest_delimited_column(reader, b',', column_index, sketch)
+}
+
+/// Streams a delimited file (CSV, TSV, ...) and adds every value of one column to a sketch.
+///
+/// The input is read in fixed-size chunks and only the bytes of the selected column are
+/// copied, into a single reused buffer, so arbitrarily large files are processed in
+/// constant memory. Fields may be quoted with `"` following RFC 4180, in which case
+/// delimiters and line breaks inside the quotes are part of the value and `""` stands for
+/// a literal quote. Both `\n` and `\r\n` line endings are accepted, empty lines are skipped
+/// and rows with fewer columns than `column_index + 1` are ignored.
+///
+/// Values are added as byte slices, so they hash identically to `sketch.add(value.as_bytes())`.
+/// A header row, if present, is counted like any other value.
+///
+/// # Arguments
+///
+/// * `reader`: The source of the delimited data.
+/// * `delimiter`: The byte separating fields, e.g. `b','` or `b'\t'`.
+/// * `column_index`: Zero-based index of the column to ingest.
+/// * `sketch`: The sketch the column values are added to.
+///
+/// # Returns
+/// The number of values added to the sketch.
+pub fn ingest_delimited_column<R: Read, S: Sketch>(
+    reader: R,
+    delimiter: u8,
+    column_index: usize,
+    sketch: &mut S,
+) -> io::Result<u64> {
+    let mut reader = BufReader::with_capacity(READ_BUFFER_SIZE, reader);
+    let mut field = Vec::new();
+    let mut state = FieldState::Unquoted;
+    let mut column = 0;
+    let mut field_started = false;
+    let mut row_started = false;
+    let mut added = 0;
+
+    loop {
+        let buf = reader.fill_buf()?;
+
+        if buf.is_empty() {
+            break;
+        }
+
+        for &byte in buf {
+            let selected = column == column_index;
+
+            match state {
+                FieldState::Quoted => {
+                    if byte == b'"' {
+                        state = FieldState::QuoteInQuoted;
+                    } else if selected {
+                        field.push(byte);
+                    }
+                    continue;
+                }
+                FieldState::QuoteInQuoted if byte == b'"' => {
+                    if selected {
+                        field.push(b'"');
+                    }
+                    state = FieldState::Quoted;
+                    continue;
+                }
+                FieldState::QuoteInQuoted => state = FieldState::Unquoted,
+                FieldState::Unquoted => {}
+            }
+
+            if byte == delimiter || byte == b'\n' {
+                // A delimiter always belongs to a row, even when the field before it is
+                // empty, while a line break on its own is just an empty line.
+                row_started |= byte == delimiter;
+
+                if selected && row_started {
+                    sketch.add(field.as_slice());
+                    field.clear();
+                    added += 1;
+                }
+
+                if byte == b'\n' {
+                    column = 0;
+                    row_started = false;
+                } else {
+                    column += 1;
+                }
+
+                field_started = false;
+            } else if byte == b'"' && !field_started {
+                state = FieldState::Quoted;
+                field_started = true;
+                row_started = true;
+            } else if byte != b'\r' {
+                if selected {
+                    field.push(byte);
+                }
+                field_started = true;
+                row_started = true;
+            }
+        }
+
+        let len = buf.len();
+        reader.consume(len);
+    }
+
+    // The last row may not be terminated by a line break.
+    if row_started && column == column_index {
+        sketch.add(field.as_slice());
+        added += 1;
+    }
+
+    Ok(added)
+}
+
+/// Adds an item to several sketches while hashing it only once.
+///
+/// Multi-dimensional aggregation (e.g. a global, a per-hour and a per-country sketch fed by
+/// the same stream) otherwise pays the hashing cost once per dimension. The result is
+/// identical to calling `add(item)` on every sketch.
+///
+/// # Arguments
+///
+/// * `item`: The item to be added. It should implement the `Hash` trait.
+/// * `sketches`: The sketches the item is added to.
+///
+/// # Panics
+/// Panics if the sketches do not all use the same hasher and seed.
+pub fn add_to_all<T: Hash>(item: T, sketches: &mut [&mut HyperLogLog]) {
+    let Some(metadata) = sketches.first().map(|sketch| sketch.metadata()) else {
+        return;
+    };
+    assert!(
+        sketches.iter().all(|sketch| {
+            let other = sketch.metadata();
+            (other.hasher, other.seed, other.key) == (metadata.hasher, metadata.seed, metadata.key)
+        }),
+        "all sketches must use the same hasher and seed"
+    );
+    let hash = hash::hash_item(&metadata, &item);
+
+    for sketch in sketches.iter_mut() {
+        sketch.add_hash(hash);
+    }
+}
+
+/// Length of the slices a memory mapped file is split into for parallel ingestion, each
+/// extended to the end of its last record.
+#[cfg(feature = "rayon")]
+const PARALLEL_CHUNK_LEN: usize = 64 * 1024 * 1024;
+
+/// Splits delimited records into slices of at least `len` bytes, each extended to the
+/// end of its last record, except for the last slice, which may be shorter.
+///
+/// # Arguments
+///
+/// * `bytes`: The delimited records.
+/// * `delimiter`: The byte separating records.
+/// * `len`: The minimum length of a slice.
+#[cfg(feature = "rayon")]
+fn split_chunks(bytes: &[u8], delimiter: u8, len: usize) -> Vec<&[u8]> {
+    let mut chunks = Vec::new();
+    let mut rest = bytes;
+    while !rest.is_empty() {
+        let end = rest
+            .get(len..)
+            .and_then(|tail| tail.iter().position(|&b| b == delimiter))
+            .map_or(rest.len(), |offset| len + offset + 1);
+        let (chunk, tail) = rest.split_at(end);
+        chunks.push(chunk);
+        rest = tail;
+    }
+
+    chunks
+}
+
+/// Memory maps a file of delimited records and adds every record to a sketch, see
+/// `HyperLogLog::ingest_mmap`.
+///
+/// # Arguments
+///
+/// * `sketch`: The sketch the records are added to.
+/// * `path`: The path of the file.
+/// * `delimiter`: The byte separating records, e.g. `b'\n'` or `b'\0'`.
+#[cfg(feature = "mmap")]
+pub(crate) fn ingest_mmap(
+    sketch: &mut HyperLogLog,
+    path: &std::path::Path,
+    delimiter: u8,
+) -> io::Result<u64> {
+    let file = std::fs::File::open(path)?;
+    if file.metadata()?.len() == 0 {
+        return Ok(0);
+    }
+    // SAFETY: the map is only read while this function runs. As documented on
+    // `ingest_mmap`, the file must not be modified concurrently, which cannot be
+    // enforced for memory mapped files.
+    let map = unsafe { memmap2::Mmap::map(&file)? };
+
+    #[cfg(feature = "rayon")]
+    return Ok(add_records_parallel(sketch, &map, delimiter));
+    #[cfg(not(feature = "rayon"))]
+    return Ok(add_records(sketch, &map, delimiter));
+}
+
+/// Adds every non-empty record of `bytes` to a sketch, hashing them in batches.
+///
+/// # Arguments
+///
+/// * `sketch`: The sketch the records are added to.
+/// * `bytes`: The delimited records.
+/// * `delimiter`: The byte separating records.
+#[cfg(feature = "mmap")]
+fn add_records(sketch: &mut HyperLogLog, bytes: &[u8], delimiter: u8) -> u64 {
+    let mut added = 0;
+    sketch.add_iter_bytes(
+        bytes
+            .split(|&b| b == delimiter)
+            .filter(|record| !record.is_empty())
+            .inspect(|_| added += 1),
+    );
+
+    added
+}
+
+/// Adds every non-empty record of `bytes` to a sketch, splitting them into chunks ending
+/// on a delimiter that are added to per-thread copies of the sketch on the rayon thread
+/// pool, and merged back at the end.
+///
+/// # Arguments
+///
+/// * `sketch`: The sketch the records are added to.
+/// * `bytes`: The delimited records.
+/// * `delimiter`: The byte separating records.
+#[cfg(feature = "rayon")]
+fn add_records_parallel(sketch: &mut HyperLogLog, bytes: &[u8], delimiter: u8) -> u64 {
+    use rayon::prelude::*;
+
+    let chunks = split_chunks(bytes, delimiter, PARALLEL_CHUNK_LEN);
+    let mut empty = sketch.clone();
+    empty.clear();
+    let (shard, added) = chunks
+        .par_iter()
+        .fold(
+            || (empty.clone(), 0),
+            |(mut shard, added), chunk| {
+                let chunk_added = add_records(&mut shard, chunk, delimiter);
+                (shard, added + chunk_added)
+            },
+        )
+        .reduce(
+            || (empty.clone(), 0),
+            |(mut a, a_added), (b, b_added)| {
+                a.merge(&b);
+                (a, a_added + b_added)
+            },
+        );
+    sketch.merge(&shard);
+
+    added
+}
+
+/// An `io::Write` adapter that splits everything written to it into records on a delimiter
+/// and adds every record to the wrapped sketch.
+///
+/// This allows code that already writes lines (or NUL separated records, ...) to some
+/// writer to feed a sketch instead, without any restructuring. Complete records are added
+/// straight from the written buffer; only a record split across two `write` calls is
+/// buffered. Empty records are skipped, and a trailing record without delimiter is added
+/// by `into_inner`.
+///
+/// # Examples
+///
+/// ```ignore
+/// use std::io::Write;
+///
+/// let mut hll = HyperLogLog::new();
+/// let mut writer = SketchWriter::new(&mut hll);
+/// writeln!(writer, "user-1")?;
+/// writeln!(writer, "user-2")?;
+/// writer.into_inner();
+/// ```
+#[derive(Debug)]
+pub struct SketchWriter<S: Sketch> {
+    sketch: S,
+    delimiter: u8,
+    pending: Vec<u8>,
+}
+
+impl<S: Sketch> SketchWriter<S> {
+    /// Creates a writer adding every newline separated record to `sketch`.
+    ///
+    /// # Arguments
+    ///
+    /// * `sketch`: The sketch records are added to. Pass `&mut sketch` to keep ownership.
+    pub fn new(sketch: S) -> Self {
+        Self::with_delimiter(sketch, b'\n')
+    }
+
+    /// Creates a writer adding every record separated by `delimiter` to `sketch`.
+    ///
+    /// # Arguments
+    ///
+    /// * `sketch`: The sketch records are added to. Pass `&mut sketch` to keep ownership.
+    /// * `delimiter`: The byte separating records.
+    pub fn with_delimiter(sketch: S, delimiter: u8) -> Self {
+        Self {
+            sketch,
+            delimiter,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Returns a reference to the wrapped sketch. Records still pending, i.e. not yet
+    /// terminated by a delimiter, are not reflected in it.
+    pub fn get_ref(&self) -> &S {
+        &self.sketch
+    }
+
+    /// Returns a mutable reference to the wrapped sketch.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.sketch
+    }
+
+    /// Adds the pending trailing record, if any, and returns the wrapped sketch.
+    pub fn into_inner(mut self) -> S {
+        if !self.pending.is_empty() {
+            self.sketch.add(self.pending.as_slice());
+        }
+
+        self.sketch
+    }
+}
+
+impl<S: Sketch> io::Write for SketchWriter<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut records = buf.split(|&b| b == self.delimiter);
+        // `split` always yields at least one element: the part after the last delimiter,
+        // which is the start of a record that is not complete yet.
+        let tail = records.next_back().unwrap_or_default();
+
+        for record in records {
+            if self.pending.is_empty() {
+                if !record.is_empty() {
+                    self.sketch.add(record);
+                }
+            } else {
+                self.pending.extend_from_slice(record);
+                self.sketch.add(self.pending.as_slice());
+                self.pending.clear();
+            }
+        }
+
+        self.pending.extend_from_slice(tail);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ingest_csv_column() {
+        let csv = "id,name,city\r\n1,alice,paris\n2,bob,\"new york, ny\"\n\n3,carol,paris\n4,dave\n5,eve,\"say \"\"hi\"\"\"";
+        let mut hll = HyperLogLog::new();
+
+        let added = ingest_csv_column(csv.as_bytes(), 2, &mut hll).unwrap();
+        assert_eq!(added, 5);
+
+        let mut expected = HyperLogLog::new();
+        for city in ["city", "paris", "new york, ny", "paris", "say \"hi\""] {
+            expected.add(city.as_bytes());
+        }
+        assert!(hll.diff(&expected).is_identical());
+    }
+
+    #[test]
+    fn test_add_iter_bytes() {
+        let data = (0..1_000).map(|i| format!("key-{}", i)).collect::<Vec<_>>();
+        let mut hll1 = HyperLogLog::new();
+        let mut hll2 = HyperLogLog::new();
+
+        hll1.add_iter_bytes(data.iter().map(|s| s.as_bytes()));
+        for s in &data {
+            hll2.add(s.as_bytes());
+        }
+
+        assert!(hll1.diff(&hll2).is_identical());
+    }
+
+    #[test]
+    fn test_add_to_all() {
+        let mut global = HyperLogLog::new();
+        let mut hourly = HyperLogLog::new();
+        let mut expected = HyperLogLog::new();
+
+        for i in 0..1_000 {
+            add_to_all(i, &mut [&mut global, &mut hourly]);
+            expected.add(i);
+        }
+
+        assert!(global.diff(&expected).is_identical());
+        assert!(hourly.diff(&expected).is_identical());
+    }
+
+    #[test]
+    fn test_sketch_writer() {
+        use std::io::Write;
+
+        let mut hll = HyperLogLog::new();
+        let mut writer = SketchWriter::new(&mut hll);
+
+        writer.write_all(b"alpha\nbe").unwrap();
+        writer.write_all(b"ta\n\ngamma\nalpha\ndel").unwrap();
+        writer.write_all(b"ta").unwrap();
+        writer.into_inner();
+
+        let mut expected = HyperLogLog::new();
+        for record in ["alpha", "beta", "gamma", "delta"] {
+            expected.add(record.as_bytes());
+        }
+        assert!(hll.diff(&expected).is_identical());
+    }
+
+    #[test]
+    fn test_ingest_tsv_column() {
+        let tsv = "a\tx\nb\ty\nc\tx";
+        let mut hll = HyperLogLog::new();
+
+        let added = ingest_delimited_column(tsv.as_bytes(), b'\t', 1, &mut hll).unwrap();
+        assert_eq!(added, 3);
+        assert_eq!(hll.estimate().round() as u32, 2);
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_ingest_mmap() {
+        let path = std::env::temp_dir().join(format!("hyperlog-ingest-{}", std::process::id()));
+        let records = (0..10_000)
+            .map(|i| format!("user-{}\0", i % 5_000))
+            .collect::<String>();
+        std::fs::write(&path, format!("{}\0\0trailing", records)).unwrap();
+
+        let mut hll = HyperLogLog::new();
+        let added = hll.ingest_mmap(&path, b'\0').unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut expected = HyperLogLog::new();
+        for i in 0..5_000 {
+            expected.add(format!("user-{}", i).as_bytes());
+        }
+        expected.add(b"trailing".as_slice());
+        assert_eq!(added, 10_001);
+        assert!(hll.diff(&expected).is_identical());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_split_chunks() {
+        let chunks = split_chunks(b"aa\nbbbb\nc\n\ndd", b'\n', 3);
+        assert_eq!(chunks, [&b"aa\nbbbb\n"[..], b"c\n\ndd"]);
+        assert_eq!(split_chunks(b"abc", b'\n', 1), [b"abc"]);
+    }
+}
@@ -0,0 +1,247 @@
+use crate::{
+    hll, interop,
+    registers::{self, Registers},
+    DecodeError, Error, HyperLogLog,
+};
+
+/// Smallest number of index bits of an Aerospike HLL bin.
+pub const MIN_INDEX_BITS: u8 = 4;
+/// Largest number of index bits of an Aerospike HLL bin.
+pub const MAX_INDEX_BITS: u8 = 16;
+/// Largest number of MinHash bits of an Aerospike HLL bin.
+pub const MAX_MINHASH_BITS: u8 = 51;
+
+/// Number of bits holding the HyperLogLog rank of every Aerospike register.
+const RANK_BITS: u32 = 6;
+/// Size of the header: flags, index bits, MinHash bits and the 8-byte cached count.
+const HEADER_LEN: usize = 11;
+
+/// A HyperLogLog in the layout of an Aerospike HLL bin, so sketches can be written into
+/// Aerospike and combined there with its native HLL operations, or read back and merged
+/// in Rust.
+///
+/// An Aerospike HLL bin is a header followed by `2^index_bits` registers packed
+/// most-significant bit first. Every register holds a 6-bit HyperLogLog rank followed by
+/// `minhash_bits` MinHash bits. Sketches written by this type carry no MinHash bits;
+/// those of bins read from Aerospike are dropped, as only the ranks are used here.
+///
+/// Aerospike supports at most 16 index bits, so `HyperLogLog`s are folded down to the
+/// requested precision when converted. Aerospike also hashes items with its own hash
+/// function: an item added both natively in Aerospike and to a sketch merged into the
+/// same bin is counted twice.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AerospikeHll {
+    index_bits: u8,
+    registers: Vec<u8>,
+}
+
+impl AerospikeHll {
+    /// Creates an empty sketch.
+    ///
+    /// # Parameters
+    /// * `index_bits`: The number of index bits, between `MIN_INDEX_BITS` and
+    ///   `MAX_INDEX_BITS`.
+    ///
+    /// # Returns
+    /// A new `AerospikeHll` instance.
+    ///
+    /// # Panics
+    /// Panics if `index_bits` is out of range.
+    pub fn new(index_bits: u8) -> Self {
+        assert!(
+            (MIN_INDEX_BITS..=MAX_INDEX_BITS).contains(&index_bits),
+            "Aerospike HLL bins have between {} and {} index bits",
+            MIN_INDEX_BITS,
+            MAX_INDEX_BITS
+        );
+
+        Self {
+            index_bits,
+            registers: vec![0; 1 << index_bits],
+        }
+    }
+
+    /// Converts a HyperLogLog, folding its registers down to `index_bits`.
+    ///
+    /// # Parameters
+    /// * `hll`: The sketch to convert.
+    /// * `index_bits`: The number of index bits of the Aerospike bin.
+    ///
+    /// # Returns
+    /// A new `AerospikeHll` holding the folded registers.
+    ///
+    /// # Panics
+    /// Panics if `index_bits` is out of range.
+    pub fn from_hyperloglog<R: Registers>(hll: &HyperLogLog<R>, index_bits: u8) -> Self {
+        let mut sketch = Self::new(index_bits);
+        registers::fold_max(&hll.registers, &mut sketch.registers);
+
+        sketch
+    }
+
+    /// Returns the number of index bits.
+    pub fn index_bits(&self) -> u8 {
+        self.index_bits
+    }
+
+    /// Returns the ranks of the registers.
+    pub fn registers(&self) -> &[u8] {
+        &self.registers
+    }
+
+    /// Provides an estimate of the number of unique items.
+    ///
+    /// # Returns
+    /// A `f64` approximate count of unique items.
+    pub fn estimate(&self) -> f64 {
+        hll::estimate_registers(&self.registers)
+    }
+
+    /// Merges another sketch into this one. A sketch with more index bits is folded down
+    /// to the index bits of this one.
+    ///
+    /// # Parameters
+    /// * `other`: The sketch to merge in.
+    ///
+    /// # Returns
+    /// `Ok(())`, or an `Error::Incompatible` if `other` has fewer index bits.
+    pub fn merge(&mut self, other: &AerospikeHll) -> Result<(), Error> {
+        interop::merge_registers(&mut self.registers, &other.registers)
+    }
+
+    /// Encodes the sketch as the value of an Aerospike HLL bin, without MinHash bits.
+    ///
+    /// # Returns
+    /// The encoded bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0u8; HEADER_LEN + packed_len(self.registers.len(), RANK_BITS)];
+        bytes[1] = self.index_bits;
+
+        let mut bit = HEADER_LEN * 8;
+        for &rank in &self.registers {
+            write_bits(&mut bytes, bit, RANK_BITS, u64::from(rank.min(63)));
+            bit += RANK_BITS as usize;
+        }
+
+        bytes
+    }
+
+    /// Decodes the value of an Aerospike HLL bin.
+    ///
+    /// # Parameters
+    /// * `bytes`: The encoded bin.
+    ///
+    /// # Returns
+    /// The decoded `AerospikeHll`, or an `Error` if the header is invalid or the bytes are
+    /// truncated.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let header = bytes.get(..HEADER_LEN).ok_or(DecodeError::Truncated {
+            expected: HEADER_LEN,
+            found: bytes.len(),
+        })?;
+        let (index_bits, minhash_bits) = (header[1], header[2]);
+
+        if !(MIN_INDEX_BITS..=MAX_INDEX_BITS).contains(&index_bits) {
+            return Err(
+                DecodeError::Malformed(format!("invalid index bits {}", index_bits)).into(),
+            );
+        }
+        if minhash_bits > MAX_MINHASH_BITS {
+            return Err(
+                DecodeError::Malformed(format!("invalid MinHash bits {}", minhash_bits)).into(),
+            );
+        }
+
+        let register_bits = RANK_BITS + u32::from(minhash_bits);
+        let expected = HEADER_LEN + packed_len(1 << index_bits, register_bits);
+        if bytes.len() != expected {
+            return Err(Error::Length {
+                expected,
+                found: bytes.len(),
+            }
+            .into_decode());
+        }
+
+        let mut bit = HEADER_LEN * 8;
+        let registers = (0..1usize << index_bits)
+            .map(|_| {
+                let register = read_bits(bytes, bit, register_bits);
+                bit += register_bits as usize;
+                (register >> minhash_bits) as u8
+            })
+            .collect();
+
+        Ok(Self {
+            index_bits,
+            registers,
+        })
+    }
+}
+
+/// Returns the number of bytes holding `count` registers of `bits` bits each.
+fn packed_len(count: usize, bits: u32) -> usize {
+    (count * bits as usize).div_ceil(8)
+}
+
+/// Writes the low `bits` bits of `value` at bit offset `start`, most-significant bit first.
+fn write_bits(bytes: &mut [u8], start: usize, bits: u32, value: u64) {
+    for i in 0..bits as usize {
+        if value >> (bits as usize - 1 - i) & 1 == 1 {
+            let bit = start + i;
+            bytes[bit / 8] |= 0x80 >> (bit % 8);
+        }
+    }
+}
+
+/// Reads `bits` bits at bit offset `start`, most-significant bit first.
+fn read_bits(bytes: &[u8], start: usize, bits: u32) -> u64 {
+    (start..start + bits as usize).fold(0, |value, bit| {
+        value << 1 | u64::from(bytes[bit / 8] >> (7 - bit % 8) & 1)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::P;
+
+    #[test]
+    fn test_round_trip() {
+        let mut hll = HyperLogLog::new();
+        for i in 0..50_000 {
+            hll.add(i);
+        }
+
+        let bits = P.min(14);
+        let sketch = AerospikeHll::from_hyperloglog(&hll, bits as u8);
+        let bytes = sketch.to_bytes();
+        assert_eq!(bytes.len(), 11 + (1 << bits) * 6 / 8);
+        assert_eq!(AerospikeHll::from_bytes(&bytes), Ok(sketch.clone()));
+
+        let error = (sketch.estimate() - 50_000.0).abs() / 50_000.0;
+        assert!(error < 0.03, "relative error {}", error);
+
+        assert!(AerospikeHll::from_bytes(&bytes[..100]).is_err());
+    }
+
+    #[test]
+    fn test_minhash_bits_are_dropped() {
+        // Four index bits, two MinHash bits: 16 registers of 8 bits
+        let mut bytes = vec![0, 4, 2, 0, 0, 0, 0, 0, 0, 0, 0];
+        bytes.extend((0..16u8).map(|i| i << 2 | 0b11));
+
+        let sketch = AerospikeHll::from_bytes(&bytes).unwrap();
+        assert_eq!(sketch.registers(), (0..16).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn test_merge_folds() {
+        let mut coarse = AerospikeHll::new(10);
+        let mut fine = AerospikeHll::new(12);
+        fine.registers[5] = 3;
+
+        coarse.merge(&fine).unwrap();
+        assert_eq!(coarse.registers()[5], 3);
+        assert!(fine.merge(&coarse).is_err());
+    }
+}
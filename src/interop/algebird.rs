@@ -0,0 +1,275 @@
+use crate::{
+    hll, interop,
+    registers::{self, Registers},
+    DecodeError, Error, HyperLogLog, P,
+};
+
+/// Smallest number of index bits accepted for an Algebird HLL.
+pub const MIN_BITS: u8 = 4;
+/// Largest number of index bits an Algebird HLL can be serialized with, as sparse
+/// register indices are stored in at most three bytes.
+pub const MAX_BITS: u8 = 24;
+
+/// Leading byte of a dense Algebird HLL.
+const DENSE_VERSION: u8 = 2;
+/// Leading byte of a sparse Algebird HLL.
+const SPARSE_VERSION: u8 = 3;
+
+/// A HyperLogLog in the serialization of Twitter Algebird's `HyperLogLog.toBytes`, so
+/// sketches persisted by Scalding or Spark pipelines can be finalized in Rust, and
+/// sketches built in Rust handed back.
+///
+/// Both encodings start with a version byte and the number of index bits. The dense one
+/// (version 2) continues with one byte per register, the sparse one (version 3) with the
+/// non-zero registers only, each as its index in 1 to 3 big-endian bytes, as Algebird
+/// writes it through a `java.nio.ByteBuffer`, followed by its rank.
+///
+/// Algebird hashes items with its own hash function, so an item added on both sides is
+/// counted twice when the sketches are merged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlgebirdHll {
+    bits: u8,
+    registers: Vec<u8>,
+}
+
+impl AlgebirdHll {
+    /// Creates an empty sketch.
+    ///
+    /// # Parameters
+    /// * `bits`: The number of index bits, between `MIN_BITS` and `MAX_BITS`.
+    ///
+    /// # Returns
+    /// A new `AlgebirdHll` instance.
+    ///
+    /// # Panics
+    /// Panics if `bits` is out of range.
+    pub fn new(bits: u8) -> Self {
+        assert!(
+            (MIN_BITS..=MAX_BITS).contains(&bits),
+            "Algebird HLLs have between {} and {} index bits",
+            MIN_BITS,
+            MAX_BITS
+        );
+
+        Self {
+            bits,
+            registers: vec![0; 1 << bits],
+        }
+    }
+
+    /// Converts a HyperLogLog, folding its registers down when `bits` is below `P`.
+    ///
+    /// # Parameters
+    /// * `hll`: The sketch to convert.
+    /// * `bits`: The number of index bits of the Algebird HLL, at most `P`.
+    ///
+    /// # Returns
+    /// A new `AlgebirdHll` holding the registers of `hll`.
+    ///
+    /// # Panics
+    /// Panics if `bits` is out of range or larger than `P`.
+    pub fn from_hyperloglog<R: Registers>(hll: &HyperLogLog<R>, bits: u8) -> Self {
+        assert!(bits as usize <= P, "cannot convert to more than {} bits", P);
+
+        let mut sketch = Self::new(bits);
+        registers::fold_max(&hll.registers, &mut sketch.registers);
+
+        sketch
+    }
+
+    /// Converts the sketch into a `HyperLogLog`, which needs exactly `P` index bits.
+    ///
+    /// # Returns
+    /// The `HyperLogLog`, or an `Error` if the sketch has a different number of index bits
+    /// or holds ranks a `HyperLogLog` cannot.
+    pub fn to_hyperloglog(&self) -> Result<HyperLogLog, Error> {
+        HyperLogLog::try_from_bytes(&self.registers)
+    }
+
+    /// Returns the number of index bits.
+    pub fn bits(&self) -> u8 {
+        self.bits
+    }
+
+    /// Returns the ranks of the registers.
+    pub fn registers(&self) -> &[u8] {
+        &self.registers
+    }
+
+    /// Provides an estimate of the number of unique items.
+    ///
+    /// # Returns
+    /// A `f64` approximate count of unique items.
+    pub fn estimate(&self) -> f64 {
+        hll::estimate_registers(&self.registers)
+    }
+
+    /// Merges another sketch into this one. A sketch with more index bits is folded down
+    /// to the index bits of this one.
+    ///
+    /// # Parameters
+    /// * `other`: The sketch to merge in.
+    ///
+    /// # Returns
+    /// `Ok(())`, or an `Error::Incompatible` if `other` has fewer index bits.
+    pub fn merge(&mut self, other: &AlgebirdHll) -> Result<(), Error> {
+        interop::merge_registers(&mut self.registers, &other.registers)
+    }
+
+    /// Encodes the sketch in the dense format.
+    ///
+    /// # Returns
+    /// The encoded bytes.
+    pub fn to_dense_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(2 + self.registers.len());
+        bytes.extend([DENSE_VERSION, self.bits]);
+        bytes.extend_from_slice(&self.registers);
+
+        bytes
+    }
+
+    /// Encodes the sketch in the sparse format.
+    ///
+    /// # Returns
+    /// The encoded bytes.
+    pub fn to_sparse_bytes(&self) -> Vec<u8> {
+        let index_len = index_len(self.bits);
+        let mut bytes = vec![SPARSE_VERSION, self.bits];
+
+        for (index, &rank) in self.registers.iter().enumerate() {
+            if rank > 0 {
+                bytes.extend_from_slice(&(index as u32).to_be_bytes()[4 - index_len..]);
+                bytes.push(rank);
+            }
+        }
+
+        bytes
+    }
+
+    /// Encodes the sketch in whichever format is smaller, as Algebird does.
+    ///
+    /// # Returns
+    /// The encoded bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let non_zero = self.registers.iter().filter(|&&rank| rank > 0).count();
+
+        match non_zero * (index_len(self.bits) + 1) < self.registers.len() {
+            true => self.to_sparse_bytes(),
+            false => self.to_dense_bytes(),
+        }
+    }
+
+    /// Decodes a sketch in either format.
+    ///
+    /// # Parameters
+    /// * `bytes`: The encoded sketch.
+    ///
+    /// # Returns
+    /// The decoded `AlgebirdHll`, or an `Error` if the bytes are not a valid encoding.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let (version, bits, body) = match bytes {
+            [version, bits, body @ ..] => (*version, *bits, body),
+            _ => {
+                return Err(DecodeError::Truncated {
+                    expected: 2,
+                    found: bytes.len(),
+                }
+                .into())
+            }
+        };
+
+        if !(MIN_BITS..=MAX_BITS).contains(&bits) {
+            return Err(DecodeError::Malformed(format!("invalid index bits {}", bits)).into());
+        }
+        let mut sketch = Self::new(bits);
+
+        match version {
+            DENSE_VERSION if body.len() == sketch.registers.len() => {
+                sketch.registers.copy_from_slice(body)
+            }
+            DENSE_VERSION => {
+                return Err(Error::Length {
+                    expected: sketch.registers.len(),
+                    found: body.len(),
+                }
+                .into_decode())
+            }
+            SPARSE_VERSION => {
+                let index_len = index_len(bits);
+                if body.len() % (index_len + 1) != 0 {
+                    return Err(DecodeError::Truncated {
+                        expected: index_len + 1,
+                        found: body.len() % (index_len + 1),
+                    }
+                    .into());
+                }
+
+                for entry in body.chunks_exact(index_len + 1) {
+                    let mut index = [0u8; 4];
+                    index[4 - index_len..].copy_from_slice(&entry[..index_len]);
+                    let index = u32::from_be_bytes(index) as usize;
+
+                    let register = sketch.registers.get_mut(index).ok_or_else(|| {
+                        DecodeError::Malformed(format!("register index {} out of range", index))
+                    })?;
+                    *register = (*register).max(entry[index_len]);
+                }
+            }
+            version => {
+                return Err(
+                    DecodeError::Malformed(format!("unsupported version {}", version)).into(),
+                )
+            }
+        }
+
+        Ok(sketch)
+    }
+}
+
+/// Returns the number of bytes a sparse register index takes for `bits` index bits.
+fn index_len(bits: u8) -> usize {
+    (bits as usize).div_ceil(8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut hll = HyperLogLog::new();
+        for i in 0..1_000 {
+            hll.add(i);
+        }
+
+        let sketch = AlgebirdHll::from_hyperloglog(&hll, P as u8);
+        let sparse = sketch.to_bytes();
+        assert_eq!(sparse[0], SPARSE_VERSION);
+        let non_zero = sketch.registers().iter().filter(|&&rank| rank > 0).count();
+        assert_eq!(sparse.len(), 2 + non_zero * (index_len(P as u8) + 1));
+        assert_eq!(AlgebirdHll::from_bytes(&sparse), Ok(sketch.clone()));
+
+        let dense = sketch.to_dense_bytes();
+        assert_eq!(dense.len(), 2 + (1 << P));
+        assert_eq!(AlgebirdHll::from_bytes(&dense), Ok(sketch.clone()));
+
+        let restored = sketch.to_hyperloglog().unwrap();
+        assert!(restored.diff(&hll).is_identical());
+
+        assert!(AlgebirdHll::from_bytes(&sparse[..7]).is_err());
+        assert!(AlgebirdHll::from_bytes(&[1, 12]).is_err());
+    }
+
+    #[test]
+    fn test_sparse_layout() {
+        let mut sketch = AlgebirdHll::new(12);
+        sketch.registers[0x0a01] = 7;
+
+        assert_eq!(sketch.to_bytes(), [3, 12, 0x0a, 0x01, 7]);
+        assert_eq!(
+            AlgebirdHll::from_bytes(&[3, 12, 0x0a, 0x01, 7]).unwrap(),
+            sketch
+        );
+        assert_eq!(sketch.to_hyperloglog().is_err(), P != 12);
+    }
+}
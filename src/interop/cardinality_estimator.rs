@@ -0,0 +1,90 @@
+use std::hash::{Hash, Hasher};
+
+use cardinality_estimator::CardinalityEstimator;
+
+use crate::{
+    registers::{self, Registers},
+    HyperLogLog, P,
+};
+
+/// Converts a HyperLogLog into a `CardinalityEstimator` of the `cardinality-estimator`
+/// crate, so projects built on it can adopt this crate incrementally, e.g. only for the
+/// merge and estimate hot paths, and hand the result back.
+///
+/// `CardinalityEstimator` keeps its registers private and only accepts hashes, so every
+/// non-zero register is replayed as one synthetic hash selecting the register and
+/// carrying its rank in the `cardinality-estimator` layout: the register index in the low
+/// `Q` bits and the rank as the number of trailing ones above them, plus one. Registers
+/// are folded down first when `Q` is below `P`, and ranks above what `W`-bit registers
+/// hold are clamped.
+///
+/// The opposite conversion is not possible, since the registers of a `CardinalityEstimator`
+/// cannot be read. `cardinality-estimator` hashes items with `H`, so an item added on both
+/// sides is counted twice once the sketches are merged.
+///
+/// # Panics
+/// Panics if `Q` is larger than `P`.
+impl<T, H, R, const Q: usize, const W: usize> From<&HyperLogLog<R>>
+    for CardinalityEstimator<T, H, Q, W>
+where
+    T: Hash + ?Sized,
+    H: Hasher + Default,
+    R: Registers,
+{
+    fn from(hll: &HyperLogLog<R>) -> Self {
+        assert!(Q <= P, "precisions above {} are not supported", P);
+
+        let mut folded = vec![0; 1 << Q];
+        registers::fold_max(&hll.registers, &mut folded);
+
+        let max_rank = ((1usize << W) - 1).min(64 - Q + 1) as u8;
+        let mut estimator = Self::new();
+        for (index, &rank) in folded.iter().enumerate() {
+            if rank > 0 {
+                estimator.insert_hash(synthetic_hash::<Q>(index, rank.min(max_rank)));
+            }
+        }
+
+        estimator
+    }
+}
+
+/// Builds a hash a `CardinalityEstimator` of precision `Q` maps to the register `index`
+/// with the given rank.
+///
+/// # Arguments
+/// * `index`: The register index, below `2^Q`.
+/// * `rank`: The rank, between 1 and `64 - Q + 1`.
+fn synthetic_hash<const Q: usize>(index: usize, rank: u8) -> u64 {
+    let ones = (1u64 << (rank - 1)) - 1;
+
+    index as u64 | ones << Q
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_into_cardinality_estimator() {
+        let mut hll = HyperLogLog::new();
+        for i in 0..10_000 {
+            hll.add(i);
+        }
+
+        let estimator: CardinalityEstimator<str> = (&hll).into();
+        let estimate = estimator.estimate() as f64;
+        assert!(
+            (estimate - 10_000.0).abs() / 10_000.0 < 0.05,
+            "estimate {}",
+            estimate
+        );
+    }
+
+    #[test]
+    fn test_synthetic_hash() {
+        let hash = synthetic_hash::<12>(5, 3);
+        assert_eq!(hash & 0xfff, 5);
+        assert_eq!((!hash >> 12).trailing_zeros() + 1, 3);
+    }
+}
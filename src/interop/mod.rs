@@ -0,0 +1,40 @@
+use crate::{
+    metadata::{Algorithm, MetadataMismatch, SketchMetadata},
+    registers::{self, RegistersMut},
+    Error,
+};
+
+pub mod aerospike;
+pub mod algebird;
+#[cfg(feature = "cardinality-estimator")]
+pub mod cardinality_estimator;
+pub mod streamlib;
+
+/// Merges the registers of a foreign sketch into another one, folding `src` down when it
+/// has more registers.
+///
+/// # Parameters
+/// * `dst`: The registers to be updated, a power of two of them.
+/// * `src`: The registers to merge in, a power of two of them.
+///
+/// # Returns
+/// `Ok(())`, or an `Error::Incompatible` if `src` has fewer registers than `dst`.
+pub(crate) fn merge_registers(dst: &mut [u8], src: &[u8]) -> Result<(), Error> {
+    let dst_bits = dst.len().trailing_zeros() as usize;
+    let src_bits = src.len().trailing_zeros() as usize;
+
+    if src_bits < dst_bits {
+        return Err(MetadataMismatch {
+            expected: SketchMetadata::new(Algorithm::HyperLogLog, dst_bits),
+            found: SketchMetadata::new(Algorithm::HyperLogLog, src_bits),
+        }
+        .into());
+    }
+
+    match src_bits == dst_bits {
+        true => dst.merge_max(src),
+        false => registers::fold_max(src, dst),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,432 @@
+use crate::{
+    hll, interop,
+    registers::{self, Registers},
+    DecodeError, Error, HyperLogLog, P,
+};
+
+/// Smallest precision of a stream-lib `HyperLogLogPlus`.
+pub const MIN_P: u8 = 4;
+/// Largest precision, and sparse precision, of a stream-lib `HyperLogLogPlus`.
+pub const MAX_SP: u8 = 32;
+/// Largest sparse precision whose entries, an index shifted by seven bits, fit 32 bits.
+pub const MAX_SPARSE_WRITE: u8 = 25;
+
+/// Version written, negated, as the leading big-endian int of the serialized form.
+const VERSION: i32 = 2;
+/// Format tag of the normal (dense) representation.
+const NORMAL: u32 = 0;
+/// Format tag of the sparse representation.
+const SPARSE: u32 = 1;
+/// Number of bits of a register in a stream-lib `RegisterSet`.
+const REGISTER_SIZE: u32 = 5;
+/// Number of registers packed into every 32-bit word of a `RegisterSet`.
+const REGISTERS_PER_WORD: usize = 6;
+/// Largest rank a `RegisterSet` register can hold.
+const MAX_REGISTER: u8 = (1 << REGISTER_SIZE) - 1;
+
+/// A HyperLogLog in the serialized format of stream-lib's (clearspring) `HyperLogLogPlus`,
+/// as written by `getBytes()`, so JVM services and Rust services can merge each other's
+/// sketches.
+///
+/// The format starts with the negated version as a big-endian int, followed by the
+/// precision `p`, the sparse precision `sp` and the format tag as unsigned varints. The
+/// normal format continues with the byte length and the `RegisterSet`: 5-bit registers
+/// packed six to a big-endian 32-bit word, lowest register in the lowest bits. The sparse
+/// format continues with the number of entries and the entries themselves, delta encoded
+/// as varints; every entry holds a register index at precision `sp` and enough bits to
+/// recover the rank at precision `p`.
+///
+/// Sparse sketches are expanded to their registers at precision `p` when read. Ranks above
+/// 31 do not fit a `RegisterSet` and are clamped when written in the normal format.
+/// stream-lib hashes items with its own hash function, so an item added on both sides is
+/// counted twice when the sketches are merged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamLibHll {
+    p: u8,
+    sp: u8,
+    registers: Vec<u8>,
+}
+
+impl StreamLibHll {
+    /// Creates an empty sketch.
+    ///
+    /// # Parameters
+    /// * `p`: The precision, between `MIN_P` and `MAX_SP`.
+    /// * `sp`: The sparse precision written to the header, `0` or between `p` and `MAX_SP`.
+    ///
+    /// # Returns
+    /// A new `StreamLibHll` instance.
+    ///
+    /// # Panics
+    /// Panics if `p` or `sp` are out of range, or `p` is larger than `P`.
+    pub fn new(p: u8, sp: u8) -> Self {
+        assert!(
+            valid_precisions(p, sp),
+            "invalid stream-lib precisions p = {}, sp = {}",
+            p,
+            sp
+        );
+        assert!(p as usize <= P, "precisions above {} are not supported", P);
+
+        Self {
+            p,
+            sp,
+            registers: vec![0; 1 << p],
+        }
+    }
+
+    /// Converts a HyperLogLog, folding its registers down when `p` is below `P`.
+    ///
+    /// # Parameters
+    /// * `hll`: The sketch to convert.
+    /// * `p`: The precision of the stream-lib sketch, at most `P`.
+    /// * `sp`: The sparse precision written to the header, see `new`.
+    ///
+    /// # Returns
+    /// A new `StreamLibHll` holding the registers of `hll`.
+    ///
+    /// # Panics
+    /// Panics if the precisions are out of range.
+    pub fn from_hyperloglog<R: Registers>(hll: &HyperLogLog<R>, p: u8, sp: u8) -> Self {
+        let mut sketch = Self::new(p, sp);
+        registers::fold_max(&hll.registers, &mut sketch.registers);
+
+        sketch
+    }
+
+    /// Converts the sketch into a `HyperLogLog`, which needs a precision of exactly `P`.
+    ///
+    /// # Returns
+    /// The `HyperLogLog`, or an `Error` if the sketch has a different precision or holds
+    /// ranks a `HyperLogLog` cannot.
+    pub fn to_hyperloglog(&self) -> Result<HyperLogLog, Error> {
+        HyperLogLog::try_from_bytes(&self.registers)
+    }
+
+    /// Returns the precision.
+    pub fn p(&self) -> u8 {
+        self.p
+    }
+
+    /// Returns the sparse precision.
+    pub fn sp(&self) -> u8 {
+        self.sp
+    }
+
+    /// Returns the ranks of the registers.
+    pub fn registers(&self) -> &[u8] {
+        &self.registers
+    }
+
+    /// Provides an estimate of the number of unique items.
+    ///
+    /// # Returns
+    /// A `f64` approximate count of unique items.
+    pub fn estimate(&self) -> f64 {
+        hll::estimate_registers(&self.registers)
+    }
+
+    /// Merges another sketch into this one. A sketch with a higher precision is folded
+    /// down to the precision of this one.
+    ///
+    /// # Parameters
+    /// * `other`: The sketch to merge in.
+    ///
+    /// # Returns
+    /// `Ok(())`, or an `Error::Incompatible` if `other` has a lower precision.
+    pub fn merge(&mut self, other: &StreamLibHll) -> Result<(), Error> {
+        interop::merge_registers(&mut self.registers, &other.registers)
+    }
+
+    /// Encodes the sketch in the normal format.
+    ///
+    /// # Returns
+    /// The encoded bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut words = vec![0u32; self.registers.len().div_ceil(REGISTERS_PER_WORD)];
+        for (index, &rank) in self.registers.iter().enumerate() {
+            let shift = REGISTER_SIZE as usize * (index % REGISTERS_PER_WORD);
+            words[index / REGISTERS_PER_WORD] |= u32::from(rank.min(MAX_REGISTER)) << shift;
+        }
+
+        let mut bytes = self.header(NORMAL);
+        write_varint(&mut bytes, (words.len() * 4) as u32);
+        for word in words {
+            bytes.extend_from_slice(&word.to_be_bytes());
+        }
+
+        bytes
+    }
+
+    /// Encodes the sketch in the sparse format, with one entry per non-zero register,
+    /// at the sparse precision of the header, or at `p` if it is `0`.
+    ///
+    /// # Returns
+    /// The encoded bytes.
+    ///
+    /// # Panics
+    /// Panics if the sparse precision is above `MAX_SPARSE_WRITE`, as the entries would not
+    /// fit 32 bits.
+    pub fn to_sparse_bytes(&self) -> Vec<u8> {
+        let sp = self.sp.max(self.p);
+        assert!(
+            sp <= MAX_SPARSE_WRITE,
+            "sparse entries at sp = {} do not fit 32 bits",
+            sp
+        );
+
+        let entries = self
+            .registers
+            .iter()
+            .enumerate()
+            .filter(|(_, &rank)| rank > 0)
+            .map(|(index, &rank)| encode_sparse(index as u32, rank, self.p, sp))
+            .collect::<Vec<_>>();
+
+        let mut bytes = self.header(SPARSE);
+        write_varint(&mut bytes, entries.len() as u32);
+
+        let mut previous = 0u32;
+        for entry in entries {
+            write_varint(&mut bytes, entry.wrapping_sub(previous));
+            previous = entry;
+        }
+
+        bytes
+    }
+
+    /// Writes the version, precisions and format tag.
+    fn header(&self, format: u32) -> Vec<u8> {
+        let mut bytes = (-VERSION).to_be_bytes().to_vec();
+        write_varint(&mut bytes, u32::from(self.p));
+        write_varint(&mut bytes, u32::from(self.sp));
+        write_varint(&mut bytes, format);
+
+        bytes
+    }
+
+    /// Decodes a sketch in either format.
+    ///
+    /// # Parameters
+    /// * `bytes`: The encoded sketch.
+    ///
+    /// # Returns
+    /// The decoded `StreamLibHll`, or an `Error` if the bytes are not a valid encoding.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader { bytes, offset: 0 };
+
+        let version = reader.take(4)?;
+        let version = i32::from_be_bytes(version.try_into().expect("4 bytes"));
+        if version != -VERSION {
+            return Err(DecodeError::Malformed(format!(
+                "unsupported stream-lib version {}",
+                -version
+            ))
+            .into());
+        }
+
+        let p = reader.varint()?;
+        let sp = reader.varint()?;
+        let (p, sp) = match (u8::try_from(p), u8::try_from(sp)) {
+            (Ok(p), Ok(sp)) if valid_precisions(p, sp) && p as usize <= P => (p, sp),
+            _ => {
+                return Err(DecodeError::Malformed(format!(
+                    "unsupported precisions p = {}, sp = {}",
+                    p, sp
+                ))
+                .into())
+            }
+        };
+        let mut sketch = Self::new(p, sp);
+
+        match reader.varint()? {
+            NORMAL => {
+                let len = reader.varint()? as usize;
+                let words = sketch.registers.len().div_ceil(REGISTERS_PER_WORD);
+                if len != words * 4 {
+                    return Err(DecodeError::Length {
+                        expected: words * 4,
+                        found: len,
+                    }
+                    .into());
+                }
+
+                for (i, word) in reader.take(len)?.chunks_exact(4).enumerate() {
+                    let word = u32::from_be_bytes(word.try_into().expect("4 bytes"));
+                    for slot in 0..REGISTERS_PER_WORD {
+                        if let Some(register) =
+                            sketch.registers.get_mut(i * REGISTERS_PER_WORD + slot)
+                        {
+                            *register =
+                                (word >> (REGISTER_SIZE as usize * slot)) as u8 & MAX_REGISTER;
+                        }
+                    }
+                }
+            }
+            SPARSE => {
+                // Entries of a sketch written without a sparse precision are at `p`
+                let sp = sp.max(p);
+                let count = reader.varint()?;
+                let mut entry = 0u32;
+
+                for _ in 0..count {
+                    entry = entry.wrapping_add(reader.varint()?);
+                    let (index, rank) = decode_sparse(entry, p, sp);
+
+                    let register = sketch.registers.get_mut(index).ok_or_else(|| {
+                        DecodeError::Malformed(format!("sparse entry {:#x} out of range", entry))
+                    })?;
+                    *register = (*register).max(rank);
+                }
+            }
+            format => {
+                return Err(DecodeError::Malformed(format!("unknown format {}", format)).into())
+            }
+        }
+
+        if reader.offset != bytes.len() {
+            return Err(DecodeError::Malformed("trailing bytes".to_string()).into());
+        }
+
+        Ok(sketch)
+    }
+}
+
+/// Checks the precisions stream-lib accepts.
+fn valid_precisions(p: u8, sp: u8) -> bool {
+    (MIN_P..=MAX_SP).contains(&p) && (sp == 0 || (p..=MAX_SP).contains(&sp))
+}
+
+/// Encodes a register as a sparse entry at precision `sp`, choosing the index bits below
+/// precision `p` so that decoding recovers `rank`.
+fn encode_sparse(index: u32, rank: u8, p: u8, sp: u8) -> u32 {
+    let extra_bits = sp - p;
+
+    match rank > extra_bits {
+        true => (index << extra_bits) << 7 | u32::from(rank - extra_bits) << 1 | 1,
+        // The first set bit lies within the extra index bits
+        false => ((index << extra_bits) | 1 << (extra_bits - rank)) << 1,
+    }
+}
+
+/// Decodes a sparse entry into the register index and rank at precision `p`.
+///
+/// Entries with their lowest bit set hold the index at precision `sp` above a 6-bit rank,
+/// counted from bit `sp`. The others only hold the index at precision `sp`, whose bits
+/// below precision `p` are then known to contain a set bit, which determines the rank.
+fn decode_sparse(entry: u32, p: u8, sp: u8) -> (usize, u8) {
+    let extra_bits = u32::from(sp - p);
+
+    match entry & 1 {
+        1 => {
+            let index = entry >> 7;
+            let rank = ((entry >> 1) & 0x3f) as u8 + (sp - p);
+            ((index >> extra_bits) as usize, rank)
+        }
+        _ => {
+            let index = entry >> 1;
+            let low = index & ((1u64 << extra_bits) - 1) as u32;
+            let rank = low
+                .checked_shl(32 - extra_bits)
+                .unwrap_or(0)
+                .leading_zeros() as u8
+                + 1;
+            ((index >> extra_bits) as usize, rank)
+        }
+    }
+}
+
+/// Appends an unsigned varint, seven bits at a time, lowest first.
+fn write_varint(bytes: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// Cursor over the serialized bytes.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let taken =
+            self.bytes
+                .get(self.offset..self.offset + len)
+                .ok_or(DecodeError::Truncated {
+                    expected: len,
+                    found: self.bytes.len() - self.offset,
+                })?;
+        self.offset += len;
+
+        Ok(taken)
+    }
+
+    fn varint(&mut self) -> Result<u32, Error> {
+        let mut value = 0u32;
+        for shift in (0..35).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= u32::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(DecodeError::Malformed("varint too long".to_string()).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut hll = HyperLogLog::new();
+        for i in 0..1_000 {
+            hll.add(i);
+        }
+
+        let sketch = StreamLibHll::from_hyperloglog(&hll, P as u8, 25);
+        for bytes in [sketch.to_bytes(), sketch.to_sparse_bytes()] {
+            assert_eq!(&bytes[..4], &[0xff, 0xff, 0xff, 0xfe]);
+            assert_eq!(StreamLibHll::from_bytes(&bytes), Ok(sketch.clone()));
+        }
+        assert!(sketch.to_hyperloglog().unwrap().diff(&hll).is_identical());
+
+        let bytes = sketch.to_bytes();
+        assert!(StreamLibHll::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_register_set_layout() {
+        let mut sketch = StreamLibHll::new(4, 0);
+        sketch.registers[0] = 1;
+        sketch.registers[1] = 2;
+        sketch.registers[6] = 31;
+
+        // Header: -2, p = 4, sp = 0, normal, 12 bytes of registers
+        let bytes = sketch.to_bytes();
+        assert_eq!(&bytes[4..8], &[4, 0, 0, 12]);
+        assert_eq!(&bytes[8..16], &[0, 0, 0, 0x41, 0, 0, 0, 31]);
+    }
+
+    #[test]
+    fn test_decode_sparse() {
+        // Index 0b1010 at sp = 6, whose two bits below p = 4 hold the first set bit
+        assert_eq!(decode_sparse(0b1010 << 1, 4, 6), (0b10, 1));
+        assert_eq!(decode_sparse(0b1001 << 1, 4, 6), (0b10, 2));
+        // Index 0b1000 at sp = 6 with a rank of 3 counted from bit sp
+        assert_eq!(decode_sparse(0b1000 << 7 | 3 << 1 | 1, 4, 6), (0b10, 5));
+
+        for rank in 1..=45 {
+            assert_eq!(
+                decode_sparse(encode_sparse(0b10, rank, 4, 6), 4, 6),
+                (0b10, rank)
+            );
+        }
+    }
+}
@@ -0,0 +1,102 @@
+/// Small user metadata describing where a sketch comes from, carried by the sketch and
+/// its serialized form so its provenance does not have to be tracked alongside it.
+///
+/// Times are opaque `u64`s, e.g. Unix timestamps, in whatever unit the application uses
+/// consistently. When a sketch carrying labels has another one merged into it, the labels
+/// are combined with `combine`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct SketchLabels {
+    /// A name for the sketch, e.g. the metric it counts.
+    pub name: Option<String>,
+    /// Where the items were collected, e.g. a shard or host.
+    pub source: Option<String>,
+    /// Start of the time range the items were collected in.
+    pub start: Option<u64>,
+    /// End of the time range the items were collected in.
+    pub end: Option<u64>,
+}
+
+impl SketchLabels {
+    /// Creates labels holding only a name.
+    ///
+    /// # Parameters
+    /// * `name`: The name of the sketch.
+    ///
+    /// # Returns
+    /// A new `SketchLabels` instance.
+    pub fn named<S: Into<String>>(name: S) -> Self {
+        Self {
+            name: Some(name.into()),
+            ..Self::default()
+        }
+    }
+
+    /// Returns the number of heap bytes held by the labels once boxed in a sketch.
+    pub(crate) fn heap_size(&self) -> usize {
+        let len = |s: &Option<String>| s.as_ref().map_or(0, String::capacity);
+
+        std::mem::size_of::<Self>() + len(&self.name) + len(&self.source)
+    }
+
+    /// Sets the source, returning the updated labels.
+    ///
+    /// # Parameters
+    /// * `source`: Where the items were collected.
+    pub fn with_source<S: Into<String>>(mut self, source: S) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    /// Sets the time range, returning the updated labels.
+    ///
+    /// # Parameters
+    /// * `start`: Start of the time range.
+    /// * `end`: End of the time range.
+    pub fn with_time_range(mut self, start: u64, end: u64) -> Self {
+        self.start = Some(start);
+        self.end = Some(end);
+        self
+    }
+
+    /// Combines the labels of a sketch merged into the one these labels belong to: the
+    /// time range grows to cover both, and the name and source are only taken from
+    /// `other` where these labels lack them.
+    ///
+    /// # Parameters
+    /// * `other`: The labels of the merged sketch.
+    pub fn combine(&mut self, other: &SketchLabels) {
+        self.start = min_some(self.start, other.start);
+        self.end = self.end.max(other.end);
+
+        if self.name.is_none() {
+            self.name.clone_from(&other.name);
+        }
+        if self.source.is_none() {
+            self.source.clone_from(&other.source);
+        }
+    }
+}
+
+/// Returns the smaller of two optional values, ignoring missing ones.
+fn min_some(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_combine() {
+        let mut labels = SketchLabels::named("users").with_time_range(100, 200);
+        labels.combine(&SketchLabels::named("other").with_source("eu-1"));
+        labels.combine(&SketchLabels::default().with_time_range(50, 150));
+
+        assert_eq!(labels.name.as_deref(), Some("users"));
+        assert_eq!(labels.source.as_deref(), Some("eu-1"));
+        assert_eq!((labels.start, labels.end), (Some(50), Some(200)));
+    }
+}
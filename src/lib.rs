@@ -1,22 +1,214 @@
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
+
 /// `hyperlog-simd` - A SIMD accelerated HLL/HLL++ implementation
 ///
 /// # Modules
 /// * `hll` - Contains implementations of canonical HyperLogLog
 /// * `plusplus` - Contains the improved HyperLogLog++ variant
+/// * `builder` - Contains the builder validating the configuration of a sketch
 /// * `serde` - Contains serialization/deserialization utilities for HyperLogLog structures
+/// * `binary` - Contains the allocation free binary layout written by `serialize_into`
+/// * `dump` - Contains the hex dump debugging format shared by both structures
+/// * `compare` - Contains utilities for comparing the state of two sketches
+/// * `stats` - Contains the operational counters and error simulation enabled by the `stats` feature
+/// * `validate` - Contains the exact shadow set enabled by the `validate` feature
+/// * `sketch` - Contains the `Sketch` trait implemented by both structures and one-shot counting helpers
+/// * `ingest` - Contains helpers for bulk ingestion from files and streams
+/// * `cascade` - Contains a coarse + full precision sketch pair for cheap triage estimates
+/// * `sync` - Contains thread-safe wrappers around the sketch types
+/// * `frozen` - Contains the immutable, query-only form of a sketch
+/// * `view` - Contains a zero-copy view over borrowed registers
+/// * `registers` - Contains the `Registers` traits abstracting over register storage
+/// * `observer` - Contains a register store reporting every raised register to an observer
+/// * `metadata` - Contains the metadata checked before sketches are merged
+/// * `labels` - Contains the user metadata describing the provenance of a sketch
+/// * `activity` - Contains the exact add count and update times a sketch can record
+/// * `error` - Contains the `Error` returned by the fallible operations and the `DecodeError`
+///   describing corrupt serialized sketches
+/// * `multi` - Contains a fixed set of sketches counting several dimensions at once
+/// * `health` - Contains the indicators reported by `health()`
+/// * `hash` - Contains the hash functions sketches can be built with
+/// * `ertl` - Contains the histogram based estimator of Ertl
+/// * `bias` - Contains the empirical bias tables HyperLogLog++ corrects its estimates with
+/// * `arena` - Contains an arena allocating many sketches from one buffer
+/// * `pool` - Contains a pool recycling cleared sketches
+/// * `merge_queue` - Contains a queue folding the sketches of many producers into one
+/// * `window` - Contains a distinct counter over the last N items
+/// * `progress` - Contains a wrapper emitting the estimate of a sketch while items are added
+/// * `sampled` - Contains a sketch counting a deterministic sample of the items added
+/// * `dynamic` - Contains a HyperLogLog whose precision is chosen at runtime
+/// * `tiered` - Contains a sketch moving from an exact list to sparse and dense registers as it grows
+/// * `overlap` - Contains pairwise overlap, similarity search and clustering over sets of sketches
+/// * `interop` - Contains conversions to and from the formats of other HyperLogLog implementations
+/// * `envelope` - Contains the authenticated encryption of serialized sketches enabled by the `crypto` feature
+/// * `rocksdb_merge` - Contains the RocksDB merge operator enabled by the `rocksdb` feature
+/// * `sled_store` - Contains the sled-backed persistent store of keyed sketches enabled by the `sled` feature
+/// * `mmap` - Contains the HyperLogLog stored in a memory mapped file enabled by the `mmap` feature
+/// * `shm` - Contains the HyperLogLog shared by several processes through shared memory enabled by the `mmap` feature
 pub mod hll;
 pub mod plusplus;
 
+pub mod activity;
+pub mod arena;
+pub mod bias;
+pub mod binary;
+pub mod builder;
+pub mod cascade;
+pub mod compare;
+pub mod dump;
+pub mod dynamic;
+pub mod error;
+pub mod ertl;
+pub mod frozen;
+pub mod hash;
+pub mod health;
+pub mod ingest;
+pub mod interop;
+pub mod labels;
+pub mod merge_queue;
+pub mod metadata;
+pub mod multi;
+pub mod observer;
+pub mod overlap;
+pub mod pool;
+pub mod progress;
+pub mod registers;
+pub mod sampled;
+pub mod sketch;
+pub mod sync;
+pub mod tiered;
+pub mod view;
+pub mod window;
+
+mod simd;
+
+#[cfg(feature = "crypto")]
+pub mod envelope;
+
+#[cfg(feature = "mmap")]
+pub mod mmap;
+
+#[cfg(feature = "mmap")]
+pub mod shm;
+
+#[cfg(feature = "rocksdb")]
+pub mod rocksdb_merge;
+
+#[cfg(feature = "sled")]
+pub mod sled_store;
+
+#[cfg(feature = "stats")]
+pub mod stats;
+
+#[cfg(feature = "validate")]
+pub mod validate;
+
 #[cfg(feature = "serde_support")]
 pub mod serde;
 
+/// `arena::HllArena` made available at the top level
+pub use arena::HllArena;
+/// `builder::HllBuilder` made available at the top level
+pub use builder::HllBuilder;
+/// `dynamic::error_for_precision` made available at the top level
+pub use dynamic::error_for_precision;
+/// `dynamic::precision_for_error` made available at the top level
+pub use dynamic::precision_for_error;
+/// `dynamic::DynHyperLogLog` made available at the top level
+pub use dynamic::DynHyperLogLog;
+/// `error::DecodeError` made available at the top level
+pub use error::DecodeError;
+/// `error::Error` made available at the top level
+pub use error::Error;
+/// `frozen::FrozenHyperLogLog` made available at the top level
+pub use frozen::FrozenHyperLogLog;
+/// `hll::AlignedHyperLogLog` made available at the top level
+pub use hll::AlignedHyperLogLog;
+/// `hll::CowHyperLogLog` made available at the top level
+pub use hll::CowHyperLogLog;
 /// `hll::HyperLogLog` made available at the top level
 pub use hll::HyperLogLog;
+/// `hll::InlineHyperLogLog` made available at the top level
+#[cfg(any(feature = "p12", feature = "p14"))]
+pub use hll::InlineHyperLogLog;
+/// `merge_queue::HllMergeQueue` made available at the top level
+pub use merge_queue::HllMergeQueue;
+/// `mmap::HllMmap` made available at the top level
+#[cfg(feature = "mmap")]
+pub use mmap::HllMmap;
+/// `multi::MultiHll` made available at the top level
+pub use multi::MultiHll;
+/// `plusplus::AlignedHyperLogLogPlusPlus` made available at the top level
+pub use plusplus::AlignedHyperLogLogPlusPlus;
+/// `plusplus::CowHyperLogLogPlusPlus` made available at the top level
+pub use plusplus::CowHyperLogLogPlusPlus;
 /// `plusplus::HyperLogLogPlusPlus` made available at the top level
 pub use plusplus::HyperLogLogPlusPlus;
+/// `plusplus::InlineHyperLogLogPlusPlus` made available at the top level
+#[cfg(any(feature = "p12", feature = "p14"))]
+pub use plusplus::InlineHyperLogLogPlusPlus;
+/// `pool::SketchPool` made available at the top level
+pub use pool::SketchPool;
+/// `progress::ProgressSketch` made available at the top level
+pub use progress::ProgressSketch;
+/// `registers::RegisterStore` made available at the top level
+pub use registers::RegisterStore;
+/// `registers::Registers` made available at the top level
+pub use registers::Registers;
+/// `sampled::SampledHyperLogLog` made available at the top level
+pub use sampled::SampledHyperLogLog;
+/// `shm::ShmHyperLogLog` made available at the top level
+#[cfg(feature = "mmap")]
+pub use shm::ShmHyperLogLog;
+/// `sketch::count_distinct` made available at the top level
+pub use sketch::count_distinct;
+/// `sketch::estimate_distinct` made available at the top level
+pub use sketch::estimate_distinct;
+/// `sketch::Sketch` made available at the top level
+pub use sketch::Sketch;
+/// `sled_store::SledHllStore` made available at the top level
+#[cfg(feature = "sled")]
+pub use sled_store::SledHllStore;
+/// `sync::ConcurrentHllMap` made available at the top level
+pub use sync::ConcurrentHllMap;
+/// `sync::SharedHyperLogLog` made available at the top level
+pub use sync::SharedHyperLogLog;
+/// `tiered::TieredHyperLogLog` made available at the top level
+pub use tiered::TieredHyperLogLog;
+/// `view::HllView` made available at the top level
+pub use view::HllView;
+/// `window::CountWindowHll` made available at the top level
+pub use window::CountWindowHll;
+
+#[cfg(any(
+    all(
+        feature = "p12",
+        any(feature = "p14", feature = "p16", feature = "p20")
+    ),
+    all(feature = "p14", any(feature = "p16", feature = "p20")),
+    all(feature = "p16", feature = "p20"),
+))]
+compile_error!("the precision features p12, p14, p16 and p20 are mutually exclusive");
 
 /// Number of distinct register tracks in the HyperLogLog structures,
-/// defined as 2^P where P is the number of bits used to select a register
+/// defined as 2^P where P is the number of bits used to select a register.
+/// Selected by the `p12`, `p14` and `p16` features, 20 otherwise.
+#[cfg(feature = "p12")]
+pub const P: usize = 12;
+/// Number of distinct register tracks in the HyperLogLog structures,
+/// defined as 2^P where P is the number of bits used to select a register.
+/// Selected by the `p12`, `p14` and `p16` features, 20 otherwise.
+#[cfg(feature = "p14")]
+pub const P: usize = 14;
+/// Number of distinct register tracks in the HyperLogLog structures,
+/// defined as 2^P where P is the number of bits used to select a register.
+/// Selected by the `p12`, `p14` and `p16` features, 20 otherwise.
+#[cfg(feature = "p16")]
+pub const P: usize = 16;
+/// Number of distinct register tracks in the HyperLogLog structures,
+/// defined as 2^P where P is the number of bits used to select a register.
+/// Selected by the `p12`, `p14` and `p16` features, 20 otherwise.
+#[cfg(not(any(feature = "p12", feature = "p14", feature = "p16")))]
 pub const P: usize = 20;
 /// Number of registers, it is computed as 2^P
 pub const M: usize = 1 << P;
@@ -24,4 +216,6 @@ pub const M: usize = 1 << P;
 /// It is defined as  0.7213 / (1 + 1.079 / M), where M is the number of registers.
 pub const ALPHA: f64 = 0.7213 / (1.0 + 1.079 / (M as f64));
 
-pub static mut EMPTY_REGISTERS: [u8; M] = [0; M];
+/// Largest rank a register can hold, reached when none of the 64 - P hash bits left
+/// after the register index is set.
+pub(crate) const MAX_RANK: u8 = (64 - P + 1) as u8;
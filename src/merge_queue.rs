@@ -0,0 +1,286 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use crate::{Error, HyperLogLog};
+
+/// Number of sketches a queue created with `HllMergeQueue::new` folds per `merge_many`.
+pub const DEFAULT_BATCH_SIZE: usize = 32;
+
+/// A sketch waiting to be folded, as pushed by a producer.
+#[derive(Debug)]
+enum Pending {
+    Sketch(Box<HyperLogLog>),
+    Serialized(Vec<u8>),
+}
+
+/// A fan-in component folding the sketches of many producers into one accumulator, the
+/// core of an aggregation service.
+///
+/// Producers obtained with `producer` push owned sketches, or sketches in the binary
+/// layout of `serialize_into`, onto an unbounded channel without waiting for the consumer.
+/// The consumer owns the queue and folds pending sketches in batches with `merge_many`,
+/// either on demand with `drain`, or on a dedicated thread with `run`.
+///
+/// Serialized sketches that cannot be decoded, and sketches that cannot be merged into the
+/// accumulator, are dropped and counted by `rejected`, with the last error kept in
+/// `last_error`.
+#[derive(Debug)]
+pub struct HllMergeQueue {
+    accumulator: HyperLogLog,
+    sender: Sender<Pending>,
+    receiver: Receiver<Pending>,
+    batch_size: usize,
+    folded: u64,
+    rejected: u64,
+    last_error: Option<Error>,
+}
+
+/// The producing side of an `HllMergeQueue`. Clones push onto the same queue, and can be
+/// sent to other threads.
+#[derive(Debug, Clone)]
+pub struct HllMergeProducer {
+    sender: Sender<Pending>,
+}
+
+impl HllMergeQueue {
+    /// Creates a queue folding into an empty `HyperLogLog`, `DEFAULT_BATCH_SIZE` sketches
+    /// at a time.
+    ///
+    /// # Returns
+    /// A new `HllMergeQueue` instance.
+    pub fn new() -> Self {
+        Self::with_accumulator(HyperLogLog::new(), DEFAULT_BATCH_SIZE)
+    }
+
+    /// Creates a queue folding into an existing sketch.
+    ///
+    /// # Parameters
+    /// * `accumulator`: The sketch pushed sketches are merged into.
+    /// * `batch_size`: The number of sketches merged per `merge_many`, at least one.
+    ///
+    /// # Returns
+    /// A new `HllMergeQueue` instance.
+    ///
+    /// # Panics
+    /// Panics if `batch_size` is zero.
+    pub fn with_accumulator(accumulator: HyperLogLog, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "batch_size must be at least one");
+        let (sender, receiver) = mpsc::channel();
+
+        Self {
+            accumulator,
+            sender,
+            receiver,
+            batch_size,
+            folded: 0,
+            rejected: 0,
+            last_error: None,
+        }
+    }
+
+    /// Returns a new producer pushing onto this queue.
+    pub fn producer(&self) -> HllMergeProducer {
+        HllMergeProducer {
+            sender: self.sender.clone(),
+        }
+    }
+
+    /// Folds every sketch pushed so far into the accumulator, without waiting for more.
+    ///
+    /// # Returns
+    /// The number of sketches folded.
+    pub fn drain(&mut self) -> usize {
+        let mut folded = 0;
+        loop {
+            let batch = self
+                .receiver
+                .try_iter()
+                .take(self.batch_size)
+                .collect::<Vec<_>>();
+            if batch.is_empty() {
+                return folded;
+            }
+            folded += self.fold(batch);
+        }
+    }
+
+    /// Folds pushed sketches as they arrive, until every producer has been dropped. Meant
+    /// to be the body of a consumer thread.
+    ///
+    /// # Returns
+    /// The accumulator holding every folded sketch.
+    pub fn run(mut self) -> HyperLogLog {
+        // The sender kept for `producer` would hold the channel open forever
+        self.sender = mpsc::channel().0;
+
+        while let Ok(pending) = self.receiver.recv() {
+            let mut batch = vec![pending];
+            batch.extend(self.receiver.try_iter().take(self.batch_size - 1));
+            self.fold(batch);
+        }
+
+        self.accumulator
+    }
+
+    /// Returns the sketch every folded sketch was merged into.
+    pub fn accumulator(&self) -> &HyperLogLog {
+        &self.accumulator
+    }
+
+    /// Returns the accumulator and starts over with an empty sketch of the same kind,
+    /// e.g. at the end of every reporting period. Pending sketches stay queued.
+    ///
+    /// # Returns
+    /// The accumulator holding every sketch folded since the last call.
+    pub fn take(&mut self) -> HyperLogLog {
+        let mut empty = self.accumulator.clone();
+        empty.clear();
+
+        std::mem::replace(&mut self.accumulator, empty)
+    }
+
+    /// Returns the number of sketches folded into the accumulator so far.
+    pub fn folded(&self) -> u64 {
+        self.folded
+    }
+
+    /// Returns the number of pushed sketches that were dropped.
+    pub fn rejected(&self) -> u64 {
+        self.rejected
+    }
+
+    /// Returns the reason the last rejected sketch was dropped, if any.
+    pub fn last_error(&self) -> Option<&Error> {
+        self.last_error.as_ref()
+    }
+
+    /// Decodes a batch of pending sketches and merges them into the accumulator, the
+    /// compatible ones in a single `merge_many`.
+    fn fold(&mut self, batch: Vec<Pending>) -> usize {
+        let mut sketches = Vec::with_capacity(batch.len());
+        let mut folded = 0;
+
+        for pending in batch {
+            let sketch = match pending {
+                Pending::Sketch(sketch) => Ok(*sketch),
+                Pending::Serialized(bytes) => HyperLogLog::deserialize_from(&bytes),
+            };
+            let result = sketch.and_then(|sketch| {
+                let metadata = self.accumulator.metadata();
+                match metadata.check_compatible(&sketch.metadata()) {
+                    Ok(()) => sketches.push(sketch),
+                    // Folded under `MergePolicy::Fold`, refused otherwise
+                    Err(_) => self.accumulator.try_merge(&sketch).map(|()| folded += 1)?,
+                }
+                Ok(())
+            });
+            if let Err(e) = result {
+                self.rejected += 1;
+                self.last_error = Some(e);
+            }
+        }
+
+        self.accumulator.merge_many(&sketches);
+        folded += sketches.len();
+        self.folded += folded as u64;
+
+        folded
+    }
+}
+
+impl Default for HllMergeQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HllMergeProducer {
+    /// Pushes a sketch to be folded into the accumulator.
+    ///
+    /// # Parameters
+    /// * `sketch`: The sketch to fold.
+    ///
+    /// # Returns
+    /// `true` if the sketch was queued, `false` if the queue was dropped.
+    pub fn push(&self, sketch: HyperLogLog) -> bool {
+        self.sender.send(Pending::Sketch(Box::new(sketch))).is_ok()
+    }
+
+    /// Pushes a sketch serialized with `serialize_into`, decoded by the consumer.
+    ///
+    /// # Parameters
+    /// * `bytes`: The serialized sketch.
+    ///
+    /// # Returns
+    /// `true` if the sketch was queued, `false` if the queue was dropped.
+    pub fn push_serialized(&self, bytes: Vec<u8>) -> bool {
+        self.sender.send(Pending::Serialized(bytes)).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    #[test]
+    fn test_fan_in() {
+        let queue = HllMergeQueue::with_accumulator(HyperLogLog::new(), 3);
+        let producer = queue.producer();
+        let consumer = thread::spawn(move || queue.run());
+
+        let handles = (0..4)
+            .map(|t| {
+                let producer = producer.clone();
+                thread::spawn(move || {
+                    for batch in 0..5 {
+                        let mut sketch = HyperLogLog::new();
+                        for i in 0..500 {
+                            sketch.add(t * 10_000 + batch * 500 + i);
+                        }
+                        match t % 2 {
+                            0 => assert!(producer.push(sketch)),
+                            _ => {
+                                let mut bytes = Vec::new();
+                                sketch.serialize_into(&mut bytes);
+                                assert!(producer.push_serialized(bytes));
+                            }
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        drop(producer);
+
+        let merged = consumer.join().unwrap();
+        let mut expected = HyperLogLog::new();
+        for t in 0..4 {
+            for i in 0..2_500 {
+                expected.add(t * 10_000 + i);
+            }
+        }
+        assert!(merged.diff(&expected).is_identical());
+    }
+
+    #[test]
+    fn test_drain_and_reject() {
+        let mut queue = HllMergeQueue::new();
+        let producer = queue.producer();
+
+        let mut sketch = HyperLogLog::new();
+        sketch.add("item");
+        producer.push(sketch.clone());
+        producer.push_serialized(vec![1, 2, 3]);
+        assert_eq!(queue.drain(), 1);
+        assert_eq!(queue.drain(), 0);
+        assert_eq!((queue.folded(), queue.rejected()), (1, 1));
+        assert!(matches!(queue.last_error(), Some(Error::Decode(_))));
+
+        let taken = queue.take();
+        assert!(taken.diff(&sketch).is_identical());
+        assert_eq!(queue.accumulator().estimate(), 0.0);
+    }
+}
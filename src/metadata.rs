@@ -0,0 +1,274 @@
+use std::{error::Error, fmt};
+
+use crate::{hash::DEFAULT_SEAHASH_KEY, P};
+
+/// The estimation algorithm a sketch was built for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Algorithm {
+    /// Canonical HyperLogLog, see `HyperLogLog`.
+    HyperLogLog,
+    /// HyperLogLog++, see `HyperLogLogPlusPlus`.
+    HyperLogLogPlusPlus,
+}
+
+impl Algorithm {
+    /// Returns the name used for the algorithm in serialized sketches.
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::HyperLogLog => "hll",
+            Algorithm::HyperLogLogPlusPlus => "hll++",
+        }
+    }
+
+    /// Parses a name produced by `Algorithm::name`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "hll" => Some(Algorithm::HyperLogLog),
+            "hll++" => Some(Algorithm::HyperLogLogPlusPlus),
+            _ => None,
+        }
+    }
+}
+
+/// The hash function used to turn items into register updates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum HasherId {
+    /// SeaHash, keyed by the 256-bit key of the sketch.
+    SeaHash,
+    /// SipHash-2-4, keyed by the secret key of the deployment set with
+    /// `hash::siphash::set_key`. The seed of the sketch holds a fingerprint of that key.
+    SipHash,
+    /// MurmurHash3 x64_128, seeded with the low 32 bits of the seed of the sketch. Only
+    /// available with the `murmur3` feature.
+    #[cfg(feature = "murmur3")]
+    Murmur3,
+    /// XXH3 64-bit, keyed by the seed of the sketch. Only available with the `xxh3`
+    /// feature.
+    #[cfg(feature = "xxh3")]
+    Xxh3,
+    /// wyhash, keyed by the seed of the sketch. Only available with the `wyhash` feature.
+    #[cfg(feature = "wyhash")]
+    WyHash,
+    /// aHash, keyed by the seed of the sketch. Its hashes depend on the version of `ahash`
+    /// and the target, so only sketches of the same build can be merged. Only available
+    /// with the `ahash` feature.
+    #[cfg(feature = "ahash")]
+    AHash,
+}
+
+impl HasherId {
+    /// Returns the name used for the hasher in serialized sketches.
+    pub fn name(self) -> &'static str {
+        match self {
+            HasherId::SeaHash => "seahash",
+            HasherId::SipHash => "siphash24",
+            #[cfg(feature = "murmur3")]
+            HasherId::Murmur3 => "murmur3_128",
+            #[cfg(feature = "xxh3")]
+            HasherId::Xxh3 => "xxh3",
+            #[cfg(feature = "wyhash")]
+            HasherId::WyHash => "wyhash",
+            #[cfg(feature = "ahash")]
+            HasherId::AHash => "ahash",
+        }
+    }
+
+    /// Parses a name produced by `HasherId::name`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "seahash" => Some(HasherId::SeaHash),
+            "siphash24" => Some(HasherId::SipHash),
+            #[cfg(feature = "murmur3")]
+            "murmur3_128" => Some(HasherId::Murmur3),
+            #[cfg(feature = "xxh3")]
+            "xxh3" => Some(HasherId::Xxh3),
+            #[cfg(feature = "wyhash")]
+            "wyhash" => Some(HasherId::WyHash),
+            #[cfg(feature = "ahash")]
+            "ahash" => Some(HasherId::AHash),
+            _ => None,
+        }
+    }
+}
+
+/// What to do when merging a sketch built with a different precision.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum MergePolicy {
+    /// Refuse to merge sketches whose precisions differ.
+    Strict,
+    /// Fold a sketch of higher precision down to the precision of the sketch it is merged
+    /// into, as Redis and DataSketches do. Merging a sketch of lower precision is still
+    /// refused, since the registers it would need cannot be recovered.
+    #[default]
+    Fold,
+}
+
+/// Everything that has to match for the registers of two sketches to be comparable.
+///
+/// Sketches built with a different precision, algorithm, hasher or seed map the same item
+/// to unrelated registers, so merging them silently produces garbage. The metadata is
+/// carried by every sketch and its serialized form, and checked on merge and
+/// deserialization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SketchMetadata {
+    /// Number of bits used to select a register.
+    pub precision: u8,
+    /// The estimation algorithm.
+    pub algorithm: Algorithm,
+    /// The hash function applied to items.
+    pub hasher: HasherId,
+    /// The seed of the hash function.
+    pub seed: u64,
+    /// The 256-bit key of `HasherId::SeaHash`, `hash::DEFAULT_SEAHASH_KEY` unless set with
+    /// `with_seed`. Left at its default by the other hashers.
+    pub key: [u64; 4],
+}
+
+impl SketchMetadata {
+    /// Creates the metadata of a sketch using the default hasher and seed.
+    ///
+    /// # Parameters
+    /// * `algorithm`: The estimation algorithm.
+    /// * `precision`: The number of bits used to select a register.
+    pub const fn new(algorithm: Algorithm, precision: usize) -> Self {
+        Self {
+            precision: precision as u8,
+            algorithm,
+            hasher: HasherId::SeaHash,
+            seed: 0,
+            key: DEFAULT_SEAHASH_KEY,
+        }
+    }
+
+    /// Checks that sketches described by `self` and `other` can be merged.
+    ///
+    /// # Parameters
+    /// * `other`: The metadata of the other sketch.
+    ///
+    /// # Returns
+    /// `Ok(())` if both are identical, or a `MetadataMismatch` naming the first difference.
+    pub fn check_compatible(&self, other: &SketchMetadata) -> Result<(), MetadataMismatch> {
+        match self == other {
+            true => Ok(()),
+            false => Err(MetadataMismatch {
+                expected: *self,
+                found: *other,
+            }),
+        }
+    }
+}
+
+impl Default for SketchMetadata {
+    /// The metadata of a default `HyperLogLog`.
+    fn default() -> Self {
+        Self::new(Algorithm::HyperLogLog, P)
+    }
+}
+
+/// Error returned when two sketches with different metadata are combined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MetadataMismatch {
+    /// The metadata of the sketch being merged into or deserialized.
+    pub expected: SketchMetadata,
+    /// The metadata of the other sketch or of the serialized payload.
+    pub found: SketchMetadata,
+}
+
+impl MetadataMismatch {
+    /// Returns `true` when the only difference is that the other sketch has a higher
+    /// precision, in which case its registers can be folded down under `MergePolicy::Fold`.
+    pub fn is_foldable(&self) -> bool {
+        self.found.precision > self.expected.precision
+            && SketchMetadata {
+                precision: self.expected.precision,
+                ..self.found
+            } == self.expected
+    }
+}
+
+impl fmt::Display for MetadataMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (expected, found) = (&self.expected, &self.found);
+
+        if expected.precision != found.precision {
+            write!(
+                f,
+                "incompatible sketches: precision {} vs {}",
+                expected.precision, found.precision
+            )
+        } else if expected.algorithm != found.algorithm {
+            write!(
+                f,
+                "incompatible sketches: algorithm {} vs {}",
+                expected.algorithm.name(),
+                found.algorithm.name()
+            )
+        } else if expected.hasher != found.hasher {
+            write!(
+                f,
+                "incompatible sketches: hasher {} vs {}",
+                expected.hasher.name(),
+                found.hasher.name()
+            )
+        } else if expected.seed != found.seed {
+            write!(
+                f,
+                "incompatible sketches: seed {} vs {}",
+                expected.seed, found.seed
+            )
+        } else {
+            write!(
+                f,
+                "incompatible sketches: key {} vs {}",
+                format_key(expected.key),
+                format_key(found.key)
+            )
+        }
+    }
+}
+
+impl Error for MetadataMismatch {}
+
+/// Formats a 256-bit SeaHash key as 64 hex digits, most significant word first, the form
+/// in which serialized sketches store it.
+pub(crate) fn format_key(key: [u64; 4]) -> String {
+    key.iter().map(|word| format!("{:016x}", word)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_compatible() {
+        let hll = SketchMetadata::new(Algorithm::HyperLogLog, P);
+        let plusplus = SketchMetadata::new(Algorithm::HyperLogLogPlusPlus, P);
+
+        assert!(hll.check_compatible(&SketchMetadata::default()).is_ok());
+
+        let finer = SketchMetadata::new(Algorithm::HyperLogLog, P + 1);
+        assert!(hll.check_compatible(&finer).unwrap_err().is_foldable());
+        assert!(!finer.check_compatible(&hll).unwrap_err().is_foldable());
+
+        let err = hll.check_compatible(&plusplus).unwrap_err();
+        assert!(!err.is_foldable());
+        assert_eq!(err.found, plusplus);
+        assert_eq!(
+            err.to_string(),
+            "incompatible sketches: algorithm hll vs hll++"
+        );
+
+        let keyed = SketchMetadata {
+            key: [0, 0, 0, 1],
+            ..hll
+        };
+        assert!(!hll.check_compatible(&keyed).unwrap_err().is_foldable());
+        assert!(hll
+            .check_compatible(&keyed)
+            .unwrap_err()
+            .to_string()
+            .ends_with(&format!("vs {:064x}", 1)));
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    fs::{File, OpenOptions},
+    ops::{Deref, DerefMut},
+    path::Path,
+};
+
+use memmap2::MmapMut;
+
+use crate::{Error, HyperLogLog, M};
+
+/// A HyperLogLog whose registers live in a memory mapped file.
+///
+/// The file holds exactly the `M` raw registers, in the layout of `try_from_bytes`, and
+/// `add` and `merge` update them in place: a fleet of sketches can be persisted one file
+/// each and reopened without a serialize/deserialize cycle, the kernel paging the
+/// registers in and out as needed. Only the registers are stored, so a reopened sketch
+/// hashes items with the default hasher and seed.
+///
+/// Updates reach the file when the kernel writes the dirty pages back, at the latest when
+/// the sketch is dropped; `flush` forces them out, e.g. before taking a backup.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut hll = HllMmap::create("visitors.hll")?;
+/// hll.add("alice");
+/// hll.flush()?;
+/// drop(hll);
+///
+/// let hll = HllMmap::open("visitors.hll")?;
+/// assert_eq!(hll.estimate().round(), 1.0);
+/// ```
+pub type HllMmap = HyperLogLog<MmapRegisters>;
+
+/// Registers stored in a writable memory map of a file, see `HllMmap`.
+#[derive(Debug)]
+pub struct MmapRegisters {
+    map: MmapMut,
+}
+
+impl MmapRegisters {
+    /// Maps a file holding raw registers for reading and writing.
+    ///
+    /// # Arguments
+    ///
+    /// * `file`: The file, opened for reading and writing.
+    fn map(file: &File) -> Result<Self, Error> {
+        // SAFETY: as documented on `HllMmap::create` and `HllMmap::open`, the file must
+        // not be truncated or modified by anyone else while it is mapped, which cannot be
+        // enforced for memory mapped files.
+        let map = unsafe { MmapMut::map_mut(file)? };
+
+        Ok(Self { map })
+    }
+}
+
+impl Deref for MmapRegisters {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.map
+    }
+}
+
+impl DerefMut for MmapRegisters {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.map
+    }
+}
+
+impl HyperLogLog<MmapRegisters> {
+    /// Creates an empty sketch in a new file, replacing any existing file at `path`.
+    ///
+    /// The file must not be truncated or modified by another sketch or process while the
+    /// sketch is alive: accessing a truncated map raises `SIGBUS`.
+    ///
+    /// # Parameters
+    /// * `path`: The path of the file.
+    ///
+    /// # Returns
+    /// A new `HllMmap` instance, or an `Error::Storage` if the file cannot be created or
+    /// mapped.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len(M as u64)?;
+
+        Ok(Self::with_registers(MmapRegisters::map(&file)?))
+    }
+
+    /// Opens a sketch stored by `create`, checking its registers like
+    /// `try_with_registers`.
+    ///
+    /// The file must not be truncated or modified by another sketch or process while the
+    /// sketch is alive: accessing a truncated map raises `SIGBUS`.
+    ///
+    /// # Parameters
+    /// * `path`: The path of the file.
+    ///
+    /// # Returns
+    /// The `HllMmap`, an `Error::Storage` if the file cannot be opened or mapped, or an
+    /// `Error::Length` or `Error::InvalidRegister` if it does not hold `M` valid registers.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let len = file.metadata()?.len();
+        if len != M as u64 {
+            return Err(Error::Length {
+                expected: M,
+                found: len as usize,
+            });
+        }
+
+        Self::try_with_registers(MmapRegisters::map(&file)?)
+    }
+
+    /// Writes the updated registers back to the file and waits for the write to complete.
+    ///
+    /// # Returns
+    /// An `Error::Storage` if the registers could not be written.
+    pub fn flush(&self) -> Result<(), Error> {
+        self.registers.map.flush()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mmap_roundtrip() {
+        let path = std::env::temp_dir().join(format!("hyperlog-mmap-{}", std::process::id()));
+
+        let mut hll = HllMmap::create(&path).unwrap();
+        let mut expected = HyperLogLog::new();
+        for i in 0..10_000 {
+            hll.add(i);
+            expected.add(i);
+        }
+        hll.flush().unwrap();
+        drop(hll);
+
+        let mut hll = HllMmap::open(&path).unwrap();
+        assert_eq!(hll.estimate(), expected.estimate());
+        hll.merge(&expected);
+        assert_eq!(hll.as_bytes(), expected.as_bytes());
+        drop(hll);
+
+        std::fs::write(&path, [0u8; 10]).unwrap();
+        assert_eq!(
+            HllMmap::open(&path).unwrap_err(),
+            Error::Length {
+                expected: M,
+                found: 10
+            }
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
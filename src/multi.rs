@@ -0,0 +1,205 @@
+use std::hash::{Hash, Hasher};
+
+use seahash::SeaHasher;
+
+#[cfg(feature = "serde_support")]
+use serde::{
+    de::{Deserializer, Error},
+    ser::SerializeSeq,
+    Deserialize, Serialize, Serializer,
+};
+
+use crate::HyperLogLog;
+
+/// Object safe counterpart of `Hash`, implemented for every `Hash` type, so items of
+/// different types can be passed together to `MultiHll::observe`.
+pub trait DynHash {
+    /// Feeds the value into the given hasher, exactly as `Hash::hash` would.
+    fn dyn_hash(&self, state: &mut dyn Hasher);
+}
+
+impl<T: Hash + ?Sized> DynHash for T {
+    #[inline(always)]
+    fn dyn_hash(&self, mut state: &mut dyn Hasher) {
+        self.hash(&mut state)
+    }
+}
+
+/// A fixed set of `N` HyperLogLogs counting the distinct values of `N` dimensions of the
+/// same records, such as the distinct users, IPs and URLs seen in a window.
+///
+/// Each dimension is a plain `HyperLogLog`, holding exactly the registers it would hold if
+/// its values had been added to it directly.
+#[derive(Debug, Clone)]
+pub struct MultiHll<const N: usize> {
+    sketches: [HyperLogLog; N],
+}
+
+impl<const N: usize> MultiHll<N> {
+    /// Creates `N` empty sketches.
+    ///
+    /// # Returns
+    /// A new `MultiHll` instance.
+    pub fn new() -> Self {
+        Self {
+            sketches: std::array::from_fn(|_| HyperLogLog::new()),
+        }
+    }
+
+    /// Adds one record, given as the value of each dimension in order.
+    ///
+    /// # Parameters
+    /// * `items`: The value of every dimension, e.g. `[&user_id, &ip, &url]`.
+    #[inline(always)]
+    pub fn observe(&mut self, items: [&dyn DynHash; N]) {
+        for (sketch, item) in self.sketches.iter_mut().zip(items) {
+            let mut hasher = SeaHasher::new();
+            item.dyn_hash(&mut hasher);
+            sketch.add_hash(hasher.finish());
+        }
+    }
+
+    /// Adds a value to a single dimension.
+    ///
+    /// # Parameters
+    /// * `dimension`: The index of the dimension, less than `N`.
+    /// * `item`: An item that implements the `Hash` trait to be added.
+    #[inline(always)]
+    pub fn add<T: Hash>(&mut self, dimension: usize, item: T) {
+        self.sketches[dimension].add(item);
+    }
+
+    /// Provides the estimate of a single dimension.
+    ///
+    /// # Parameters
+    /// * `dimension`: The index of the dimension, less than `N`.
+    ///
+    /// # Returns
+    /// A `f64` approximate count of the distinct values of the dimension.
+    pub fn estimate(&self, dimension: usize) -> f64 {
+        self.sketches[dimension].estimate()
+    }
+
+    /// Provides the estimates of all dimensions.
+    ///
+    /// # Returns
+    /// The approximate count of distinct values of every dimension, in order.
+    pub fn estimates(&self) -> [f64; N] {
+        std::array::from_fn(|dimension| self.estimate(dimension))
+    }
+
+    /// Merges another `MultiHll` into this one, dimension by dimension.
+    ///
+    /// # Parameters
+    /// * `other`: A reference to another `MultiHll` instance to be merged.
+    pub fn merge(&mut self, other: &MultiHll<N>) {
+        for (sketch, other) in self.sketches.iter_mut().zip(&other.sketches) {
+            sketch.merge(other);
+        }
+    }
+
+    /// Returns the sketch of a single dimension.
+    pub fn sketch(&self, dimension: usize) -> &HyperLogLog {
+        &self.sketches[dimension]
+    }
+
+    /// Returns the sketches of all dimensions.
+    pub fn sketches(&self) -> &[HyperLogLog; N] {
+        &self.sketches
+    }
+
+    /// Consumes the `MultiHll`, returning the sketch of every dimension.
+    pub fn into_sketches(self) -> [HyperLogLog; N] {
+        self.sketches
+    }
+}
+
+impl<const N: usize> Default for MultiHll<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> From<[HyperLogLog; N]> for MultiHll<N> {
+    /// Groups existing sketches into a `MultiHll`, one per dimension.
+    fn from(sketches: [HyperLogLog; N]) -> Self {
+        Self { sketches }
+    }
+}
+
+#[cfg(feature = "serde_support")]
+impl<const N: usize> Serialize for MultiHll<N> {
+    /// Serializes the sketches as a sequence, in dimension order.
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(N))?;
+        for sketch in &self.sketches {
+            seq.serialize_element(sketch)?;
+        }
+        seq.end()
+    }
+}
+
+#[cfg(feature = "serde_support")]
+impl<'de, const N: usize> Deserialize<'de> for MultiHll<N> {
+    /// Deserializes a sequence of exactly `N` sketches.
+    fn deserialize<D>(deserializer: D) -> Result<MultiHll<N>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let sketches = Vec::<HyperLogLog>::deserialize(deserializer)?;
+        let found = sketches.len();
+
+        <[HyperLogLog; N]>::try_from(sketches)
+            .map(Self::from)
+            .map_err(|_| D::Error::invalid_length(found, &format!("{} sketches", N).as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::M;
+
+    #[test]
+    fn test_observe() {
+        let mut multi = MultiHll::<3>::new();
+        for i in 0..1_000u32 {
+            let user = format!("user_{}", i % 100);
+            let ip = [10u8, 0, (i % 4) as u8, 1];
+            multi.observe([&user, &ip, &i]);
+        }
+
+        let bound = 4.0 * 1.04 / (M as f64).sqrt();
+        for (estimate, expected) in multi.estimates().into_iter().zip([100.0, 4.0, 1_000.0]) {
+            assert!(
+                (estimate - expected).abs() / expected < bound,
+                "estimate {}, expected {}",
+                estimate,
+                expected
+            );
+        }
+
+        let mut users = HyperLogLog::new();
+        for i in 0..100 {
+            users.add(format!("user_{}", i));
+        }
+        assert!(multi.sketch(0).diff(&users).is_identical());
+    }
+
+    #[cfg(feature = "serde_support")]
+    #[test]
+    fn test_serde() {
+        let mut multi = MultiHll::<2>::new();
+        multi.observe([&"a", &1]);
+        multi.add(1, 2);
+
+        let json = serde_json::to_string(&multi).unwrap();
+        let decoded: MultiHll<2> = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.estimates(), multi.estimates());
+
+        assert!(serde_json::from_str::<MultiHll<3>>(&json).is_err());
+    }
+}
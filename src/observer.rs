@@ -0,0 +1,157 @@
+use std::fmt;
+
+use crate::registers::{Registers, RegistersMut};
+
+/// Receives every change made to the registers of an `ObservedRegisters` store.
+///
+/// Closures taking the index, the previous value and the new value of a raised register
+/// implement it, e.g. to feed a change-data-capture stream.
+pub trait RegisterObserver {
+    /// Called after a register was raised by an add or a merge.
+    ///
+    /// # Arguments
+    /// * `index`: The index of the register.
+    /// * `previous`: The value of the register before it was raised.
+    /// * `rank`: The new value of the register.
+    fn on_raise(&mut self, index: usize, previous: u8, rank: u8);
+
+    /// Called after every register was reset to zero. Does nothing by default.
+    fn on_clear(&mut self) {}
+}
+
+impl<F: FnMut(usize, u8, u8)> RegisterObserver for F {
+    #[inline(always)]
+    fn on_raise(&mut self, index: usize, previous: u8, rank: u8) {
+        self(index, previous, rank)
+    }
+}
+
+/// A register store notifying an observer of every register it raises, so replication
+/// layers, change-data-capture or incrementally maintained fingerprints can follow a
+/// sketch without forking its add path.
+///
+/// Sketches are built on top of it like on any other store, e.g. with
+/// `HyperLogLog::with_observer`. Adds that do not raise a register are not reported.
+#[derive(Clone)]
+pub struct ObservedRegisters<R, O> {
+    registers: R,
+    observer: O,
+}
+
+impl<R: Registers, O: RegisterObserver> ObservedRegisters<R, O> {
+    /// Wraps a register store.
+    ///
+    /// # Parameters
+    /// * `registers`: The store holding the registers.
+    /// * `observer`: The observer notified of every raised register.
+    ///
+    /// # Returns
+    /// A new `ObservedRegisters` instance.
+    pub fn new(registers: R, observer: O) -> Self {
+        Self {
+            registers,
+            observer,
+        }
+    }
+
+    /// Returns the observer.
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    /// Returns the observer mutably, e.g. to drain the changes it collected.
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    /// Returns the wrapped store.
+    pub fn inner(&self) -> &R {
+        &self.registers
+    }
+
+    /// Splits the store into the wrapped store and the observer.
+    pub fn into_parts(self) -> (R, O) {
+        (self.registers, self.observer)
+    }
+}
+
+impl<R: fmt::Debug, O> fmt::Debug for ObservedRegisters<R, O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ObservedRegisters")
+            .field("registers", &self.registers)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<R: Registers, O> Registers for ObservedRegisters<R, O> {
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.registers.len()
+    }
+
+    #[inline(always)]
+    fn get(&self, index: usize) -> u8 {
+        self.registers.get(index)
+    }
+
+    #[inline(always)]
+    fn for_each_chunk<F: FnMut(&[u8])>(&self, f: F) {
+        self.registers.for_each_chunk(f)
+    }
+
+    fn heap_size(&self) -> usize {
+        self.registers.heap_size()
+    }
+}
+
+impl<R: RegistersMut, O: RegisterObserver> RegistersMut for ObservedRegisters<R, O> {
+    #[inline(always)]
+    fn update_max(&mut self, index: usize, value: u8) -> bool {
+        let previous = self.registers.get(index);
+        let raised = self.registers.update_max(index, value);
+        if raised {
+            self.observer.on_raise(index, previous, value);
+        }
+
+        raised
+    }
+
+    fn clear(&mut self) {
+        self.registers.clear();
+        self.observer.on_clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{registers, HyperLogLog};
+
+    #[test]
+    fn test_incremental_fingerprint() {
+        let mut fingerprint = 0;
+        let mut raises = 0;
+        let mut hll = HyperLogLog::with_observer(|index, previous, rank| {
+            fingerprint ^= registers::fingerprint_term(index, previous)
+                ^ registers::fingerprint_term(index, rank);
+            raises += 1;
+        });
+
+        let mut other = HyperLogLog::new();
+        for i in 0..1_000 {
+            hll.add(i);
+            other.add(i + 500);
+        }
+        hll.merge(&other);
+
+        let expected = hll.fingerprint();
+        let (registers, _) = hll.registers.into_parts();
+        let raised = registers.iter().filter(|&&rank| rank > 0).count();
+        assert_eq!(fingerprint, expected);
+        assert!(
+            raises >= raised,
+            "{} raises for {} registers",
+            raises,
+            raised
+        );
+    }
+}
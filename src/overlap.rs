@@ -0,0 +1,309 @@
+use crate::{
+    hll,
+    registers::{Registers, RegistersMut},
+    simd, HyperLogLog, M,
+};
+
+/// Pairwise union, intersection and Jaccard estimates of a set of named sketches, such as
+/// daily cohorts in a retention report or audiences in an overlap report.
+///
+/// Entries are addressed by the position of the sketches passed to `overlap_matrix`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverlapMatrix {
+    names: Vec<String>,
+    estimates: Vec<f64>,
+    unions: Vec<f64>,
+}
+
+impl OverlapMatrix {
+    /// Returns the names of the sketches, in order.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Returns the number of sketches.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Returns `true` if the matrix was built from no sketches.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Returns the estimate of a single sketch.
+    ///
+    /// # Parameters
+    /// * `i`: The position of the sketch.
+    pub fn estimate(&self, i: usize) -> f64 {
+        self.estimates[i]
+    }
+
+    /// Returns the estimated size of the union of two sketches.
+    ///
+    /// # Parameters
+    /// * `i`, `j`: The positions of the sketches.
+    pub fn union(&self, i: usize, j: usize) -> f64 {
+        self.unions[i * self.len() + j]
+    }
+
+    /// Returns the estimated size of the intersection of two sketches, computed by
+    /// inclusion-exclusion as `|A| + |B| - |A ∪ B|` and clamped to `[0, min(|A|, |B|)]`.
+    /// Like every intersection derived from HyperLogLogs, its absolute error scales with
+    /// the size of the union rather than with the intersection itself.
+    ///
+    /// # Parameters
+    /// * `i`, `j`: The positions of the sketches.
+    pub fn intersection(&self, i: usize, j: usize) -> f64 {
+        let (a, b) = (self.estimates[i], self.estimates[j]);
+
+        (a + b - self.union(i, j)).clamp(0.0, a.min(b))
+    }
+
+    /// Returns the estimated Jaccard similarity of two sketches, `|A ∩ B| / |A ∪ B|`, or
+    /// `0.0` when both are empty.
+    ///
+    /// # Parameters
+    /// * `i`, `j`: The positions of the sketches.
+    pub fn jaccard(&self, i: usize, j: usize) -> f64 {
+        match self.union(i, j) {
+            union if union > 0.0 => self.intersection(i, j) / union,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Computes the pairwise overlaps of a set of named sketches.
+///
+/// Every sketch is estimated once and every pair is unioned once, into a single scratch
+/// buffer, so `n` sketches cost `n (n - 1) / 2` merges and no allocation per pair.
+///
+/// # Parameters
+/// * `sketches`: The sketches, each with the name it is reported under.
+///
+/// # Returns
+/// An `OverlapMatrix` holding the estimates of every pair.
+pub fn overlap_matrix<R: Registers>(sketches: &[(&str, &HyperLogLog<R>)]) -> OverlapMatrix {
+    let n = sketches.len();
+    let estimates = sketches
+        .iter()
+        .map(|(_, sketch)| sketch.estimate())
+        .collect::<Vec<_>>();
+    let mut unions = vec![0.0; n * n];
+    let mut scratch = vec![0u8; M];
+
+    for i in 0..n {
+        unions[i * n + i] = estimates[i];
+
+        for j in i + 1..n {
+            let mut offset = 0;
+            sketches[i].1.registers.for_each_chunk(|chunk| {
+                scratch[offset..offset + chunk.len()].copy_from_slice(chunk);
+                offset += chunk.len();
+            });
+            scratch.merge_max(&sketches[j].1.registers);
+
+            let union = hll::estimate_registers(&scratch);
+            unions[i * n + j] = union;
+            unions[j * n + i] = union;
+        }
+    }
+
+    OverlapMatrix {
+        names: sketches.iter().map(|(name, _)| name.to_string()).collect(),
+        estimates,
+        unions,
+    }
+}
+
+/// Finds the sketches most similar to a query sketch, by estimated Jaccard similarity,
+/// e.g. the segments overlapping most with an audience.
+///
+/// The union of the query with every candidate is estimated straight from the lane-wise
+/// maximum of their registers, without materializing the merged sketch.
+///
+/// # Parameters
+/// * `query`: The sketch to compare against.
+/// * `candidates`: The sketches to search.
+/// * `k`: The number of results to return at most.
+///
+/// # Returns
+/// The positions of the `k` most similar candidates with their Jaccard similarity, most
+/// similar first.
+pub fn find_most_similar(
+    query: &HyperLogLog,
+    candidates: &[HyperLogLog],
+    k: usize,
+) -> Vec<(usize, f64)> {
+    let query_estimate = query.estimate();
+    let mut similarities = candidates
+        .iter()
+        .enumerate()
+        .map(|(i, candidate)| {
+            (
+                i,
+                jaccard(query, query_estimate, candidate, candidate.estimate()),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    similarities.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    similarities.truncate(k);
+
+    similarities
+}
+
+/// Estimates the Jaccard similarity of two sketches whose estimates are already known,
+/// computing their union without materializing the merged sketch.
+fn jaccard(a: &HyperLogLog, a_estimate: f64, b: &HyperLogLog, b_estimate: f64) -> f64 {
+    let (sum, num_zeros) =
+        simd::union_harmonic_sum_and_zeros(a.registers.as_slice(), b.registers.as_slice());
+    let (union, _) = hll::estimate_sums(sum, num_zeros, M);
+    let intersection = (a_estimate + b_estimate - union).clamp(0.0, a_estimate.min(b_estimate));
+
+    match union > 0.0 {
+        true => intersection / union,
+        false => 0.0,
+    }
+}
+
+/// A group of similar sketches found by `cluster_by_similarity`.
+#[derive(Debug, Clone)]
+pub struct SketchCluster {
+    /// Positions of the member sketches, in the order they joined.
+    pub members: Vec<usize>,
+    /// The union of all members, which new sketches are compared against.
+    pub representative: HyperLogLog,
+}
+
+/// Greedily clusters sketches by estimated Jaccard similarity, e.g. to deduplicate
+/// near-identical audiences or datasets using only their sketches.
+///
+/// Sketches are visited in order. Each one joins the cluster whose representative it is
+/// most similar to, provided the similarity reaches `threshold`, and is merged into that
+/// representative; otherwise it starts a new cluster. The result therefore depends on the
+/// order of `sketches`, and representatives grow as members join, which lowers their
+/// similarity to sketches covering only part of them.
+///
+/// # Parameters
+/// * `sketches`: The sketches to cluster.
+/// * `threshold`: The minimum Jaccard similarity, in `[0, 1]`, to join a cluster.
+///
+/// # Returns
+/// The clusters, in the order they were started.
+pub fn cluster_by_similarity(sketches: &[HyperLogLog], threshold: f64) -> Vec<SketchCluster> {
+    let mut clusters: Vec<(SketchCluster, f64)> = Vec::new();
+
+    for (i, sketch) in sketches.iter().enumerate() {
+        let estimate = sketch.estimate();
+        let best = clusters
+            .iter()
+            .enumerate()
+            .map(|(c, (cluster, representative_estimate))| {
+                let similarity = jaccard(
+                    sketch,
+                    estimate,
+                    &cluster.representative,
+                    *representative_estimate,
+                );
+                (c, similarity)
+            })
+            .filter(|&(_, similarity)| similarity >= threshold)
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        match best {
+            Some((c, _)) => {
+                let (cluster, representative_estimate) = &mut clusters[c];
+                cluster.members.push(i);
+                cluster.representative.merge(sketch);
+                *representative_estimate = cluster.representative.estimate();
+            }
+            None => clusters.push((
+                SketchCluster {
+                    members: vec![i],
+                    representative: sketch.clone(),
+                },
+                estimate,
+            )),
+        }
+    }
+
+    clusters.into_iter().map(|(cluster, _)| cluster).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::M;
+
+    #[test]
+    fn test_overlap_matrix() {
+        let mut cohorts = [HyperLogLog::new(), HyperLogLog::new(), HyperLogLog::new()];
+        for i in 0..10_000 {
+            cohorts[0].add(i);
+            cohorts[1].add(i + 5_000);
+            cohorts[2].add(i + 100_000);
+        }
+
+        let matrix = overlap_matrix(&[
+            ("mon", &cohorts[0]),
+            ("tue", &cohorts[1]),
+            ("wed", &cohorts[2]),
+        ]);
+        assert_eq!(matrix.len(), 3);
+        assert_eq!(matrix.names()[1], "tue");
+
+        // Errors of intersections scale with the sizes of the sketches and their union
+        let bound = 4.0 * 1.04 / (M as f64).sqrt();
+        assert!((matrix.intersection(0, 1) - 5_000.0).abs() < bound * 35_000.0);
+        assert_eq!(matrix.intersection(0, 1), matrix.intersection(1, 0));
+        assert!((matrix.jaccard(0, 1) - 1.0 / 3.0).abs() < bound * 35_000.0 / 15_000.0);
+        assert!(matrix.intersection(0, 2) < bound * 40_000.0);
+        assert_eq!(matrix.intersection(2, 2), matrix.estimate(2));
+        assert_eq!(matrix.jaccard(1, 1), 1.0);
+    }
+
+    #[test]
+    fn test_find_most_similar() {
+        let mut query = HyperLogLog::new();
+        for i in 0..10_000 {
+            query.add(i);
+        }
+
+        let candidates = [0, 9_000, 2_000, 50_000].map(|start| {
+            let mut candidate = HyperLogLog::new();
+            for i in start..start + 10_000 {
+                candidate.add(i);
+            }
+            candidate
+        });
+
+        let similar = find_most_similar(&query, &candidates, 3);
+        let order = similar.iter().map(|&(i, _)| i).collect::<Vec<_>>();
+        assert_eq!(order, [0, 2, 1]);
+        assert!((similar[0].1 - 1.0).abs() < 1e-9);
+        assert!(
+            (similar[1].1 - 8.0 / 12.0).abs()
+                < 4.0 * 1.04 / (M as f64).sqrt() * 32_000.0 / 12_000.0
+        );
+    }
+
+    #[test]
+    fn test_cluster_by_similarity() {
+        let sketches = [0, 100_000, 200, 100_300, 400].map(|start| {
+            let mut sketch = HyperLogLog::new();
+            for i in start..start + 10_000 {
+                sketch.add(i);
+            }
+            sketch
+        });
+
+        let clusters = cluster_by_similarity(&sketches, 0.8);
+        let members = clusters
+            .iter()
+            .map(|cluster| cluster.members.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(members, [vec![0, 2, 4], vec![1, 3]]);
+        assert!((clusters[0].representative.estimate() - 10_400.0).abs() < 200.0);
+    }
+}
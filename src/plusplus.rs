@@ -1,34 +1,794 @@
-use std::hash::{Hash, Hasher};
-
-use packed_simd::{f64x8, u32x2, u8x16};
-use seahash::SeaHasher;
+use std::{hash::Hash, sync::Arc};
 
 #[cfg(feature = "serde_support")]
 use crate::serde::{serialize_registers, CompressedRegistersVisitor};
+#[cfg(feature = "stats")]
+use crate::stats::SketchStats;
+#[cfg(feature = "validate")]
+use crate::validate::{ShadowSet, DEFAULT_VALIDATION_LIMIT};
 #[cfg(feature = "serde_support")]
 use serde::{de::Deserializer, Deserialize, Serialize, Serializer};
 
-use crate::{ALPHA, EMPTY_REGISTERS, M, P};
+use crate::{
+    activity::SketchActivity,
+    bias::{self, BiasTable},
+    binary,
+    compare::{self, RegisterDiff},
+    dump::{self, HexDumpError},
+    dynamic::DynHyperLogLog,
+    ertl, hash,
+    health::{self, SketchHealth},
+    hll::{self, EstimateRegime, Estimator},
+    ingest,
+    labels::SketchLabels,
+    metadata::{Algorithm, HasherId, MergePolicy, SketchMetadata},
+    observer::{ObservedRegisters, RegisterObserver},
+    registers::{self, AlignedRegisters, CowRegisters, RegisterStore, Registers, RegistersMut},
+    simd, Error, ALPHA, M, MAX_RANK, P,
+};
+
+/// An enhanced HyperLogLog data structure, often termed HyperLogLog++,
+/// for estimating the cardinality of a dataset without storing individual elements.
+///
+/// Like `HyperLogLog`, the registers can be held in any `Registers` store of `M` registers.
+#[derive(Debug, Clone)]
+pub struct HyperLogLogPlusPlus<R = Box<[u8; M]>> {
+    /// Registers used for maintaining the cardinality estimate.
+    /// The number of registers (`M`) impacts precision and memory usage. Only readable
+    /// from the outside through `registers` and `register`.
+    pub(crate) registers: R,
+    /// Precision, algorithm and hasher the registers were built with.
+    metadata: SketchMetadata,
+    /// How sketches of a different precision are merged.
+    merge_policy: MergePolicy,
+    /// User metadata describing the provenance of the sketch, if any.
+    labels: Option<Box<SketchLabels>>,
+    /// Exact add count and update times, only recorded once `track_activity` was called.
+    activity: Option<SketchActivity>,
+    /// The estimator `estimate` applies to the registers.
+    estimator: Estimator,
+    /// Bias table replacing `BiasTable::empirical` for mid-range raw estimates, if any.
+    bias_table: Option<Arc<BiasTable>>,
+    /// Operational counters, only maintained with the `stats` feature.
+    #[cfg(feature = "stats")]
+    stats: SketchStats,
+    /// Exact set of added hashes, only maintained with the `validate` feature.
+    #[cfg(feature = "validate")]
+    shadow: ShadowSet,
+}
+
+impl HyperLogLogPlusPlus {
+    /// Constructs a new instance of HyperLogLog++ with all registers initialized to zero.
+    ///
+    /// # Returns
+    /// A new `HyperLogLogPlusPlus` instance.
+    #[inline(always)]
+    pub fn new() -> Self {
+        Self::from(registers::zeroed_box())
+    }
+
+    /// Creates a new HyperLogLog++ hashing items with the given hash function instead of
+    /// SeaHash, e.g. to build registers compatible with another implementation. Only
+    /// sketches using the same hasher can be merged.
+    ///
+    /// # Parameters
+    /// * `hasher`: The hash function applied to added items.
+    ///
+    /// # Returns
+    /// A new `HyperLogLogPlusPlus` instance.
+    ///
+    /// # Panics
+    /// Panics if `hasher` is `HasherId::SipHash` and no deployment key was set with
+    /// `hash::siphash::set_key`.
+    pub fn with_hasher(hasher: HasherId) -> Self {
+        let mut sketch = Self::new();
+        sketch.metadata.hasher = hasher;
+        sketch.metadata.seed = hash::default_seed(hasher);
+
+        sketch
+    }
+
+    /// Creates a new HyperLogLog++ hashing items with the hasher and seed of `metadata`,
+    /// which the caller has checked, e.g. `HllBuilder::build`.
+    pub(crate) fn with_metadata(metadata: SketchMetadata) -> Self {
+        let mut sketch = Self::new();
+        sketch.metadata = metadata;
+
+        sketch
+    }
+
+    /// Creates a new HyperLogLog++ hashing items with SeaHash keyed by `key` instead of the
+    /// default key. Producers that agree on a key build mergeable sketches, while sketches
+    /// built with different keys refuse to be merged, see `try_merge`.
+    ///
+    /// # Parameters
+    /// * `key`: The 256-bit SeaHash key, e.g. drawn at random once and shared by all
+    ///   producers.
+    ///
+    /// # Returns
+    /// A new `HyperLogLogPlusPlus` instance.
+    pub fn with_seed(key: [u64; 4]) -> Self {
+        let mut sketch = Self::new();
+        sketch.metadata.key = key;
+
+        sketch
+    }
+
+    /// Creates a new HyperLogLog++ correcting its estimates with another bias table than
+    /// the built-in `BiasTable::empirical`, e.g. one calibrated for an unusual hasher or
+    /// loaded from another system. Raw estimates up to five times the number of registers
+    /// have the interpolated bias subtracted, whenever `estimate_with_regime` does not use
+    /// linear counting. The table only applies to `estimate` with the legacy
+    /// `Estimator::Classic`, see `set_estimator`.
+    ///
+    /// The table is shared, so any number of sketches can use the same one.
+    ///
+    /// # Parameters
+    /// * `table`: The bias table, calibrated for precision `P` and the hasher in use.
+    ///
+    /// # Returns
+    /// A new `HyperLogLogPlusPlus` instance.
+    pub fn with_bias_table(table: Arc<BiasTable>) -> Self {
+        let mut sketch = Self::new();
+        sketch.bias_table = Some(table);
+
+        sketch
+    }
+
+    /// Creates a new HyperLogLog++ notifying `observer` of every register raised by an add or
+    /// a merge, see `ObservedRegisters`.
+    ///
+    /// # Parameters
+    /// * `observer`: The observer, e.g. a closure taking the index, previous value and
+    ///   new value of the raised register.
+    ///
+    /// # Returns
+    /// A new `HyperLogLogPlusPlus` instance whose registers report to `observer`.
+    pub fn with_observer<O: RegisterObserver>(
+        observer: O,
+    ) -> HyperLogLogPlusPlus<ObservedRegisters<Box<[u8; M]>, O>> {
+        HyperLogLogPlusPlus::with_registers(ObservedRegisters::new(
+            registers::zeroed_box(),
+            observer,
+        ))
+    }
+
+    /// Compares the registers of this HyperLogLog++ with another one, reporting how many
+    /// registers differ, which side holds the larger value, and the resulting gap between
+    /// the two estimates. Useful for tracking down drift between replicas that should
+    /// hold identical state.
+    ///
+    /// # Parameters
+    /// * `other`: The `HyperLogLogPlusPlus` to compare against.
+    ///
+    /// # Returns
+    /// A `RegisterDiff` summarizing the differences.
+    pub fn diff(&self, other: &HyperLogLogPlusPlus) -> RegisterDiff {
+        compare::diff(
+            self.registers.as_slice(),
+            other.registers.as_slice(),
+            self.estimate(),
+            other.estimate(),
+        )
+    }
+
+    /// Approximates how many distinct items were added after `earlier` was taken,
+    /// where `earlier` is a snapshot (clone) of this HyperLogLog++ from a previous point in time.
+    ///
+    /// The result is the estimate of the union of both sketches minus the estimate of the
+    /// snapshot, clamped at zero. Taking the union makes the result robust against a
+    /// snapshot holding registers this sketch has not seen. Note that the absolute error
+    /// is that of the two estimates combined, i.e. it scales with the total cardinality
+    /// rather than with the number of new items, so small changes on top of a large
+    /// snapshot are indistinguishable from noise.
+    ///
+    /// # Parameters
+    /// * `earlier`: A snapshot of this sketch taken at an earlier point in time.
+    ///
+    /// # Returns
+    /// A non-negative `f64` approximate count of items that appeared since `earlier`.
+    pub fn estimate_new_since(&self, earlier: &HyperLogLogPlusPlus) -> f64 {
+        let mut union = self.clone();
+        union.merge(earlier);
+
+        (union.estimate() - earlier.estimate()).max(0.0)
+    }
+
+    /// Estimates how many distinct items were added to this sketch but not to `other`,
+    /// the size of the set difference `A \ B`.
+    ///
+    /// The result is computed as `|A ∪ B| - |B|`, which equals `|A| - |A ∩ B|` by
+    /// inclusion-exclusion but needs one estimate less, and is clamped to `[0, |A|]`.
+    /// Its absolute error is that of the union and `other` estimates combined, roughly
+    /// `1.04 / sqrt(M) * (|A ∪ B| + |B|)`, so it is independent of the size of the
+    /// difference itself: differences much smaller than that bound, e.g. a few new items
+    /// between two large, nearly identical sets, cannot be told apart from zero.
+    ///
+    /// # Parameters
+    /// * `other`: The `HyperLogLogPlusPlus` whose items are removed.
+    ///
+    /// # Returns
+    /// A non-negative `f64` approximate count of items in this sketch but not in `other`.
+    pub fn difference_estimate(&self, other: &HyperLogLogPlusPlus) -> f64 {
+        let mut union = self.clone();
+        union.merge(other);
+
+        (union.estimate() - other.estimate()).clamp(0.0, self.estimate())
+    }
+
+    /// Renders the registers as a compact hex dump, one line per 64 registers with
+    /// repeated values run-length encoded (e.g. `000040: 00*12 03 01 00*50`).
+    /// Dumps of two sketches can be diffed line by line when comparing their states.
+    ///
+    /// # Returns
+    /// A `String` containing the hex dump.
+    pub fn to_hex_dump(&self) -> String {
+        dump::to_hex_dump(self.registers.as_slice())
+    }
+
+    /// Reconstructs a `HyperLogLogPlusPlus` from a dump produced by `to_hex_dump`.
+    ///
+    /// # Parameters
+    /// * `dump`: The textual hex dump.
+    ///
+    /// # Returns
+    /// The reconstructed `HyperLogLogPlusPlus`, or a `HexDumpError` describing the first problem found.
+    pub fn from_hex_dump(dump: &str) -> Result<Self, HexDumpError> {
+        dump::from_hex_dump(dump).map(Self::from)
+    }
+
+    /// Creates a `HyperLogLogPlusPlus` from a copy of raw registers, e.g. read from storage.
+    ///
+    /// # Parameters
+    /// * `bytes`: The registers, one byte each.
+    ///
+    /// # Returns
+    /// The `HyperLogLogPlusPlus`, or an `Error` if `bytes` does not hold `M` valid registers.
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let registers: Box<[u8; M]> =
+            bytes
+                .to_vec()
+                .into_boxed_slice()
+                .try_into()
+                .map_err(|_| Error::Length {
+                    expected: M,
+                    found: bytes.len(),
+                })?;
+
+        Self::try_with_registers(registers)
+    }
+
+    /// Reads a sketch written by `serialize_into`.
+    ///
+    /// # Parameters
+    /// * `bytes`: The serialized sketch.
+    ///
+    /// # Returns
+    /// The `HyperLogLogPlusPlus`, or an `Error` if `bytes` does not hold a valid HyperLogLog++ of precision
+    /// `P`.
+    pub fn deserialize_from(bytes: &[u8]) -> Result<Self, Error> {
+        let (registers, metadata) = binary::deserialize(
+            bytes,
+            SketchMetadata::new(Algorithm::HyperLogLogPlusPlus, P),
+        )?;
+        let mut sketch = Self::try_from_bytes(registers).map_err(Error::into_decode)?;
+        sketch.metadata = metadata;
+
+        Ok(sketch)
+    }
+}
+
+impl<R: Registers> HyperLogLogPlusPlus<R> {
+    /// Creates a HyperLogLog++ on top of an existing register store.
+    ///
+    /// # Parameters
+    /// * `registers`: The store, holding `M` registers.
+    ///
+    /// # Returns
+    /// A new `HyperLogLogPlusPlus` instance using `registers` as its state.
+    ///
+    /// # Panics
+    /// Panics if `registers` does not hold exactly `M` registers.
+    pub fn with_registers(registers: R) -> Self {
+        assert_eq!(registers.len(), M, "a HyperLogLog++ needs {} registers", M);
+
+        Self::with_registers_unchecked(registers)
+    }
+
+    /// Creates a HyperLogLog++ on top of a register store the caller has checked to hold `M`
+    /// registers, in constant expressions too.
+    const fn with_registers_unchecked(registers: R) -> Self {
+        HyperLogLogPlusPlus {
+            registers,
+            metadata: SketchMetadata::new(Algorithm::HyperLogLogPlusPlus, P),
+            merge_policy: MergePolicy::Fold,
+            labels: None,
+            activity: None,
+            estimator: Estimator::Ertl,
+            bias_table: None,
+            #[cfg(feature = "stats")]
+            stats: SketchStats::new(),
+            #[cfg(feature = "validate")]
+            shadow: ShadowSet::new(DEFAULT_VALIDATION_LIMIT),
+        }
+    }
+
+    /// Creates a HyperLogLog++ on top of an existing register store, checking that it holds
+    /// `M` registers and that every register holds a value `add` can produce.
+    ///
+    /// # Parameters
+    /// * `registers`: The store, holding `M` registers.
+    ///
+    /// # Returns
+    /// A new `HyperLogLogPlusPlus` instance, or an `Error` describing the problem with `registers`.
+    pub fn try_with_registers(registers: R) -> Result<Self, Error> {
+        if registers.len() != M {
+            return Err(Error::Length {
+                expected: M,
+                found: registers.len(),
+            });
+        }
+        registers::check_ranks(&registers, MAX_RANK)?;
+
+        Ok(Self::with_registers(registers))
+    }
+
+    /// Estimates the cardinality or unique count of the items added to the HyperLogLog++,
+    /// computed by the `Estimator` set with `set_estimator`, `Estimator::Ertl` by default.
+    ///
+    /// # Returns
+    /// An approximate count (as `f64`) of unique items added.
+    #[inline(always)]
+    pub fn estimate(&self) -> f64 {
+        match self.estimator {
+            Estimator::Classic => self.estimate_with_regime().0,
+            Estimator::Ertl => self.estimate_ertl(),
+        }
+    }
+
+    /// Folds the registers down to a lower precision, e.g. to move sketches of ingest
+    /// nodes into compact long-term storage. Each folded register keeps the largest rank
+    /// of the registers it collapses, so its estimates have the standard error of a
+    /// sketch of that precision, see `dynamic::error_for_precision`.
+    ///
+    /// The folded sketch records the HyperLogLog++ algorithm, so it only merges with other
+    /// folded HyperLogLog++ sketches. Bias tables are built for one precision, so its
+    /// estimates are never bias corrected.
+    ///
+    /// # Parameters
+    /// * `precision`: The precision of the folded sketch, between
+    ///   `dynamic::MIN_PRECISION` and `P`.
+    ///
+    /// # Returns
+    /// A `DynHyperLogLog` of the given precision, with the hasher and seed of this sketch.
+    ///
+    /// # Panics
+    /// Panics if `precision` is out of range.
+    pub fn fold(&self, precision: u8) -> DynHyperLogLog {
+        DynHyperLogLog::folded(&self.registers, self.metadata, precision)
+    }
+
+    /// Provides an estimate computed in single precision, summing twice as many registers
+    /// per SIMD vector as `estimate`. Meant for latency critical callers that can accept a
+    /// slightly coarser estimate.
+    ///
+    /// Like `estimate`, it applies the `Estimator` set with `set_estimator`. Ertl's
+    /// estimator works on integer register counts rather than a sum, so with
+    /// `Estimator::Ertl` this is `estimate_ertl` rounded to single precision.
+    ///
+    /// # Returns
+    /// A `f32` approximate count of unique items.
+    #[inline(always)]
+    pub fn estimate_f32(&self) -> f32 {
+        if self.estimator == Estimator::Ertl {
+            return self.estimate_ertl() as f32;
+        }
+
+        let mut sum = 0.0;
+        let mut zero_reg_count = 0;
+        self.registers.for_each_chunk(|registers| {
+            let (chunk_sum, chunk_zeros) = simd::harmonic_sum_and_zeros_f32(registers);
+            sum += chunk_sum;
+            zero_reg_count += chunk_zeros;
+        });
+
+        let m = M as f32;
+        let approx_cardinality = ALPHA as f32 * m * m / sum;
+
+        self.classic_estimate(approx_cardinality as f64, zero_reg_count, 1)
+            .0 as f32
+    }
+
+    /// Provides a rough estimate from every `stride`-th register only, for callers that
+    /// need a number in microseconds. The sampled registers form a sketch of
+    /// `M / stride` registers, whose estimate is scaled back up by `stride`, so the
+    /// standard error grows by a factor `sqrt(stride)`. Use `estimate` for final reporting.
+    ///
+    /// Like `estimate`, it applies the `Estimator` set with `set_estimator`. With
+    /// `Estimator::Classic`, the linear counting threshold and the bias table are scaled
+    /// down to the sampled registers, as the bias depends on the ratio of the raw estimate
+    /// to the number of registers.
+    ///
+    /// # Parameters
+    /// * `stride`: Distance between sampled registers, a power of two. `1` scans all of
+    ///   them and matches `estimate`.
+    ///
+    /// # Returns
+    /// A `f64` approximate count of unique items.
+    ///
+    /// # Panics
+    /// Panics if `stride` is not a power of two, or larger than the number of registers.
+    pub fn estimate_with_budget(&self, stride: usize) -> f64 {
+        if stride == 1 {
+            return self.estimate();
+        }
+
+        let estimate = match self.estimator {
+            Estimator::Classic => {
+                let (sum, zero_reg_count) = hll::strided_sums(&self.registers, stride);
+                let m = (M / stride) as f64;
+                let approx_cardinality = hll::alpha(M / stride) * m * m / sum;
+
+                self.classic_estimate(approx_cardinality, zero_reg_count, stride)
+                    .0
+            }
+            Estimator::Ertl => ertl::estimate_strided(&self.registers, stride),
+        };
+
+        estimate * stride as f64
+    }
+
+    /// Provides an estimate computed with the bias corrected estimator of HyperLogLog++,
+    /// `Estimator::Classic`, together with the branch of the estimator that produced it,
+    /// which helps explain accuracy anomalies.
+    ///
+    /// As described by Heule et al., linear counting is used up to
+    /// `bias::LINEAR_COUNTING_THRESHOLD`, then raw estimates up to five times the number
+    /// of registers have the bias interpolated from the bias table subtracted, and larger
+    /// ones are used as they are.
+    ///
+    /// # Returns
+    /// The same value as `estimate` with `Estimator::Classic`, and the `EstimateRegime` it
+    /// was computed in.
+    #[inline(always)]
+    pub fn estimate_with_regime(&self) -> (f64, EstimateRegime) {
+        let (sum, zero_reg_count) = self.register_sums();
+        let approx_cardinality: f64 = ALPHA * (M * M) as f64 / sum;
+
+        self.classic_estimate(approx_cardinality, zero_reg_count, 1)
+    }
+
+    /// Turns the raw estimate of `M / stride` registers into the estimate of
+    /// `Estimator::Classic`, see `estimate_with_regime`. For a sample of the registers,
+    /// the linear counting threshold and the bias table are scaled down by `stride`.
+    ///
+    /// # Arguments
+    ///
+    /// * `approx_cardinality`: The raw estimate of the registers.
+    /// * `zero_reg_count`: The number of registers that are zero.
+    /// * `stride`: Distance between the registers, `1` for all of them.
+    #[inline(always)]
+    fn classic_estimate(
+        &self,
+        approx_cardinality: f64,
+        zero_reg_count: usize,
+        stride: usize,
+    ) -> (f64, EstimateRegime) {
+        let m = (M / stride) as f64;
+        let scale = stride as f64;
+
+        if zero_reg_count > 0 {
+            let linear_count = m * (m / zero_reg_count as f64).ln();
+            if linear_count <= bias::LINEAR_COUNTING_THRESHOLD / scale {
+                return (linear_count, EstimateRegime::LinearCounting);
+            }
+        }
+
+        if approx_cardinality <= 5.0 * m {
+            let table = match &self.bias_table {
+                Some(table) => table,
+                None => BiasTable::empirical(),
+            };
+            return (
+                approx_cardinality - table.bias(approx_cardinality * scale) / scale,
+                EstimateRegime::BiasCorrected,
+            );
+        }
+
+        (approx_cardinality, EstimateRegime::Raw)
+    }
+
+    /// Provides an estimate computed with the improved estimator of Ertl, which works on
+    /// the register histogram in integer counts instead of summing `2^-register` per
+    /// register, and needs no switch to linear counting for small cardinalities nor a bias
+    /// table. See `ertl::estimate_histogram`. `estimate` applies it by default.
+    ///
+    /// # Returns
+    /// A `f64` approximate count of unique items.
+    pub fn estimate_ertl(&self) -> f64 {
+        ertl::estimate_registers(&self.registers)
+    }
+
+    /// Provides the raw HyperLogLog estimate, `alpha * m^2 / sum(2^-register)`, whatever
+    /// regime `estimate` would use.
+    ///
+    /// # Returns
+    /// A `f64` raw estimate.
+    pub fn raw_estimate(&self) -> f64 {
+        ALPHA * (M * M) as f64 / self.register_sums().0
+    }
+
+    /// Sums `2^-register` over all registers and counts the registers that are still zero.
+    #[inline(always)]
+    fn register_sums(&self) -> (f64, usize) {
+        let mut sum = 0.0;
+        let mut zero_reg_count = 0;
+
+        self.registers.for_each_chunk(|registers| {
+            let (chunk_sum, chunk_zeros) = simd::harmonic_sum_and_zeros(registers);
+            sum += chunk_sum;
+            zero_reg_count += chunk_zeros;
+        });
+
+        (sum, zero_reg_count)
+    }
+
+    /// Computes a fast, non-cryptographic fingerprint of the registers, so replicas can
+    /// cheaply check whether their sketches already agree before shipping deltas or full
+    /// states. Sketches with equal registers have equal fingerprints; see
+    /// `registers::fingerprint`.
+    ///
+    /// # Returns
+    /// The `u64` fingerprint of this HyperLogLog++.
+    pub fn fingerprint(&self) -> u64 {
+        registers::fingerprint(&self.registers)
+    }
+
+    /// Returns the number of bytes `serialize_into` appends for this HyperLogLog++, so
+    /// buffers can be sized up front.
+    ///
+    /// # Returns
+    /// The exact serialized size in bytes.
+    pub fn serialized_size_hint(&self) -> usize {
+        binary::serialized_size(&self.registers, &self.metadata)
+    }
+
+    /// Appends this HyperLogLog++ to `buf` in a compact binary layout (a short metadata
+    /// header followed by the raw registers, without labels) that `deserialize_from`
+    /// reads back. Unlike
+    /// the serde implementation, no intermediate buffers or strings are allocated, so a
+    /// buffer reused across snapshots makes serialization allocation free.
+    ///
+    /// # Parameters
+    /// * `buf`: The buffer the sketch is appended to.
+    pub fn serialize_into(&self, buf: &mut Vec<u8>) {
+        binary::serialize_into(&self.registers, &self.metadata, buf)
+    }
+
+    /// Reports indicators of whether the estimate can be trusted: the fraction of empty
+    /// and of nearly saturated registers, whether the estimate is within the reliable
+    /// range for the precision, and a higher precision to use if it is not.
+    ///
+    /// # Returns
+    /// The `SketchHealth` of this HyperLogLog++.
+    pub fn health(&self) -> SketchHealth {
+        health::assess(&self.registers, MAX_RANK, self.estimate())
+    }
+
+    /// Returns the precision, algorithm and hasher the registers were built with.
+    pub fn metadata(&self) -> SketchMetadata {
+        self.metadata
+    }
+
+    /// Returns the 256-bit SeaHash key items are hashed with, to be handed to other
+    /// producers building mergeable sketches with `with_seed`. Sketches using another
+    /// hasher report its seed in `metadata().seed`.
+    pub fn seed(&self) -> [u64; 4] {
+        self.metadata.key
+    }
+
+    /// Returns the precision, the number of bits used to select a register.
+    pub fn precision(&self) -> u8 {
+        self.metadata.precision
+    }
+
+    /// Returns the number of registers, `2^precision`.
+    pub fn register_count(&self) -> usize {
+        self.registers.len()
+    }
+
+    /// Returns the register store. Registers can only be changed through the methods of
+    /// the HyperLogLog++; sketches holding registers from elsewhere are built with
+    /// `try_with_registers` or `try_from_bytes`, which check them.
+    pub fn registers(&self) -> &R {
+        &self.registers
+    }
+
+    /// Returns the value of one register, the largest rank of the items mapped to it.
+    ///
+    /// # Parameters
+    /// * `index`: The index of the register, below `register_count()`.
+    ///
+    /// # Panics
+    /// Panics if `index` is out of bounds.
+    pub fn register(&self, index: usize) -> u8 {
+        self.registers.get(index)
+    }
+
+    /// Counts the registers that are not zero, i.e. that at least one added item was
+    /// mapped to.
+    ///
+    /// # Returns
+    /// The number of non-zero registers, at most `register_count()`.
+    pub fn nonzero_register_count(&self) -> usize {
+        registers::count_nonzero(&self.registers)
+    }
+
+    /// Returns the number of heap bytes used by the HyperLogLog++, i.e. by its registers
+    /// and its labels, so dashboards can report the footprint of a fleet of sketches.
+    /// Registers held inline or borrowed take no heap bytes. The bias table, shared
+    /// between sketches, is not counted.
+    pub fn memory_usage(&self) -> usize {
+        self.registers.heap_size() + self.labels.as_ref().map_or(0, |labels| labels.heap_size())
+    }
+
+    /// Returns how sketches of a different precision are merged into this one.
+    pub fn merge_policy(&self) -> MergePolicy {
+        self.merge_policy
+    }
+
+    /// Sets how sketches of a different precision are merged into this one.
+    ///
+    /// # Parameters
+    /// * `policy`: The `MergePolicy` to apply on subsequent merges.
+    pub fn set_merge_policy(&mut self, policy: MergePolicy) {
+        self.merge_policy = policy;
+    }
+
+    /// Returns the estimator `estimate` applies.
+    pub fn estimator(&self) -> Estimator {
+        self.estimator
+    }
+
+    /// Sets the estimator `estimate` applies. `Estimator::Classic` restores the bias
+    /// corrected estimator of Heule et al. that `estimate` applied before Ertl's became
+    /// the default, e.g. to keep reported numbers stable. The other estimation methods,
+    /// such as `estimate_with_regime` or `estimate_ertl`, are not affected.
+    ///
+    /// # Parameters
+    /// * `estimator`: The `Estimator` to apply on subsequent estimates.
+    pub fn set_estimator(&mut self, estimator: Estimator) {
+        self.estimator = estimator;
+    }
+
+    /// Returns the bias table set with `with_bias_table`, if any. Otherwise estimates are
+    /// corrected with `BiasTable::empirical`.
+    pub fn bias_table(&self) -> Option<&BiasTable> {
+        self.bias_table.as_deref()
+    }
+
+    /// Returns the labels describing the provenance of this HyperLogLog++, if any.
+    pub fn labels(&self) -> Option<&SketchLabels> {
+        self.labels.as_deref()
+    }
+
+    /// Attaches labels to this HyperLogLog++. They are serialized with it, and combined with
+    /// the labels of sketches merged into it, see `SketchLabels::combine`.
+    ///
+    /// # Parameters
+    /// * `labels`: The labels to attach, replacing any previous ones.
+    pub fn set_labels(&mut self, labels: SketchLabels) {
+        self.labels = Some(Box::new(labels));
+    }
+
+    /// Removes the labels of this HyperLogLog++.
+    ///
+    /// # Returns
+    /// The removed labels, if any.
+    pub fn take_labels(&mut self) -> Option<SketchLabels> {
+        self.labels.take().map(|labels| *labels)
+    }
+
+    /// Starts recording the exact number of items added to this HyperLogLog++ and when it was
+    /// last updated, from now on. The activity is serialized with the sketch, and combined
+    /// with the activity of sketches merged into it. Every add then reads the system clock.
+    pub fn track_activity(&mut self) {
+        if self.activity.is_none() {
+            self.activity = Some(SketchActivity::new());
+        }
+    }
+
+    /// Returns the activity recorded by this HyperLogLog++, or `None` unless `track_activity`
+    /// was called.
+    pub fn activity(&self) -> Option<SketchActivity> {
+        self.activity
+    }
+
+    /// Returns the fraction of the items added to this HyperLogLog++ that were distinct, see
+    /// `SketchActivity::distinct_ratio`.
+    ///
+    /// # Returns
+    /// The estimate divided by the exact add count, or `None` unless `track_activity` was
+    /// called and items were added since.
+    pub fn distinct_ratio(&self) -> Option<f64> {
+        self.activity?.distinct_ratio(self.estimate())
+    }
+
+    /// Returns the average number of times each distinct item was added to this HyperLogLog++,
+    /// see `SketchActivity::duplication_factor`.
+    ///
+    /// # Returns
+    /// The exact add count divided by the estimate, or `None` unless `track_activity` was
+    /// called and items were added since.
+    pub fn duplication_factor(&self) -> Option<f64> {
+        self.activity?.duplication_factor(self.estimate())
+    }
+
+    /// Returns the operational counters recorded by this HyperLogLog++.
+    ///
+    /// # Returns
+    /// A copy of the `SketchStats` accumulated so far.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> SketchStats {
+        self.stats
+    }
+
+    /// Computes the relative error of the current estimate against the exact number of
+    /// distinct items added, tracked alongside the registers.
+    ///
+    /// # Returns
+    /// `(estimate - exact) / exact`, or `None` when nothing was added yet or more distinct
+    /// items than the validation limit were added.
+    #[cfg(feature = "validate")]
+    pub fn observed_error(&self) -> Option<f64> {
+        self.shadow.relative_error(self.estimate())
+    }
+
+    /// Returns the exact number of distinct items added, or `None` once more than the
+    /// validation limit were added.
+    #[cfg(feature = "validate")]
+    pub fn exact_count(&self) -> Option<usize> {
+        self.shadow.exact_count()
+    }
+
+    /// Sets the number of distinct items tracked exactly, `DEFAULT_VALIDATION_LIMIT` by
+    /// default. Lowering it below the number already tracked stops tracking.
+    ///
+    /// # Parameters
+    /// * `limit`: The maximum number of distinct items to track.
+    #[cfg(feature = "validate")]
+    pub fn set_validation_limit(&mut self, limit: usize) {
+        self.shadow.set_limit(limit);
+    }
+}
+
+impl<R: RegisterStore> HyperLogLogPlusPlus<R> {
+    /// Returns the registers of the HyperLogLog++ as bytes, one per register, for stores
+    /// holding them contiguously.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.registers.as_bytes()
+    }
 
-/// An enhanced HyperLogLog data structure, often termed HyperLogLog++,
-/// for estimating the cardinality of a dataset without storing individual elements.
-#[derive(Debug, Clone)]
-pub struct HyperLogLogPlusPlus {
-    /// Registers used for maintaining the cardinality estimate.
-    /// The number of registers (`M`) impacts precision and memory usage.
-    pub registers: Box<[u8; M]>,
+    /// Counts the registers that are still zero, see `RegisterStore::zero_count`.
+    pub fn zero_register_count(&self) -> usize {
+        self.registers.zero_count()
+    }
 }
 
-impl HyperLogLogPlusPlus {
-    /// Constructs a new instance of HyperLogLog++ with all registers initialized to zero.
-    ///
-    /// # Returns
-    /// A new `HyperLogLogPlusPlus` instance.
-    #[inline(always)]
-    pub fn new() -> Self {
-        Self {
-            registers: Box::new(unsafe { EMPTY_REGISTERS.clone() }),
+impl<R: RegistersMut> HyperLogLogPlusPlus<R> {
+    /// Resets the HyperLogLog++ to its empty state, keeping its register allocation so it can
+    /// be reused instead of allocating a new one.
+    pub fn clear(&mut self) {
+        self.registers.clear();
+        if let Some(activity) = &mut self.activity {
+            *activity = SketchActivity::new();
         }
+        #[cfg(feature = "stats")]
+        {
+            self.stats = SketchStats::default();
+        }
+        #[cfg(feature = "validate")]
+        self.shadow.clear();
     }
 
     /// Adds an item to the HyperLogLog++. This will update the registers based on
@@ -38,69 +798,181 @@ impl HyperLogLogPlusPlus {
     /// * `item`: The item to be added. It should implement the `Hash` trait.
     #[inline(always)]
     pub fn add<T: Hash>(&mut self, item: T) {
-        let mut h = SeaHasher::default();
+        self.add_hash(hash::hash_item(&self.metadata, &item));
+    }
 
-        item.hash(&mut h);
+    /// Adds an item given as raw bytes, hashed with the hasher and seed of the sketch but
+    /// without the framing of their `Hash` implementation, as libraries in other languages
+    /// hash them. With `HasherId::Murmur3`, a sketch fed the same bytes with the same seed
+    /// and precision as a sketch using the first 64-bit half of MurmurHash3 x64_128, the
+    /// low bits as register index and the remaining ones for the rank, holds the same
+    /// registers.
+    ///
+    /// # Parameters
+    /// * `bytes`: The bytes of the item, e.g. the UTF-8 encoding of a string.
+    #[inline(always)]
+    pub fn add_raw<B: AsRef<[u8]>>(&mut self, bytes: B) {
+        self.add_hash(hash::hash_raw(&self.metadata, bytes.as_ref()));
+    }
 
-        let mut hash = h.finish();
+    /// Adds an item given as a byte slice, with the same result as `add(bytes)`. Keys of
+    /// at least `hash::long::LONG_KEY_LEN` bytes are hashed with the vectorized SeaHash of
+    /// `hash::long`, which is faster for long keys such as URLs.
+    ///
+    /// # Parameters
+    /// * `bytes`: The item.
+    #[inline(always)]
+    pub fn add_bytes(&mut self, bytes: &[u8]) {
+        self.add_hash(hash::hash_bytes(&self.metadata, bytes));
+    }
 
-        for _ in 0..2 {
-            let vec_hash = u32x2::new(
-                (hash & (M as u64 - 1)) as u32,
-                ((hash >> 32) & (M as u64 - 1)) as u32,
-            );
+    /// Adds an item given as a string, with the same result as `add(s)`, hashing long keys
+    /// like `add_bytes`.
+    ///
+    /// # Parameters
+    /// * `s`: The item.
+    #[inline(always)]
+    pub fn add_str(&mut self, s: &str) {
+        self.add_hash(hash::hash_str(&self.metadata, s));
+    }
 
-            let vec_w = u32x2::new((hash >> P) as u32, (hash >> (32 + P)) as u32);
-            let vec_rank = vec_w.min_element().leading_zeros() as u8 + 1;
-            let max_index = vec_hash.extract(0) as usize;
+    /// Adds every byte slice yielded by an iterator to the HyperLogLog++. The slices are
+    /// hashed in place, without copying them into owned values, and the register updates
+    /// are applied in batches. Each slice is added exactly as `add(slice)` would.
+    ///
+    /// # Parameters
+    /// * `it`: An iterator over borrowed byte slices, e.g. fields yielded by a parser.
+    pub fn add_iter_bytes<'a>(&mut self, it: impl Iterator<Item = &'a [u8]>) {
+        let metadata = self.metadata;
+        ingest::for_each_hash_batch(it, &metadata, |hashes| {
+            for &hash in hashes {
+                self.add_hash(hash);
+            }
+        });
+    }
 
-            if self.registers[max_index] < vec_rank {
-                self.registers[max_index] = vec_rank;
+    /// Adds every item yielded by an iterator to the HyperLogLog++, with the same registers as
+    /// calling `add` for every one of them. Items are hashed a chunk at a time and the
+    /// register updates of a chunk are applied in register order, which keeps bulk loads
+    /// from missing the cache on nearly every update. Since updates are reordered, the
+    /// number of register-raising adds counted by `stats` may differ from item-at-a-time
+    /// adds.
+    ///
+    /// # Parameters
+    /// * `items`: The items to add, e.g. a collection or an iterator over one.
+    pub fn add_all<T: Hash, I: IntoIterator<Item = T>>(&mut self, items: I) {
+        let metadata = self.metadata;
+        ingest::for_each_sorted_hash_chunk(items, &metadata, |hashes| {
+            for &hash in hashes {
+                self.add_hash(hash);
             }
+        });
+    }
 
-            hash = hash.wrapping_shr(64);
-        }
+    /// Adds a slice of integers to the HyperLogLog++, with the same result as calling `add` for
+    /// every one of them. The integers are hashed a batch at a time, four per iteration
+    /// with the default SeaHash hasher, before the register updates of the batch are
+    /// applied in one pass.
+    ///
+    /// # Parameters
+    /// * `items`: The integers to add.
+    pub fn add_batch(&mut self, items: &[u64]) {
+        let metadata = self.metadata;
+        ingest::for_each_slice_hash_batch(
+            items,
+            |items, hashes| hash::batch::hash_u64s(&metadata, items, hashes),
+            |hashes| {
+                for &hash in hashes {
+                    self.add_hash(hash);
+                }
+            },
+        );
     }
 
-    /// Estimates the cardinality or unique count of the items added to the HyperLogLog++.
+    /// Adds a slice of strings to the HyperLogLog++, with the same result as calling `add` for
+    /// every one of them. The strings are hashed a batch at a time, four short strings per
+    /// iteration with the default SeaHash hasher, before the register updates of the batch
+    /// are applied in one pass.
     ///
-    /// # Returns
-    /// An approximate count (as `f64`) of unique items added.
-    #[inline(always)]
-    pub fn estimate(&self) -> f64 {
-        let mut acc_sum = f64x8::splat(0.0);
-        let len = self.registers.len();
-        let simd_iteration_count = len / 8;
+    /// # Parameters
+    /// * `items`: The strings to add, e.g. `&[&str]` or `&[String]`.
+    pub fn add_batch_strs<S: AsRef<str>>(&mut self, items: &[S]) {
+        let metadata = self.metadata;
+        ingest::for_each_slice_hash_batch(
+            items,
+            |items, hashes| hash::batch::hash_strs(&metadata, items, hashes),
+            |hashes| {
+                for &hash in hashes {
+                    self.add_hash(hash);
+                }
+            },
+        );
+    }
 
-        for i in 0..simd_iteration_count {
-            let chunk = self.registers[i * 8..(i + 1) * 8]
-                .iter()
-                .map(|&x| x as f64)
-                .collect::<Vec<f64>>();
-            let vector = f64x8::from_slice_unaligned(&chunk);
-            acc_sum += f64x8::splat(2.0).powf(-vector);
-        }
+    /// Adds a slice of byte slices to the HyperLogLog++, with the same result as calling
+    /// `add_bytes` for every one of them. The slices are hashed a batch at a time before
+    /// the register updates of the batch are applied in one pass, see `add_iter_bytes`.
+    ///
+    /// # Parameters
+    /// * `items`: The byte slices to add, e.g. `&[&[u8]]` or `&[Vec<u8>]`.
+    pub fn add_batch_bytes<B: AsRef<[u8]>>(&mut self, items: &[B]) {
+        self.add_iter_bytes(items.iter().map(AsRef::as_ref));
+    }
+
+    /// Adds an item already hashed to 64 bits upstream, e.g. by a columnar engine, skipping
+    /// the hasher of the HyperLogLog++. The low bits of the hash select the register and the
+    /// rank is taken from the remaining ones, so the hash should be uniformly distributed
+    /// over all 64 bits. Sketches fed this way can only be merged with sketches fed hashes
+    /// of the same function, which their metadata cannot tell.
+    ///
+    /// # Parameters
+    /// * `hash`: The 64-bit hash of the item.
+    #[inline(always)]
+    pub fn add_hashed(&mut self, hash: u64) {
+        self.add_hash(hash);
+    }
 
-        let rem = len % 8;
+    /// Adds an item already hashed to 128 bits, e.g. with MurmurHash3 x64_128, splitting
+    /// the hash into the register index and rank as described by `hash::wide::split`.
+    ///
+    /// # Parameters
+    /// * `hash`: The 128-bit hash of the item.
+    #[inline(always)]
+    pub fn add_hashed128(&mut self, hash: u128) {
+        let (j, rho) = hash::wide::split(hash, P);
 
-        if rem > 0 {
-            let chunk = self.registers[len - rem..]
-                .iter()
-                .map(|&x| x as f64)
-                .collect::<Vec<f64>>();
-            let vector = f64x8::from_slice_unaligned(&chunk);
-            acc_sum += f64x8::splat(2.0).powf(-vector);
+        #[cfg(feature = "stats")]
+        self.stats.record_add(rho > self.registers.get(j));
+        #[cfg(feature = "validate")]
+        self.shadow.record(hash::wide::fold(hash));
+        if let Some(activity) = &mut self.activity {
+            activity.record_add();
         }
 
-        let harmonic_mean: f64 = 1.0 / acc_sum.sum();
-        let approx_cardinality: f64 = ALPHA * (M * M) as f64 * harmonic_mean;
-        let zero_reg_count: f64 = self.registers.iter().filter(|&rank| *rank == 0).count() as f64;
+        self.registers.update_max(j, rho);
+    }
+
+    /// Updates the registers for an already hashed item. The low `P` bits of the hash
+    /// select the register and the rank is taken from all `64 - P` remaining bits, see
+    /// `hll::rank`, so ranks are not capped below `MAX_RANK` and estimates stay unbiased
+    /// far beyond 2^32 items.
+    ///
+    /// # Parameters
+    /// * `hash`: The 64-bit hash of the item.
+    #[inline(always)]
+    pub(crate) fn add_hash(&mut self, hash: u64) {
+        let j = hash as usize & (M - 1);
+        let rho = hll::rank(hash, P);
 
-        if approx_cardinality <= 2.5 * M as f64 && zero_reg_count > 0.0 {
-            M as f64 * (M as f64 / zero_reg_count).ln()
-        } else {
-            approx_cardinality
+        #[cfg(feature = "stats")]
+        self.stats.record_add(rho > self.registers.get(j));
+        #[cfg(feature = "validate")]
+        self.shadow.record(hash);
+        if let Some(activity) = &mut self.activity {
+            activity.record_add();
         }
+
+        self.registers.update_max(j, rho);
     }
 
     /// Merges the state of another HyperLogLog++ instance into this one.
@@ -108,25 +980,47 @@ impl HyperLogLogPlusPlus {
     ///
     /// # Parameters
     /// * `other`: The other `HyperLogLogPlusPlus` instance whose state is to be merged into this one.
+    ///
+    /// # Panics
+    /// Panics if the sketches were built with different metadata, see `try_merge`.
     #[inline(always)]
-    pub fn merge(&mut self, other: &HyperLogLogPlusPlus) {
-        const CHUNKS: usize = M / 16; // This needs to be a const
-
-        unsafe {
-            let self_regs =
-                std::slice::from_raw_parts_mut(self.registers.as_mut_ptr() as *mut u8x16, CHUNKS);
-            let other_regs =
-                std::slice::from_raw_parts(other.registers.as_ptr() as *const u8x16, CHUNKS);
+    pub fn merge<O: Registers>(&mut self, other: &HyperLogLogPlusPlus<O>) {
+        if let Err(e) = self.try_merge(other) {
+            panic!("{}", e);
+        }
+    }
 
-            for i in 0..CHUNKS {
-                self_regs[i] = self_regs[i].max(other_regs[i]);
+    /// Merges another HyperLogLog++ into this one after checking that both were built with
+    /// the same precision, algorithm, hasher and seed. Under `MergePolicy::Fold`, a sketch
+    /// of higher precision is folded down to the precision of this one instead.
+    ///
+    /// # Parameters
+    /// * `other`: A reference to another `HyperLogLogPlusPlus` instance to be merged.
+    ///
+    /// # Returns
+    /// `Ok(())` once merged, or an `Error::Incompatible` leaving this sketch untouched.
+    #[inline(always)]
+    pub fn try_merge<O: Registers>(&mut self, other: &HyperLogLogPlusPlus<O>) -> Result<(), Error> {
+        match self.metadata.check_compatible(&other.metadata) {
+            Ok(()) => self.registers.merge_max(&other.registers),
+            Err(e) if self.merge_policy == MergePolicy::Fold && e.is_foldable() => {
+                registers::fold_max(&other.registers, &mut self.registers)
             }
+            Err(e) => return Err(e.into()),
         }
 
-        // If M is not a multiple of 16, process remaining elements
-        for i in (CHUNKS * 16)..M {
-            self.registers[i] = std::cmp::max(self.registers[i], other.registers[i]);
+        if let (Some(labels), Some(other)) = (&mut self.labels, &other.labels) {
+            labels.combine(other);
         }
+        if let (Some(activity), Some(other)) = (&mut self.activity, &other.activity) {
+            activity.record_merge(other);
+        }
+        #[cfg(feature = "stats")]
+        self.stats.record_merge(&other.stats);
+        #[cfg(feature = "validate")]
+        self.shadow.record_merge(&other.shadow);
+
+        Ok(())
     }
 }
 
@@ -154,9 +1048,130 @@ impl From<[u8; M]> for HyperLogLogPlusPlus {
     /// * `registers`: An array of `u8` representing the internal state
     ///   of the HyperLogLogPlusPlus.
     fn from(registers: [u8; M]) -> Self {
-        HyperLogLogPlusPlus {
-            registers: Box::new(registers),
-        }
+        Self::from(Box::new(registers))
+    }
+}
+
+impl From<Box<[u8; M]>> for HyperLogLogPlusPlus {
+    /// Creates a `HyperLogLogPlusPlus` instance from an already boxed array of registers,
+    /// avoiding a copy of the register array.
+    ///
+    /// # Arguments
+    ///
+    /// * `registers`: A boxed array of `u8` representing the internal state
+    ///   of the HyperLogLogPlusPlus.
+    fn from(registers: Box<[u8; M]>) -> Self {
+        Self::with_registers(registers)
+    }
+}
+
+impl<T: Hash, R: RegistersMut> Extend<T> for HyperLogLogPlusPlus<R> {
+    /// Adds every item of an iterator to the HyperLogLog++ through the bulk path of `add_all`,
+    /// e.g. `hll.extend(lines)`.
+    ///
+    /// # Arguments
+    ///
+    /// * `iter`: The items to add.
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.add_all(iter)
+    }
+}
+
+/// A `HyperLogLogPlusPlus` holding its registers inline instead of boxed, so a sketch is a plain
+/// value without an allocation of its own and arrays of sketches are laid out
+/// contiguously. Only available with the `p12` and `p14` features, where the registers
+/// take at most 16 KiB; larger values would risk overflowing the stack.
+#[cfg(any(feature = "p12", feature = "p14"))]
+pub type InlineHyperLogLogPlusPlus = HyperLogLogPlusPlus<[u8; M]>;
+
+#[cfg(any(feature = "p12", feature = "p14"))]
+impl InlineHyperLogLogPlusPlus {
+    /// Creates a new HyperLogLog++ with inline registers, all initialized to zero.
+    ///
+    /// # Returns
+    /// A new `InlineHyperLogLogPlusPlus` instance.
+    pub const fn new_inline() -> Self {
+        Self::new_const()
+    }
+}
+
+impl HyperLogLogPlusPlus<[u8; M]> {
+    /// Creates a new HyperLogLog++ with inline registers, all initialized to zero, in a
+    /// constant expression, e.g. the initializer of a `static` holding a process-wide
+    /// sketch behind a `Mutex`. Outside of statics, prefer `new`: with the default
+    /// precision, the registers take 1 MiB, which would risk overflowing the stack.
+    ///
+    /// # Returns
+    /// A new `HyperLogLogPlusPlus<[u8; M]>` instance.
+    pub const fn new_const() -> Self {
+        Self::with_registers_unchecked([0; M])
+    }
+}
+
+#[cfg(any(feature = "p12", feature = "p14"))]
+impl Default for InlineHyperLogLogPlusPlus {
+    fn default() -> Self {
+        Self::new_inline()
+    }
+}
+
+/// A `HyperLogLogPlusPlus` whose registers are allocated on a 64-byte boundary, so the SIMD merge
+/// and estimate kernels use aligned loads and stores on them. See `AlignedRegisters`.
+pub type AlignedHyperLogLogPlusPlus = HyperLogLogPlusPlus<Box<AlignedRegisters<M>>>;
+
+impl AlignedHyperLogLogPlusPlus {
+    /// Creates a new HyperLogLog++ with aligned registers, all initialized to zero.
+    ///
+    /// # Returns
+    /// A new `AlignedHyperLogLogPlusPlus` instance.
+    pub fn new_aligned() -> Self {
+        Self::with_registers(AlignedRegisters::new_boxed())
+    }
+}
+
+impl Default for AlignedHyperLogLogPlusPlus {
+    fn default() -> Self {
+        Self::new_aligned()
+    }
+}
+
+/// A `HyperLogLogPlusPlus` whose clones share their registers until one of them raises a register, so
+/// snapshots for concurrent readers are cheap. See `CowRegisters`.
+pub type CowHyperLogLogPlusPlus = HyperLogLogPlusPlus<CowRegisters>;
+
+impl CowHyperLogLogPlusPlus {
+    /// Creates a new copy-on-write HyperLogLog++ with all registers initialized to zero.
+    ///
+    /// # Returns
+    /// A new `CowHyperLogLogPlusPlus` instance.
+    pub fn new_cow() -> Self {
+        Self::with_registers(CowRegisters::new(M))
+    }
+}
+
+impl Default for CowHyperLogLogPlusPlus {
+    fn default() -> Self {
+        Self::new_cow()
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+impl<A: std::alloc::Allocator> HyperLogLogPlusPlus<Box<[u8; M], A>> {
+    /// Creates a new HyperLogLog++ whose registers are allocated by `alloc`, e.g. an arena or
+    /// bump allocator, for services creating and dropping sketches at a rate where the
+    /// global allocator becomes the bottleneck. Only available with the `allocator_api`
+    /// feature, which needs a nightly toolchain.
+    ///
+    /// # Parameters
+    /// * `alloc`: The allocator of the register block.
+    ///
+    /// # Returns
+    /// A new `HyperLogLogPlusPlus` instance with all registers initialized to zero.
+    pub fn new_in(alloc: A) -> Self {
+        // SAFETY: zeroed bytes are valid registers
+        let registers = unsafe { Box::<[u8; M], A>::new_zeroed_in(alloc).assume_init() };
+
+        Self::with_registers(registers)
     }
 }
 
@@ -170,7 +1185,13 @@ impl Serialize for HyperLogLogPlusPlus {
     where
         S: Serializer,
     {
-        serialize_registers(&self.registers, serializer)
+        serialize_registers(
+            self.registers.as_slice(),
+            &self.metadata,
+            self.labels.as_deref(),
+            self.activity.as_ref(),
+            serializer,
+        )
     }
 }
 
@@ -180,12 +1201,22 @@ impl<'de> Deserialize<'de> for HyperLogLogPlusPlus {
     ///
     /// The data is expected to contain a `registers` field in a specific
     /// serialized format. The `CompressedRegistersVisitor` is used to assist
-    /// in this deserialization process.
+    /// in this deserialization process. Payloads recording a different precision,
+    /// algorithm or hasher are rejected.
     fn deserialize<D>(deserializer: D) -> Result<HyperLogLogPlusPlus, D::Error>
     where
         D: Deserializer<'de>,
     {
-        deserializer.deserialize_map(CompressedRegistersVisitor::new())
+        let (registers, metadata, labels, activity) = deserializer.deserialize_map(
+            CompressedRegistersVisitor::new(SketchMetadata::new(Algorithm::HyperLogLogPlusPlus, P)),
+        )?;
+
+        Ok(HyperLogLogPlusPlus {
+            metadata,
+            labels: labels.map(Box::new),
+            activity,
+            ..Self::from(registers)
+        })
     }
 }
 
@@ -218,7 +1249,8 @@ mod tests {
 
         let estimate = hllpp.estimate();
         assert_eq!(
-            estimate as usize, 1,
+            estimate.round() as usize,
+            1,
             "Estimate should be 1 for identical elements"
         );
     }
@@ -265,6 +1297,84 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_rank_uses_full_hash() {
+        let mut hllpp = HyperLogLogPlusPlus::new();
+        hllpp.add_hashed(5);
+        hllpp.add_hashed(1 << 63 | 7);
+        hllpp.add_hashed(1 << 40 | 9);
+
+        assert_eq!(hllpp.register(5), MAX_RANK);
+        assert_eq!(hllpp.register(7), 1);
+        assert_eq!(hllpp.register(9), 24);
+        assert_eq!(hllpp.zero_register_count(), M - 3);
+    }
+
+    #[test]
+    fn test_raw_range_accuracy() {
+        // Past 5 * M items, the raw estimate is unbiased and only as good as the ranks
+        let mut hllpp = HyperLogLogPlusPlus::new();
+        let mut hll = crate::HyperLogLog::new();
+        for i in 0..6_000_000u64 {
+            let hash = seahash::hash(&i.to_le_bytes());
+            hllpp.add_hashed(hash);
+            hll.add_hashed(hash);
+        }
+
+        let error = (hllpp.estimate() - 6_000_000.0).abs() / 6_000_000.0;
+        assert!(error < 0.01, "relative error {}", error);
+        assert_eq!(hllpp.as_bytes(), hll.as_bytes());
+    }
+
+    #[test]
+    fn test_bias_corrected_range_accuracy() {
+        // Between 2.5 * M and 5 * M items, the raw estimate is too large by a few percent
+        // and linear counting runs out of zero registers
+        let n = 3 * M as u64;
+        let mut hllpp = HyperLogLogPlusPlus::new();
+        for i in 0..n {
+            hllpp.add_hashed(seahash::hash(&i.to_le_bytes()));
+        }
+
+        let (estimate, regime) = hllpp.estimate_with_regime();
+        assert_eq!(regime, EstimateRegime::BiasCorrected);
+        let error = (estimate - n as f64).abs() / n as f64;
+        assert!(
+            error < 4.0 * 1.04 / (M as f64).sqrt(),
+            "relative error {}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_coarse_estimates_follow_estimator() {
+        let n = 3 * M as u64;
+        let mut hllpp = HyperLogLogPlusPlus::new();
+        for i in 0..n {
+            hllpp.add_hashed(seahash::hash(&i.to_le_bytes()));
+        }
+
+        for estimator in [Estimator::Ertl, Estimator::Classic] {
+            hllpp.set_estimator(estimator);
+            let estimate = hllpp.estimate();
+
+            let f32_error = (hllpp.estimate_f32() as f64 - estimate).abs() / estimate;
+            assert!(f32_error < 1e-4, "{:?}: f32 error {}", estimator, f32_error);
+
+            // Sampling 1 register in 4 doubles the standard error
+            let sampled = hllpp.estimate_with_budget(4);
+            let error = (sampled - n as f64).abs() / n as f64;
+            let bound = 4.0 * 1.04 / ((M / 4) as f64).sqrt();
+            assert!(
+                error < bound,
+                "{:?}: sampled estimate {}, relative error {}",
+                estimator,
+                sampled,
+                error
+            );
+        }
+    }
+
     #[test]
     fn test_merge() {
         let mut hll1 = HyperLogLogPlusPlus::new();
@@ -279,4 +1389,217 @@ mod tests {
 
         assert_eq!(hll1.estimate().round() as u32, 4);
     }
+
+    #[test]
+    fn test_estimate_new_since() {
+        let mut hllpp = HyperLogLogPlusPlus::new();
+        for i in 0..10_000 {
+            hllpp.add(i);
+        }
+
+        let snapshot = hllpp.clone();
+        assert_eq!(hllpp.estimate_new_since(&snapshot), 0.0);
+
+        for i in 10_000..15_000 {
+            hllpp.add(i);
+        }
+
+        let new_items = hllpp.estimate_new_since(&snapshot);
+        assert!(
+            (new_items - 5_000.0).abs() < 4.0 * 1.04 / (M as f64).sqrt() * (15_000.0 + 10_000.0),
+            "Estimate {} out of expected range",
+            new_items
+        );
+        assert_eq!(snapshot.estimate_new_since(&hllpp), 0.0);
+    }
+
+    #[test]
+    fn test_estimator() {
+        let mut hllpp = HyperLogLogPlusPlus::new();
+        for i in 0..200_000 {
+            hllpp.add(i);
+        }
+        assert_eq!(hllpp.estimator(), Estimator::Ertl);
+        assert_eq!(hllpp.estimate(), hllpp.estimate_ertl());
+
+        let error = (hllpp.estimate() - 200_000.0).abs() / 200_000.0;
+        let bound = 4.0 * 1.04 / (M as f64).sqrt();
+        assert!(error < bound, "relative error {}", error);
+
+        hllpp.set_estimator(Estimator::Classic);
+        assert_eq!(hllpp.estimate(), hllpp.estimate_with_regime().0);
+    }
+
+    #[test]
+    fn test_bias_table() {
+        let table = BiasTable::new(vec![0.0, 1e9], vec![1_000.0, 1_000.0]).unwrap();
+        let mut hllpp = HyperLogLogPlusPlus::with_bias_table(Arc::new(table));
+
+        // Every register at 2 puts the raw estimate at 4 * ALPHA * M, within the corrected range
+        hllpp.registers.fill(2);
+        let (estimate, regime) = hllpp.estimate_with_regime();
+        assert_eq!(regime, EstimateRegime::BiasCorrected);
+        assert_eq!(estimate, hllpp.raw_estimate() - 1_000.0);
+
+        hllpp.registers.fill(4);
+        assert_eq!(hllpp.estimate_with_regime().1, EstimateRegime::Raw);
+
+        hllpp.clear();
+        hllpp.add(1);
+        assert_eq!(
+            hllpp.estimate_with_regime().1,
+            EstimateRegime::LinearCounting
+        );
+    }
+
+    #[test]
+    fn test_add_hashed() {
+        let mut hll = HyperLogLogPlusPlus::new();
+        for i in 0..10_000u64 {
+            hll.add_hashed(seahash::hash(&i.to_le_bytes()));
+        }
+
+        let error = (hll.estimate() - 10_000.0).abs() / 10_000.0;
+        assert!(
+            error < 4.0 * 1.04 / (M as f64).sqrt(),
+            "relative error {}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_add_all() {
+        let (mut bulk, mut hll) = (HyperLogLogPlusPlus::new(), HyperLogLogPlusPlus::new());
+        bulk.add_all((0..10_000u64).map(|i| i.to_string()));
+        for i in 0..10_000u64 {
+            hll.add(i.to_string());
+        }
+
+        assert_eq!(bulk.as_bytes(), hll.as_bytes());
+    }
+
+    #[test]
+    fn test_extend() {
+        let mut hll = HyperLogLogPlusPlus::new();
+        hll.extend((0..1_000).map(|i| i % 100));
+
+        let mut expected = HyperLogLogPlusPlus::new();
+        for i in 0..100 {
+            expected.add(i);
+        }
+        assert_eq!(hll.as_bytes(), expected.as_bytes());
+    }
+
+    #[test]
+    fn test_add_batch() {
+        let items = (0..10_000u64).collect::<Vec<_>>();
+        let (mut batched, mut hll) = (HyperLogLogPlusPlus::new(), HyperLogLogPlusPlus::new());
+        batched.add_batch(&items);
+        batched.add_batch_bytes(&[b"a", b"b"]);
+        for item in &items {
+            hll.add(item);
+        }
+        hll.add_bytes(b"a");
+        hll.add_bytes(b"b");
+
+        assert_eq!(batched.as_bytes(), hll.as_bytes());
+    }
+
+    #[test]
+    fn test_add_batch_strs() {
+        let words = [
+            "the", "quick", "brown", "fox", "jumps", "over", "the", "lazy", "dog",
+        ];
+        let (mut batched, mut hll) = (HyperLogLogPlusPlus::new(), HyperLogLogPlusPlus::new());
+        batched.add_batch_strs(&words);
+        for word in words {
+            hll.add(word);
+        }
+
+        assert_eq!(batched.as_bytes(), hll.as_bytes());
+    }
+
+    #[test]
+    fn test_add_raw() {
+        let (mut raw, mut hashed) = (HyperLogLogPlusPlus::new(), HyperLogLogPlusPlus::new());
+        for i in 0..10_000 {
+            let key = format!("user-{}", i);
+            raw.add_raw(&key);
+            hashed.add_hashed(seahash::hash(key.as_bytes()));
+        }
+
+        assert_eq!(raw.as_bytes(), hashed.as_bytes());
+    }
+
+    #[test]
+    fn test_new_const() {
+        static SKETCH: std::sync::Mutex<HyperLogLogPlusPlus<[u8; M]>> =
+            std::sync::Mutex::new(HyperLogLogPlusPlus::new_const());
+
+        let mut expected = HyperLogLogPlusPlus::new();
+        for i in 0..1_000 {
+            SKETCH.lock().unwrap().add(i);
+            expected.add(i);
+        }
+
+        let sketch = SKETCH.lock().unwrap();
+        assert_eq!(sketch.metadata(), expected.metadata());
+        assert_eq!(sketch.estimate(), expected.estimate());
+    }
+
+    #[test]
+    fn test_introspection() {
+        let mut hllpp = HyperLogLogPlusPlus::new();
+        for i in 0..100 {
+            hllpp.add(i);
+        }
+
+        assert_eq!(hllpp.precision() as usize, P);
+        assert_eq!(hllpp.register_count(), M);
+        assert!((95..=100).contains(&hllpp.nonzero_register_count()));
+        assert_eq!(hllpp.memory_usage(), M);
+    }
+
+    #[test]
+    fn test_fold() {
+        let mut hllpp = HyperLogLogPlusPlus::new();
+        for i in 0..2_000 {
+            hllpp.add(i);
+        }
+
+        let folded = hllpp.fold(12);
+        assert_eq!(folded.precision(), 12);
+        assert_eq!(folded.metadata().algorithm, Algorithm::HyperLogLogPlusPlus);
+        let estimate = folded.estimate();
+        assert!(
+            (estimate - 2_000.0).abs() / 2_000.0 < 4.0 * crate::error_for_precision(12),
+            "estimate {}",
+            estimate
+        );
+
+        // Folds of plain HyperLogLogs are not merged with those of HyperLogLog++
+        assert!(folded
+            .clone()
+            .try_merge(&crate::DynHyperLogLog::new(12))
+            .is_err());
+    }
+
+    #[cfg(feature = "serde_support")]
+    #[test]
+    fn test_deserialize_checks_metadata() {
+        let mut hllpp = HyperLogLogPlusPlus::new();
+        for i in 0..1_000 {
+            hllpp.add(i);
+        }
+
+        let json = serde_json::to_string(&hllpp).unwrap();
+        let err = serde_json::from_str::<crate::HyperLogLog>(&json).unwrap_err();
+        assert!(err.to_string().contains("algorithm hll vs hll++"));
+
+        // Payloads written before the metadata was recorded only hold the registers
+        let legacy = format!("{{{}", &json[json.find("\"registers\"").unwrap()..]);
+        let decoded: HyperLogLogPlusPlus = serde_json::from_str(&legacy).unwrap();
+        assert!(decoded.diff(&hllpp).is_identical());
+        assert_eq!(decoded.metadata(), hllpp.metadata());
+    }
 }
@@ -0,0 +1,115 @@
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use crate::{HyperLogLog, Sketch};
+
+/// Number of idle sketches a pool created with `SketchPool::default` keeps.
+pub const DEFAULT_MAX_IDLE: usize = 16;
+
+/// A thread-safe pool of cleared sketches, so per-request or per-window sketches can reuse
+/// the register buffers of earlier ones instead of allocating and zeroing new ones.
+///
+/// Sketches are cleared when they are returned with `put`, and at most `max_idle` of them
+/// are kept; any further returned sketch is dropped. Lock poisoning is ignored, since a
+/// panic cannot leave the list of idle sketches in an inconsistent state.
+#[derive(Debug)]
+pub struct SketchPool<S = HyperLogLog> {
+    idle: Mutex<Vec<S>>,
+    max_idle: usize,
+}
+
+impl<S: Sketch + Default> SketchPool<S> {
+    /// Creates an empty pool keeping at most `max_idle` sketches.
+    ///
+    /// # Parameters
+    /// * `max_idle`: The maximum number of idle sketches kept for reuse.
+    ///
+    /// # Returns
+    /// A new `SketchPool` instance.
+    pub fn new(max_idle: usize) -> Self {
+        Self {
+            idle: Mutex::new(Vec::new()),
+            max_idle,
+        }
+    }
+
+    /// Takes an empty sketch from the pool, or creates a new one if the pool is empty.
+    ///
+    /// # Returns
+    /// An empty sketch.
+    pub fn get(&self) -> S {
+        self.lock().pop().unwrap_or_default()
+    }
+
+    /// Clears a sketch and returns it to the pool, unless the pool already holds
+    /// `max_idle` sketches.
+    ///
+    /// # Parameters
+    /// * `sketch`: The sketch to recycle.
+    pub fn put(&self, mut sketch: S) {
+        if self.idle() >= self.max_idle {
+            return;
+        }
+
+        // Clear before taking the lock, it is the expensive part
+        sketch.clear();
+
+        let mut idle = self.lock();
+        if idle.len() < self.max_idle {
+            idle.push(sketch);
+        }
+    }
+
+    /// Returns the number of sketches currently waiting for reuse.
+    pub fn idle(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Returns the maximum number of idle sketches kept for reuse.
+    pub fn max_idle(&self) -> usize {
+        self.max_idle
+    }
+
+    /// Drops all idle sketches, releasing their registers, e.g. once a burst of requests
+    /// is over. The pool keeps recycling sketches returned afterwards.
+    pub fn compact(&self) {
+        let mut idle = self.lock();
+        idle.clear();
+        idle.shrink_to_fit();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<S>> {
+        self.idle.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<S: Sketch + Default> Default for SketchPool<S> {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_IDLE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reuse() {
+        let pool = SketchPool::<HyperLogLog>::new(1);
+        let mut first = pool.get();
+        first.add("item");
+        let buffer = first.registers.as_ptr();
+
+        pool.put(first);
+        pool.put(HyperLogLog::new());
+        assert_eq!(pool.idle(), 1);
+
+        let second = pool.get();
+        assert_eq!(second.registers.as_ptr(), buffer);
+        assert_eq!(second.estimate(), 0.0);
+        assert_eq!(pool.idle(), 0);
+
+        pool.put(second);
+        pool.compact();
+        assert_eq!(pool.idle(), 0);
+    }
+}
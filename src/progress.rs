@@ -0,0 +1,209 @@
+use std::{
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+use crate::sketch::Sketch;
+
+/// Number of items added between two reads of the clock by a `ProgressSketch` emitting at
+/// an `EmitEvery::Interval`, so that monitoring does not cost a clock read per item.
+pub const CLOCK_CHECK_ITEMS: u64 = 1024;
+
+/// How often a `ProgressSketch` emits its estimate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmitEvery {
+    /// After every given number of added items.
+    Items(u64),
+    /// Once at least the given time has passed since the last emission. The clock is
+    /// only read every `CLOCK_CHECK_ITEMS` items, so emissions are late by up to that
+    /// many items, and ingestion pausing altogether pauses emissions too.
+    Interval(Duration),
+}
+
+/// Wraps a sketch and calls a callback with its current estimate every N added items or
+/// every T duration, to monitor the progress and convergence of long ingestion jobs
+/// without interleaving manual `estimate` calls with the ingestion loop.
+///
+/// `ProgressSketch` implements `Sketch`, so it can be handed to the `ingest` helpers,
+/// and wrapping a `&mut` sketch monitors a sketch owned elsewhere.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut sketch = HyperLogLog::new().on_estimate(EmitEvery::Items(1_000_000), |estimate| {
+///     println!("{} distinct so far", estimate)
+/// });
+/// ingest_csv_column(file, 0, &mut sketch)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct ProgressSketch<S, F> {
+    sketch: S,
+    every: EmitEvery,
+    on_estimate: F,
+    pending: u64,
+    last_emitted: Instant,
+}
+
+impl<S: Sketch, F: FnMut(f64)> ProgressSketch<S, F> {
+    /// Wraps a sketch.
+    ///
+    /// # Parameters
+    /// * `sketch`: The sketch items are added to.
+    /// * `every`: How often the estimate is emitted.
+    /// * `on_estimate`: Called with the estimate of `sketch` at every emission.
+    ///
+    /// # Returns
+    /// A new `ProgressSketch` instance.
+    ///
+    /// # Panics
+    /// Panics if `every` is `EmitEvery::Items(0)`.
+    pub fn new(sketch: S, every: EmitEvery, on_estimate: F) -> Self {
+        assert!(
+            every != EmitEvery::Items(0),
+            "estimates must be emitted after at least one item"
+        );
+
+        Self {
+            sketch,
+            every,
+            on_estimate,
+            pending: 0,
+            last_emitted: Instant::now(),
+        }
+    }
+
+    /// Adds an item to the wrapped sketch, emitting the estimate if it is due.
+    ///
+    /// # Parameters
+    /// * `item`: An item that implements the `Hash` trait to be added.
+    #[inline(always)]
+    pub fn add<T: Hash>(&mut self, item: T) {
+        self.sketch.add(item);
+        self.pending += 1;
+
+        let due = match self.every {
+            EmitEvery::Items(items) => self.pending >= items,
+            EmitEvery::Interval(interval) => {
+                self.pending.is_multiple_of(CLOCK_CHECK_ITEMS)
+                    && self.last_emitted.elapsed() >= interval
+            }
+        };
+        if due {
+            self.emit();
+        }
+    }
+
+    /// Calls the callback with the current estimate right away, e.g. once ingestion is
+    /// over, and restarts the count towards the next emission.
+    pub fn emit(&mut self) {
+        (self.on_estimate)(self.sketch.estimate());
+        self.pending = 0;
+        self.last_emitted = Instant::now();
+    }
+
+    /// Returns the wrapped sketch.
+    pub fn sketch(&self) -> &S {
+        &self.sketch
+    }
+
+    /// Unwraps the sketch, dropping the callback without a final emission.
+    ///
+    /// # Returns
+    /// The wrapped sketch.
+    pub fn into_inner(self) -> S {
+        self.sketch
+    }
+}
+
+impl<S: Sketch, F: FnMut(f64)> Sketch for ProgressSketch<S, F> {
+    #[inline(always)]
+    fn add<T: Hash>(&mut self, item: T) {
+        ProgressSketch::add(self, item)
+    }
+
+    #[inline(always)]
+    fn estimate(&self) -> f64 {
+        self.sketch.estimate()
+    }
+
+    /// Merges the sketch wrapped by `other` into the wrapped sketch, without emitting.
+    #[inline(always)]
+    fn merge(&mut self, other: &Self) {
+        self.sketch.merge(&other.sketch)
+    }
+
+    #[inline(always)]
+    fn clear(&mut self) {
+        self.sketch.clear();
+        self.pending = 0;
+    }
+}
+
+/// Extension wrapping any sketch in a `ProgressSketch`.
+pub trait OnEstimate: Sketch + Sized {
+    /// Wraps the sketch so that its estimate is emitted while items are added, see
+    /// `ProgressSketch`.
+    ///
+    /// # Parameters
+    /// * `every`: How often the estimate is emitted.
+    /// * `on_estimate`: Called with the estimate at every emission.
+    ///
+    /// # Returns
+    /// The wrapped sketch.
+    fn on_estimate<F: FnMut(f64)>(
+        self,
+        every: EmitEvery,
+        on_estimate: F,
+    ) -> ProgressSketch<Self, F> {
+        ProgressSketch::new(self, every, on_estimate)
+    }
+}
+
+impl<S: Sketch> OnEstimate for S {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{ingest::ingest_csv_column, HyperLogLog};
+
+    #[test]
+    fn test_emit_every_items() {
+        let mut estimates = Vec::new();
+        let mut sketch =
+            HyperLogLog::new().on_estimate(EmitEvery::Items(1_000), |e| estimates.push(e));
+        for i in 0..3_500 {
+            sketch.add(i);
+        }
+        sketch.emit();
+        let hll = sketch.into_inner();
+
+        assert_eq!(estimates.len(), 4);
+        for (estimate, expected) in estimates.iter().zip([1_000.0, 2_000.0, 3_000.0]) {
+            assert!(
+                (estimate - expected).abs() < expected * 0.02,
+                "{}",
+                estimate
+            );
+        }
+        assert_eq!(estimates[3], hll.estimate());
+    }
+
+    #[test]
+    fn test_emit_every_interval() {
+        let mut emitted = 0;
+        let mut hll = HyperLogLog::new();
+        let csv = (0..3 * CLOCK_CHECK_ITEMS)
+            .map(|i| format!("{}\n", i))
+            .collect::<String>();
+
+        let mut sketch = ProgressSketch::new(&mut hll, EmitEvery::Interval(Duration::ZERO), |_| {
+            emitted += 1
+        });
+        ingest_csv_column(csv.as_bytes(), 0, &mut sketch).unwrap();
+        sketch.into_inner();
+
+        assert_eq!(emitted, 3);
+        assert!(hll.estimate() > 0.0);
+    }
+}
@@ -0,0 +1,1071 @@
+use std::{
+    ops::{Deref, DerefMut},
+    sync::Arc,
+};
+
+use crate::{simd, view::HllView, Error};
+
+/// Number of registers decoded at a time by stores that cannot lend their registers as a
+/// plain byte slice, such as `PackedRegisters`.
+pub const CHUNK_LEN: usize = 64;
+
+/// Read access to the register storage of a sketch.
+///
+/// Implementors only need to provide indexed access and a way to visit all registers as
+/// byte chunks. The chunks are what the SIMD kernels operate on, so stores holding plain
+/// bytes should lend them in as few chunks as possible, ideally a single one.
+pub trait Registers {
+    /// Returns the number of registers.
+    fn len(&self) -> usize;
+
+    /// Returns `true` when the store holds no registers.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the value of a register.
+    ///
+    /// # Parameters
+    /// * `index`: The index of the register, less than `len()`.
+    fn get(&self, index: usize) -> u8;
+
+    /// Calls `f` with consecutive chunks of registers, in order, together covering all of
+    /// them.
+    ///
+    /// # Parameters
+    /// * `f`: The function called with each chunk.
+    fn for_each_chunk<F: FnMut(&[u8])>(&self, f: F);
+
+    /// Returns the number of heap bytes owned by the store, `0` for stores that hold
+    /// their registers inline or borrow them.
+    fn heap_size(&self) -> usize {
+        0
+    }
+}
+
+/// Write access to the register storage of a sketch.
+pub trait RegistersMut: Registers {
+    /// Raises a register to `value` if it currently holds a smaller one.
+    ///
+    /// # Parameters
+    /// * `index`: The index of the register, less than `len()`.
+    /// * `value`: The candidate value.
+    ///
+    /// # Returns
+    /// `true` if the register was raised.
+    fn update_max(&mut self, index: usize, value: u8) -> bool;
+
+    /// Resets every register to zero.
+    fn clear(&mut self);
+
+    /// Raises every register to the value of the corresponding register in `other`.
+    ///
+    /// # Parameters
+    /// * `other`: The registers to merge in, holding as many registers as `self`.
+    fn merge_max<R: Registers + ?Sized>(&mut self, other: &R) {
+        debug_assert_eq!(self.len(), other.len());
+
+        let mut offset = 0;
+        other.for_each_chunk(|chunk| {
+            for (i, &value) in chunk.iter().enumerate() {
+                self.update_max(offset + i, value);
+            }
+            offset += chunk.len();
+        });
+    }
+}
+
+/// Register storage holding its registers as one contiguous byte slice, one byte per
+/// register, e.g. a boxed array, a memory mapped region, a shared memory segment or a
+/// database page borrowed as `&mut [u8]`.
+///
+/// Both sketch types are generic over `Registers`, which any store implements; stores
+/// implementing `RegisterStore` on top get the SIMD max-merge of `RegistersMut::merge_max`
+/// and zero count of `zero_count` over their bytes, and expose them through the `as_bytes`
+/// methods of the sketches, so backends plug in without forking the estimate and merge
+/// logic.
+pub trait RegisterStore: RegistersMut {
+    /// Returns the registers.
+    fn as_bytes(&self) -> &[u8];
+
+    /// Returns the registers mutably. Callers must only raise registers to values an
+    /// added item can produce, as `update_max` does.
+    fn as_bytes_mut(&mut self) -> &mut [u8];
+
+    /// Counts the registers that are still zero.
+    fn zero_count(&self) -> usize {
+        simd::count_zeros(self.as_bytes())
+    }
+}
+
+/// Implements both traits and `RegisterStore` for a store that dereferences to a
+/// contiguous `[u8]`.
+macro_rules! impl_contiguous_registers {
+    ($($ty:ty $(, const $n:ident)? $(, heap $heap:path)?);*) => {
+        $(
+            impl<$(const $n: usize)?> Registers for $ty {
+                #[inline(always)]
+                fn len(&self) -> usize {
+                    <[u8]>::len(self)
+                }
+
+                #[inline(always)]
+                fn get(&self, index: usize) -> u8 {
+                    self[index]
+                }
+
+                #[inline(always)]
+                fn for_each_chunk<F: FnMut(&[u8])>(&self, mut f: F) {
+                    f(self)
+                }
+
+                $(
+                    fn heap_size(&self) -> usize {
+                        $heap(self)
+                    }
+                )?
+            }
+
+            impl<$(const $n: usize)?> RegistersMut for $ty {
+                #[inline(always)]
+                fn update_max(&mut self, index: usize, value: u8) -> bool {
+                    let raised = value > self[index];
+                    if raised {
+                        self[index] = value;
+                    }
+                    raised
+                }
+
+                #[inline(always)]
+                fn clear(&mut self) {
+                    self.fill(0);
+                }
+
+                #[inline(always)]
+                fn merge_max<R: Registers + ?Sized>(&mut self, other: &R) {
+                    debug_assert_eq!(Registers::len(self), other.len());
+
+                    let mut offset = 0;
+                    other.for_each_chunk(|chunk| {
+                        simd::merge_max(&mut self[offset..offset + chunk.len()], chunk);
+                        offset += chunk.len();
+                    });
+                }
+            }
+
+            impl<$(const $n: usize)?> RegisterStore for $ty {
+                #[inline(always)]
+                fn as_bytes(&self) -> &[u8] {
+                    self
+                }
+
+                #[inline(always)]
+                fn as_bytes_mut(&mut self) -> &mut [u8] {
+                    self
+                }
+            }
+        )*
+    };
+}
+
+impl_contiguous_registers!(
+    [u8];
+    [u8; N], const N;
+    AlignedRegisters<N>, const N;
+    Vec<u8>, heap Vec::capacity
+);
+#[cfg(feature = "mmap")]
+impl_contiguous_registers!(crate::mmap::MmapRegisters);
+
+impl<R: Registers + ?Sized> Registers for &R {
+    #[inline(always)]
+    fn len(&self) -> usize {
+        R::len(self)
+    }
+
+    #[inline(always)]
+    fn get(&self, index: usize) -> u8 {
+        R::get(self, index)
+    }
+
+    #[inline(always)]
+    fn for_each_chunk<F: FnMut(&[u8])>(&self, f: F) {
+        R::for_each_chunk(self, f)
+    }
+}
+
+impl<R: Registers + ?Sized> Registers for &mut R {
+    #[inline(always)]
+    fn len(&self) -> usize {
+        R::len(self)
+    }
+
+    #[inline(always)]
+    fn get(&self, index: usize) -> u8 {
+        R::get(self, index)
+    }
+
+    #[inline(always)]
+    fn for_each_chunk<F: FnMut(&[u8])>(&self, f: F) {
+        R::for_each_chunk(self, f)
+    }
+}
+
+impl<R: RegistersMut + ?Sized> RegistersMut for &mut R {
+    #[inline(always)]
+    fn update_max(&mut self, index: usize, value: u8) -> bool {
+        R::update_max(self, index, value)
+    }
+
+    #[inline(always)]
+    fn clear(&mut self) {
+        R::clear(self)
+    }
+
+    #[inline(always)]
+    fn merge_max<O: Registers + ?Sized>(&mut self, other: &O) {
+        R::merge_max(self, other)
+    }
+}
+
+impl<R: RegisterStore + ?Sized> RegisterStore for &mut R {
+    #[inline(always)]
+    fn as_bytes(&self) -> &[u8] {
+        R::as_bytes(self)
+    }
+
+    #[inline(always)]
+    fn as_bytes_mut(&mut self) -> &mut [u8] {
+        R::as_bytes_mut(self)
+    }
+}
+
+/// Implements both traits for boxed stores, generic over the allocator of the box with
+/// the `allocator_api` feature.
+macro_rules! impl_boxed_registers {
+    ($($alloc:ident: $bound:path)?) => {
+        impl<R: Registers + ?Sized $(, $alloc: $bound)?> Registers for Box<R $(, $alloc)?> {
+            #[inline(always)]
+            fn len(&self) -> usize {
+                R::len(self)
+            }
+
+            #[inline(always)]
+            fn get(&self, index: usize) -> u8 {
+                R::get(self, index)
+            }
+
+            #[inline(always)]
+            fn for_each_chunk<F: FnMut(&[u8])>(&self, f: F) {
+                R::for_each_chunk(self, f)
+            }
+
+            fn heap_size(&self) -> usize {
+                std::mem::size_of_val::<R>(self) + R::heap_size(self)
+            }
+        }
+
+        impl<R: RegistersMut + ?Sized $(, $alloc: $bound)?> RegistersMut for Box<R $(, $alloc)?> {
+            #[inline(always)]
+            fn update_max(&mut self, index: usize, value: u8) -> bool {
+                R::update_max(self, index, value)
+            }
+
+            #[inline(always)]
+            fn clear(&mut self) {
+                R::clear(self)
+            }
+
+            #[inline(always)]
+            fn merge_max<O: Registers + ?Sized>(&mut self, other: &O) {
+                R::merge_max(self, other)
+            }
+        }
+
+        impl<R: RegisterStore + ?Sized $(, $alloc: $bound)?> RegisterStore for Box<R $(, $alloc)?> {
+            #[inline(always)]
+            fn as_bytes(&self) -> &[u8] {
+                R::as_bytes(self)
+            }
+
+            #[inline(always)]
+            fn as_bytes_mut(&mut self) -> &mut [u8] {
+                R::as_bytes_mut(self)
+            }
+        }
+    };
+}
+
+#[cfg(not(feature = "allocator_api"))]
+impl_boxed_registers!();
+#[cfg(feature = "allocator_api")]
+impl_boxed_registers!(A: std::alloc::Allocator);
+
+impl Registers for HllView<'_> {
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.registers().len()
+    }
+
+    #[inline(always)]
+    fn get(&self, index: usize) -> u8 {
+        self.registers()[index]
+    }
+
+    #[inline(always)]
+    fn for_each_chunk<F: FnMut(&[u8])>(&self, mut f: F) {
+        f(self.registers())
+    }
+}
+
+/// Checks that no register holds a value above `max_rank`.
+///
+/// # Parameters
+/// * `registers`: The registers to check.
+/// * `max_rank`: The largest value a register may hold.
+///
+/// # Returns
+/// `Ok(())`, or an `Error::InvalidRegister` for the first offending register.
+pub(crate) fn check_ranks<R: Registers + ?Sized>(registers: &R, max_rank: u8) -> Result<(), Error> {
+    let mut offset = 0;
+    let mut result = Ok(());
+
+    registers.for_each_chunk(|chunk| {
+        if result.is_ok() {
+            if let Some(index) = chunk.iter().position(|&value| value > max_rank) {
+                result = Err(Error::InvalidRegister {
+                    index: offset + index,
+                    value: chunk[index],
+                });
+            }
+        }
+        offset += chunk.len();
+    });
+
+    result
+}
+
+/// Merges registers built with a higher precision into registers of a lower precision,
+/// folding the extra index bits of `src` back into the ranks.
+///
+/// Both stores must hold a power of two number of registers, `src` at least as many as
+/// `dst`. The index of an item is the low bits of its hash and its rank is taken from the
+/// remaining high bits, counted from the top. Folding thus keeps every rank that was
+/// decided by a bit above the source index, and only ranks saturated at the source
+/// precision continue into the dropped index bits.
+///
+/// # Parameters
+/// * `src`: The higher precision registers to merge in.
+/// * `dst`: The lower precision registers to be updated.
+pub(crate) fn fold_max<S, D>(src: &S, dst: &mut D)
+where
+    S: Registers + ?Sized,
+    D: RegistersMut + ?Sized,
+{
+    debug_assert!(src.len().is_power_of_two() && dst.len().is_power_of_two());
+    debug_assert!(src.len() >= dst.len());
+
+    let src_p = src.len().trailing_zeros();
+    let dst_p = dst.len().trailing_zeros();
+    let dropped_bits = src_p - dst_p;
+    let saturated = (64 - src_p + 1) as u8;
+
+    let mut index = 0;
+    src.for_each_chunk(|chunk| {
+        for &rank in chunk {
+            let folded = match rank {
+                0 => 0,
+                r if r < saturated => r,
+                // All bits above the source index are zero, so the rank continues into
+                // the index bits that are being dropped
+                _ => {
+                    let dropped = (index >> dst_p) as u64;
+                    let zeros = match dropped {
+                        0 => dropped_bits,
+                        d => d.leading_zeros() - (64 - dropped_bits),
+                    };
+                    (64 - src_p + zeros + 1) as u8
+                }
+            };
+
+            dst.update_max(index & (dst.len() - 1), folded);
+            index += 1;
+        }
+    });
+}
+
+/// Allocates `N` registers initialized to zero on the heap, without building them on the
+/// stack first: a `Box::new([0; N])` inlined into a caller puts the whole array in its
+/// frame in debug builds.
+///
+/// # Returns
+/// The boxed registers.
+pub(crate) fn zeroed_box<const N: usize>() -> Box<[u8; N]> {
+    // SAFETY: zeroed bytes are valid registers
+    unsafe { Box::<[u8; N]>::new_zeroed().assume_init() }
+}
+
+/// Counts the registers that are not zero, i.e. that at least one added item was mapped
+/// to.
+///
+/// # Parameters
+/// * `registers`: The registers to count.
+///
+/// # Returns
+/// The number of non-zero registers.
+pub(crate) fn count_nonzero<R: Registers + ?Sized>(registers: &R) -> usize {
+    let mut count = 0;
+    registers.for_each_chunk(|chunk| {
+        count += chunk.iter().filter(|&&rank| rank != 0).count();
+    });
+
+    count
+}
+
+/// Computes a 64-bit fingerprint of registers: the XOR of `fingerprint_term` over every
+/// register. Equal registers always have equal fingerprints, whatever store holds them,
+/// while different registers collide with a probability of about 2^-64.
+///
+/// The fingerprint is not cryptographic and must not be relied upon against adversaries
+/// crafting colliding sketches.
+///
+/// # Parameters
+/// * `registers`: The registers to fingerprint.
+///
+/// # Returns
+/// The `u64` fingerprint, `0` for empty registers.
+pub fn fingerprint<R: Registers + ?Sized>(registers: &R) -> u64 {
+    let mut index = 0;
+    let mut fingerprint = 0;
+
+    registers.for_each_chunk(|chunk| {
+        for &rank in chunk {
+            fingerprint ^= fingerprint_term(index, rank);
+            index += 1;
+        }
+    });
+
+    fingerprint
+}
+
+/// Returns the contribution of a single register to `fingerprint`, `0` for a register
+/// that is still zero.
+///
+/// Since contributions are combined by XOR, a fingerprint can be maintained incrementally
+/// while a register is raised from `old` to `new`, by XOR-ing in
+/// `fingerprint_term(index, old) ^ fingerprint_term(index, new)`.
+///
+/// # Parameters
+/// * `index`: The index of the register.
+/// * `rank`: The value of the register.
+///
+/// # Returns
+/// The `u64` contribution.
+#[inline(always)]
+pub fn fingerprint_term(index: usize, rank: u8) -> u64 {
+    if rank == 0 {
+        return 0;
+    }
+
+    // SplitMix64 finalizer over the index and rank
+    let mut z = ((index as u64) << 8 | u64::from(rank)).wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// `N` one-byte registers starting on a 64-byte boundary, the width of a cache line and
+/// of the widest SIMD vectors, so the merge and estimate kernels use aligned loads and
+/// stores on them. Boxed, the registers are allocated with that alignment too, see
+/// `new_boxed`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[repr(C, align(64))]
+pub struct AlignedRegisters<const N: usize>(pub [u8; N]);
+
+impl<const N: usize> AlignedRegisters<N> {
+    /// Allocates a boxed store with all registers initialized to zero, without building
+    /// the registers on the stack first.
+    ///
+    /// # Returns
+    /// A new boxed `AlignedRegisters` instance.
+    pub fn new_boxed() -> Box<Self> {
+        // SAFETY: zeroed bytes are valid registers
+        unsafe { Box::<Self>::new_zeroed().assume_init() }
+    }
+}
+
+impl<const N: usize> Deref for AlignedRegisters<N> {
+    type Target = [u8];
+
+    #[inline(always)]
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl<const N: usize> DerefMut for AlignedRegisters<N> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+/// Copy-on-write registers, shared by every clone until one of them raises a register.
+///
+/// Cloning only bumps a reference count, so a sketch backed by `CowRegisters` can be
+/// snapshotted for concurrent readers without copying its registers. The first
+/// `update_max` that actually raises a register, `merge_max` or `as_bytes_mut` on a shared
+/// store copies the registers, which are then owned by that clone alone; adding an item
+/// that does not raise its register never copies them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CowRegisters {
+    registers: Arc<[u8]>,
+}
+
+impl CowRegisters {
+    /// Creates a store with `len` registers, all initialized to zero.
+    ///
+    /// # Parameters
+    /// * `len`: The number of registers.
+    ///
+    /// # Returns
+    /// A new `CowRegisters` instance.
+    pub fn new(len: usize) -> Self {
+        Self {
+            registers: Arc::from(vec![0; len]),
+        }
+    }
+
+    /// Returns whether the registers are shared with a clone, i.e. whether the next
+    /// update copies them.
+    pub fn is_shared(&self) -> bool {
+        Arc::strong_count(&self.registers) > 1
+    }
+
+    /// Returns the registers mutably, copying them first if they are shared.
+    #[inline(always)]
+    fn make_mut(&mut self) -> &mut [u8] {
+        if Arc::get_mut(&mut self.registers).is_none() {
+            self.registers = Arc::from(&*self.registers);
+        }
+
+        Arc::get_mut(&mut self.registers).expect("the registers were just copied")
+    }
+}
+
+impl From<&[u8]> for CowRegisters {
+    /// Creates a store holding a copy of the given registers.
+    fn from(registers: &[u8]) -> Self {
+        Self {
+            registers: Arc::from(registers),
+        }
+    }
+}
+
+impl Registers for CowRegisters {
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.registers.len()
+    }
+
+    #[inline(always)]
+    fn get(&self, index: usize) -> u8 {
+        self.registers[index]
+    }
+
+    #[inline(always)]
+    fn for_each_chunk<F: FnMut(&[u8])>(&self, mut f: F) {
+        f(&self.registers)
+    }
+
+    fn heap_size(&self) -> usize {
+        self.registers.len()
+    }
+}
+
+impl RegistersMut for CowRegisters {
+    #[inline(always)]
+    fn update_max(&mut self, index: usize, value: u8) -> bool {
+        if value <= self.registers[index] {
+            return false;
+        }
+        self.make_mut()[index] = value;
+
+        true
+    }
+
+    fn clear(&mut self) {
+        match Arc::get_mut(&mut self.registers) {
+            Some(registers) => registers.fill(0),
+            None => *self = Self::new(self.registers.len()),
+        }
+    }
+
+    #[inline(always)]
+    fn merge_max<R: Registers + ?Sized>(&mut self, other: &R) {
+        self.make_mut().merge_max(other)
+    }
+}
+
+impl RegisterStore for CowRegisters {
+    #[inline(always)]
+    fn as_bytes(&self) -> &[u8] {
+        &self.registers
+    }
+
+    #[inline(always)]
+    fn as_bytes_mut(&mut self) -> &mut [u8] {
+        self.make_mut()
+    }
+}
+
+/// Registers packed into 6 bits each, taking three quarters of the memory of one byte
+/// per register.
+///
+/// Four registers are stored in every group of three bytes. Values above `MAX_VALUE` are
+/// saturated, which only affects ranks that occur with a probability below 2^-63.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedRegisters {
+    len: usize,
+    bytes: Box<[u8]>,
+}
+
+impl PackedRegisters {
+    /// Largest value a packed register can hold.
+    pub const MAX_VALUE: u8 = 63;
+
+    /// Creates a new store of `len` registers, all initialized to zero.
+    ///
+    /// # Parameters
+    /// * `len`: The number of registers.
+    ///
+    /// # Returns
+    /// A new `PackedRegisters` instance.
+    pub fn new(len: usize) -> Self {
+        Self {
+            len,
+            bytes: vec![0; len.div_ceil(4) * 3].into_boxed_slice(),
+        }
+    }
+
+    /// Returns the packed bytes backing the registers.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Returns the three bytes of the group holding `index` as a `u32`, and the bit offset
+    /// of the register within it.
+    #[inline(always)]
+    fn group(&self, index: usize) -> (u32, usize) {
+        let base = index / 4 * 3;
+        let word = u32::from_le_bytes([
+            self.bytes[base],
+            self.bytes[base + 1],
+            self.bytes[base + 2],
+            0,
+        ]);
+
+        (word, index % 4 * 6)
+    }
+}
+
+impl Registers for PackedRegisters {
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline(always)]
+    fn get(&self, index: usize) -> u8 {
+        assert!(index < self.len, "register index out of bounds");
+
+        let (word, shift) = self.group(index);
+        ((word >> shift) & 0x3f) as u8
+    }
+
+    fn for_each_chunk<F: FnMut(&[u8])>(&self, mut f: F) {
+        let mut chunk = [0u8; CHUNK_LEN];
+
+        for start in (0..self.len).step_by(CHUNK_LEN) {
+            let end = (start + CHUNK_LEN).min(self.len);
+            for (i, register) in chunk[..end - start].iter_mut().enumerate() {
+                *register = self.get(start + i);
+            }
+            f(&chunk[..end - start]);
+        }
+    }
+
+    fn heap_size(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+impl RegistersMut for PackedRegisters {
+    #[inline(always)]
+    fn update_max(&mut self, index: usize, value: u8) -> bool {
+        let value = value.min(Self::MAX_VALUE);
+        if value <= self.get(index) {
+            return false;
+        }
+
+        let (word, shift) = self.group(index);
+        let word = (word & !(0x3f << shift)) | (u32::from(value) << shift);
+        let base = index / 4 * 3;
+        self.bytes[base..base + 3].copy_from_slice(&word.to_le_bytes()[..3]);
+
+        true
+    }
+
+    fn clear(&mut self) {
+        self.bytes.fill(0);
+    }
+}
+
+/// Registers stored in 4 bits each as an offset from a shared base value, as the HLL_4
+/// mode of Apache DataSketches does, taking half the memory of one byte per register.
+///
+/// Since every register is at least the base, which rises along with the smallest
+/// register, offsets stay small: after `n` distinct items, registers cluster within a few
+/// ranks of `log2(n / m)`. The few registers more than `MAX_OFFSET` above the base are
+/// marked with `EXCEPTION` and their value is kept in a sorted exception list.
+///
+/// Raising the last register holding the base value moves the base up, which rewrites
+/// every offset once. This happens about `log2(n / m)` times over the life of a sketch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hll4Registers {
+    len: usize,
+    nibbles: Box<[u8]>,
+    base: u8,
+    at_base: usize,
+    exceptions: Vec<(u32, u8)>,
+}
+
+impl Hll4Registers {
+    /// Largest offset from the base a 4-bit register holds.
+    pub const MAX_OFFSET: u8 = 14;
+
+    /// Marks a register whose value is held in the exception list.
+    pub const EXCEPTION: u8 = 15;
+
+    /// Creates a new store of `len` registers, all initialized to zero.
+    ///
+    /// # Parameters
+    /// * `len`: The number of registers.
+    ///
+    /// # Returns
+    /// A new `Hll4Registers` instance.
+    pub fn new(len: usize) -> Self {
+        Self {
+            len,
+            nibbles: vec![0; len.div_ceil(2)].into_boxed_slice(),
+            base: 0,
+            at_base: len,
+            exceptions: Vec::new(),
+        }
+    }
+
+    /// Returns the value every register is at least.
+    pub fn base(&self) -> u8 {
+        self.base
+    }
+
+    /// Returns the number of registers held in the exception list.
+    pub fn exception_count(&self) -> usize {
+        self.exceptions.len()
+    }
+
+    /// Returns the 4 bits of a register.
+    #[inline(always)]
+    fn nibble(&self, index: usize) -> u8 {
+        (self.nibbles[index / 2] >> (index % 2 * 4)) & 0xf
+    }
+
+    /// Overwrites the 4 bits of a register.
+    #[inline(always)]
+    fn set_nibble(&mut self, index: usize, nibble: u8) {
+        let shift = index % 2 * 4;
+        let byte = &mut self.nibbles[index / 2];
+        *byte = (*byte & !(0xf << shift)) | (nibble << shift);
+    }
+
+    /// Stores a value at least as large as the base, in the nibble or as an exception.
+    fn store(&mut self, index: usize, value: u8) {
+        let key = index as u32;
+        let position = self.exceptions.binary_search_by_key(&key, |&(i, _)| i);
+
+        match (value - self.base <= Self::MAX_OFFSET, position) {
+            (true, Ok(position)) => {
+                self.exceptions.remove(position);
+                self.set_nibble(index, value - self.base);
+            }
+            (true, Err(_)) => self.set_nibble(index, value - self.base),
+            (false, Ok(position)) => self.exceptions[position].1 = value,
+            (false, Err(position)) => {
+                self.exceptions.insert(position, (key, value));
+                self.set_nibble(index, Self::EXCEPTION);
+            }
+        }
+    }
+
+    /// Moves the base up to the smallest register once no register holds it anymore,
+    /// shifting every offset down and taking the exceptions that now fit back into their
+    /// nibbles.
+    fn rebase(&mut self) {
+        let min_offset = (0..self.len)
+            .map(|index| self.nibble(index))
+            .filter(|&nibble| nibble != Self::EXCEPTION)
+            .min()
+            .unwrap_or(Self::EXCEPTION);
+        let min_exception = self.exceptions.iter().map(|&(_, value)| value).min();
+        let base = match min_exception {
+            Some(value) => value.min(self.base + min_offset),
+            None => self.base + min_offset,
+        };
+        let shift = base - self.base;
+
+        for index in 0..self.len {
+            let nibble = self.nibble(index);
+            if nibble != Self::EXCEPTION {
+                self.set_nibble(index, nibble - shift);
+            }
+        }
+        self.base = base;
+
+        let exceptions = std::mem::take(&mut self.exceptions);
+        for (index, value) in exceptions {
+            self.store(index as usize, value);
+        }
+        self.at_base = (0..self.len)
+            .filter(|&index| self.get(index) == base)
+            .count();
+    }
+}
+
+impl Registers for Hll4Registers {
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline(always)]
+    fn get(&self, index: usize) -> u8 {
+        assert!(index < self.len, "register index out of bounds");
+
+        match self.nibble(index) {
+            Self::EXCEPTION => {
+                let position = self
+                    .exceptions
+                    .binary_search_by_key(&(index as u32), |&(i, _)| i)
+                    .expect("exception recorded");
+                self.exceptions[position].1
+            }
+            offset => self.base + offset,
+        }
+    }
+
+    fn for_each_chunk<F: FnMut(&[u8])>(&self, mut f: F) {
+        let mut chunk = [0u8; CHUNK_LEN];
+
+        for start in (0..self.len).step_by(CHUNK_LEN) {
+            let end = (start + CHUNK_LEN).min(self.len);
+            for (i, register) in chunk[..end - start].iter_mut().enumerate() {
+                *register = self.get(start + i);
+            }
+            f(&chunk[..end - start]);
+        }
+    }
+
+    fn heap_size(&self) -> usize {
+        self.nibbles.len() + self.exceptions.capacity() * std::mem::size_of::<(u32, u8)>()
+    }
+}
+
+impl RegistersMut for Hll4Registers {
+    fn update_max(&mut self, index: usize, value: u8) -> bool {
+        let previous = self.get(index);
+        if value <= previous {
+            return false;
+        }
+
+        self.store(index, value);
+        if previous == self.base {
+            self.at_base -= 1;
+            if self.at_base == 0 {
+                self.rebase();
+            }
+        }
+
+        true
+    }
+
+    fn clear(&mut self) {
+        *self = Self::new(self.len);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packed_registers() {
+        let mut packed = PackedRegisters::new(130);
+        let mut plain = vec![0u8; 130];
+
+        for i in 0..130 {
+            let value = (i * 7 % 70) as u8;
+            assert_eq!(
+                packed.update_max(i, value),
+                plain.update_max(i, value.min(PackedRegisters::MAX_VALUE))
+            );
+        }
+        assert!(!packed.update_max(5, 0));
+
+        for (i, &value) in plain.iter().enumerate() {
+            assert_eq!(packed.get(i), value);
+        }
+
+        let mut chunks = Vec::new();
+        packed.for_each_chunk(|chunk| chunks.extend_from_slice(chunk));
+        assert_eq!(chunks, plain);
+    }
+
+    #[test]
+    fn test_hll4_registers() {
+        let mut hll4 = Hll4Registers::new(65);
+        let mut plain = vec![0u8; 65];
+
+        // Every register raised above zero moves the base, large values become exceptions
+        for round in 1..4u8 {
+            for i in 0..65 {
+                let value = round * 2 + (i * 11 % 30) as u8;
+                assert_eq!(hll4.update_max(i, value), plain.update_max(i, value));
+            }
+        }
+        assert!(!hll4.update_max(5, 0));
+        assert_eq!(hll4.base(), *plain.iter().min().unwrap());
+        assert!(hll4.exception_count() > 0);
+
+        let mut chunks = Vec::new();
+        hll4.for_each_chunk(|chunk| chunks.extend_from_slice(chunk));
+        assert_eq!(chunks, plain);
+
+        hll4.clear();
+        assert_eq!((hll4.base(), hll4.exception_count()), (0, 0));
+        assert_eq!(hll4.heap_size(), 33);
+    }
+
+    #[test]
+    fn test_aligned_registers() {
+        let mut aligned = AlignedRegisters::<200>::new_boxed();
+        assert!((aligned.as_ptr() as usize).is_multiple_of(64));
+        assert_eq!(Registers::len(&aligned), 200);
+
+        let other = (0..200).map(|i| (i % 9) as u8).collect::<Vec<_>>();
+        aligned.update_max(3, 20);
+        aligned.merge_max(other.as_slice());
+        assert_eq!(aligned.get(3), 20);
+        assert_eq!(aligned.get(199), 199 % 9);
+        assert_eq!(aligned.heap_size(), 256);
+    }
+
+    #[test]
+    fn test_cow_registers() {
+        let mut original = CowRegisters::new(100);
+        original.update_max(1, 4);
+        let mut snapshot = original.clone();
+        assert!(original.is_shared());
+
+        // Adds that do not raise a register keep the registers shared
+        assert!(!original.update_max(1, 2));
+        assert!(snapshot.is_shared());
+
+        assert!(original.update_max(2, 5));
+        assert!(!original.is_shared() && !snapshot.is_shared());
+        assert_eq!((original.get(2), snapshot.get(2)), (5, 0));
+
+        let copy = snapshot.clone();
+        snapshot.clear();
+        assert_eq!((snapshot.get(1), copy.get(1)), (0, 4));
+        snapshot.merge_max(&original);
+        assert_eq!(snapshot.as_bytes(), original.as_bytes());
+    }
+
+    #[test]
+    fn test_register_store() {
+        // A page of a larger buffer, as a database or shared memory segment would lend it
+        let mut page = vec![0u8; 300];
+        let store = &mut page[100..200];
+        assert_eq!(store.zero_count(), 100);
+
+        store.update_max(5, 3);
+        store.merge_max([1u8; 100].as_slice());
+        assert_eq!(store.zero_count(), 0);
+        assert_eq!(store.as_bytes()[5], 3);
+
+        store.as_bytes_mut().fill(0);
+        assert_eq!(Box::new([0u8; 64]).zero_count(), 64);
+        assert_eq!(page.iter().filter(|&&r| r != 0).count(), 0);
+    }
+
+    #[test]
+    fn test_merge_max_across_stores() {
+        let mut packed = PackedRegisters::new(100);
+        packed.update_max(3, 9);
+        packed.update_max(99, 2);
+
+        let mut plain = vec![1u8; 100];
+        plain.merge_max(&packed);
+        assert_eq!((plain[3], plain[99], plain[0]), (9, 2, 1));
+
+        let mut packed = PackedRegisters::new(100);
+        packed.merge_max(plain.as_slice());
+        for (i, &value) in plain.iter().enumerate() {
+            assert_eq!(packed.get(i), value);
+        }
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let mut bytes = vec![0u8; 64];
+        let mut packed = PackedRegisters::new(64);
+        assert_eq!(fingerprint(&bytes), 0);
+
+        let mut incremental = 0;
+        for (index, rank) in [(3, 5), (40, 2), (3, 7)] {
+            incremental ^= fingerprint_term(index, bytes[index]) ^ fingerprint_term(index, rank);
+            bytes.update_max(index, rank);
+            packed.update_max(index, rank);
+        }
+
+        assert_eq!(fingerprint(&bytes), incremental);
+        assert_eq!(fingerprint(&packed), incremental);
+
+        bytes.update_max(41, 2);
+        assert_ne!(fingerprint(&bytes), incremental);
+    }
+
+    #[test]
+    fn test_fold_max() {
+        // Registers filled as a sketch of precision `p` would, with the rank taken from
+        // the hash bits above the index
+        fn sketch(p: u32, hashes: &[u64]) -> Vec<u8> {
+            let mut registers = vec![0u8; 1 << p];
+            for &hash in hashes {
+                let rank = crate::hll::rank(hash, p as usize);
+                registers.update_max(hash as usize & ((1 << p) - 1), rank);
+            }
+            registers
+        }
+
+        let mut hashes = (0..5_000u64)
+            .map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+            .collect::<Vec<_>>();
+        // Hashes whose bits above the source index are all zero
+        hashes.extend([0, 1 << 13, 1 << 12, 0x3ff]);
+
+        let mut folded = vec![0u8; 1 << 10];
+        fold_max(sketch(14, &hashes).as_slice(), &mut folded);
+        assert_eq!(folded, sketch(10, &hashes));
+    }
+}
@@ -0,0 +1,110 @@
+use rocksdb::{MergeOperands, Options};
+
+use crate::{registers::RegistersMut, Error, HyperLogLog, M};
+
+/// Name the merge operator is registered under. RocksDB refuses to open a database with
+/// a merge operator of a different name than it was written with.
+pub const MERGE_OPERATOR_NAME: &str = "hyperlog_simd.registers_max";
+
+/// Registers the HyperLogLog merge operator on `options`, so per-key sketches can be
+/// updated with `merge()` writes and combined by RocksDB at read and compaction time,
+/// without a read-modify-write in the application.
+///
+/// Values and merge operands are the raw registers of a `HyperLogLog`, one byte each, as
+/// returned by `to_value` and read back by `from_value`. Merging takes the register-wise
+/// maximum, which is associative, so operands are also combined with each other before
+/// the base value is known.
+///
+/// # Parameters
+/// * `options`: The options of the database or column family holding the sketches.
+pub fn set_merge_operator(options: &mut Options) {
+    options.set_merge_operator_associative(MERGE_OPERATOR_NAME, merge);
+}
+
+/// The merge function registered by `set_merge_operator`.
+///
+/// # Arguments
+/// * `_key`: The key being merged.
+/// * `existing`: The current value, if any.
+/// * `operands`: The operands to merge in, oldest first.
+///
+/// # Returns
+/// The merged registers, or `None` if a value does not hold `M` registers, which RocksDB
+/// reports as a corruption.
+pub fn merge(_key: &[u8], existing: Option<&[u8]>, operands: &MergeOperands) -> Option<Vec<u8>> {
+    merge_values(existing, operands.iter())
+}
+
+/// Encodes a sketch as a value or merge operand.
+///
+/// # Parameters
+/// * `hll`: The sketch to encode.
+///
+/// # Returns
+/// The raw registers of `hll`.
+pub fn to_value(hll: &HyperLogLog) -> Vec<u8> {
+    hll.registers.to_vec()
+}
+
+/// Decodes a value written through the merge operator.
+///
+/// # Parameters
+/// * `value`: The value read from RocksDB.
+///
+/// # Returns
+/// The `HyperLogLog`, or an `Error` if the value does not hold `M` valid registers.
+pub fn from_value(value: &[u8]) -> Result<HyperLogLog, Error> {
+    HyperLogLog::try_from_bytes(value)
+}
+
+/// Merges register arrays into a copy of `existing`, or of the first operand.
+fn merge_values<'a>(
+    existing: Option<&[u8]>,
+    operands: impl IntoIterator<Item = &'a [u8]>,
+) -> Option<Vec<u8>> {
+    let mut operands = operands.into_iter();
+    let mut merged = match existing {
+        Some(value) => value.to_vec(),
+        None => operands.next()?.to_vec(),
+    };
+    if merged.len() != M {
+        return None;
+    }
+
+    for operand in operands {
+        if operand.len() != M {
+            return None;
+        }
+        merged.merge_max(operand);
+    }
+
+    Some(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_values() {
+        let mut a = HyperLogLog::new();
+        let mut b = HyperLogLog::new();
+        let mut c = HyperLogLog::new();
+        for i in 0..3_000 {
+            [&mut a, &mut b, &mut c][i % 3].add(i);
+        }
+
+        let (a_value, b_value, c_value) = (to_value(&a), to_value(&b), to_value(&c));
+
+        // Partial merge of the operands, then a full merge onto the base value
+        let partial = merge_values(None, [&b_value[..], &c_value[..]]).unwrap();
+        let full = merge_values(Some(&a_value), [&partial[..]]).unwrap();
+
+        a.merge(&b);
+        a.merge(&c);
+        assert!(from_value(&full).unwrap().diff(&a).is_identical());
+
+        assert_eq!(merge_values(Some(&a_value), [&b_value[..10]]), None);
+        assert_eq!(merge_values(None, []), None);
+    }
+}
@@ -0,0 +1,202 @@
+use std::hash::Hash;
+
+use crate::{hash, metadata::SketchMetadata, sketch::Sketch, HyperLogLog, M};
+
+/// Largest sampling rate a `SampledHyperLogLog` accepts, one item in 2^16.
+pub const MAX_SAMPLING_RATE: u64 = 1 << 16;
+
+/// A `HyperLogLog` counting a deterministic sample of one in `rate` items and rescaling
+/// its estimates accordingly, for ingest volumes where updating the registers for every
+/// item is too expensive and a less accurate estimate is acceptable.
+///
+/// Every item is still hashed, but only items whose hash starts with `log2(rate)` zero
+/// bits reach the registers, skipping the register update, a likely cache miss on a
+/// 1 MiB array, for all others. Since the sample is selected by hash, an item is either
+/// always or never sampled, so duplicates do not skew the sample, and sketches sampled at
+/// the same rate stay mergeable. The sampling bits are stripped from the hash before it
+/// is added, so the ranks of the sampled items are distributed as for an unsampled
+/// sketch.
+///
+/// Estimates are those of the sample times `rate`. Their relative error grows by roughly
+/// the square root of the number of distinct items per sampled one, so sampling only
+/// pays off for cardinalities well above `rate` times the register count.
+#[derive(Debug, Clone)]
+pub struct SampledHyperLogLog {
+    sketch: HyperLogLog,
+    metadata: SketchMetadata,
+    shift: u32,
+}
+
+impl SampledHyperLogLog {
+    /// Creates an empty sampled sketch.
+    ///
+    /// # Parameters
+    /// * `rate`: One item in `rate` is counted, a power of two up to `MAX_SAMPLING_RATE`.
+    ///
+    /// # Returns
+    /// A new `SampledHyperLogLog` instance.
+    ///
+    /// # Panics
+    /// Panics if `rate` is not a power of two or is above `MAX_SAMPLING_RATE`.
+    pub fn new(rate: u64) -> Self {
+        Self::with_sketch(HyperLogLog::new(), rate)
+    }
+
+    /// Samples items into an existing sketch, e.g. one built with another hasher. The
+    /// sketch should only ever be fed through sampled sketches of the same rate.
+    ///
+    /// # Parameters
+    /// * `sketch`: The sketch the sampled items are added to.
+    /// * `rate`: One item in `rate` is counted, a power of two up to `MAX_SAMPLING_RATE`.
+    ///
+    /// # Returns
+    /// A new `SampledHyperLogLog` instance.
+    ///
+    /// # Panics
+    /// Panics if `rate` is not a power of two or is above `MAX_SAMPLING_RATE`.
+    pub fn with_sketch(sketch: HyperLogLog, rate: u64) -> Self {
+        assert!(
+            rate.is_power_of_two() && rate <= MAX_SAMPLING_RATE,
+            "the sampling rate must be a power of two up to {}",
+            MAX_SAMPLING_RATE
+        );
+
+        Self {
+            metadata: sketch.metadata(),
+            sketch,
+            shift: rate.trailing_zeros(),
+        }
+    }
+
+    /// Adds an item if it belongs to the sample.
+    ///
+    /// # Parameters
+    /// * `item`: An item that implements the `Hash` trait to be added.
+    #[inline(always)]
+    pub fn add<T: Hash>(&mut self, item: T) {
+        let hash = hash::hash_item(&self.metadata, &item);
+
+        if let Some(hash) = sample(hash, self.shift) {
+            self.sketch.add_hash(hash);
+        }
+    }
+
+    /// Estimates the number of unique items added, sampled or not.
+    ///
+    /// # Returns
+    /// The estimate of the sample times the sampling rate.
+    pub fn estimate(&self) -> f64 {
+        self.sketch.estimate() * self.rate() as f64
+    }
+
+    /// Merges another sampled sketch into this one.
+    ///
+    /// # Parameters
+    /// * `other`: A reference to the `SampledHyperLogLog` instance to be merged.
+    ///
+    /// # Panics
+    /// Panics if `other` samples at a different rate, or its sketch cannot be merged into
+    /// this one, see `HyperLogLog::merge`.
+    pub fn merge(&mut self, other: &SampledHyperLogLog) {
+        assert_eq!(
+            self.shift, other.shift,
+            "sketches sampled at different rates cannot be merged"
+        );
+
+        self.sketch.merge(&other.sketch);
+    }
+
+    /// Removes all items, keeping the sampling rate.
+    pub fn clear(&mut self) {
+        self.sketch.clear();
+    }
+
+    /// Returns the sampling rate: one item in `rate` is counted.
+    pub fn rate(&self) -> u64 {
+        1 << self.shift
+    }
+
+    /// Returns the sketch of the sampled items, whose estimate is not rescaled.
+    pub fn sketch(&self) -> &HyperLogLog {
+        &self.sketch
+    }
+
+    /// Unwraps the sketch of the sampled items.
+    ///
+    /// # Returns
+    /// The sketch, whose estimate is not rescaled.
+    pub fn into_inner(self) -> HyperLogLog {
+        self.sketch
+    }
+}
+
+impl Sketch for SampledHyperLogLog {
+    #[inline(always)]
+    fn add<T: Hash>(&mut self, item: T) {
+        SampledHyperLogLog::add(self, item)
+    }
+
+    #[inline(always)]
+    fn estimate(&self) -> f64 {
+        SampledHyperLogLog::estimate(self)
+    }
+
+    #[inline(always)]
+    fn merge(&mut self, other: &Self) {
+        SampledHyperLogLog::merge(self, other)
+    }
+
+    #[inline(always)]
+    fn clear(&mut self) {
+        SampledHyperLogLog::clear(self)
+    }
+}
+
+/// Selects a hash for the sample when its top `shift` bits are zero, and strips them by
+/// shifting the rank bits up, keeping the register index in the low bits.
+///
+/// # Arguments
+/// * `hash`: The hash of the item.
+/// * `shift`: The base 2 logarithm of the sampling rate.
+#[inline(always)]
+fn sample(hash: u64, shift: u32) -> Option<u64> {
+    if shift > 0 && hash >> (64 - shift) != 0 {
+        return None;
+    }
+    let index = hash & (M as u64 - 1);
+
+    Some((hash & !(M as u64 - 1)) << shift | index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sampled_estimate() {
+        let mut sampled = SampledHyperLogLog::new(16);
+        for i in (0..1_000_000).chain(0..100_000) {
+            sampled.add(i);
+        }
+
+        let estimate = sampled.estimate();
+        assert!(
+            (estimate - 1_000_000.0).abs() / 1_000_000.0 < 0.03,
+            "estimate {}",
+            estimate
+        );
+
+        // About one item in 16 reached the registers
+        let sample = sampled.sketch().estimate();
+        assert!((sample - 62_500.0).abs() < 2_000.0, "sample {}", sample);
+    }
+
+    #[test]
+    fn test_sample() {
+        assert_eq!(sample(u64::MAX, 0), Some(u64::MAX));
+        assert_eq!(sample(1 << 63, 1), None);
+
+        let hash = (1 << 60) | 5;
+        assert_eq!(sample(hash, 2), Some((1 << 62) | 5));
+    }
+}
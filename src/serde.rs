@@ -1,37 +1,79 @@
-use std::{collections::HashMap, fmt, io, marker::PhantomData};
+use std::{
+    fmt,
+    io::{self, Read},
+    str::FromStr,
+};
 
 use base64::{engine::general_purpose, Engine};
 use lz4::{Decoder, EncoderBuilder};
 use serde::{
-    de::{Error, MapAccess, Visitor},
-    ser::Error as SerError,
-    Serialize, Serializer,
+    de::{Error as DeError, MapAccess, Visitor},
+    ser::{Error as SerError, SerializeMap},
+    Deserialize, Deserializer, Serialize, Serializer,
 };
 
-use crate::M;
+use crate::{
+    activity::SketchActivity,
+    hash::DEFAULT_SEAHASH_KEY,
+    labels::SketchLabels,
+    metadata::{self, Algorithm, HasherId, SketchMetadata},
+    DecodeError, M,
+};
 
 // A constant representing the key used to store serialized registers.
 const REGISTER_KEY: &str = "registers";
+// Key storing the version of the format. Version 1 payloads, written by releases that
+// only stored `{"registers": base64(lz4)}`, lack it.
+const VERSION_KEY: &str = "version";
+// The latest version written by `serialize_registers`. Version 3 adds the SeaHash key, and
+// is only written for sketches whose key was set with `with_seed`, so that readers only
+// knowing version 2 keep accepting all other sketches.
+const FORMAT_VERSION: u32 = 3;
+// Keys used to store the sketch metadata. Version 1 payloads lack them, in which case the
+// metadata of the target type is assumed.
+const PRECISION_KEY: &str = "precision";
+const ALGORITHM_KEY: &str = "algorithm";
+const HASHER_KEY: &str = "hasher";
+const SEED_KEY: &str = "seed";
+// Key storing the SeaHash key as 64 hex digits, only present in version 3 payloads.
+const KEY_KEY: &str = "key";
+// Keys used to store the `SketchLabels` of a sketch, only present when set.
+const NAME_KEY: &str = "name";
+const SOURCE_KEY: &str = "source";
+const START_KEY: &str = "start";
+const END_KEY: &str = "end";
+// Keys used to store the `SketchActivity` of a sketch, only present when tracked.
+const ADDS_KEY: &str = "adds";
+const CREATED_KEY: &str = "created";
+const UPDATED_KEY: &str = "updated";
+
+/// The registers, metadata, labels and activity of a deserialized sketch.
+pub(crate) type Decoded<const N: usize> = (
+    Box<[u8; N]>,
+    SketchMetadata,
+    Option<SketchLabels>,
+    Option<SketchActivity>,
+);
 
 /// Represents a visitor for deserializing compressed register values in HLL structures.
 ///
 /// The visitor pattern in Serde allows for data structures to be deserialized
 /// in a customized manner. In this case, the `CompressedRegistersVisitor` is
-/// tailored for handling the compressed format of the registers.
-pub(crate) struct CompressedRegistersVisitor<T>(PhantomData<T>);
+/// tailored for handling the compressed format of the registers. `N` is the number
+/// of registers of the structure being deserialized, and `expected` its metadata.
+pub(crate) struct CompressedRegistersVisitor<const N: usize = M> {
+    expected: SketchMetadata,
+}
 
-impl<T> CompressedRegistersVisitor<T> {
-    /// Create a new compressed register visitor.
-    pub(crate) fn new() -> Self {
-        Self(PhantomData)
+impl<const N: usize> CompressedRegistersVisitor<N> {
+    /// Create a new compressed register visitor for a sketch described by `expected`.
+    pub(crate) fn new(expected: SketchMetadata) -> Self {
+        Self { expected }
     }
 }
 
-impl<'de, T> Visitor<'de> for CompressedRegistersVisitor<T>
-where
-    T: From<[u8; M]>,
-{
-    type Value = T;
+impl<'de, const N: usize> Visitor<'de> for CompressedRegistersVisitor<N> {
+    type Value = Decoded<N>;
 
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
         formatter.write_str("base64 encoded lz4 compressed sequence of bytes")
@@ -41,41 +83,161 @@ where
     where
         A: MapAccess<'de>,
     {
-        extract_and_decompress(map, &[0; M])
+        extract_and_decompress(map, self.expected)
     }
 }
 
-/// Extracts and decompresses the serialized registers from the provided map.
+/// Extracts and decompresses the serialized registers from the provided map. Both the
+/// current format and the registers-only map of version 1 are accepted. Errors carry the
+/// message of the `DecodeError` describing the problem.
+///
+/// The metadata recorded in the map must match `expected`, except for the hasher, seed and
+/// key, which are taken from the map. Labels and activity are returned if the map holds any.
 ///
 /// # Arguments
 ///
 /// * `map`: The serialized map containing the compressed registers.
-/// * `default_registers`: The default registers to be used if no compressed registers are found.
-pub(crate) fn extract_and_decompress<'de, A, T>(
+/// * `expected`: The metadata of the sketch being deserialized.
+pub(crate) fn extract_and_decompress<'de, A, const N: usize>(
     mut map: A,
-    default_registers: &[u8; M],
-) -> Result<T, <A as MapAccess<'de>>::Error>
+    expected: SketchMetadata,
+) -> Result<Decoded<N>, <A as MapAccess<'de>>::Error>
 where
     A: MapAccess<'de>,
-    T: From<[u8; M]>,
 {
     let mut registers = String::new();
+    let mut found = expected;
+    let mut labels = SketchLabels::default();
+    let (mut adds, mut created, mut updated) = (None, None, None);
 
     while let Some((key, value)) = map.next_entry::<String, String>()? {
-        if key == REGISTER_KEY {
-            registers = value;
+        match key.as_str() {
+            REGISTER_KEY => registers = value,
+            VERSION_KEY => {
+                let version: u32 = parse(VERSION_KEY, &value).map_err(A::Error::custom)?;
+                if version > FORMAT_VERSION {
+                    return Err(A::Error::custom(DecodeError::Version {
+                        found: version,
+                        supported: FORMAT_VERSION,
+                    }));
+                }
+            }
+            PRECISION_KEY => {
+                found.precision = parse(PRECISION_KEY, &value).map_err(A::Error::custom)?
+            }
+            ALGORITHM_KEY => {
+                found.algorithm = Algorithm::from_name(&value).ok_or_else(|| {
+                    A::Error::custom(DecodeError::Malformed(format!(
+                        "unknown algorithm {}",
+                        value
+                    )))
+                })?
+            }
+            HASHER_KEY => {
+                found.hasher = HasherId::from_name(&value).ok_or_else(|| {
+                    A::Error::custom(DecodeError::Malformed(format!("unknown hasher {}", value)))
+                })?
+            }
+            SEED_KEY => found.seed = parse(SEED_KEY, &value).map_err(A::Error::custom)?,
+            KEY_KEY => {
+                found.key = parse_key(&value).ok_or_else(|| {
+                    A::Error::custom(DecodeError::Malformed(format!("invalid key {}", value)))
+                })?
+            }
+            NAME_KEY => labels.name = Some(value),
+            SOURCE_KEY => labels.source = Some(value),
+            START_KEY => labels.start = Some(parse(START_KEY, &value).map_err(A::Error::custom)?),
+            END_KEY => labels.end = Some(parse(END_KEY, &value).map_err(A::Error::custom)?),
+            ADDS_KEY => adds = Some(parse(ADDS_KEY, &value).map_err(A::Error::custom)?),
+            CREATED_KEY => created = Some(parse(CREATED_KEY, &value).map_err(A::Error::custom)?),
+            UPDATED_KEY => updated = Some(parse(UPDATED_KEY, &value).map_err(A::Error::custom)?),
+            _ => {}
         }
     }
 
-    let compressed = general_purpose::STANDARD
-        .decode(registers)
+    let expected = SketchMetadata {
+        hasher: found.hasher,
+        seed: found.seed,
+        key: found.key,
+        ..expected
+    };
+    expected
+        .check_compatible(&found)
         .map_err(A::Error::custom)?;
-    let mut decoder = Decoder::new(io::Cursor::new(compressed)).map_err(A::Error::custom)?;
-    let mut result_registers = default_registers.clone();
 
-    io::copy(&mut decoder, &mut result_registers.as_mut_slice()).map_err(A::Error::custom)?;
+    let max_rank = (64 - found.precision as usize + 1) as u8;
+    let registers = decode_registers(&registers, max_rank).map_err(A::Error::custom)?;
 
-    Ok(T::from(result_registers))
+    let labels = (labels != SketchLabels::default()).then_some(labels);
+    let activity = match (adds, created, updated) {
+        (Some(adds), Some(created), Some(updated)) => Some(SketchActivity {
+            adds,
+            created,
+            updated,
+        }),
+        (None, None, None) => None,
+        _ => {
+            return Err(A::Error::custom(DecodeError::Malformed(
+                "incomplete activity".to_string(),
+            )))
+        }
+    };
+
+    Ok((registers, found, labels, activity))
+}
+
+/// Decodes base64 encoded, lz4 compressed registers, checking that they hold exactly `N`
+/// registers of at most `max_rank`.
+///
+/// # Arguments
+///
+/// * `encoded`: The encoded registers, as stored under `REGISTER_KEY`.
+/// * `max_rank`: The largest rank a register of the sketch can hold.
+fn decode_registers<const N: usize>(
+    encoded: &str,
+    max_rank: u8,
+) -> Result<Box<[u8; N]>, DecodeError> {
+    let compressed = general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(|e| DecodeError::Base64(e.to_string()))?;
+    let mut decoder =
+        Decoder::new(io::Cursor::new(compressed)).map_err(|e| DecodeError::Lz4(e.to_string()))?;
+    let mut registers = Vec::with_capacity(N);
+    decoder
+        .read_to_end(&mut registers)
+        .map_err(|e| DecodeError::Lz4(e.to_string()))?;
+
+    let registers: Box<[u8; N]> = match registers.len() {
+        found if found < N => return Err(DecodeError::Truncated { expected: N, found }),
+        found if found > N => return Err(DecodeError::Length { expected: N, found }),
+        _ => registers
+            .into_boxed_slice()
+            .try_into()
+            .expect("N registers"),
+    };
+    if let Some(index) = registers.iter().position(|&value| value > max_rank) {
+        return Err(DecodeError::InvalidRegister {
+            index,
+            value: registers[index],
+        });
+    }
+
+    Ok(registers)
+}
+
+/// Parses the value of a numeric field.
+///
+/// # Arguments
+///
+/// * `key`: The key of the field, reported if the value is invalid.
+/// * `value`: The serialized value.
+fn parse<T: FromStr>(key: &str, value: &str) -> Result<T, DecodeError>
+where
+    T::Err: fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|e| DecodeError::Malformed(format!("invalid {} {:?}: {}", key, value, e)))
 }
 
 /// Serializes the provided registers into a compressed format suitable for transmission or storage.
@@ -83,8 +245,17 @@ where
 /// # Arguments
 ///
 /// * `registers`: The registers to be serialized.
+/// * `metadata`: The metadata of the sketch, stored alongside the registers.
+/// * `labels`: The labels of the sketch, if any.
+/// * `activity`: The activity of the sketch, if tracked.
 /// * `serializer`: The Serde serializer to use.
-pub(crate) fn serialize_registers<S>(registers: &[u8; M], serializer: S) -> Result<S::Ok, S::Error>
+pub(crate) fn serialize_registers<S>(
+    registers: &[u8],
+    metadata: &SketchMetadata,
+    labels: Option<&SketchLabels>,
+    activity: Option<&SketchActivity>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
@@ -96,9 +267,166 @@ where
 
     io::copy(&mut io::Cursor::new(registers), &mut enc).map_err(S::Error::custom)?;
 
+    // The lz4 frame is only complete once the encoder has been finished
+    let (_, result) = enc.finish();
+    result.map_err(S::Error::custom)?;
+
     let s = general_purpose::STANDARD.encode(&compressed);
-    let mut map = HashMap::new();
+    let default_labels = SketchLabels::default();
+    let labels = labels.unwrap_or(&default_labels);
+    let text_labels = [(NAME_KEY, &labels.name), (SOURCE_KEY, &labels.source)];
+    let time_labels = [(START_KEY, labels.start), (END_KEY, labels.end)];
+    let label_count = text_labels.iter().filter(|(_, v)| v.is_some()).count()
+        + time_labels.iter().filter(|(_, v)| v.is_some()).count();
+    let activity_count = if activity.is_some() { 3 } else { 0 };
+    let keyed = metadata.key != DEFAULT_SEAHASH_KEY;
+    let version = if keyed { FORMAT_VERSION } else { 2 };
 
-    map.insert(REGISTER_KEY, s);
-    map.serialize(serializer)
+    let mut map =
+        serializer.serialize_map(Some(6 + keyed as usize + label_count + activity_count))?;
+
+    map.serialize_entry(VERSION_KEY, &version.to_string())?;
+    map.serialize_entry(PRECISION_KEY, &metadata.precision.to_string())?;
+    map.serialize_entry(ALGORITHM_KEY, metadata.algorithm.name())?;
+    map.serialize_entry(HASHER_KEY, metadata.hasher.name())?;
+    map.serialize_entry(SEED_KEY, &metadata.seed.to_string())?;
+    if keyed {
+        map.serialize_entry(KEY_KEY, &metadata::format_key(metadata.key))?;
+    }
+    for (key, value) in text_labels {
+        if let Some(value) = value {
+            map.serialize_entry(key, value)?;
+        }
+    }
+    for (key, value) in time_labels {
+        if let Some(value) = value {
+            map.serialize_entry(key, &value.to_string())?;
+        }
+    }
+    if let Some(activity) = activity {
+        map.serialize_entry(ADDS_KEY, &activity.adds.to_string())?;
+        map.serialize_entry(CREATED_KEY, &activity.created.to_string())?;
+        map.serialize_entry(UPDATED_KEY, &activity.updated.to_string())?;
+    }
+    map.serialize_entry(REGISTER_KEY, &s)?;
+    map.end()
+}
+
+/// Rewrites a serialized sketch of type `T` into the current format, e.g. to upgrade
+/// sketches persisted in the registers-only format of version 1.
+///
+/// # Arguments
+///
+/// * `deserializer`: The deserializer reading the sketch in any supported format.
+/// * `serializer`: The serializer the sketch is written to in the current format.
+///
+/// # Examples
+///
+/// ```ignore
+/// let value: serde_json::Value = serde_json::from_str(&legacy)?;
+/// let migrated = migrate::<HyperLogLog, _, _>(value, serde_json::value::Serializer)?;
+/// ```
+pub fn migrate<'de, T, D, S>(deserializer: D, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Deserialize<'de> + Serialize,
+    D: Deserializer<'de>,
+    S: Serializer,
+{
+    T::deserialize(deserializer)
+        .map_err(S::Error::custom)?
+        .serialize(serializer)
+}
+
+/// Parses a SeaHash key formatted by `metadata::format_key`.
+///
+/// # Arguments
+///
+/// * `s`: The 64 hex digits of the key.
+fn parse_key(s: &str) -> Option<[u64; 4]> {
+    if s.len() != 64 || !s.is_ascii() {
+        return None;
+    }
+    let mut key = [0; 4];
+    for (word, digits) in key.iter_mut().zip(s.as_bytes().chunks(16)) {
+        *word = u64::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()?;
+    }
+
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::HyperLogLog;
+
+    #[test]
+    fn test_migrate_version_1() {
+        let mut hll = HyperLogLog::new();
+        for i in 0..1_000 {
+            hll.add(i);
+        }
+
+        let current = serde_json::to_string(&hll).unwrap();
+        let registers = &current[current.find("\"registers\"").unwrap()..];
+        let legacy = format!("{{{}", registers);
+
+        let value: serde_json::Value = serde_json::from_str(&legacy).unwrap();
+        let migrated = migrate::<HyperLogLog, _, _>(value, serde_json::value::Serializer).unwrap();
+        let mut expected: serde_json::Value = serde_json::from_str(&current).unwrap();
+        assert_eq!(migrated, expected);
+
+        expected["version"] = serde_json::Value::from((FORMAT_VERSION + 1).to_string());
+        assert!(serde_json::from_value::<HyperLogLog>(expected).is_err());
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let encode = |registers: &[u8]| {
+            let mut enc = EncoderBuilder::new().build(Vec::new()).unwrap();
+            io::copy(&mut io::Cursor::new(registers), &mut enc).unwrap();
+            let (compressed, result) = enc.finish();
+            result.unwrap();
+            general_purpose::STANDARD.encode(compressed)
+        };
+
+        assert_eq!(
+            decode_registers::<4>(&encode(&[1, 2, 3, 4]), 4).unwrap()[..],
+            [1, 2, 3, 4]
+        );
+        assert!(matches!(
+            decode_registers::<4>("not base64!", 4),
+            Err(DecodeError::Base64(_))
+        ));
+        assert!(matches!(
+            decode_registers::<4>(&general_purpose::STANDARD.encode(b"not lz4"), 4),
+            Err(DecodeError::Lz4(_))
+        ));
+        assert_eq!(
+            decode_registers::<4>(&encode(&[1, 2]), 4).unwrap_err(),
+            DecodeError::Truncated {
+                expected: 4,
+                found: 2
+            }
+        );
+        assert_eq!(
+            decode_registers::<4>(&encode(&[0; 5]), 4).unwrap_err(),
+            DecodeError::Length {
+                expected: 4,
+                found: 5
+            }
+        );
+        assert_eq!(
+            decode_registers::<4>(&encode(&[0, 0, 5, 0]), 4).unwrap_err(),
+            DecodeError::InvalidRegister { index: 2, value: 5 }
+        );
+        assert!(matches!(
+            parse::<u64>(SEED_KEY, "x"),
+            Err(DecodeError::Malformed(_))
+        ));
+
+        let key = [1, 2, u64::MAX, 4];
+        assert_eq!(parse_key(&metadata::format_key(key)), Some(key));
+        assert_eq!(parse_key(&"+1".repeat(32)), None);
+    }
 }
@@ -0,0 +1,271 @@
+use std::{
+    fs::OpenOptions,
+    hash::Hash,
+    path::PathBuf,
+    ptr::NonNull,
+    slice,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use memmap2::MmapMut;
+
+use crate::{
+    hash,
+    hll::{self, rank},
+    metadata::SketchMetadata,
+    registers::{Registers, CHUNK_LEN},
+    Error, HyperLogLog, M, P,
+};
+
+/// A HyperLogLog whose registers live in a named shared memory segment, so that several
+/// worker processes on one host can feed the same sketch and any of them can read its
+/// estimate.
+///
+/// Every process opens the segment by name, which creates it on first use. Registers are
+/// raised with an atomic `fetch_max`, so `add` and `merge` take `&self`, never lock and
+/// never lose an update to a concurrent writer, in this process or another one. Estimates
+/// read the registers while they are being raised, so they may miss the updates made
+/// during the call.
+///
+/// On Linux, segments are files in `/dev/shm`, elsewhere in the temporary directory, and
+/// outlive the processes that opened them until `unlink` is called. Only the registers
+/// are shared, so every process must hash items with the default hasher and seed.
+///
+/// # Examples
+///
+/// ```ignore
+/// // In every worker process
+/// let hll = ShmHyperLogLog::open("visitors")?;
+/// hll.add(user_id);
+/// println!("{} distinct so far", hll.estimate());
+/// ```
+#[derive(Debug)]
+pub struct ShmHyperLogLog {
+    registers: NonNull<AtomicU8>,
+    metadata: SketchMetadata,
+    _map: MmapMut,
+}
+
+// SAFETY: the registers point into the map owned alongside them, which stays mapped at the
+// same address until it is dropped, and are only accessed through atomic operations.
+unsafe impl Send for ShmHyperLogLog {}
+unsafe impl Sync for ShmHyperLogLog {}
+
+impl ShmHyperLogLog {
+    /// Opens the shared memory segment with the given name, creating an empty sketch in
+    /// it if it does not exist yet.
+    ///
+    /// The segment must only be written to through `ShmHyperLogLog`, and must not be
+    /// truncated while it is open: accessing a truncated map raises `SIGBUS`.
+    ///
+    /// # Parameters
+    /// * `name`: The name of the segment, shared by all processes feeding the sketch.
+    ///
+    /// # Returns
+    /// A new `ShmHyperLogLog` instance, an `Error::InvalidConfig` if `name` is empty or
+    /// holds a path separator, an `Error::Storage` if the segment cannot be opened or
+    /// mapped, or an `Error::Length` if it does not hold `M` registers.
+    pub fn open(name: &str) -> Result<Self, Error> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(segment_path(name)?)?;
+
+        // Processes racing to create the segment all extend it to the same length, which
+        // leaves registers already raised by the winner untouched.
+        let len = match file.metadata()?.len() {
+            0 => {
+                file.set_len(M as u64)?;
+                M as u64
+            }
+            len => len,
+        };
+        if len != M as u64 {
+            return Err(Error::Length {
+                expected: M,
+                found: len as usize,
+            });
+        }
+
+        // SAFETY: as documented on `open`, the segment is only written to through atomic
+        // operations and is not truncated while mapped, which cannot be enforced for
+        // memory mapped files.
+        let mut map = unsafe { MmapMut::map_mut(&file)? };
+        let registers =
+            NonNull::new(map.as_mut_ptr().cast::<AtomicU8>()).expect("memory maps are never null");
+
+        Ok(Self {
+            registers,
+            metadata: SketchMetadata::default(),
+            _map: map,
+        })
+    }
+
+    /// Removes the shared memory segment with the given name. Processes that have it open
+    /// keep using it, but later calls to `open` create a new, empty one.
+    ///
+    /// # Parameters
+    /// * `name`: The name of the segment.
+    ///
+    /// # Returns
+    /// An `Error::Storage` if the segment does not exist or cannot be removed.
+    pub fn unlink(name: &str) -> Result<(), Error> {
+        std::fs::remove_file(segment_path(name)?)?;
+
+        Ok(())
+    }
+
+    /// Adds an item to the shared sketch.
+    ///
+    /// # Parameters
+    /// * `item`: An item that implements the `Hash` trait to be added.
+    #[inline(always)]
+    pub fn add<T: Hash>(&self, item: T) {
+        let hash = hash::hash_item(&self.metadata, &item);
+
+        self.registers()[hash as usize & (M - 1)].fetch_max(rank(hash, P), Ordering::Relaxed);
+    }
+
+    /// Provides an estimate of the number of unique items added by all processes.
+    ///
+    /// # Returns
+    /// A `f64` approximate count of unique items.
+    pub fn estimate(&self) -> f64 {
+        hll::estimate_registers(&AtomicRegisters(self.registers()))
+    }
+
+    /// Merges a HyperLogLog into the shared one.
+    ///
+    /// # Parameters
+    /// * `other`: A reference to the `HyperLogLog` instance to be merged.
+    ///
+    /// # Panics
+    /// Panics if `other` was built with different metadata, see `try_merge`.
+    pub fn merge(&self, other: &HyperLogLog) {
+        if let Err(e) = self.try_merge(other) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Merges a HyperLogLog into the shared one, after checking that it was built with the
+    /// default hasher and seed the shared sketch hashes items with.
+    ///
+    /// # Parameters
+    /// * `other`: A reference to the `HyperLogLog` instance to be merged.
+    ///
+    /// # Returns
+    /// An `Error::Incompatible` describing the mismatch if `other` cannot be merged.
+    pub fn try_merge(&self, other: &HyperLogLog) -> Result<(), Error> {
+        self.metadata.check_compatible(&other.metadata())?;
+
+        for (register, &value) in self.registers().iter().zip(other.registers.iter()) {
+            if value > 0 {
+                register.fetch_max(value, Ordering::Relaxed);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns a copy of the current state of the sketch.
+    ///
+    /// # Returns
+    /// An owned `HyperLogLog` holding the registers at the time of the call.
+    pub fn snapshot(&self) -> HyperLogLog {
+        let mut hll = HyperLogLog::new();
+        for (copy, register) in hll.registers.iter_mut().zip(self.registers()) {
+            *copy = register.load(Ordering::Relaxed);
+        }
+
+        hll
+    }
+
+    fn registers(&self) -> &[AtomicU8] {
+        // SAFETY: the map holds `M` bytes and lives as long as `self`, see `open`.
+        unsafe { slice::from_raw_parts(self.registers.as_ptr(), M) }
+    }
+}
+
+/// Read access to registers raised concurrently, loading them chunk by chunk.
+struct AtomicRegisters<'a>(&'a [AtomicU8]);
+
+impl Registers for AtomicRegisters<'_> {
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    #[inline(always)]
+    fn get(&self, index: usize) -> u8 {
+        self.0[index].load(Ordering::Relaxed)
+    }
+
+    fn for_each_chunk<F: FnMut(&[u8])>(&self, mut f: F) {
+        let mut chunk = [0u8; CHUNK_LEN];
+
+        for registers in self.0.chunks(CHUNK_LEN) {
+            for (copy, register) in chunk.iter_mut().zip(registers) {
+                *copy = register.load(Ordering::Relaxed);
+            }
+            f(&chunk[..registers.len()]);
+        }
+    }
+}
+
+/// Locates the file backing the shared memory segment with the given name.
+///
+/// # Arguments
+///
+/// * `name`: The name of the segment.
+fn segment_path(name: &str) -> Result<PathBuf, Error> {
+    if name.is_empty() || name.contains(['/', '\\']) {
+        return Err(Error::InvalidConfig(format!(
+            "invalid shared memory segment name {:?}",
+            name
+        )));
+    }
+
+    #[cfg(target_os = "linux")]
+    let dir = PathBuf::from("/dev/shm");
+    #[cfg(not(target_os = "linux"))]
+    let dir = std::env::temp_dir();
+
+    Ok(dir.join(format!("hyperlog-{}", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_between_handles() {
+        let name = format!("test-{}", std::process::id());
+        // Two handles on the segment, as two worker processes would hold
+        let first = ShmHyperLogLog::open(&name).unwrap();
+        let second = ShmHyperLogLog::open(&name).unwrap();
+
+        std::thread::scope(|s| {
+            s.spawn(|| (0..5_000).for_each(|i| first.add(i)));
+            s.spawn(|| (2_500..7_500).for_each(|i| second.add(i)));
+        });
+
+        let mut expected = HyperLogLog::new();
+        for i in 0..7_500 {
+            expected.add(i);
+        }
+        assert_eq!(first.estimate(), expected.estimate());
+        assert_eq!(second.estimate(), expected.estimate());
+
+        let mut other = HyperLogLog::new();
+        other.add("merged");
+        second.merge(&other);
+        expected.add("merged");
+        assert!(first.snapshot().diff(&expected).is_identical());
+
+        ShmHyperLogLog::unlink(&name).unwrap();
+        assert!(ShmHyperLogLog::open("").is_err());
+        assert!(ShmHyperLogLog::open("a/b").is_err());
+    }
+}
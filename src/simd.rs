@@ -0,0 +1,493 @@
+#[cfg(feature = "simd")]
+use packed_simd::{f32x16, f64x8, u8x16, u8x64};
+
+/// Computes `2^-rank` exactly by building the IEEE 754 representation directly,
+/// which is valid for every rank a `u8` register can hold.
+#[inline(always)]
+pub(crate) fn pow2_neg(rank: u8) -> f64 {
+    f64::from_bits((1023 - u64::from(rank)) << 52)
+}
+
+/// Computes `2^-rank` as a `f32` by building its IEEE 754 representation directly. Ranks
+/// beyond the smallest normal exponent, far above any rank `add` produces, are clamped to it.
+#[inline(always)]
+pub(crate) fn pow2_neg_f32(rank: u8) -> f32 {
+    f32::from_bits((127 - u32::from(rank.min(126))) << 23)
+}
+
+/// Number of 16-register chunks accumulated in the `f32` lanes of
+/// `harmonic_sum_and_zeros_f32` before they are flushed, which bounds the rounding error
+/// of summing many small values in single precision.
+const F32_FLUSH_CHUNKS: usize = 256;
+
+/// Merges `src` into `dst` by taking the lane-wise maximum of every register.
+///
+/// On x86_64 hosts supporting both AVX-512F and AVX-512BW, detected at runtime, 64
+/// registers are merged per instruction instead of 16.
+///
+/// # Arguments
+///
+/// * `dst`: The registers to be updated.
+/// * `src`: The registers to merge in, of the same length as `dst`.
+#[cfg(feature = "simd")]
+#[inline(always)]
+pub(crate) fn merge_max(dst: &mut [u8], src: &[u8]) {
+    debug_assert_eq!(dst.len(), src.len());
+
+    #[cfg(target_arch = "x86_64")]
+    if dst.len() >= 64
+        && std::is_x86_feature_detected!("avx512f")
+        && std::is_x86_feature_detected!("avx512bw")
+    {
+        // SAFETY: The host supports the target features enabled by `merge_max_avx512`
+        unsafe { merge_max_avx512(dst, src) };
+        return;
+    }
+
+    merge_max_u8x16(dst, src)
+}
+
+/// Scalar counterpart of `merge_max`, compiled without the `simd` feature.
+#[cfg(not(feature = "simd"))]
+#[inline(always)]
+pub(crate) fn merge_max(dst: &mut [u8], src: &[u8]) {
+    debug_assert_eq!(dst.len(), src.len());
+
+    for (d, s) in dst.iter_mut().zip(src) {
+        *d = (*d).max(*s);
+    }
+}
+
+/// Merges `src` into `dst` 64 registers at a time, compiled with AVX-512 enabled so
+/// every `u8x64` maximum is a single instruction.
+///
+/// # Safety
+///
+/// The host must support AVX-512F and AVX-512BW.
+#[cfg(all(target_arch = "x86_64", feature = "simd"))]
+#[target_feature(enable = "avx512f,avx512bw")]
+unsafe fn merge_max_avx512(dst: &mut [u8], src: &[u8]) {
+    merge_max_u8x64(dst, src)
+}
+
+/// Merges `src` into `dst` using 64-byte lanes.
+#[cfg(feature = "simd")]
+#[inline(always)]
+fn merge_max_u8x64(dst: &mut [u8], src: &[u8]) {
+    let aligned = is_aligned(dst, 64) && is_aligned(src, 64);
+    let mut dst_chunks = dst.chunks_exact_mut(64);
+    let mut src_chunks = src.chunks_exact(64);
+
+    for (d, s) in (&mut dst_chunks).zip(&mut src_chunks) {
+        let merged = load_u8x64(d, aligned).max(load_u8x64(s, aligned));
+        store_u8x64(merged, d, aligned);
+    }
+
+    // Fewer than 64 registers remain, which the narrower lanes handle
+    merge_max_u8x16(dst_chunks.into_remainder(), src_chunks.remainder())
+}
+
+/// Merges `src` into `dst` using 16-byte lanes.
+#[cfg(feature = "simd")]
+#[inline(always)]
+fn merge_max_u8x16(dst: &mut [u8], src: &[u8]) {
+    let aligned = is_aligned(dst, 16) && is_aligned(src, 16);
+    let mut dst_chunks = dst.chunks_exact_mut(16);
+    let mut src_chunks = src.chunks_exact(16);
+
+    for (d, s) in (&mut dst_chunks).zip(&mut src_chunks) {
+        let merged = load_u8x16(d, aligned).max(load_u8x16(s, aligned));
+        store_u8x16(merged, d, aligned);
+    }
+
+    // Process the remaining registers when the length is not a multiple of 16
+    for (d, s) in dst_chunks
+        .into_remainder()
+        .iter_mut()
+        .zip(src_chunks.remainder())
+    {
+        *d = (*d).max(*s);
+    }
+}
+
+/// Returns whether a slice starts on a multiple of `align` bytes. Every chunk of `align`
+/// bytes of such a slice can then be accessed with aligned vector loads and stores, e.g.
+/// the registers of an `AlignedRegisters` store.
+#[cfg(feature = "simd")]
+#[inline(always)]
+fn is_aligned(bytes: &[u8], align: usize) -> bool {
+    (bytes.as_ptr() as usize).is_multiple_of(align)
+}
+
+/// Loads 16 registers, with an aligned load if `aligned` is set.
+///
+/// # Arguments
+///
+/// * `chunk`: At least 16 registers, starting on a 16-byte boundary if `aligned` is set.
+/// * `aligned`: Whether `chunk` is known to be aligned, see `is_aligned`.
+#[cfg(feature = "simd")]
+#[inline(always)]
+fn load_u8x16(chunk: &[u8], aligned: bool) -> u8x16 {
+    match aligned {
+        // SAFETY: The caller checked the alignment, and the length is checked below
+        true if chunk.len() >= 16 => unsafe { u8x16::from_slice_aligned_unchecked(chunk) },
+        _ => u8x16::from_slice_unaligned(chunk),
+    }
+}
+
+/// Stores 16 registers, with an aligned store if `aligned` is set, see `load_u8x16`.
+#[cfg(feature = "simd")]
+#[inline(always)]
+fn store_u8x16(registers: u8x16, chunk: &mut [u8], aligned: bool) {
+    match aligned {
+        // SAFETY: The caller checked the alignment, and the length is checked below
+        true if chunk.len() >= 16 => unsafe { registers.write_to_slice_aligned_unchecked(chunk) },
+        _ => registers.write_to_slice_unaligned(chunk),
+    }
+}
+
+/// Loads 64 registers, with an aligned load if `aligned` is set, see `load_u8x16`.
+#[cfg(feature = "simd")]
+#[inline(always)]
+fn load_u8x64(chunk: &[u8], aligned: bool) -> u8x64 {
+    match aligned {
+        // SAFETY: The caller checked the alignment, and the length is checked below
+        true if chunk.len() >= 64 => unsafe { u8x64::from_slice_aligned_unchecked(chunk) },
+        _ => u8x64::from_slice_unaligned(chunk),
+    }
+}
+
+/// Stores 64 registers, with an aligned store if `aligned` is set, see `load_u8x16`.
+#[cfg(feature = "simd")]
+#[inline(always)]
+fn store_u8x64(registers: u8x64, chunk: &mut [u8], aligned: bool) {
+    match aligned {
+        // SAFETY: The caller checked the alignment, and the length is checked below
+        true if chunk.len() >= 64 => unsafe { registers.write_to_slice_aligned_unchecked(chunk) },
+        _ => registers.write_to_slice_unaligned(chunk),
+    }
+}
+
+/// Computes, in a single pass, the sum of `2^-register` over all registers, the
+/// denominator of the HyperLogLog raw estimate, and the number of registers that are
+/// still zero, used by linear counting.
+///
+/// # Arguments
+///
+/// * `registers`: The registers to scan.
+#[cfg(feature = "simd")]
+#[inline(always)]
+pub(crate) fn harmonic_sum_and_zeros(registers: &[u8]) -> (f64, usize) {
+    let chunks = registers.chunks_exact(16);
+    let remainder = chunks.remainder();
+    let aligned = is_aligned(registers, 16);
+    let zero = u8x16::splat(0);
+    let mut z = f64x8::splat(0.0);
+    let mut count = 0;
+
+    for c in chunks {
+        count += load_u8x16(c, aligned).eq(zero).bitmask().count_ones() as usize;
+
+        for half in c.chunks_exact(8) {
+            z += f64x8::new(
+                pow2_neg(half[0]),
+                pow2_neg(half[1]),
+                pow2_neg(half[2]),
+                pow2_neg(half[3]),
+                pow2_neg(half[4]),
+                pow2_neg(half[5]),
+                pow2_neg(half[6]),
+                pow2_neg(half[7]),
+            );
+        }
+    }
+
+    let sum = z.sum() + remainder.iter().map(|&r| pow2_neg(r)).sum::<f64>();
+
+    (sum, count + remainder.iter().filter(|&&r| r == 0).count())
+}
+
+/// Counts the registers that are zero.
+///
+/// # Arguments
+///
+/// * `registers`: The registers to scan.
+#[cfg(feature = "simd")]
+#[inline(always)]
+pub(crate) fn count_zeros(registers: &[u8]) -> usize {
+    let chunks = registers.chunks_exact(16);
+    let remainder = chunks.remainder();
+    let aligned = is_aligned(registers, 16);
+    let zero = u8x16::splat(0);
+
+    chunks
+        .map(|c| load_u8x16(c, aligned).eq(zero).bitmask().count_ones() as usize)
+        .sum::<usize>()
+        + remainder.iter().filter(|&&r| r == 0).count()
+}
+
+/// Scalar counterpart of `count_zeros`, compiled without the `simd` feature.
+#[cfg(not(feature = "simd"))]
+#[inline(always)]
+pub(crate) fn count_zeros(registers: &[u8]) -> usize {
+    registers.iter().filter(|&&r| r == 0).count()
+}
+
+/// Computes `harmonic_sum_and_zeros` of the union of two register arrays, i.e. of their
+/// lane-wise maximum, without materializing the merged registers.
+///
+/// # Arguments
+///
+/// * `a`: The registers of the first sketch.
+/// * `b`: The registers of the second sketch, of the same length as `a`.
+#[cfg(feature = "simd")]
+#[inline(always)]
+pub(crate) fn union_harmonic_sum_and_zeros(a: &[u8], b: &[u8]) -> (f64, usize) {
+    debug_assert_eq!(a.len(), b.len());
+
+    let a_chunks = a.chunks_exact(16);
+    let b_chunks = b.chunks_exact(16);
+    let remainder = a_chunks
+        .remainder()
+        .iter()
+        .zip(b_chunks.remainder())
+        .map(|(&a, &b)| a.max(b));
+    let aligned = is_aligned(a, 16) && is_aligned(b, 16);
+    let zero = u8x16::splat(0);
+    let mut z = f64x8::splat(0.0);
+    let mut count = 0;
+    let mut merged = [0u8; 16];
+
+    for (a, b) in a_chunks.zip(b_chunks) {
+        let max = load_u8x16(a, aligned).max(load_u8x16(b, aligned));
+        count += max.eq(zero).bitmask().count_ones() as usize;
+        max.write_to_slice_unaligned(&mut merged);
+
+        for half in merged.chunks_exact(8) {
+            z += f64x8::new(
+                pow2_neg(half[0]),
+                pow2_neg(half[1]),
+                pow2_neg(half[2]),
+                pow2_neg(half[3]),
+                pow2_neg(half[4]),
+                pow2_neg(half[5]),
+                pow2_neg(half[6]),
+                pow2_neg(half[7]),
+            );
+        }
+    }
+
+    remainder.fold((z.sum(), count), |(sum, count), r| {
+        (sum + pow2_neg(r), count + (r == 0) as usize)
+    })
+}
+
+/// Single precision counterpart of `harmonic_sum_and_zeros`, summing 16 registers per
+/// vector instead of 8.
+///
+/// # Arguments
+///
+/// * `registers`: The registers to scan.
+#[cfg(feature = "simd")]
+#[inline(always)]
+pub(crate) fn harmonic_sum_and_zeros_f32(registers: &[u8]) -> (f32, usize) {
+    let chunks = registers.chunks_exact(16);
+    let remainder = chunks.remainder();
+    let aligned = is_aligned(registers, 16);
+    let zero = u8x16::splat(0);
+    let mut z = f32x16::splat(0.0);
+    let mut sum = 0.0;
+    let mut count = 0;
+
+    for (i, c) in chunks.enumerate() {
+        count += load_u8x16(c, aligned).eq(zero).bitmask().count_ones() as usize;
+
+        z += f32x16::new(
+            pow2_neg_f32(c[0]),
+            pow2_neg_f32(c[1]),
+            pow2_neg_f32(c[2]),
+            pow2_neg_f32(c[3]),
+            pow2_neg_f32(c[4]),
+            pow2_neg_f32(c[5]),
+            pow2_neg_f32(c[6]),
+            pow2_neg_f32(c[7]),
+            pow2_neg_f32(c[8]),
+            pow2_neg_f32(c[9]),
+            pow2_neg_f32(c[10]),
+            pow2_neg_f32(c[11]),
+            pow2_neg_f32(c[12]),
+            pow2_neg_f32(c[13]),
+            pow2_neg_f32(c[14]),
+            pow2_neg_f32(c[15]),
+        );
+
+        if (i + 1) % F32_FLUSH_CHUNKS == 0 {
+            sum += z.sum();
+            z = f32x16::splat(0.0);
+        }
+    }
+
+    let sum = sum + z.sum() + remainder.iter().map(|&r| pow2_neg_f32(r)).sum::<f32>();
+
+    (sum, count + remainder.iter().filter(|&&r| r == 0).count())
+}
+
+/// Scalar counterpart of `harmonic_sum_and_zeros`, compiled without the `simd` feature.
+/// The sum is spread over eight accumulators like the lanes of the vectorized kernel.
+#[cfg(not(feature = "simd"))]
+#[inline(always)]
+pub(crate) fn harmonic_sum_and_zeros(registers: &[u8]) -> (f64, usize) {
+    let mut lanes = [0.0; 8];
+    let mut count = 0;
+
+    for chunk in registers.chunks(8) {
+        for (lane, &r) in lanes.iter_mut().zip(chunk) {
+            *lane += pow2_neg(r);
+            count += (r == 0) as usize;
+        }
+    }
+
+    (lanes.iter().sum(), count)
+}
+
+/// Scalar counterpart of `union_harmonic_sum_and_zeros`, compiled without the `simd`
+/// feature.
+#[cfg(not(feature = "simd"))]
+#[inline(always)]
+pub(crate) fn union_harmonic_sum_and_zeros(a: &[u8], b: &[u8]) -> (f64, usize) {
+    debug_assert_eq!(a.len(), b.len());
+
+    let mut lanes = [0.0; 8];
+    let mut count = 0;
+
+    for (a, b) in a.chunks(8).zip(b.chunks(8)) {
+        for ((lane, &a), &b) in lanes.iter_mut().zip(a).zip(b) {
+            let r = a.max(b);
+            *lane += pow2_neg(r);
+            count += (r == 0) as usize;
+        }
+    }
+
+    (lanes.iter().sum(), count)
+}
+
+/// Scalar counterpart of `harmonic_sum_and_zeros_f32`, compiled without the `simd`
+/// feature.
+#[cfg(not(feature = "simd"))]
+#[inline(always)]
+pub(crate) fn harmonic_sum_and_zeros_f32(registers: &[u8]) -> (f32, usize) {
+    let mut lanes = [0.0f32; 16];
+    let mut sum = 0.0;
+    let mut count = 0;
+
+    for (i, chunk) in registers.chunks(16).enumerate() {
+        for (lane, &r) in lanes.iter_mut().zip(chunk) {
+            *lane += pow2_neg_f32(r);
+            count += (r == 0) as usize;
+        }
+
+        if (i + 1) % F32_FLUSH_CHUNKS == 0 {
+            sum += lanes.iter().sum::<f32>();
+            lanes = [0.0; 16];
+        }
+    }
+
+    (sum + lanes.iter().sum::<f32>(), count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pow2_neg() {
+        for rank in 0..=u8::MAX {
+            assert_eq!(pow2_neg(rank), 2f64.powi(-i32::from(rank)));
+        }
+    }
+
+    #[test]
+    fn test_pow2_neg_f32() {
+        for rank in 0..=126 {
+            assert_eq!(pow2_neg_f32(rank), 2f32.powi(-i32::from(rank)));
+        }
+        assert_eq!(pow2_neg_f32(u8::MAX), pow2_neg_f32(126));
+    }
+
+    #[test]
+    fn test_kernels_with_remainder() {
+        let mut a = (0..37).map(|i| (i % 5) as u8).collect::<Vec<_>>();
+        let b = (0..37).map(|i| (i % 3) as u8).collect::<Vec<_>>();
+
+        assert_eq!(
+            harmonic_sum_and_zeros(&a),
+            (a.iter().map(|&r| 2f64.powi(-i32::from(r))).sum::<f64>(), 8)
+        );
+        assert_eq!(
+            harmonic_sum_and_zeros_f32(&a),
+            (a.iter().map(|&r| 2f32.powi(-i32::from(r))).sum::<f32>(), 8)
+        );
+
+        assert_eq!(count_zeros(&a), 8);
+
+        let mut union = a.clone();
+        merge_max(&mut union, &b);
+        assert_eq!(
+            union_harmonic_sum_and_zeros(&a, &b),
+            harmonic_sum_and_zeros(&union)
+        );
+
+        merge_max(&mut a, &b);
+        for (i, &r) in a.iter().enumerate() {
+            assert_eq!(r, ((i % 5) as u8).max((i % 3) as u8));
+        }
+    }
+
+    #[cfg(feature = "simd")]
+    #[test]
+    fn test_merge_max_lane_widths() {
+        let src = (0..200).map(|i| (i % 7) as u8).collect::<Vec<_>>();
+        let dst = (0..200).map(|i| (i % 11) as u8).collect::<Vec<_>>();
+        let expected = dst
+            .iter()
+            .zip(&src)
+            .map(|(&d, &s)| d.max(s))
+            .collect::<Vec<_>>();
+
+        let mut narrow = dst.clone();
+        merge_max_u8x16(&mut narrow, &src);
+        assert_eq!(narrow, expected);
+
+        let mut wide = dst.clone();
+        merge_max_u8x64(&mut wide, &src);
+        assert_eq!(wide, expected);
+
+        let mut dispatched = dst;
+        merge_max(&mut dispatched, &src);
+        assert_eq!(dispatched, expected);
+    }
+
+    #[cfg(feature = "simd")]
+    #[test]
+    fn test_aligned_kernels() {
+        use crate::registers::AlignedRegisters;
+
+        let src = AlignedRegisters::<200>(std::array::from_fn(|i| (i % 7) as u8));
+        let mut dst = AlignedRegisters::<200>(std::array::from_fn(|i| (i % 11) as u8));
+        let mut unaligned = dst.to_vec();
+        assert!(is_aligned(&src, 64) && is_aligned(&dst, 64));
+
+        assert_eq!(
+            harmonic_sum_and_zeros(&dst),
+            harmonic_sum_and_zeros(&unaligned)
+        );
+        assert_eq!(
+            union_harmonic_sum_and_zeros(&dst, &src),
+            union_harmonic_sum_and_zeros(&unaligned, &src)
+        );
+
+        merge_max_u8x64(&mut dst, &src);
+        merge_max(&mut unaligned, &src);
+        assert_eq!(&dst[..], &unaligned[..]);
+    }
+}
@@ -0,0 +1,210 @@
+use std::hash::Hash;
+
+use crate::{registers::RegistersMut, HyperLogLog, HyperLogLogPlusPlus};
+
+/// Operations shared by all cardinality sketches in this crate, so helpers can be written
+/// once and used with either `HyperLogLog` or `HyperLogLogPlusPlus`.
+pub trait Sketch {
+    /// Adds an item to the sketch.
+    ///
+    /// # Parameters
+    /// * `item`: The item to be added. It should implement the `Hash` trait.
+    fn add<T: Hash>(&mut self, item: T);
+
+    /// Estimates the number of unique items added to the sketch.
+    ///
+    /// # Returns
+    /// An approximate count (as `f64`) of unique items added.
+    fn estimate(&self) -> f64;
+
+    /// Merges the state of another sketch of the same type into this one.
+    ///
+    /// # Parameters
+    /// * `other`: The sketch whose state is to be merged into this one.
+    fn merge(&mut self, other: &Self);
+
+    /// Resets the sketch to its empty state, keeping its allocations for reuse.
+    fn clear(&mut self);
+}
+
+/// Estimates the number of distinct items yielded by an iterator, using a temporary
+/// `HyperLogLog` fed through the bulk path of `HyperLogLog::add_all`.
+///
+/// # Parameters
+/// * `iter`: The items to count.
+///
+/// # Returns
+/// The estimate, a `f64` approximate count of distinct items.
+///
+/// # Examples
+///
+/// ```ignore
+/// let distinct = estimate_distinct(lines);
+/// ```
+pub fn estimate_distinct<I>(iter: I) -> f64
+where
+    I: IntoIterator,
+    I::Item: Hash,
+{
+    let mut sketch = HyperLogLog::new();
+    sketch.add_all(iter);
+
+    sketch.estimate()
+}
+
+/// Estimates the number of distinct items yielded by an iterator, using a temporary
+/// `HyperLogLog`, see `estimate_distinct`.
+///
+/// # Parameters
+/// * `iter`: The items to count.
+///
+/// # Returns
+/// The estimate, rounded to the nearest integer.
+///
+/// # Examples
+///
+/// ```ignore
+/// let distinct = count_distinct(["a", "b", "a"]);
+/// ```
+pub fn count_distinct<I>(iter: I) -> u64
+where
+    I: IntoIterator,
+    I::Item: Hash,
+{
+    estimate_distinct(iter).round() as u64
+}
+
+/// Estimates the number of distinct items yielded by an iterator, using a temporary
+/// `HyperLogLogPlusPlus`.
+///
+/// # Parameters
+/// * `iter`: The items to count.
+///
+/// # Returns
+/// The estimate, rounded to the nearest integer.
+pub fn count_distinct_plusplus<I>(iter: I) -> u64
+where
+    I: IntoIterator,
+    I::Item: Hash,
+{
+    count_distinct_with::<HyperLogLogPlusPlus, _>(iter)
+}
+
+/// Estimates the number of distinct items yielded by an iterator, using a temporary
+/// sketch of type `S`.
+///
+/// # Parameters
+/// * `iter`: The items to count.
+///
+/// # Returns
+/// The estimate, rounded to the nearest integer.
+pub fn count_distinct_with<S, I>(iter: I) -> u64
+where
+    S: Sketch + Default,
+    I: IntoIterator,
+    I::Item: Hash,
+{
+    let mut sketch = S::default();
+    for item in iter {
+        sketch.add(item);
+    }
+
+    sketch.estimate().round() as u64
+}
+
+impl<R: RegistersMut> Sketch for HyperLogLog<R> {
+    #[inline(always)]
+    fn add<T: Hash>(&mut self, item: T) {
+        HyperLogLog::add(self, item)
+    }
+
+    #[inline(always)]
+    fn estimate(&self) -> f64 {
+        HyperLogLog::estimate(self)
+    }
+
+    #[inline(always)]
+    fn merge(&mut self, other: &Self) {
+        HyperLogLog::merge(self, other)
+    }
+
+    #[inline(always)]
+    fn clear(&mut self) {
+        HyperLogLog::clear(self)
+    }
+}
+
+impl<R: RegistersMut> Sketch for HyperLogLogPlusPlus<R> {
+    #[inline(always)]
+    fn add<T: Hash>(&mut self, item: T) {
+        HyperLogLogPlusPlus::add(self, item)
+    }
+
+    #[inline(always)]
+    fn estimate(&self) -> f64 {
+        HyperLogLogPlusPlus::estimate(self)
+    }
+
+    #[inline(always)]
+    fn merge(&mut self, other: &Self) {
+        HyperLogLogPlusPlus::merge(self, other)
+    }
+
+    #[inline(always)]
+    fn clear(&mut self) {
+        HyperLogLogPlusPlus::clear(self)
+    }
+}
+
+impl<S: Sketch> Sketch for &mut S {
+    #[inline(always)]
+    fn add<T: Hash>(&mut self, item: T) {
+        S::add(self, item)
+    }
+
+    #[inline(always)]
+    fn estimate(&self) -> f64 {
+        S::estimate(self)
+    }
+
+    #[inline(always)]
+    fn merge(&mut self, other: &Self) {
+        S::merge(self, other)
+    }
+
+    #[inline(always)]
+    fn clear(&mut self) {
+        S::clear(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::M;
+
+    #[test]
+    fn test_estimate_distinct() {
+        assert_eq!(estimate_distinct(Vec::<u64>::new()), 0.0);
+        assert_eq!(estimate_distinct(["a", "b", "a"]).round(), 2.0);
+
+        let estimate = estimate_distinct((0..200_000).map(|i| i % 100_000));
+        assert!(
+            (estimate - 100_000.0).abs() / 100_000.0 < 4.0 * 1.04 / (M as f64).sqrt(),
+            "{}",
+            estimate
+        );
+    }
+
+    #[test]
+    fn test_count_distinct() {
+        let bound = 4.0 * 1.04 / (M as f64).sqrt();
+        assert_eq!(count_distinct(["a", "b", "a", "c"]), 3);
+
+        let estimate = count_distinct_plusplus(0..100);
+        assert!((estimate as f64 - 100.0).abs() / 100.0 < bound);
+
+        let estimate = count_distinct((0..100_000).map(|i| i % 50_000));
+        assert!((estimate as f64 - 50_000.0).abs() / 50_000.0 < bound);
+    }
+}
@@ -0,0 +1,218 @@
+use std::{
+    hash::{Hash, Hasher},
+    path::Path,
+};
+
+use seahash::SeaHasher;
+use sled::{IVec, Tree};
+
+use crate::{metadata::SketchMetadata, registers::RegistersMut, Error, HyperLogLog, M};
+
+/// A durable store of per-key HyperLogLogs, embedded in a sled tree.
+///
+/// Every value holds the raw registers of one sketch. Writes never read the sketch back
+/// into the application: `add` merges in the 8-byte hash of the item and `merge` the
+/// registers of a whole sketch, both through the merge operator installed by `new`, which
+/// sled applies atomically to the stored value.
+///
+/// The merge operator is not persisted by sled, so trees holding sketches must always be
+/// opened through this type.
+#[derive(Debug, Clone)]
+pub struct SledHllStore {
+    tree: Tree,
+}
+
+impl SledHllStore {
+    /// Creates a store on top of a sled tree, installing the merge operator on it.
+    ///
+    /// # Parameters
+    /// * `tree`: The tree holding the sketches, e.g. `db.open_tree("sketches")`.
+    ///
+    /// # Returns
+    /// A new `SledHllStore` instance.
+    pub fn new(tree: Tree) -> Self {
+        tree.set_merge_operator(merge_operator);
+
+        Self { tree }
+    }
+
+    /// Opens or creates a sled database at `path` and stores the sketches in its default
+    /// tree.
+    ///
+    /// # Parameters
+    /// * `path`: The directory of the database.
+    ///
+    /// # Returns
+    /// A new `SledHllStore` instance, or an `Error::Storage` if the database cannot be
+    /// opened.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let db = sled::open(path)?;
+
+        Ok(Self::new((*db).clone()))
+    }
+
+    /// Adds an item to the sketch of a key, creating the sketch if needed.
+    ///
+    /// # Parameters
+    /// * `key`: The key of the sketch.
+    /// * `item`: An item that implements the `Hash` trait to be added.
+    ///
+    /// # Returns
+    /// `Ok(())`, or an `Error::Storage` if the write fails.
+    pub fn add<K: AsRef<[u8]>, T: Hash>(&self, key: K, item: T) -> Result<(), Error> {
+        let mut hasher = SeaHasher::new();
+        item.hash(&mut hasher);
+        self.tree.merge(key, hasher.finish().to_le_bytes())?;
+
+        Ok(())
+    }
+
+    /// Merges a sketch into the sketch of a key, creating the latter if needed.
+    ///
+    /// # Parameters
+    /// * `key`: The key of the sketch.
+    /// * `hll`: The sketch to merge in, built with the default hasher and seed `add` uses.
+    ///
+    /// # Returns
+    /// `Ok(())`, an `Error::Incompatible` if `hll` was built with other metadata, or an
+    /// `Error::Storage` if the write fails.
+    pub fn merge<K: AsRef<[u8]>>(&self, key: K, hll: &HyperLogLog) -> Result<(), Error> {
+        SketchMetadata::default().check_compatible(&hll.metadata())?;
+        self.tree.merge(key, &hll.registers[..])?;
+
+        Ok(())
+    }
+
+    /// Reads the sketch of a key.
+    ///
+    /// # Parameters
+    /// * `key`: The key of the sketch.
+    ///
+    /// # Returns
+    /// The sketch, `None` if nothing was added for `key`, or an `Error` if the read fails or
+    /// the stored value is not a sketch.
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<HyperLogLog>, Error> {
+        self.tree
+            .get(key)?
+            .map(|value| HyperLogLog::try_from_bytes(&value))
+            .transpose()
+    }
+
+    /// Estimates the number of unique items added for a key.
+    ///
+    /// # Parameters
+    /// * `key`: The key of the sketch.
+    ///
+    /// # Returns
+    /// A `f64` approximate count of unique items, `0.0` if nothing was added for `key`, or
+    /// an `Error` if the read fails or the stored value is not a sketch.
+    pub fn estimate<K: AsRef<[u8]>>(&self, key: K) -> Result<f64, Error> {
+        Ok(self.get(key)?.map_or(0.0, |hll| hll.estimate()))
+    }
+
+    /// Removes the sketch of a key.
+    ///
+    /// # Parameters
+    /// * `key`: The key of the sketch.
+    ///
+    /// # Returns
+    /// `Ok(())`, or an `Error::Storage` if the write fails.
+    pub fn remove<K: AsRef<[u8]>>(&self, key: K) -> Result<(), Error> {
+        self.tree.remove(key)?;
+
+        Ok(())
+    }
+
+    /// Iterates over all keys and their sketches, in key order.
+    ///
+    /// # Returns
+    /// An iterator over the keys and sketches, yielding an `Error` for every failed read or
+    /// stored value that is not a sketch.
+    pub fn iter(&self) -> impl Iterator<Item = Result<(IVec, HyperLogLog), Error>> {
+        self.tree.iter().map(|entry| {
+            let (key, value) = entry?;
+
+            Ok((key, HyperLogLog::try_from_bytes(&value)?))
+        })
+    }
+
+    /// Returns the underlying sled tree.
+    pub fn tree(&self) -> &Tree {
+        &self.tree
+    }
+}
+
+/// Applies an operand written by `add` (an 8-byte hash) or `merge` (`M` registers) to the
+/// stored registers. Operands of any other length are ignored.
+fn merge_operator(_key: &[u8], existing: Option<&[u8]>, operand: &[u8]) -> Option<Vec<u8>> {
+    let mut registers = match existing {
+        Some(value) if value.len() == M => value.to_vec(),
+        Some(value) => return Some(value.to_vec()),
+        None => vec![0; M],
+    };
+
+    match operand.len() {
+        8 => {
+            let hash = u64::from_le_bytes(operand.try_into().expect("8-byte operand"));
+            let registers: &mut [u8; M] = registers.as_mut_slice().try_into().ok()?;
+            HyperLogLog::with_registers(registers).add_hash(hash);
+        }
+        M => registers.merge_max(operand),
+        _ => return existing.map(<[u8]>::to_vec),
+    }
+
+    Some(registers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let store = SledHllStore::new((*db).clone());
+
+        let mut expected = HyperLogLog::new();
+        for i in 0..1_000 {
+            store.add("users", i).unwrap();
+            expected.add(i);
+        }
+        assert!(store
+            .get("users")
+            .unwrap()
+            .unwrap()
+            .diff(&expected)
+            .is_identical());
+
+        let mut other = HyperLogLog::new();
+        for i in 1_000..6_000 {
+            other.add(i);
+        }
+        store.merge("users", &other).unwrap();
+        store.merge("sessions", &other).unwrap();
+        expected.merge(&other);
+        assert!(store
+            .get("users")
+            .unwrap()
+            .unwrap()
+            .diff(&expected)
+            .is_identical());
+
+        assert_eq!(store.estimate("missing").unwrap(), 0.0);
+        let keys = store
+            .iter()
+            .map(|entry| entry.unwrap().0.to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, [b"sessions".to_vec(), b"users".to_vec()]);
+
+        let seeded = HyperLogLog::with_seed([1, 2, 3, 4]);
+        assert!(matches!(
+            store.merge("users", &seeded),
+            Err(Error::Incompatible(_))
+        ));
+
+        store.remove("sessions").unwrap();
+        assert!(store.get("sessions").unwrap().is_none());
+    }
+}
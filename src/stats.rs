@@ -0,0 +1,174 @@
+use crate::{hll, registers::RegistersMut};
+
+/// Operational counters maintained by a sketch when the `stats` feature is enabled.
+///
+/// Counters are carried over on merge, so a sketch produced by merging shards reports
+/// the totals of all of them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SketchStats {
+    /// Total number of items added.
+    pub adds: u64,
+    /// Number of added items that raised at least one register.
+    pub raising_adds: u64,
+    /// Number of merges performed, including those recorded by merged sketches.
+    pub merges: u64,
+}
+
+impl SketchStats {
+    /// Creates counters with nothing recorded yet, usable in constant expressions.
+    pub const fn new() -> Self {
+        Self {
+            adds: 0,
+            raising_adds: 0,
+            merges: 0,
+        }
+    }
+
+    /// Records an added item and whether it raised a register.
+    #[inline(always)]
+    pub(crate) fn record_add(&mut self, raised: bool) {
+        self.adds += 1;
+        self.raising_adds += raised as u64;
+    }
+
+    /// Records a merge with a sketch carrying the `other` counters.
+    #[inline(always)]
+    pub(crate) fn record_merge(&mut self, other: &SketchStats) {
+        self.adds += other.adds;
+        self.raising_adds += other.raising_adds;
+        self.merges += other.merges + 1;
+    }
+
+    /// Fraction of added items that raised a register.
+    ///
+    /// A low value means most items were duplicates or the registers are saturated, and
+    /// that further adds barely change the state of the sketch.
+    ///
+    /// # Returns
+    /// A `f64` in `[0, 1]`, or `0.0` when nothing has been added yet.
+    pub fn raising_ratio(&self) -> f64 {
+        if self.adds == 0 {
+            return 0.0;
+        }
+
+        self.raising_adds as f64 / self.adds as f64
+    }
+}
+
+/// Empirical distribution of the relative error of the HyperLogLog estimator, as sampled
+/// by `simulate_error`.
+///
+/// Relative errors are `(estimate - cardinality) / cardinality`; the percentiles and the
+/// maximum are taken over their absolute values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ErrorDistribution {
+    /// Number of simulated sketches.
+    pub trials: usize,
+    /// Mean relative error, positive when the estimator overestimates.
+    pub bias: f64,
+    /// Root mean square of the relative errors.
+    pub rmse: f64,
+    /// Median absolute relative error.
+    pub p50: f64,
+    /// 90th percentile of the absolute relative errors.
+    pub p90: f64,
+    /// 99th percentile of the absolute relative errors.
+    pub p99: f64,
+    /// Largest absolute relative error observed.
+    pub max: f64,
+}
+
+/// Samples the error distribution of the HyperLogLog estimator at a given precision and
+/// cardinality, e.g. to find the p99 error to expect at 10 million uniques with 14 index
+/// bits before picking a precision.
+///
+/// Every trial fills a fresh sketch of `2^precision` registers with `cardinality` random
+/// 64-bit hashes, standing in for the hashes of distinct items, and estimates it. Trials
+/// are seeded deterministically, so results are reproducible. The cost grows with
+/// `cardinality * trials`.
+///
+/// # Parameters
+/// * `precision`: The number of index bits, between 4 and 24.
+/// * `cardinality`: The number of distinct items per trial, at least 1.
+/// * `trials`: The number of sketches to simulate, at least 1.
+///
+/// # Returns
+/// The `ErrorDistribution` of the relative errors.
+///
+/// # Panics
+/// Panics if any parameter is out of range.
+pub fn simulate_error(precision: u8, cardinality: u64, trials: usize) -> ErrorDistribution {
+    assert!(
+        (4..=24).contains(&precision),
+        "precision must be between 4 and 24"
+    );
+    assert!(
+        cardinality > 0 && trials > 0,
+        "cardinality and trials must be positive"
+    );
+
+    let p = precision as usize;
+    let mut registers = vec![0u8; 1 << p];
+    let mut errors = Vec::with_capacity(trials);
+
+    for trial in 0..trials {
+        registers.clear();
+        registers.resize(1 << p, 0);
+
+        let mut state = trial as u64;
+        for _ in 0..cardinality {
+            let hash = splitmix64(&mut state);
+            registers.update_max(hash as usize & ((1 << p) - 1), hll::rank(hash, p));
+        }
+
+        let estimate = hll::estimate_registers(&registers);
+        errors.push((estimate - cardinality as f64) / cardinality as f64);
+    }
+
+    let n = trials as f64;
+    let bias = errors.iter().sum::<f64>() / n;
+    let rmse = (errors.iter().map(|e| e * e).sum::<f64>() / n).sqrt();
+
+    let mut absolute = errors.iter().map(|e| e.abs()).collect::<Vec<_>>();
+    absolute.sort_by(f64::total_cmp);
+    let percentile = |q: f64| absolute[((q * n).ceil() as usize).clamp(1, trials) - 1];
+
+    ErrorDistribution {
+        trials,
+        bias,
+        rmse,
+        p50: percentile(0.5),
+        p90: percentile(0.9),
+        p99: percentile(0.99),
+        max: absolute[trials - 1],
+    }
+}
+
+/// SplitMix64, a small generator whose outputs are uniformly distributed 64-bit values.
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simulate_error() {
+        let distribution = simulate_error(12, 100_000, 30);
+
+        // The standard error at 4096 registers is 1.04 / 64, about 1.6%
+        assert_eq!(distribution.trials, 30);
+        assert!(distribution.rmse < 0.03, "{:?}", distribution);
+        assert!(distribution.bias.abs() < 0.01, "{:?}", distribution);
+        assert!(distribution.p50 <= distribution.p90);
+        assert!(distribution.p90 <= distribution.p99);
+        assert!(distribution.p99 <= distribution.max);
+
+        assert_eq!(simulate_error(12, 100_000, 30), distribution);
+    }
+}
@@ -0,0 +1,513 @@
+use std::{
+    borrow::Borrow,
+    collections::{HashMap, HashSet},
+    hash::{Hash, Hasher},
+    mem,
+    sync::PoisonError,
+};
+
+// In test builds with `--cfg loom`, locks and reference counts are loom's instrumented
+// counterparts, so that its model tests can explore every interleaving.
+#[cfg(all(test, loom))]
+use loom::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(not(all(test, loom)))]
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use seahash::SeaHasher;
+
+use crate::{hash, metadata::SketchMetadata, HyperLogLog};
+
+/// A thread-safe, cheaply clonable handle to a `HyperLogLog`.
+///
+/// All clones share the same sketch, so it can be handed to as many threads as needed.
+/// Items are hashed before the lock is taken, which keeps the exclusive section of `add`
+/// down to a single register update, and `estimate` only needs shared access.
+///
+/// A panic while the lock is held cannot leave the registers in an inconsistent state,
+/// so lock poisoning is ignored.
+#[derive(Debug, Clone)]
+pub struct SharedHyperLogLog {
+    inner: Arc<RwLock<HyperLogLog>>,
+    /// Metadata of the shared sketch, selecting the hash function applied before locking.
+    metadata: SketchMetadata,
+}
+
+impl SharedHyperLogLog {
+    /// Creates a new shared HyperLogLog with all registers initialized to zero.
+    ///
+    /// # Returns
+    /// A new `SharedHyperLogLog` instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an item to the shared HyperLogLog.
+    ///
+    /// # Parameters
+    /// * `item`: An item that implements the `Hash` trait to be added.
+    #[inline(always)]
+    pub fn add<T: Hash>(&self, item: T) {
+        let hash = hash::hash_item(&self.metadata, &item);
+
+        self.write().add_hash(hash);
+    }
+
+    /// Provides an estimate of the number of unique items added by all handles.
+    ///
+    /// # Returns
+    /// A `f64` approximate count of unique items.
+    pub fn estimate(&self) -> f64 {
+        self.read().estimate()
+    }
+
+    /// Merges a HyperLogLog into the shared one.
+    ///
+    /// # Parameters
+    /// * `other`: A reference to the `HyperLogLog` instance to be merged.
+    pub fn merge(&self, other: &HyperLogLog) {
+        self.write().merge(other);
+    }
+
+    /// Merges another shared HyperLogLog into this one. Merging a handle into a clone of
+    /// itself is a no-op.
+    ///
+    /// # Parameters
+    /// * `other`: A reference to the `SharedHyperLogLog` instance to be merged.
+    pub fn merge_shared(&self, other: &SharedHyperLogLog) {
+        if Arc::ptr_eq(&self.inner, &other.inner) {
+            return;
+        }
+
+        // Copy the other sketch first so that both locks are never held at once, which
+        // could deadlock with a concurrent merge in the opposite direction.
+        let other = other.snapshot();
+        self.merge(&other);
+    }
+
+    /// Returns a copy of the current state of the sketch.
+    ///
+    /// # Returns
+    /// An owned `HyperLogLog` holding the registers at the time of the call.
+    pub fn snapshot(&self) -> HyperLogLog {
+        self.read().clone()
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, HyperLogLog> {
+        self.inner.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, HyperLogLog> {
+        self.inner.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl From<HyperLogLog> for SharedHyperLogLog {
+    /// Wraps an existing `HyperLogLog` so it can be shared between threads.
+    fn from(hll: HyperLogLog) -> Self {
+        Self {
+            metadata: hll.metadata(),
+            inner: Arc::new(RwLock::new(hll)),
+        }
+    }
+}
+
+impl Default for SharedHyperLogLog {
+    fn default() -> Self {
+        Self::from(HyperLogLog::new())
+    }
+}
+
+/// Number of shards of a `ConcurrentHllMap` created with `ConcurrentHllMap::new`.
+pub const DEFAULT_SHARDS: usize = 64;
+
+/// Number of distinct hashes a key tracks exactly before it switches to a `HyperLogLog`,
+/// for maps created with `ConcurrentHllMap::new`.
+pub const DEFAULT_SPARSE_LIMIT: usize = 4096;
+
+/// The sketch of a single key: the exact set of hashes while it is small, so that the
+/// many keys of a multi-tenant service seeing few items do not take 1 MiB each.
+#[derive(Debug, Clone)]
+enum KeySketch {
+    Sparse(HashSet<u64>),
+    Dense(Box<HyperLogLog>),
+}
+
+impl KeySketch {
+    fn add_hash(&mut self, hash: u64, sparse_limit: usize) {
+        match self {
+            KeySketch::Sparse(hashes) => {
+                hashes.insert(hash);
+                if hashes.len() > sparse_limit {
+                    *self = KeySketch::Dense(Box::new(self.to_hyperloglog()));
+                }
+            }
+            KeySketch::Dense(hll) => hll.add_hash(hash),
+        }
+    }
+
+    fn estimate(&self) -> f64 {
+        match self {
+            KeySketch::Sparse(hashes) => hashes.len() as f64,
+            KeySketch::Dense(hll) => hll.estimate(),
+        }
+    }
+
+    fn to_hyperloglog(&self) -> HyperLogLog {
+        match self {
+            KeySketch::Sparse(hashes) => {
+                let mut hll = HyperLogLog::new();
+                for &hash in hashes {
+                    hll.add_hash(hash);
+                }
+                hll
+            }
+            KeySketch::Dense(hll) => (**hll).clone(),
+        }
+    }
+
+    fn into_hyperloglog(self) -> HyperLogLog {
+        match self {
+            KeySketch::Dense(hll) => *hll,
+            sparse => sparse.to_hyperloglog(),
+        }
+    }
+}
+
+/// A keyed collection of distinct counters that many threads can add to concurrently, the
+/// typical shape of a multi-tenant counting service.
+///
+/// Keys are spread over independently locked shards, so threads only contend when they
+/// update keys of the same shard, and items are hashed before any lock is taken. Every key
+/// starts out tracking the exact set of its hashes, and only switches to a 1 MiB
+/// `HyperLogLog` once it has seen more than `sparse_limit` distinct items, which bounds the
+/// memory taken by the long tail of small keys. `drain_merged` hands out the sketches of
+/// all keys and starts over, e.g. at the end of every reporting period.
+///
+/// A panic while a shard is locked cannot leave its sketches in an inconsistent state, so
+/// lock poisoning is ignored.
+#[derive(Debug)]
+pub struct ConcurrentHllMap<K> {
+    shards: Box<[Mutex<HashMap<K, KeySketch>>]>,
+    sparse_limit: usize,
+}
+
+impl<K: Eq + Hash> ConcurrentHllMap<K> {
+    /// Creates an empty map with `DEFAULT_SHARDS` shards and a sparse limit of
+    /// `DEFAULT_SPARSE_LIMIT`.
+    ///
+    /// # Returns
+    /// A new `ConcurrentHllMap` instance.
+    pub fn new() -> Self {
+        Self::with_config(DEFAULT_SHARDS, DEFAULT_SPARSE_LIMIT)
+    }
+
+    /// Creates an empty map.
+    ///
+    /// # Parameters
+    /// * `shards`: The number of independently locked shards, at least one.
+    /// * `sparse_limit`: The number of distinct items a key tracks exactly before it
+    ///   switches to a `HyperLogLog`.
+    ///
+    /// # Returns
+    /// A new `ConcurrentHllMap` instance.
+    ///
+    /// # Panics
+    /// Panics if `shards` is zero.
+    pub fn with_config(shards: usize, sparse_limit: usize) -> Self {
+        assert!(shards > 0, "a ConcurrentHllMap needs at least one shard");
+
+        Self {
+            shards: (0..shards).map(|_| Mutex::new(HashMap::new())).collect(),
+            sparse_limit,
+        }
+    }
+
+    /// Adds an item to the counter of a key, creating the counter if needed.
+    ///
+    /// # Parameters
+    /// * `key`: The key to count the item under.
+    /// * `item`: An item that implements the `Hash` trait to be added.
+    pub fn add<Q, T>(&self, key: &Q, item: T)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        T: Hash,
+    {
+        let mut hasher = SeaHasher::new();
+        item.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        let mut shard = self.shard(key);
+        match shard.get_mut(key) {
+            Some(sketch) => sketch.add_hash(hash, self.sparse_limit),
+            None => {
+                let mut sketch = KeySketch::Sparse(HashSet::new());
+                sketch.add_hash(hash, self.sparse_limit);
+                shard.insert(key.to_owned(), sketch);
+            }
+        }
+    }
+
+    /// Estimates the number of distinct items added under a key since the last drain.
+    /// Keys that have not switched to a `HyperLogLog` yet report their exact count.
+    ///
+    /// # Parameters
+    /// * `key`: The key to look up.
+    ///
+    /// # Returns
+    /// The approximate count of unique items, or `None` if nothing was added under `key`.
+    pub fn estimate<Q>(&self, key: &Q) -> Option<f64>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(key).get(key).map(KeySketch::estimate)
+    }
+
+    /// Returns the number of keys holding a counter.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| lock(shard).len()).sum()
+    }
+
+    /// Returns `true` if no key holds a counter.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Releases memory the map no longer needs: the spare capacity of every shard and of
+    /// the exact hash sets of keys that have not switched to a `HyperLogLog`, which grow
+    /// in steps and otherwise keep their largest size. Useful for long-lived maps whose
+    /// key set shrank, e.g. after a traffic spike.
+    pub fn compact(&self) {
+        for shard in self.shards.iter() {
+            let mut shard = lock(shard);
+            for sketch in shard.values_mut() {
+                if let KeySketch::Sparse(hashes) = sketch {
+                    hashes.shrink_to_fit();
+                }
+            }
+            shard.shrink_to_fit();
+        }
+    }
+
+    /// Removes the counters of all keys, returning each as a `HyperLogLog`.
+    ///
+    /// Shards are drained one after the other, so items added concurrently end up either
+    /// in the returned sketches or in the map, never in both or neither.
+    ///
+    /// # Returns
+    /// The sketch of every key seen since the last drain.
+    pub fn drain_merged(&self) -> HashMap<K, HyperLogLog> {
+        let mut drained = HashMap::new();
+        for shard in self.shards.iter() {
+            let sketches = mem::take(&mut *lock(shard));
+            drained.extend(
+                sketches
+                    .into_iter()
+                    .map(|(key, sketch)| (key, sketch.into_hyperloglog())),
+            );
+        }
+
+        drained
+    }
+
+    fn shard<Q: Hash + ?Sized>(&self, key: &Q) -> MutexGuard<'_, HashMap<K, KeySketch>> {
+        let mut hasher = SeaHasher::new();
+        key.hash(&mut hasher);
+
+        lock(&self.shards[hasher.finish() as usize % self.shards.len()])
+    }
+}
+
+impl<K: Eq + Hash> Default for ConcurrentHllMap<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    #[test]
+    fn test_concurrent_adds() {
+        let shared = SharedHyperLogLog::new();
+
+        let handles = (0..4)
+            .map(|t| {
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    for i in 0..2_500 {
+                        shared.add(t * 2_500 + i);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let mut expected = HyperLogLog::new();
+        for i in 0..10_000 {
+            expected.add(i);
+        }
+
+        assert!(shared.snapshot().diff(&expected).is_identical());
+    }
+
+    #[test]
+    fn test_merge_shared() {
+        let a = SharedHyperLogLog::new();
+        let b = SharedHyperLogLog::new();
+        a.add(1);
+        b.add(2);
+
+        a.merge_shared(&b);
+        a.merge_shared(&a.clone());
+
+        assert_eq!(a.estimate().round() as u32, 2);
+    }
+
+    #[test]
+    fn test_concurrent_map() {
+        let map = Arc::new(ConcurrentHllMap::<String>::with_config(8, 100));
+
+        let handles = (0..4)
+            .map(|t| {
+                let map = map.clone();
+                thread::spawn(move || {
+                    for i in 0..2_500 {
+                        map.add("busy", t * 2_500 + i);
+                        map.add("quiet", i % 10);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.estimate("quiet"), Some(10.0));
+        assert_eq!(map.estimate("missing"), None);
+
+        let drained = map.drain_merged();
+        assert!(map.is_empty());
+
+        let mut expected = HyperLogLog::new();
+        for i in 0..10_000 {
+            expected.add(i);
+        }
+        assert!(drained["busy"].diff(&expected).is_identical());
+        assert_eq!(drained["quiet"].estimate().round(), 10.0);
+    }
+
+    #[test]
+    fn test_compact_map() {
+        let map = ConcurrentHllMap::<u32>::with_config(1, 1_000);
+        for key in 0..100 {
+            map.add(&key, key);
+        }
+        for i in 0..2_000 {
+            map.add(&0, i);
+        }
+
+        map.compact();
+        assert_eq!(map.len(), 100);
+        assert_eq!(map.estimate(&1), Some(1.0));
+
+        let estimate = map.estimate(&0).unwrap();
+        assert!((estimate - 2_000.0).abs() < 20.0, "estimate {}", estimate);
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+
+    use loom::thread;
+
+    #[test]
+    fn test_concurrent_adds_and_estimate() {
+        loom::model(|| {
+            let shared = SharedHyperLogLog::new();
+
+            let handles = (0..2)
+                .map(|t| {
+                    let shared = shared.clone();
+                    thread::spawn(move || shared.add(t))
+                })
+                .collect::<Vec<_>>();
+
+            // Any interleaving observes zero, one or both adds, never a torn register
+            let estimate = shared.estimate().round();
+            assert!((0.0..=2.0).contains(&estimate), "estimate {}", estimate);
+
+            for handle in handles {
+                handle.join().unwrap();
+            }
+            assert_eq!(shared.estimate().round(), 2.0);
+        });
+    }
+
+    #[test]
+    fn test_opposite_merges() {
+        loom::model(|| {
+            let a = SharedHyperLogLog::new();
+            let b = SharedHyperLogLog::new();
+            a.add("a");
+            b.add("b");
+
+            let handle = {
+                let (a, b) = (a.clone(), b.clone());
+                thread::spawn(move || a.merge_shared(&b))
+            };
+            b.merge_shared(&a);
+            handle.join().unwrap();
+
+            // Neither merge may deadlock, and both sides end up with both items
+            assert_eq!(a.estimate().round(), 2.0);
+            assert_eq!(b.estimate().round(), 2.0);
+        });
+    }
+
+    #[test]
+    fn test_map_adds_and_drain() {
+        loom::model(|| {
+            let map = Arc::new(ConcurrentHllMap::<u32>::with_config(2, 1));
+
+            let handles = (0..2)
+                .map(|key| {
+                    let map = map.clone();
+                    thread::spawn(move || {
+                        map.add(&key, 1);
+                        map.add(&key, 2);
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let drained = map.drain_merged();
+            for handle in handles {
+                handle.join().unwrap();
+            }
+
+            // Every item ends up either in the drained sketches or in the map
+            let rest = map.drain_merged();
+            for key in 0..2 {
+                let mut sketch = drained.get(&key).cloned().unwrap_or_default();
+                if let Some(rest) = rest.get(&key) {
+                    sketch.merge(rest);
+                }
+                assert_eq!(sketch.estimate().round(), 2.0, "key {}", key);
+            }
+        });
+    }
+}
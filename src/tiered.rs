@@ -0,0 +1,456 @@
+use std::hash::Hash;
+
+use crate::{
+    hash,
+    hll::{self, EstimateRegime},
+    metadata::SketchMetadata,
+    registers::RegistersMut,
+    Error, HyperLogLog, Sketch, M, P,
+};
+
+/// Number of distinct hashes a `TieredHyperLogLog` created with `new` lists exactly.
+pub const DEFAULT_EXACT_LIMIT: usize = 1024;
+
+/// Number of non-zero registers a `TieredHyperLogLog` created with `new` encodes sparsely.
+pub const DEFAULT_SPARSE_LIMIT: usize = M / 16;
+
+/// The representation a `TieredHyperLogLog` currently uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Tier {
+    /// The sorted list of distinct hashes, 8 bytes per item, counted exactly.
+    Exact,
+    /// The sorted list of non-zero registers, 4 bytes per register.
+    Sparse,
+    /// All `M` registers, one byte each.
+    Dense,
+}
+
+#[derive(Debug, Clone)]
+enum Repr {
+    Exact(Vec<u64>),
+    Sparse(Vec<u32>),
+    Dense(Box<HyperLogLog>),
+}
+
+/// A HyperLogLog going through the lifecycle of Redis' HyperLogLogs: it starts as an exact
+/// list of hashes, turns into a sparse list of non-zero registers once it holds more than
+/// `exact_limit` items, and into dense registers once more than `sparse_limit` registers
+/// are set. Services holding many keys, most of which see few items, pay for the dense
+/// registers only on the few keys that need them.
+///
+/// The estimate of the sparse tier equals that of the same registers held densely, and
+/// the exact tier reports the exact number of distinct hashes. Tiers only grow while
+/// items are added; `compact` moves a sketch back to the sparse tier, but only once it
+/// holds at most half of `sparse_limit` registers, so sketches hovering around the limit
+/// do not flip back and forth.
+#[derive(Debug, Clone)]
+pub struct TieredHyperLogLog {
+    repr: Repr,
+    exact_limit: usize,
+    sparse_limit: usize,
+    metadata: SketchMetadata,
+}
+
+impl TieredHyperLogLog {
+    /// Creates an empty sketch with `DEFAULT_EXACT_LIMIT` and `DEFAULT_SPARSE_LIMIT`.
+    ///
+    /// # Returns
+    /// A new `TieredHyperLogLog` instance.
+    pub fn new() -> Self {
+        Self::with_limits(DEFAULT_EXACT_LIMIT, DEFAULT_SPARSE_LIMIT)
+    }
+
+    /// Creates an empty sketch with custom thresholds.
+    ///
+    /// # Parameters
+    /// * `exact_limit`: The number of distinct items listed exactly before switching to
+    ///   the sparse tier.
+    /// * `sparse_limit`: The number of non-zero registers encoded sparsely before
+    ///   switching to the dense tier. Beyond `M / 4`, sparse registers take more memory
+    ///   than dense ones.
+    ///
+    /// # Returns
+    /// A new `TieredHyperLogLog` instance.
+    ///
+    /// # Panics
+    /// Panics if `sparse_limit` is larger than `M`.
+    pub fn with_limits(exact_limit: usize, sparse_limit: usize) -> Self {
+        Self::with_metadata(exact_limit, sparse_limit, SketchMetadata::default())
+    }
+
+    /// Creates an empty sketch with custom thresholds, hashing items with the hasher and
+    /// seed of `metadata`, which the caller has checked, e.g. `HllBuilder::build`.
+    ///
+    /// # Panics
+    /// Panics if `sparse_limit` is larger than `M`.
+    pub(crate) fn with_metadata(
+        exact_limit: usize,
+        sparse_limit: usize,
+        metadata: SketchMetadata,
+    ) -> Self {
+        assert!(sparse_limit <= M, "sparse_limit is larger than {}", M);
+
+        Self {
+            repr: Repr::Exact(Vec::new()),
+            exact_limit,
+            sparse_limit,
+            metadata,
+        }
+    }
+
+    /// Returns the precision, algorithm, hasher and seed of the sketch.
+    pub fn metadata(&self) -> SketchMetadata {
+        self.metadata
+    }
+
+    /// Returns the tier the sketch is currently in.
+    pub fn tier(&self) -> Tier {
+        match self.repr {
+            Repr::Exact(_) => Tier::Exact,
+            Repr::Sparse(_) => Tier::Sparse,
+            Repr::Dense(_) => Tier::Dense,
+        }
+    }
+
+    /// Adds an item to the sketch, moving it to the next tier if needed.
+    ///
+    /// # Parameters
+    /// * `item`: An item that implements the `Hash` trait to be added.
+    pub fn add<T: Hash>(&mut self, item: T) {
+        let hash = hash::hash_item(&self.metadata, &item);
+
+        self.add_hash(hash);
+    }
+
+    /// Estimates the number of unique items added to the sketch.
+    ///
+    /// # Returns
+    /// A `f64` approximate count of unique items, exact in the exact tier.
+    pub fn estimate(&self) -> f64 {
+        self.estimate_with_regime().0
+    }
+
+    /// Provides an estimate together with the branch of the estimator that produced it.
+    /// Estimates of the exact tier are reported as `EstimateRegime::LinearCounting`, the
+    /// regime the same items would be estimated in by the other tiers.
+    ///
+    /// # Returns
+    /// The same value as `estimate`, and the `EstimateRegime` it was computed in.
+    pub fn estimate_with_regime(&self) -> (f64, EstimateRegime) {
+        match &self.repr {
+            Repr::Exact(hashes) => (hashes.len() as f64, EstimateRegime::LinearCounting),
+            Repr::Sparse(entries) => {
+                let zeros = M - entries.len();
+                let sum = zeros as f64
+                    + entries
+                        .iter()
+                        .map(|&entry| 2f64.powi(-i32::from(entry_rank(entry))))
+                        .sum::<f64>();
+
+                hll::estimate_sums(sum, zeros, M)
+            }
+            Repr::Dense(hll) => hll.estimate_with_regime(),
+        }
+    }
+
+    /// Merges another sketch into this one, moving this one to the highest tier needed.
+    ///
+    /// # Parameters
+    /// * `other`: The sketch to merge in.
+    ///
+    /// # Panics
+    /// Panics if the sketches cannot be merged, see `try_merge`.
+    pub fn merge(&mut self, other: &TieredHyperLogLog) {
+        if let Err(e) = self.try_merge(other) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Merges another sketch into this one after checking that both use the same hasher
+    /// and seed, moving this one to the highest tier needed. Sketches in any tier can be
+    /// merged into sketches in any other.
+    ///
+    /// # Parameters
+    /// * `other`: The sketch to merge in.
+    ///
+    /// # Returns
+    /// `Ok(())` once merged, or an `Error::Incompatible` leaving this sketch untouched.
+    pub fn try_merge(&mut self, other: &TieredHyperLogLog) -> Result<(), Error> {
+        self.metadata.check_compatible(&other.metadata)?;
+
+        match &other.repr {
+            Repr::Exact(hashes) => {
+                for &hash in hashes {
+                    self.add_hash(hash);
+                }
+            }
+            Repr::Sparse(entries) => {
+                for &entry in entries {
+                    self.update_register(entry_index(entry), entry_rank(entry));
+                }
+            }
+            Repr::Dense(hll) => self.make_dense().merge(hll),
+        }
+
+        Ok(())
+    }
+
+    /// Resets the sketch to an empty exact list, keeping its thresholds.
+    pub fn clear(&mut self) {
+        self.repr = Repr::Exact(Vec::new());
+    }
+
+    /// Moves a dense sketch back to the sparse tier if it holds at most half of
+    /// `sparse_limit` non-zero registers, and releases unused capacity.
+    pub fn compact(&mut self) {
+        if let Repr::Dense(hll) = &self.repr {
+            let set = hll.registers.iter().filter(|&&rank| rank > 0).count();
+            if set <= self.sparse_limit / 2 {
+                let entries = hll
+                    .registers
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &rank)| rank > 0)
+                    .map(|(index, &rank)| entry(index, rank))
+                    .collect();
+                self.repr = Repr::Sparse(entries);
+            }
+        }
+
+        match &mut self.repr {
+            Repr::Exact(hashes) => hashes.shrink_to_fit(),
+            Repr::Sparse(entries) => entries.shrink_to_fit(),
+            Repr::Dense(_) => {}
+        }
+    }
+
+    /// Returns the registers of the sketch as a `HyperLogLog`, whatever its tier.
+    ///
+    /// # Returns
+    /// A `HyperLogLog` holding the same registers.
+    pub fn to_hyperloglog(&self) -> HyperLogLog {
+        let mut hll = HyperLogLog::with_metadata(self.metadata);
+        match &self.repr {
+            Repr::Exact(hashes) => hashes.iter().for_each(|&hash| hll.add_hash(hash)),
+            Repr::Sparse(entries) => entries.iter().for_each(|&entry| {
+                hll.registers
+                    .update_max(entry_index(entry), entry_rank(entry));
+            }),
+            Repr::Dense(dense) => hll.merge(dense),
+        }
+
+        hll
+    }
+
+    fn add_hash(&mut self, hash: u64) {
+        match &mut self.repr {
+            Repr::Exact(hashes) => {
+                if let Err(position) = hashes.binary_search(&hash) {
+                    hashes.insert(position, hash);
+                    if hashes.len() > self.exact_limit {
+                        self.make_sparse();
+                    }
+                }
+            }
+            Repr::Sparse(_) => self.update_register(hash as usize & (M - 1), hll::rank(hash, P)),
+            Repr::Dense(hll) => hll.add_hash(hash),
+        }
+    }
+
+    /// Raises a register in the sparse or dense tier, leaving the exact tier first.
+    fn update_register(&mut self, index: usize, rank: u8) {
+        if let Repr::Exact(_) = self.repr {
+            self.make_sparse();
+        }
+
+        match &mut self.repr {
+            Repr::Sparse(entries) => {
+                match entries.binary_search_by_key(&index, |&entry| entry_index(entry)) {
+                    Ok(position) => {
+                        if rank > entry_rank(entries[position]) {
+                            entries[position] = entry(index, rank);
+                        }
+                    }
+                    Err(position) => {
+                        entries.insert(position, entry(index, rank));
+                        if entries.len() > self.sparse_limit {
+                            self.make_dense();
+                        }
+                    }
+                }
+            }
+            Repr::Dense(hll) => {
+                hll.registers.update_max(index, rank);
+            }
+            Repr::Exact(_) => unreachable!("left the exact tier above"),
+        }
+    }
+
+    /// Moves the sketch from the exact to the sparse tier, or further if needed.
+    fn make_sparse(&mut self) {
+        if let Repr::Exact(hashes) = &mut self.repr {
+            let hashes = std::mem::take(hashes);
+            self.repr = Repr::Sparse(Vec::new());
+            for hash in hashes {
+                self.update_register(hash as usize & (M - 1), hll::rank(hash, P));
+            }
+        }
+    }
+
+    /// Moves the sketch to the dense tier, returning its registers.
+    fn make_dense(&mut self) -> &mut HyperLogLog {
+        if !matches!(self.repr, Repr::Dense(_)) {
+            self.repr = Repr::Dense(Box::new(self.to_hyperloglog()));
+        }
+
+        match &mut self.repr {
+            Repr::Dense(hll) => hll,
+            _ => unreachable!("moved to the dense tier above"),
+        }
+    }
+}
+
+impl Default for TieredHyperLogLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sketch for TieredHyperLogLog {
+    #[inline(always)]
+    fn add<T: Hash>(&mut self, item: T) {
+        TieredHyperLogLog::add(self, item)
+    }
+
+    #[inline(always)]
+    fn estimate(&self) -> f64 {
+        TieredHyperLogLog::estimate(self)
+    }
+
+    #[inline(always)]
+    fn merge(&mut self, other: &Self) {
+        TieredHyperLogLog::merge(self, other)
+    }
+
+    #[inline(always)]
+    fn clear(&mut self) {
+        TieredHyperLogLog::clear(self)
+    }
+}
+
+/// Packs a register index and rank into a sparse entry, ordered by index.
+#[inline(always)]
+fn entry(index: usize, rank: u8) -> u32 {
+    (index as u32) << 8 | u32::from(rank)
+}
+
+#[inline(always)]
+fn entry_index(entry: u32) -> usize {
+    (entry >> 8) as usize
+}
+
+#[inline(always)]
+fn entry_rank(entry: u32) -> u8 {
+    entry as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lifecycle() {
+        let mut tiered = TieredHyperLogLog::with_limits(100, 1_000);
+        let mut dense = HyperLogLog::new();
+
+        for i in 0..5_000 {
+            tiered.add(i);
+            dense.add(i);
+
+            match i {
+                99 => {
+                    assert_eq!(tiered.tier(), Tier::Exact);
+                    assert_eq!(tiered.estimate(), 100.0);
+                }
+                // At most 1,000 items cannot set more than 1,000 registers
+                100 | 999 => assert_eq!(tiered.tier(), Tier::Sparse),
+                _ => {}
+            }
+        }
+        assert_eq!(tiered.tier(), Tier::Dense);
+        assert!(tiered.to_hyperloglog().diff(&dense).is_identical());
+        assert_eq!(tiered.estimate(), dense.estimate());
+    }
+
+    #[test]
+    fn test_sparse_estimate_matches_dense() {
+        let mut tiered = TieredHyperLogLog::with_limits(10, M);
+        for i in 0..20_000 {
+            tiered.add(i);
+        }
+
+        assert_eq!(tiered.tier(), Tier::Sparse);
+        assert_eq!(tiered.estimate(), tiered.to_hyperloglog().estimate());
+    }
+
+    #[test]
+    fn test_merge_and_compact() {
+        let mut a = TieredHyperLogLog::with_limits(10, 100);
+        let mut b = TieredHyperLogLog::with_limits(10, 100);
+        for i in 0..5 {
+            a.add(i);
+        }
+        for i in 0..1_000 {
+            b.add(i);
+        }
+        assert_eq!(b.tier(), Tier::Dense);
+
+        a.merge(&b);
+        assert_eq!(a.tier(), Tier::Dense);
+        assert!(a.to_hyperloglog().diff(&b.to_hyperloglog()).is_identical());
+
+        // Still more registers than half the sparse limit
+        a.compact();
+        assert_eq!(a.tier(), Tier::Dense);
+
+        let mut c = TieredHyperLogLog::with_limits(10, 100);
+        for i in 0..40 {
+            c.add(i);
+        }
+        c.merge(&b);
+        c.clear();
+        c.add(1);
+        assert_eq!(c.tier(), Tier::Exact);
+
+        // Merging dense into an almost empty sketch, then compacting, goes back to sparse
+        let mut small = TieredHyperLogLog::with_limits(0, 100);
+        let mut d = TieredHyperLogLog::with_limits(0, 1);
+        for i in 0..3 {
+            d.add(i);
+        }
+        assert_eq!(d.tier(), Tier::Dense);
+        small.merge(&d);
+        assert_eq!(small.tier(), Tier::Dense);
+        small.compact();
+        assert_eq!(small.tier(), Tier::Sparse);
+        assert_eq!(small.estimate(), d.estimate());
+    }
+
+    #[cfg(feature = "xxh3")]
+    #[test]
+    fn test_try_merge_checks_metadata() {
+        use crate::{builder::Mode, metadata::HasherId, HllBuilder};
+
+        let mut a = TieredHyperLogLog::new();
+        let mut b: TieredHyperLogLog = HllBuilder::new()
+            .mode(Mode::Sparse)
+            .hasher(HasherId::Xxh3)
+            .build()
+            .unwrap();
+        a.add(1);
+        b.add(2);
+
+        assert!(matches!(a.try_merge(&b), Err(Error::Incompatible(_))));
+        assert_eq!(a.estimate(), 1.0);
+    }
+}
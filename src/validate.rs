@@ -0,0 +1,122 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashSet},
+    hash::BuildHasherDefault,
+};
+
+/// Number of distinct items tracked exactly by default before a shadow set gives up.
+pub const DEFAULT_VALIDATION_LIMIT: usize = 100_000;
+
+/// An exact set of the hashes added to a sketch, maintained alongside it when the
+/// `validate` feature is enabled.
+///
+/// Comparing the estimate against the exact count quantifies the real error of a sketch
+/// in staging environments, which catches hashing or configuration regressions that a
+/// plausible looking estimate would hide. To bound the memory used, tracking stops once
+/// more than `limit` distinct hashes have been seen.
+#[derive(Debug, Clone)]
+pub struct ShadowSet {
+    hashes: HashSet<u64, BuildHasherDefault<DefaultHasher>>,
+    limit: usize,
+    overflowed: bool,
+}
+
+impl ShadowSet {
+    /// Creates an empty shadow set tracking up to `limit` distinct hashes.
+    pub const fn new(limit: usize) -> Self {
+        Self {
+            hashes: HashSet::with_hasher(BuildHasherDefault::new()),
+            limit,
+            overflowed: false,
+        }
+    }
+
+    /// Records the hash of an added item.
+    #[inline(always)]
+    pub(crate) fn record(&mut self, hash: u64) {
+        if !self.overflowed && self.hashes.insert(hash) && self.hashes.len() > self.limit {
+            self.overflow();
+        }
+    }
+
+    /// Records the hashes tracked by the shadow set of a merged sketch.
+    pub(crate) fn record_merge(&mut self, other: &ShadowSet) {
+        if other.overflowed {
+            self.overflow();
+        }
+
+        for &hash in &other.hashes {
+            self.record(hash);
+        }
+    }
+
+    /// Changes the number of distinct hashes tracked. Lowering it below the number already
+    /// tracked stops tracking.
+    pub(crate) fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        if self.hashes.len() > limit {
+            self.overflow();
+        }
+    }
+
+    /// Forgets every tracked hash, keeping the limit.
+    pub(crate) fn clear(&mut self) {
+        self.hashes.clear();
+        self.overflowed = false;
+    }
+
+    fn overflow(&mut self) {
+        self.overflowed = true;
+        self.hashes = HashSet::default();
+    }
+
+    /// Returns the exact number of distinct items added, or `None` once more than
+    /// `limit` have been seen.
+    pub fn exact_count(&self) -> Option<usize> {
+        match self.overflowed {
+            true => None,
+            false => Some(self.hashes.len()),
+        }
+    }
+
+    /// Computes the relative error of an estimate against the exact count.
+    ///
+    /// # Parameters
+    /// * `estimate`: The estimate of the sketch the shadow set belongs to.
+    ///
+    /// # Returns
+    /// `(estimate - exact) / exact`, or `None` when nothing was added yet or tracking
+    /// stopped.
+    pub fn relative_error(&self, estimate: f64) -> Option<f64> {
+        match self.exact_count()? {
+            0 => None,
+            exact => Some((estimate - exact as f64) / exact as f64),
+        }
+    }
+}
+
+impl Default for ShadowSet {
+    fn default() -> Self {
+        Self::new(DEFAULT_VALIDATION_LIMIT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit() {
+        let mut shadow = ShadowSet::new(3);
+        for hash in [1, 2, 2, 3] {
+            shadow.record(hash);
+        }
+        assert_eq!(shadow.exact_count(), Some(3));
+        assert!((shadow.relative_error(3.3).unwrap() - 0.1).abs() < 1e-9);
+
+        let mut other = ShadowSet::new(3);
+        other.record(4);
+        shadow.record_merge(&other);
+        assert_eq!(shadow.exact_count(), None);
+        assert_eq!(shadow.relative_error(4.0), None);
+    }
+}
@@ -0,0 +1,192 @@
+use crate::{hll, metadata::SketchMetadata, registers, simd, Error, HyperLogLog, M, MAX_RANK};
+
+/// A read-only view over HyperLogLog registers owned by someone else, such as a memory
+/// mapped file, an Arrow buffer or a network frame.
+///
+/// The view can be estimated and merged into an owned sketch directly from the borrowed
+/// bytes, avoiding a 1 MiB copy just to read an estimate.
+///
+/// Raw registers do not tell which hasher filled them: views created from bytes are
+/// taken to hold the registers of a sketch with the default hasher and seed, while
+/// `HyperLogLog::view` keeps the metadata of the viewed sketch.
+#[derive(Debug, Clone, Copy)]
+pub struct HllView<'a> {
+    registers: &'a [u8; M],
+    metadata: SketchMetadata,
+}
+
+impl<'a> HllView<'a> {
+    /// Creates a view over a slice of registers.
+    ///
+    /// # Parameters
+    /// * `registers`: The registers, in the same layout as `HyperLogLog::registers`.
+    ///
+    /// # Returns
+    /// A new `HllView`, or an `Error::Length` if the slice does not hold exactly `M` registers.
+    pub fn new(registers: &'a [u8]) -> Result<Self, Error> {
+        registers
+            .try_into()
+            .map(|registers: &[u8; M]| Self::from(registers))
+            .map_err(|_| Error::Length {
+                expected: M,
+                found: registers.len(),
+            })
+    }
+
+    /// Returns the viewed registers.
+    pub fn registers(&self) -> &'a [u8; M] {
+        self.registers
+    }
+
+    /// Provides an estimate of the number of unique items in the viewed sketch.
+    ///
+    /// # Returns
+    /// A `f64` approximate count of unique items.
+    #[inline(always)]
+    pub fn estimate(&self) -> f64 {
+        hll::estimate_registers(self.registers)
+    }
+
+    /// Provides an estimate of the number of unique items in the viewed sketch, after
+    /// checking that every register holds a value `add` can produce. Views over untrusted
+    /// bytes should be estimated this way, since corrupt registers otherwise result in a
+    /// meaningless estimate.
+    ///
+    /// # Returns
+    /// A `f64` approximate count of unique items, or an `Error::InvalidRegister`.
+    pub fn try_estimate(&self) -> Result<f64, Error> {
+        registers::check_ranks(self.registers, MAX_RANK)?;
+
+        Ok(self.estimate())
+    }
+
+    /// Returns the metadata the viewed registers are taken to be built with.
+    pub fn metadata(&self) -> SketchMetadata {
+        self.metadata
+    }
+
+    /// Merges the viewed registers into an owned HyperLogLog.
+    ///
+    /// # Parameters
+    /// * `target`: The `HyperLogLog` the registers are merged into.
+    ///
+    /// # Panics
+    /// Panics if `target` was built with different metadata, see `try_merge_into`.
+    #[inline(always)]
+    pub fn merge_into(&self, target: &mut HyperLogLog) {
+        if let Err(e) = self.try_merge_into(target) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Merges the viewed registers into an owned HyperLogLog after checking that both were
+    /// built with the same precision, algorithm, hasher and seed.
+    ///
+    /// # Parameters
+    /// * `target`: The `HyperLogLog` the registers are merged into.
+    ///
+    /// # Returns
+    /// `Ok(())` once merged, or an `Error::Incompatible` leaving `target` untouched.
+    #[inline(always)]
+    pub fn try_merge_into(&self, target: &mut HyperLogLog) -> Result<(), Error> {
+        target.metadata().check_compatible(&self.metadata)?;
+        simd::merge_max(target.registers.as_mut_slice(), self.registers);
+
+        Ok(())
+    }
+
+    /// Copies the viewed registers into an owned HyperLogLog.
+    ///
+    /// # Returns
+    /// A new `HyperLogLog` holding a copy of the registers, with the metadata of the view.
+    pub fn to_hyperloglog(&self) -> HyperLogLog {
+        HyperLogLog::with_registers_and_metadata(Box::new(*self.registers), self.metadata)
+    }
+}
+
+impl<'a> From<&'a [u8; M]> for HllView<'a> {
+    /// Creates a view over an array of registers.
+    ///
+    /// # Arguments
+    ///
+    /// * `registers`: A reference to the array of `u8` registers.
+    fn from(registers: &'a [u8; M]) -> Self {
+        Self {
+            registers,
+            metadata: SketchMetadata::default(),
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for HllView<'a> {
+    type Error = Error;
+
+    /// Creates a view over a slice of registers, see `HllView::new`.
+    fn try_from(registers: &'a [u8]) -> Result<Self, Self::Error> {
+        Self::new(registers)
+    }
+}
+
+impl HyperLogLog {
+    /// Borrows the registers as an `HllView`.
+    ///
+    /// # Returns
+    /// An `HllView` over the registers of this sketch.
+    pub fn view(&self) -> HllView<'_> {
+        HllView {
+            registers: &self.registers,
+            metadata: self.metadata(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_view() {
+        let mut hll = HyperLogLog::new();
+        for i in 0..1_000 {
+            hll.add(i);
+        }
+
+        let bytes = hll.registers.to_vec();
+        let view = HllView::new(&bytes).unwrap();
+        assert_eq!(view.estimate(), hll.estimate());
+        assert!(view.to_hyperloglog().diff(&hll).is_identical());
+
+        let mut target = HyperLogLog::new();
+        target.add(1_000);
+        view.merge_into(&mut target);
+        hll.add(1_000);
+        assert!(target.diff(&hll).is_identical());
+
+        let mut seeded = HyperLogLog::with_seed([1, 2, 3, 4]);
+        assert!(matches!(
+            view.try_merge_into(&mut seeded),
+            Err(Error::Incompatible(_))
+        ));
+        assert!(seeded.view().try_merge_into(&mut seeded.clone()).is_ok());
+        assert_eq!(seeded.view().to_hyperloglog().metadata(), seeded.metadata());
+
+        assert_eq!(view.try_estimate(), Ok(view.estimate()));
+        assert_eq!(
+            HllView::new(&bytes[1..]).unwrap_err(),
+            Error::Length {
+                expected: M,
+                found: M - 1
+            }
+        );
+
+        let mut corrupt = bytes.clone();
+        corrupt[7] = 200;
+        assert_eq!(
+            HllView::new(&corrupt).unwrap().try_estimate(),
+            Err(Error::InvalidRegister {
+                index: 7,
+                value: 200
+            })
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use std::{collections::VecDeque, hash::Hash};
+
+use crate::HyperLogLog;
+
+/// Counts the distinct items among the last `window` items added, for stream processors
+/// whose windows are defined by record counts rather than time.
+///
+/// The window is split into `buckets` consecutive sub-sketches of `window / buckets` items
+/// each (rounded up). Once the newest sub-sketch is full a new one is started, and the
+/// oldest one is dropped as soon as the remaining full sub-sketches cover the window on
+/// their own. Estimates are taken over the union of all sub-sketches, so they cover at
+/// least the last `window` items and at most one sub-sketch more; more buckets tighten
+/// that bound at the cost of one register array each.
+#[derive(Debug, Clone)]
+pub struct CountWindowHll {
+    sketches: VecDeque<HyperLogLog>,
+    bucket_len: usize,
+    buckets: usize,
+    filled: usize,
+}
+
+impl CountWindowHll {
+    /// Creates an empty window.
+    ///
+    /// # Parameters
+    /// * `window`: The number of most recent items to count distinct values over.
+    /// * `buckets`: The number of sub-sketches the window is split into.
+    ///
+    /// # Returns
+    /// A new `CountWindowHll` instance.
+    ///
+    /// # Panics
+    /// Panics if `buckets` is zero or larger than `window`.
+    pub fn new(window: usize, buckets: usize) -> Self {
+        assert!(
+            buckets > 0 && buckets <= window,
+            "buckets must be between 1 and the window size"
+        );
+
+        Self {
+            sketches: VecDeque::from([HyperLogLog::new()]),
+            bucket_len: window.div_ceil(buckets),
+            buckets,
+            filled: 0,
+        }
+    }
+
+    /// Adds an item, sliding the window forward by one item.
+    ///
+    /// # Parameters
+    /// * `item`: An item that implements the `Hash` trait to be added.
+    #[inline(always)]
+    pub fn add<T: Hash>(&mut self, item: T) {
+        if self.filled == self.bucket_len {
+            self.rotate();
+        }
+
+        self.sketches
+            .back_mut()
+            .expect("the window always holds a sketch")
+            .add(item);
+        self.filled += 1;
+    }
+
+    /// Starts a new sub-sketch, recycling the oldest one once it has left the window.
+    fn rotate(&mut self) {
+        let next = match self.sketches.len() > self.buckets {
+            true => {
+                let mut oldest = self.sketches.pop_front().expect("more than one sketch");
+                oldest.clear();
+                oldest
+            }
+            false => HyperLogLog::new(),
+        };
+
+        self.sketches.push_back(next);
+        self.filled = 0;
+    }
+
+    /// Estimates the number of distinct items among the items currently covered, see
+    /// `covered`.
+    ///
+    /// # Returns
+    /// A `f64` approximate count of unique items.
+    pub fn estimate(&self) -> f64 {
+        self.union().estimate()
+    }
+
+    /// Merges all sub-sketches into a single sketch of the items currently covered.
+    ///
+    /// # Returns
+    /// A new `HyperLogLog` holding the union of the sub-sketches.
+    pub fn union(&self) -> HyperLogLog {
+        let mut union = HyperLogLog::new();
+        for sketch in &self.sketches {
+            union.merge(sketch);
+        }
+
+        union
+    }
+
+    /// Returns the number of most recent items the estimate currently covers, between
+    /// `window` and `window` plus one sub-sketch once enough items were added.
+    pub fn covered(&self) -> usize {
+        (self.sketches.len() - 1) * self.bucket_len + self.filled
+    }
+
+    /// Returns the number of items held by every sub-sketch.
+    pub fn bucket_len(&self) -> usize {
+        self.bucket_len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::M;
+
+    #[test]
+    fn test_window_slides() {
+        let mut window = CountWindowHll::new(1_000, 4);
+        assert_eq!(window.bucket_len(), 250);
+
+        for i in 0..500 {
+            window.add(i);
+        }
+        assert_eq!(window.covered(), 500);
+        let bound = 4.0 * 1.04 / (M as f64).sqrt();
+        assert!((window.estimate() - 500.0).abs() / 500.0 < bound);
+
+        for i in 500..10_100 {
+            window.add(i);
+        }
+        assert_eq!(window.covered(), 1_100);
+        assert!((window.estimate() - 1_100.0).abs() / 1_100.0 < bound);
+
+        // Repeating recent items does not grow the count
+        for i in 9_900..10_000 {
+            window.add(i);
+        }
+        assert_eq!(window.covered(), 1_200);
+        assert!((window.estimate() - 1_100.0).abs() / 1_100.0 < bound);
+    }
+}
@@ -0,0 +1,71 @@
+// Accuracy regression harness. Sweeps cardinalities from 10 to 10^8 and checks that the
+// relative error of every estimate stays within the published standard error of the
+// estimator regime in use. Too slow for every run, so the tests are ignored by default:
+//
+//     cargo test --release --test accuracy -- --ignored
+
+use hyperlog_simd::{HyperLogLog, HyperLogLogPlusPlus, Sketch, M};
+
+/// Number of sketches filled per cardinality, each with a disjoint set of items.
+const TRIALS: u64 = 3;
+
+/// Number of standard errors an estimate may be off by. Inputs are deterministic, so a
+/// failure is reproducible rather than a fluke; at 4 standard errors a correct estimator
+/// fails about once in 16,000 estimates.
+const TOLERANCE: f64 = 4.0;
+
+/// Standard error of the relative error at cardinality `n`.
+///
+/// While registers are expected to still be zero the estimate comes from linear counting,
+/// whose standard error is `sqrt(m (e^t - t - 1)) / n` with `t = n / m` (Whang et al.).
+/// Above that, the raw HyperLogLog estimate has a standard error of `1.04 / sqrt(m)`
+/// (Flajolet et al.).
+fn standard_error(n: u64) -> f64 {
+    let m = M as f64;
+    let n = n as f64;
+    let t = n / m;
+    let raw = 1.04 / m.sqrt();
+
+    match m * (-t).exp() >= 1.0 {
+        true => ((m * (t.exp() - t - 1.0)).sqrt() / n).max(raw),
+        false => raw,
+    }
+}
+
+/// Fills `TRIALS` sketches per decade of cardinality up to `10^max_exp` and checks every
+/// estimate against `standard_error`.
+fn sweep<S: Sketch>(new: impl Fn() -> S, max_exp: u32) {
+    for exp in 1..=max_exp {
+        let n = 10u64.pow(exp);
+        let bound = TOLERANCE * standard_error(n);
+
+        for trial in 0..TRIALS {
+            let mut sketch = new();
+            for i in 0..n {
+                sketch.add(trial << 40 | i);
+            }
+
+            let error = (sketch.estimate() - n as f64) / n as f64;
+            assert!(
+                error.abs() <= bound,
+                "cardinality {} trial {}: relative error {} exceeds {}",
+                n,
+                trial,
+                error,
+                bound
+            );
+        }
+    }
+}
+
+#[test]
+#[ignore]
+fn test_hyperloglog_accuracy() {
+    sweep(HyperLogLog::new, 8);
+}
+
+#[test]
+#[ignore]
+fn test_hyperloglog_plus_plus_accuracy() {
+    sweep(HyperLogLogPlusPlus::new, 8);
+}